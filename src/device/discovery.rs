@@ -0,0 +1,60 @@
+//! Enumeration of connected pico-keys across the PC/SC and FIDO HID interfaces.
+
+use crate::device::{fido::hid::HidTransport, rescue, simulator, types::DeviceHandle};
+use crate::error::PFError;
+
+/// Returns every connected candidate key.
+///
+/// Rescue readers and FIDO HID interfaces reporting the same serial number are merged into a
+/// single handle. Interfaces that cannot be matched are returned as separate entries so the
+/// user can still pick them. In demo mode only the simulated key is reported.
+pub fn list_devices() -> Result<Vec<DeviceHandle>, PFError> {
+    if simulator::is_demo_mode() {
+        return Ok(vec![simulator::global().device_handle()]);
+    }
+
+    let readers = rescue::list_devices().unwrap_or_else(|e| {
+        log::warn!("Failed to enumerate PC/SC readers: {}", e);
+        Vec::new()
+    });
+    let hid_devices = HidTransport::list_devices().unwrap_or_else(|e| {
+        log::warn!("Failed to enumerate FIDO HID devices: {}", e);
+        Vec::new()
+    });
+
+    let mut devices = readers;
+    for hid in hid_devices {
+        let matching_reader = devices.iter_mut().find(|d| {
+            d.hid_path.is_none()
+                && d.serial.is_some()
+                && d.serial.as_deref().map(str::to_uppercase) == hid.serial
+        });
+
+        match matching_reader {
+            Some(device) => {
+                device.hid_path = hid.hid_path;
+                device.vid = hid.vid;
+                device.pid = hid.pid;
+                device.product_name = hid.product_name;
+            }
+            None => devices.push(hid),
+        }
+    }
+
+    log::info!("Found {} candidate device(s)", devices.len());
+    for device in &devices {
+        log::debug!("Candidate device: {:?}", device);
+    }
+
+    Ok(devices)
+}
+
+/// Returns the FIDO HID interfaces of connected keys, without merging in their rescue readers.
+/// Much cheaper than [`list_devices`] since no reader is connected to, which matters when
+/// polling for a key to come back.
+pub fn list_hid_devices() -> Result<Vec<DeviceHandle>, PFError> {
+    if simulator::is_demo_mode() {
+        return Ok(vec![simulator::global().device_handle()]);
+    }
+    HidTransport::list_devices()
+}
@@ -0,0 +1,287 @@
+//! pico-fido authentication encryption: the device secret kept encrypted at rest with a key
+//! held by this computer.
+//!
+//! The host key never travels in the clear. Every command that carries it first runs a
+//! ManageSecurityEnvironment key agreement, which gives both sides a one-shot
+//! ChaCha20-Poly1305 key and nonce derived from the ECDH result. An encrypted key refuses to
+//! use its secret after power-up until it receives the host key through `Unlock`, and reports
+//! which of those states it is in through the [`STATE_OPTION`] GetInfo option.
+
+use directories::ProjectDirs;
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_cbor_2::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::device::fido::cbor::{self, int};
+use crate::device::fido::config;
+use crate::device::fido::constants::*;
+use crate::device::fido::pin::{KeyAgreementKey, PinUvAuthProtocol, hkdf_sha256};
+use crate::device::transport::CtapHidTransport;
+use crate::error::PFError;
+
+pub const HOST_KEY_LEN: usize = 32;
+const HOST_KEY_FILE: &str = "auth-encryption.key";
+
+/// GetInfo option reporting authentication encryption: absent while the device secret is
+/// stored in the clear, `true` while it is encrypted and locked, `false` once unlocked.
+pub const STATE_OPTION: &str = "authEnc";
+
+/// HKDF-SHA256 info strings (zero salt, as in PIN/UV auth protocol 2) for the secure channel
+/// key and nonce. The nonce is the first 12 bytes of its HKDF output.
+const MSE_KEY_INFO: &[u8] = b"PicoKeys MSE key";
+const MSE_NONCE_INFO: &[u8] = b"PicoKeys MSE nonce";
+const MSE_NONCE_LEN: usize = 12;
+
+/// Key and nonce agreed through ManageSecurityEnvironment. Each one protects a single message
+/// (ChaCha20-Poly1305, no associated data), so the nonce is never reused.
+pub struct SecureChannel {
+    key: LessSafeKey,
+    nonce: [u8; MSE_NONCE_LEN],
+}
+
+impl SecureChannel {
+    /// Derives the channel from the ECDH x-coordinate `z`.
+    pub fn derive(z: &[u8]) -> Self {
+        let key = hkdf_sha256(z, MSE_KEY_INFO);
+        let mut nonce = [0u8; MSE_NONCE_LEN];
+        nonce.copy_from_slice(&hkdf_sha256(z, MSE_NONCE_INFO)[..MSE_NONCE_LEN]);
+        Self {
+            key: LessSafeKey::new(
+                UnboundKey::new(&CHACHA20_POLY1305, &key).expect("HKDF output is 32 bytes"),
+            ),
+            nonce,
+        }
+    }
+
+    pub fn seal(self, plaintext: &[u8]) -> Result<Vec<u8>, PFError> {
+        let mut buf = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(self.nonce),
+                Aad::empty(),
+                &mut buf,
+            )
+            .map_err(|_| PFError::Io("Failed to encrypt for the secure environment".into()))?;
+        Ok(buf)
+    }
+
+    pub fn open(self, ciphertext: &[u8]) -> Result<Vec<u8>, PFError> {
+        let mut buf = ciphertext.to_vec();
+        let len = self
+            .key
+            .open_in_place(
+                Nonce::assume_unique_for_key(self.nonce),
+                Aad::empty(),
+                &mut buf,
+            )
+            .map_err(|_| PFError::Device("Secure environment message failed to decrypt".into()))?
+            .len();
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
+
+/// Runs the ManageSecurityEnvironment key agreement.
+pub fn establish_secure_channel<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+) -> Result<SecureChannel, PFError> {
+    log::debug!("Establishing secure environment...");
+    let platform = KeyAgreementKey::generate()?;
+    let mut params = BTreeMap::new();
+    params.insert(int(VendorSubParam::CoseKey as u8), platform.cose_key());
+
+    let response = cbor::send_vendor_command(
+        transport,
+        VendorCommand::ManageSecurityEnvironment,
+        MseSubCommand::KeyAgreement as u8,
+        Some(params),
+    )?;
+    let authenticator_key = response
+        .get(&int(0x01))
+        .ok_or_else(|| PFError::Device("Secure environment response is missing the key".into()))?;
+    platform.agree(authenticator_key, SecureChannel::derive)
+}
+
+/// Encrypts the device secret with `host_key`. The key must be unlocked, i.e. not encrypted
+/// yet.
+pub fn enable<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+    host_key: &[u8],
+) -> Result<(), PFError> {
+    let sealed = establish_secure_channel(transport)?.seal(host_key)?;
+    config::send_vendor_config(
+        transport,
+        protocol,
+        pin_token,
+        VendorConfigCommand::AuthEncryptionEnable,
+        Value::Bytes(sealed),
+    )
+}
+
+/// Stores the device secret in the clear again. The key must be unlocked with `host_key`.
+pub fn disable<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+    host_key: &[u8],
+) -> Result<(), PFError> {
+    let sealed = establish_secure_channel(transport)?.seal(host_key)?;
+    config::send_vendor_config(
+        transport,
+        protocol,
+        pin_token,
+        VendorConfigCommand::AuthEncryptionDisable,
+        Value::Bytes(sealed),
+    )
+}
+
+/// Unlocks the device secret until the key is power cycled.
+pub fn unlock<T: CtapHidTransport + ?Sized>(transport: &T, host_key: &[u8]) -> Result<(), PFError> {
+    let mut params = BTreeMap::new();
+    params.insert(
+        int(VendorSubParam::VendorParam as u8),
+        Value::Bytes(establish_secure_channel(transport)?.seal(host_key)?),
+    );
+    cbor::send_vendor_command(
+        transport,
+        VendorCommand::Unlock,
+        UnlockSubCommand::Unlock as u8,
+        Some(params),
+    )?;
+    Ok(())
+}
+
+fn host_key_path() -> Option<PathBuf> {
+    ProjectDirs::from("in", "suyogtandel", "picoforge")
+        .map(|dirs| dirs.data_local_dir().join(HOST_KEY_FILE))
+}
+
+/// The host key stored on this computer, if authentication encryption was ever enabled here.
+pub fn load_host_key() -> Result<Option<Vec<u8>>, PFError> {
+    let Some(path) = host_key_path() else {
+        return Ok(None);
+    };
+    match fs::read(&path) {
+        Ok(key) if key.len() == HOST_KEY_LEN => Ok(Some(key)),
+        Ok(_) => Err(PFError::Io(format!(
+            "Host key at {} is corrupt",
+            path.display()
+        ))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(PFError::Io(format!(
+            "Failed to read host key at {}: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+/// The stored host key, for a key that is already encrypted.
+pub fn require_host_key() -> Result<Vec<u8>, PFError> {
+    load_host_key()?.ok_or_else(|| {
+        PFError::Device(
+            "This key was encrypted on another computer and cannot be unlocked here".into(),
+        )
+    })
+}
+
+/// Returns the stored host key, generating and saving one on first use.
+pub fn load_or_create_host_key() -> Result<Vec<u8>, PFError> {
+    if let Some(key) = load_host_key()? {
+        return Ok(key);
+    }
+    let path = host_key_path()
+        .ok_or_else(|| PFError::Io("Could not determine the application data directory".into()))?;
+
+    let mut key = vec![0u8; HOST_KEY_LEN];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| PFError::Io("Failed to generate a host key".into()))?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| PFError::Io(e.to_string()))?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&path).map_err(|e| {
+        PFError::Io(format!(
+            "Failed to create host key at {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    std::io::Write::write_all(&mut file, &key).map_err(|e| PFError::Io(e.to_string()))?;
+
+    log::info!(
+        "Generated authentication encryption host key at {}",
+        path.display()
+    );
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::fido::{self, client_pin};
+    use crate::device::rescue;
+    use crate::device::simulator::{DEMO_PIN, Simulator};
+
+    const HOST_KEY: [u8; HOST_KEY_LEN] = [7; HOST_KEY_LEN];
+
+    fn is_locked(hid: &impl CtapHidTransport) -> bool {
+        fido::read_fido_info(hid).unwrap().auth_encryption_locked
+    }
+
+    /// Computed with Python's `cryptography` (HKDF and ChaCha20Poly1305), not with this code.
+    /// It pins the derivation down, it does not prove the firmware agrees with it.
+    #[test]
+    fn secure_channel_known_answer() {
+        let z: Vec<u8> = (0..32).collect();
+        let sealed = SecureChannel::derive(&z).seal(&HOST_KEY).unwrap();
+        assert_eq!(
+            hex::encode(&sealed),
+            "708e60ce5023b4290296fedcc5ef716b9a0d9f158f9201ad9f1044237d2eb1cc\
+             228528a81095549080989fe63d1111fc"
+        );
+        assert_eq!(SecureChannel::derive(&z).open(&sealed).unwrap(), HOST_KEY);
+
+        let mut tampered = sealed;
+        tampered[0] ^= 1;
+        assert!(SecureChannel::derive(&z).open(&tampered).is_err());
+    }
+
+    #[test]
+    fn reports_the_locked_state_until_unlocked() {
+        let sim = Simulator::new();
+        let hid = sim.open_hid();
+        let protocol = PinUvAuthProtocol::Two;
+        assert!(!is_locked(&hid));
+
+        let token = client_pin::get_pin_token(&hid, protocol, DEMO_PIN).unwrap();
+        enable(&hid, protocol, &token, &HOST_KEY).unwrap();
+        // Encrypted, but the secret stays usable until the next power-up
+        assert!(!is_locked(&hid));
+        rescue::reboot(&sim, false).unwrap();
+        assert!(is_locked(&hid));
+
+        let err = unlock(&hid, &[8; HOST_KEY_LEN]).unwrap_err();
+        assert_eq!(err.ctap_error(), Some(Ctap2Error::PinAuthInvalid));
+        assert!(is_locked(&hid));
+        unlock(&hid, &HOST_KEY).unwrap();
+        assert!(!is_locked(&hid));
+    }
+
+    #[test]
+    fn unlock_errors_are_not_swallowed() {
+        let sim = Simulator::new();
+        let err = unlock(&sim.open_hid(), &HOST_KEY).unwrap_err();
+        assert_eq!(err.ctap_error(), Some(Ctap2Error::NotAllowed));
+    }
+}
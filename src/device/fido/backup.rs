@@ -0,0 +1,211 @@
+//! pico-fido encrypted backup of the device secret, and its recovery word encoding.
+//!
+//! The key hands out its secret encrypted (it only does so while unlocked by a correct PIN)
+//! and accepts the same blob back on any pico-fido key. The blob is shown to the user as words
+//! from the BIP-39 English list, 11 bits each. It carries a nonce and tag next to the
+//! ciphertext, so it is longer than the 32 bytes a BIP-39 mnemonic holds, and gets its own
+//! framing instead: a word holding the blob length, the blob zero padded to whole words, and a
+//! checksum word (the first 11 bits of its SHA-256) that catches typos before anything is
+//! written to a key.
+
+use bip39::Language;
+use ring::digest;
+use serde_cbor_2::Value;
+use std::collections::BTreeMap;
+
+use crate::device::fido::cbor::{self, int};
+use crate::device::fido::constants::*;
+use crate::device::transport::CtapHidTransport;
+use crate::error::PFError;
+
+const BITS_PER_WORD: u32 = 11;
+const WORD_MASK: u32 = (1 << BITS_PER_WORD) - 1;
+/// The length word limits backups to this many bytes.
+const MAX_BACKUP_LEN: usize = WORD_MASK as usize;
+
+/// Reads the encrypted device secret. The key must have verified the PIN since it was
+/// powered up.
+pub fn get_encrypted_backup<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+) -> Result<Vec<u8>, PFError> {
+    log::debug!("Requesting encrypted backup...");
+    let response = cbor::send_vendor_command(
+        transport,
+        VendorCommand::Backup,
+        BackupSubCommand::GetEncryptedBackup as u8,
+        None,
+    )?;
+    cbor::get_bytes(&response, 0x01)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| PFError::Device("Backup response is missing the secret".into()))
+}
+
+/// Writes a secret read with [`get_encrypted_backup`] to the key, replacing its own.
+pub fn restore_encrypted_backup<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    backup: &[u8],
+) -> Result<(), PFError> {
+    log::debug!("Restoring encrypted backup ({} bytes)...", backup.len());
+    let mut params = BTreeMap::new();
+    params.insert(
+        int(VendorSubParam::VendorParam as u8),
+        Value::Bytes(backup.to_vec()),
+    );
+    cbor::send_vendor_command(
+        transport,
+        VendorCommand::Backup,
+        BackupSubCommand::RestoreEncryptedBackup as u8,
+        Some(params),
+    )?;
+    Ok(())
+}
+
+/// Encodes a backup as recovery words.
+pub fn to_words(backup: &[u8]) -> Result<Vec<String>, PFError> {
+    if backup.is_empty() || backup.len() > MAX_BACKUP_LEN {
+        return Err(PFError::Device(format!(
+            "A backup of {} bytes cannot be shown as words",
+            backup.len()
+        )));
+    }
+    let mut indices = vec![backup.len() as u16];
+    indices.extend(pack(backup));
+    indices.push(checksum(backup));
+
+    let list = Language::English.word_list();
+    Ok(indices
+        .into_iter()
+        .map(|i| list[usize::from(i)].to_string())
+        .collect())
+}
+
+/// Decodes recovery words typed by the user, in any case and spacing.
+pub fn from_words(words: &str) -> Result<Vec<u8>, PFError> {
+    let indices = words
+        .split_whitespace()
+        .map(|word| {
+            Language::English
+                .find_word(&word.to_lowercase())
+                .ok_or_else(|| PFError::Io(format!("\"{}\" is not a recovery word", word)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let [len, data @ .., check] = indices.as_slice() else {
+        return Err(PFError::Io("Too few recovery words".into()));
+    };
+    let len = usize::from(*len);
+    if len == 0 || data.len() != (len * 8).div_ceil(BITS_PER_WORD as usize) {
+        return Err(PFError::Io("Wrong number of recovery words".into()));
+    }
+    let backup = unpack(data, len)
+        .filter(|backup| checksum(backup) == *check)
+        .ok_or_else(|| {
+            PFError::Io("The recovery words do not add up, check them for typos".into())
+        })?;
+    Ok(backup)
+}
+
+/// Splits `data` into 11 bit word indices, zero padding the last one.
+fn pack(data: &[u8]) -> Vec<u16> {
+    let mut indices = Vec::new();
+    let (mut acc, mut bits) = (0u32, 0);
+    for &byte in data {
+        acc = (acc << 8) | u32::from(byte);
+        bits += 8;
+        if bits >= BITS_PER_WORD {
+            bits -= BITS_PER_WORD;
+            indices.push(((acc >> bits) & WORD_MASK) as u16);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    if bits > 0 {
+        indices.push(((acc << (BITS_PER_WORD - bits)) & WORD_MASK) as u16);
+    }
+    indices
+}
+
+/// Reverses [`pack`] for a `len` byte blob. Returns `None` if the padding is not all zeros.
+fn unpack(indices: &[u16], len: usize) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(len + 1);
+    let (mut acc, mut bits) = (0u32, 0);
+    for &index in indices {
+        acc = (acc << BITS_PER_WORD) | u32::from(index);
+        bits += BITS_PER_WORD;
+        while bits >= 8 {
+            bits -= 8;
+            data.push((acc >> bits) as u8);
+        }
+        acc &= (1 << bits) - 1;
+    }
+    let padding_is_zero = acc == 0 && data[len..].iter().all(|&b| b == 0);
+    data.truncate(len);
+    padding_is_zero.then_some(data)
+}
+
+/// The first 11 bits of the SHA-256 of `data`.
+fn checksum(data: &[u8]) -> u16 {
+    let hash = digest::digest(&digest::SHA256, data);
+    u16::from_be_bytes([hash.as_ref()[0], hash.as_ref()[1]]) >> (16 - BITS_PER_WORD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_round_trip() {
+        // Nonce, 32 byte ciphertext and tag, as the key hands it out
+        let backup: Vec<u8> = (0..60).collect();
+        let words = to_words(&backup).unwrap();
+        assert_eq!(words.len(), 1 + 44 + 1);
+        assert_eq!(from_words(&words.join(" ")).unwrap(), backup);
+
+        for len in [1, 31, 32, 33, 64, MAX_BACKUP_LEN] {
+            let backup = vec![0xA5; len];
+            assert_eq!(
+                from_words(&to_words(&backup).unwrap().join(" ")).unwrap(),
+                backup
+            );
+        }
+    }
+
+    /// Computed with a Python reimplementation of the encoding over the BIP-39 English list.
+    #[test]
+    fn encodes_length_data_and_checksum_words() {
+        assert_eq!(to_words(&[0xFF]).unwrap(), ["ability", "yellow", "pool"]);
+        assert_eq!(
+            to_words(&[0, 1, 2, 3]).unwrap(),
+            ["above", "abandon", "amount", "liar", "aim"]
+        );
+    }
+
+    #[test]
+    fn accepts_any_case_and_spacing() {
+        assert_eq!(from_words("  Ability\tYELLOW \n pool ").unwrap(), [0xFF]);
+    }
+
+    #[test]
+    fn rejects_a_typo() {
+        let mut words = to_words(&[7; 60]).unwrap();
+        words[10] = if words[10] == "zoo" { "zone" } else { "zoo" }.to_string();
+        assert!(from_words(&words.join(" ")).is_err());
+        assert!(from_words("ability yellow poem").is_err());
+    }
+
+    #[test]
+    fn rejects_set_padding_bits() {
+        // 0xFF followed by a set padding bit, with the checksum of 0xFF
+        assert!(from_words("ability you pool").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_words_and_lengths() {
+        assert!(from_words("ability yellow poool").is_err());
+        assert!(from_words("ability yellow yellow pool").is_err());
+        assert!(from_words("abandon pool").is_err());
+        assert!(from_words("pool").is_err());
+        assert!(from_words("").is_err());
+        assert!(to_words(&[]).is_err());
+        assert!(to_words(&vec![0; MAX_BACKUP_LEN + 1]).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+//! Small helpers for building and reading CTAP2 CBOR maps.
+
+use serde_cbor_2::{Value, from_slice, to_vec};
+use std::collections::BTreeMap;
+
+use crate::device::fido::constants::{CTAP_VENDOR_CBOR_CMD, CtapCommand, VendorCommand};
+use crate::device::fido::hid::CTAPHID_CBOR;
+use crate::device::transport::CtapHidTransport;
+use crate::error::PFError;
+
+pub type CborMap = BTreeMap<Value, Value>;
+
+/// Sends an authenticator command with an (optional) integer keyed parameter map and decodes
+/// the response map. An empty response is returned as an empty map.
+pub fn send_command<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    cmd: CtapCommand,
+    params: Option<CborMap>,
+) -> Result<CborMap, PFError> {
+    let mut payload = vec![cmd as u8];
+    if let Some(params) = params {
+        payload.extend(to_vec(&Value::Map(params)).map_err(|e| PFError::Io(e.to_string()))?);
+    }
+
+    let response = transport.send_cbor(CTAPHID_CBOR, &payload)?;
+    decode_response(&response, cmd)
+}
+
+/// Sends a pico-fido vendor command. The request map carries the sub command at 0x01 and, when
+/// given, its parameters at 0x02.
+pub fn send_vendor_command<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    cmd: VendorCommand,
+    sub_command: u8,
+    sub_params: Option<CborMap>,
+) -> Result<CborMap, PFError> {
+    let mut params = BTreeMap::new();
+    params.insert(int(0x01), int(sub_command));
+    if let Some(sub_params) = sub_params {
+        params.insert(int(0x02), Value::Map(sub_params));
+    }
+
+    let mut payload = vec![cmd as u8];
+    payload.extend(to_vec(&Value::Map(params)).map_err(|e| PFError::Io(e.to_string()))?);
+
+    let response = transport.send_cbor(CTAP_VENDOR_CBOR_CMD, &payload)?;
+    decode_response(&response, cmd)
+}
+
+fn decode_response(response: &[u8], cmd: impl std::fmt::Debug) -> Result<CborMap, PFError> {
+    if response.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    match from_slice(response) {
+        Ok(Value::Map(map)) => Ok(map),
+        Ok(_) => Err(PFError::Device(format!(
+            "Unexpected response to command {:?}: not a CBOR map",
+            cmd
+        ))),
+        Err(e) => Err(PFError::Io(format!("Failed to parse CBOR response: {}", e))),
+    }
+}
+
+pub fn int(key: impl Into<i128>) -> Value {
+    Value::Integer(key.into())
+}
+
+pub fn get_int(map: &CborMap, key: i128) -> Option<i128> {
+    match map.get(&Value::Integer(key)) {
+        Some(Value::Integer(v)) => Some(*v),
+        _ => None,
+    }
+}
+
+pub fn get_bytes(map: &CborMap, key: i128) -> Option<&[u8]> {
+    match map.get(&Value::Integer(key)) {
+        Some(Value::Bytes(b)) => Some(b),
+        _ => None,
+    }
+}
+
+pub fn get_map(map: &CborMap, key: i128) -> Option<&CborMap> {
+    match map.get(&Value::Integer(key)) {
+        Some(Value::Map(m)) => Some(m),
+        _ => None,
+    }
+}
+
+/// Reads a text entry from a map keyed by strings (e.g. `PublicKeyCredentialUserEntity`).
+pub fn get_text<'a>(map: &'a CborMap, key: &str) -> Option<&'a str> {
+    match map.get(&Value::Text(key.to_string())) {
+        Some(Value::Text(s)) => Some(s),
+        _ => None,
+    }
+}
+
+/// Reads a byte string entry from a map keyed by strings.
+pub fn get_text_keyed_bytes<'a>(map: &'a CborMap, key: &str) -> Option<&'a [u8]> {
+    match map.get(&Value::Text(key.to_string())) {
+        Some(Value::Bytes(b)) => Some(b),
+        _ => None,
+    }
+}
@@ -0,0 +1,237 @@
+//! Native authenticatorClientPIN (0x06) client.
+
+use serde_cbor_2::Value;
+use std::collections::BTreeMap;
+
+use crate::device::fido::cbor::{self, CborMap, int};
+use crate::device::fido::constants::*;
+use crate::device::fido::pin::{KeyAgreementKey, PinUvAuthProtocol, pad_pin, pin_hash};
+use crate::device::transport::CtapHidTransport;
+use crate::error::PFError;
+
+/// Shared secret established through `getKeyAgreement`, together with the platform key the
+/// authenticator needs to derive the same secret.
+struct SharedSecret {
+    protocol: PinUvAuthProtocol,
+    key: Vec<u8>,
+    platform_key: Value,
+}
+
+fn client_pin<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    sub_command: ClientPinSubCommand,
+    extra: CborMap,
+) -> Result<CborMap, PFError> {
+    let mut params = extra;
+    params.insert(
+        int(ClientPinParam::PinUvAuthProtocol as u8),
+        int(protocol.version()),
+    );
+    params.insert(
+        int(ClientPinParam::SubCommand as u8),
+        int(sub_command as u8),
+    );
+    cbor::send_command(transport, CtapCommand::ClientPin, Some(params))
+}
+
+fn key_agreement<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+) -> Result<SharedSecret, PFError> {
+    log::debug!("Requesting ClientPIN key agreement...");
+    let response = client_pin(
+        transport,
+        protocol,
+        ClientPinSubCommand::GetKeyAgreement,
+        BTreeMap::new(),
+    )?;
+    let authenticator_key = response
+        .get(&int(0x01))
+        .ok_or_else(|| PFError::Device("Key agreement response is missing the key".into()))?;
+
+    let platform = KeyAgreementKey::generate()?;
+    let platform_key = platform.cose_key();
+    let key = platform.shared_secret(protocol, authenticator_key)?;
+
+    Ok(SharedSecret {
+        protocol,
+        key,
+        platform_key,
+    })
+}
+
+/// Result of `getPinRetries` (0x01).
+#[derive(Debug, Clone, Copy)]
+pub struct PinRetries {
+    pub retries: u32,
+    /// Set when PIN attempts are blocked until the authenticator is power cycled.
+    pub power_cycle_required: bool,
+}
+
+pub fn get_pin_retries<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+) -> Result<PinRetries, PFError> {
+    let response = client_pin(
+        transport,
+        protocol,
+        ClientPinSubCommand::GetPinRetries,
+        BTreeMap::new(),
+    )?;
+    let retries = cbor::get_int(&response, 0x03)
+        .ok_or_else(|| PFError::Device("getPinRetries response is missing pinRetries".into()))?;
+    let power_cycle_required = matches!(response.get(&int(0x04)), Some(Value::Bool(true)));
+
+    Ok(PinRetries {
+        retries: retries as u32,
+        power_cycle_required,
+    })
+}
+
+pub fn get_uv_retries<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+) -> Result<u32, PFError> {
+    let response = client_pin(
+        transport,
+        protocol,
+        ClientPinSubCommand::GetUvRetries,
+        BTreeMap::new(),
+    )?;
+    cbor::get_int(&response, 0x05)
+        .map(|r| r as u32)
+        .ok_or_else(|| PFError::Device("getUVRetries response is missing uvRetries".into()))
+}
+
+/// Sets the first PIN on an authenticator that has none.
+pub fn set_pin<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    new_pin: &str,
+) -> Result<(), PFError> {
+    let secret = key_agreement(transport, protocol)?;
+    let new_pin_enc = protocol.encrypt(&secret.key, &pad_pin(new_pin)?)?;
+    let pin_auth = protocol.authenticate(&secret.key, &new_pin_enc);
+
+    let mut params = BTreeMap::new();
+    params.insert(int(ClientPinParam::KeyAgreement as u8), secret.platform_key);
+    params.insert(
+        int(ClientPinParam::PinUvAuthParam as u8),
+        Value::Bytes(pin_auth),
+    );
+    params.insert(
+        int(ClientPinParam::NewPinEnc as u8),
+        Value::Bytes(new_pin_enc),
+    );
+
+    client_pin(transport, protocol, ClientPinSubCommand::SetPin, params)?;
+    log::info!("PIN set via native ClientPIN.");
+    Ok(())
+}
+
+/// Changes the PIN, proving knowledge of the current one.
+pub fn change_pin<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    current_pin: &str,
+    new_pin: &str,
+) -> Result<(), PFError> {
+    let secret = key_agreement(transport, protocol)?;
+    let pin_hash_enc = protocol.encrypt(&secret.key, &pin_hash(current_pin))?;
+    let new_pin_enc = protocol.encrypt(&secret.key, &pad_pin(new_pin)?)?;
+
+    let mut message = new_pin_enc.clone();
+    message.extend(&pin_hash_enc);
+    let pin_auth = protocol.authenticate(&secret.key, &message);
+
+    let mut params = BTreeMap::new();
+    params.insert(int(ClientPinParam::KeyAgreement as u8), secret.platform_key);
+    params.insert(
+        int(ClientPinParam::PinUvAuthParam as u8),
+        Value::Bytes(pin_auth),
+    );
+    params.insert(
+        int(ClientPinParam::NewPinEnc as u8),
+        Value::Bytes(new_pin_enc),
+    );
+    params.insert(
+        int(ClientPinParam::PinHashEnc as u8),
+        Value::Bytes(pin_hash_enc),
+    );
+
+    client_pin(transport, protocol, ClientPinSubCommand::ChangePin, params)?;
+    log::info!("PIN changed via native ClientPIN.");
+    Ok(())
+}
+
+/// Obtains a pinUvAuthToken with `getPinToken` (0x05), for authenticators without
+/// CTAP 2.1 token permissions.
+pub fn get_pin_token<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin: &str,
+) -> Result<Vec<u8>, PFError> {
+    request_token(
+        transport,
+        protocol,
+        pin,
+        ClientPinSubCommand::GetPinToken,
+        BTreeMap::new(),
+    )
+}
+
+/// Obtains a pinUvAuthToken limited to `permissions` with
+/// `getPinUvAuthTokenUsingPinWithPermissions` (0x09). `rp_id` further limits it to one relying
+/// party, which makeCredential and getAssertion require and credential management allows.
+pub fn get_pin_uv_auth_token<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin: &str,
+    permissions: PinUvAuthTokenPermissions,
+    rp_id: Option<&str>,
+) -> Result<Vec<u8>, PFError> {
+    let mut params = BTreeMap::new();
+    params.insert(
+        int(ClientPinParam::Permissions as u8),
+        int(permissions.bits()),
+    );
+    if let Some(rp_id) = rp_id {
+        params.insert(
+            int(ClientPinParam::PermissionsRpId as u8),
+            Value::Text(rp_id.to_string()),
+        );
+    }
+    request_token(
+        transport,
+        protocol,
+        pin,
+        ClientPinSubCommand::GetPinUvAuthTokenUsingPinWithPermissions,
+        params,
+    )
+}
+
+/// Proves knowledge of the PIN and decrypts the pinUvAuthToken the authenticator returns.
+fn request_token<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin: &str,
+    sub_command: ClientPinSubCommand,
+    extra: CborMap,
+) -> Result<Vec<u8>, PFError> {
+    let secret = key_agreement(transport, protocol)?;
+    let pin_hash_enc = protocol.encrypt(&secret.key, &pin_hash(pin))?;
+
+    let mut params = extra;
+    params.insert(int(ClientPinParam::KeyAgreement as u8), secret.platform_key);
+    params.insert(
+        int(ClientPinParam::PinHashEnc as u8),
+        Value::Bytes(pin_hash_enc),
+    );
+
+    let response = client_pin(transport, protocol, sub_command, params)?;
+    let token_enc = cbor::get_bytes(&response, 0x02)
+        .ok_or_else(|| PFError::Device("ClientPIN response is missing the token".into()))?;
+
+    secret.protocol.decrypt(&secret.key, token_enc)
+}
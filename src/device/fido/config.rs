@@ -0,0 +1,254 @@
+//! authenticatorConfig (0x0D) commands, built by hand so the CBOR map keys stay in canonical
+//! order.
+
+use serde_cbor_2::{Value, to_vec};
+use std::collections::BTreeMap;
+
+use crate::device::fido::constants::*;
+use crate::device::fido::hid::CTAPHID_CBOR;
+use crate::device::fido::pin::PinUvAuthProtocol;
+use crate::device::transport::CtapHidTransport;
+use crate::error::PFError;
+
+pub fn send_vendor_config<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+    vendor_cmd: VendorConfigCommand,
+    param: Value,
+) -> Result<(), PFError> {
+    log::debug!("Sending vendor config command: {}...", vendor_cmd);
+
+    // Build subCommandParams (Key 0x02)
+    // This map contains:
+    // 0x01: vendorCommandId (u64)
+    // 0x02/0x03/0x04: param
+    let mut sub_params_inner = BTreeMap::new();
+    sub_params_inner.insert(
+        Value::Integer(0x01),
+        Value::Integer(vendor_cmd.to_u64() as i128),
+    );
+
+    match param {
+        Value::Bytes(_) => {
+            sub_params_inner.insert(Value::Integer(0x02), param.clone());
+        }
+        Value::Integer(_) => {
+            sub_params_inner.insert(Value::Integer(0x03), param.clone());
+        }
+        Value::Text(_) => {
+            sub_params_inner.insert(Value::Integer(0x04), param.clone());
+        }
+        _ => return Err(PFError::Io("Unsupported parameter type".into())),
+    }
+
+    let sub_params = Value::Map(sub_params_inner);
+    let sub_params_bytes = to_vec(&sub_params).map_err(|e| PFError::Io(e.to_string()))?;
+
+    // Calculate PIN Auth
+    let pin_auth = sign_config_command(
+        protocol,
+        pin_token,
+        ConfigSubCommand::VendorPrototype as u8,
+        &sub_params_bytes,
+    );
+
+    // Build full authenticatorConfig map
+    let mut config_map = BTreeMap::new();
+    config_map.insert(
+        Value::Integer(ConfigParam::SubCommand as i128),
+        Value::Integer(ConfigSubCommand::VendorPrototype as i128),
+    );
+    config_map.insert(
+        Value::Integer(ConfigParam::SubCommandParams as i128),
+        sub_params,
+    );
+    config_map.insert(
+        Value::Integer(ConfigParam::PinUvAuthProtocol as i128),
+        Value::Integer(protocol.version() as i128),
+    );
+    config_map.insert(
+        Value::Integer(ConfigParam::PinUvAuthParam as i128),
+        Value::Bytes(pin_auth),
+    );
+
+    let config_payload_cbor =
+        to_vec(&Value::Map(config_map)).map_err(|e| PFError::Io(e.to_string()))?;
+
+    // Encapsulate for CTAP
+    let mut payload = vec![CtapCommand::Config as u8];
+    payload.extend(config_payload_cbor);
+
+    // Send via HID
+    transport
+        .send_cbor(CTAPHID_CBOR, &payload)
+        .inspect_err(|e| log::error!("Failed to send FIDO config: {}", e))?;
+
+    Ok(())
+}
+
+/// Send authenticatorConfig command to set minimum PIN length.
+///
+/// This bypasses the ctap-hid-fido2 library which has a bug where it sends
+/// CBOR map keys out of order (0x01, 0x03, 0x04, 0x02) instead of the required
+/// ascending order (0x01, 0x02, 0x03, 0x04). The pico-fido firmware strictly
+/// enforces canonical CBOR ordering per CTAP2 spec.
+pub fn send_config_set_min_pin_length<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+    new_min_pin_length: u8,
+) -> Result<(), PFError> {
+    log::debug!(
+        "Sending setMinPINLength config command (new length: {})...",
+        new_min_pin_length
+    );
+
+    // Build subCommandParams (Key 0x02): { 0x01: newMinPINLength }
+    let mut sub_params_map = BTreeMap::new();
+    sub_params_map.insert(
+        Value::Integer(ConfigSubCommandParam::NewMinPinLength as i128),
+        Value::Integer(new_min_pin_length as i128),
+    );
+    let sub_params = Value::Map(sub_params_map);
+    let sub_params_bytes = to_vec(&sub_params).map_err(|e| PFError::Io(e.to_string()))?;
+
+    // Calculate PIN Auth
+    let pin_auth = sign_config_command(
+        protocol,
+        pin_token,
+        ConfigSubCommand::SetMinPinLength as u8,
+        &sub_params_bytes,
+    );
+
+    // Build full authenticatorConfig map with keys in ASCENDING ORDER
+    // Keeping the map item in the correct order is critical - the firmware parser rejects out-of-order keys with CTAP2_ERR_INVALID_CBOR
+    let mut config_map = BTreeMap::new();
+    config_map.insert(
+        Value::Integer(ConfigParam::SubCommand as i128), // 0x01
+        Value::Integer(ConfigSubCommand::SetMinPinLength as i128), // 0x03
+    );
+    config_map.insert(
+        Value::Integer(ConfigParam::SubCommandParams as i128), // 0x02
+        sub_params,
+    );
+    config_map.insert(
+        Value::Integer(ConfigParam::PinUvAuthProtocol as i128), // 0x03
+        Value::Integer(protocol.version() as i128),
+    );
+    config_map.insert(
+        Value::Integer(ConfigParam::PinUvAuthParam as i128), // 0x04
+        Value::Bytes(pin_auth),
+    );
+
+    let config_payload_cbor =
+        to_vec(&Value::Map(config_map)).map_err(|e| PFError::Io(e.to_string()))?;
+
+    // Prepend CTAP command byte
+    let mut payload = vec![CtapCommand::Config as u8];
+    payload.extend(config_payload_cbor);
+
+    // Send via HID
+    match transport.send_cbor(CTAPHID_CBOR, &payload) {
+        Ok(_) => {
+            log::info!(
+                "Successfully set minimum PIN length to {}",
+                new_min_pin_length
+            );
+            Ok(())
+        }
+        Err(e) => {
+            log::error!("Failed to send setMinPINLength config: {}", e);
+            // PIN_POLICY_VIOLATION here means the new minimum is lower than the current one;
+            // its remediation text explains that lowering it needs a reset.
+            Err(e)
+        }
+    }
+}
+
+/// Send authenticatorConfig toggleAlwaysUv, which flips the `alwaysUv` option.
+pub fn send_config_toggle_always_uv<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+) -> Result<(), PFError> {
+    send_config_without_params(
+        transport,
+        protocol,
+        pin_token,
+        ConfigSubCommand::ToggleAlwaysUv,
+    )
+}
+
+/// Send authenticatorConfig enableEnterpriseAttestation. The key reports the `ep` option as
+/// true afterwards.
+pub fn send_config_enable_enterprise_attestation<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+) -> Result<(), PFError> {
+    send_config_without_params(
+        transport,
+        protocol,
+        pin_token,
+        ConfigSubCommand::EnableEnterpriseAttestation,
+    )
+}
+
+/// Sends a sub command that has no subCommandParams, so nothing follows the sub command byte
+/// in the signed message.
+fn send_config_without_params<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+    sub_cmd: ConfigSubCommand,
+) -> Result<(), PFError> {
+    log::debug!("Sending {:?} config command...", sub_cmd);
+
+    let pin_auth = sign_config_command(protocol, pin_token, sub_cmd as u8, &[]);
+
+    let mut config_map = BTreeMap::new();
+    config_map.insert(
+        Value::Integer(ConfigParam::SubCommand as i128), // 0x01
+        Value::Integer(sub_cmd as i128),
+    );
+    config_map.insert(
+        Value::Integer(ConfigParam::PinUvAuthProtocol as i128), // 0x03
+        Value::Integer(protocol.version() as i128),
+    );
+    config_map.insert(
+        Value::Integer(ConfigParam::PinUvAuthParam as i128), // 0x04
+        Value::Bytes(pin_auth),
+    );
+
+    let config_payload_cbor =
+        to_vec(&Value::Map(config_map)).map_err(|e| PFError::Io(e.to_string()))?;
+
+    let mut payload = vec![CtapCommand::Config as u8];
+    payload.extend(config_payload_cbor);
+
+    transport
+        .send_cbor(CTAPHID_CBOR, &payload)
+        .inspect_err(|e| log::error!("Failed to send {:?} config: {}", sub_cmd, e))?;
+
+    log::info!("Applied {:?} config command", sub_cmd);
+    Ok(())
+}
+
+/// Helper to sign the authenticatorConfig command
+fn sign_config_command(
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+    sub_cmd: u8,
+    sub_params_bytes: &[u8],
+) -> Vec<u8> {
+    // Build HMAC message for signing
+    // According to FIDO 2.1: authenticate(pinUvAuthToken, 32×0xff || 0x0d || uint8(subCommand) || subCommandParams)
+    let mut message = vec![0xff; 32];
+    message.push(CtapCommand::Config as u8);
+    message.push(sub_cmd);
+    message.extend(sub_params_bytes);
+
+    // Sign using provided PIN token (16 bytes for protocol 1, 32 for protocol 2)
+    protocol.authenticate(pin_token, &message)
+}
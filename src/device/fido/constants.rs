@@ -54,7 +54,7 @@ pub enum ClientPinSubCommand {
     GetPinToken = 0x05,
     GetPinUvAuthTokenUsingUvWithPermissions = 0x06,
     GetUvRetries = 0x07,
-    GetPinUvAuthTokenUsingPinWithPermissions = 0x08,
+    GetPinUvAuthTokenUsingPinWithPermissions = 0x09,
 }
 
 #[repr(u8)]
@@ -84,6 +84,16 @@ pub enum GetAssertionParam {
     PinUvAuthProtocol = 0x07,
 }
 
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GetAssertionResponse {
+    Credential = 0x01,
+    AuthData = 0x02,
+    Signature = 0x03,
+    User = 0x04,
+    NumberOfCredentials = 0x05,
+}
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ClientPinParam {
@@ -97,6 +107,118 @@ pub enum ClientPinParam {
     PermissionsRpId = 0x0A,
 }
 
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialMgmtSubCommand {
+    GetCredsMetadata = 0x01,
+    EnumerateRpsBegin = 0x02,
+    EnumerateRpsGetNextRp = 0x03,
+    EnumerateCredentialsBegin = 0x04,
+    EnumerateCredentialsGetNextCredential = 0x05,
+    DeleteCredential = 0x06,
+    UpdateUserInformation = 0x07,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialMgmtParam {
+    SubCommand = 0x01,
+    SubCommandParams = 0x02,
+    PinUvAuthProtocol = 0x03,
+    PinUvAuthParam = 0x04,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialMgmtSubParam {
+    RpIdHash = 0x01,
+    CredentialId = 0x02,
+    User = 0x03,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialMgmtResponse {
+    ExistingResidentCredentialsCount = 0x01,
+    MaxPossibleRemainingResidentCredentialsCount = 0x02,
+    Rp = 0x03,
+    RpIdHash = 0x04,
+    TotalRps = 0x05,
+    User = 0x06,
+    CredentialId = 0x07,
+    PublicKey = 0x08,
+    TotalCredentials = 0x09,
+    CredProtect = 0x0A,
+    LargeBlobKey = 0x0B,
+    ThirdPartyPayment = 0x0C,
+}
+
+/// credProtect policy of a credential: when it may be used without user verification.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredProtectPolicy {
+    UserVerificationOptional = 0x01,
+    UserVerificationOptionalWithCredentialIdList = 0x02,
+    UserVerificationRequired = 0x03,
+}
+
+impl CredProtectPolicy {
+    pub fn from_id(id: i128) -> Option<Self> {
+        Some(match id {
+            0x01 => Self::UserVerificationOptional,
+            0x02 => Self::UserVerificationOptionalWithCredentialIdList,
+            0x03 => Self::UserVerificationRequired,
+            _ => return None,
+        })
+    }
+
+    /// The policy name used by WebAuthn.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::UserVerificationOptional => "userVerificationOptional",
+            Self::UserVerificationOptionalWithCredentialIdList => {
+                "userVerificationOptionalWithCredentialIDList"
+            }
+            Self::UserVerificationRequired => "userVerificationRequired",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::UserVerificationOptional => "Usable without PIN or biometrics",
+            Self::UserVerificationOptionalWithCredentialIdList => {
+                "Usable without PIN or biometrics only when the site names the credential"
+            }
+            Self::UserVerificationRequired => "Always requires PIN or biometrics",
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LargeBlobsParam {
+    Get = 0x01,
+    Set = 0x02,
+    Offset = 0x03,
+    Length = 0x04,
+    PinUvAuthParam = 0x05,
+    PinUvAuthProtocol = 0x06,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LargeBlobsResponse {
+    Config = 0x01,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LargeBlobEntryKey {
+    Ciphertext = 0x01,
+    Nonce = 0x02,
+    OrigSize = 0x03,
+}
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConfigParam {
@@ -210,6 +332,12 @@ pub enum MseSubCommand {
     KeyAgreement = 0x01,
 }
 
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnlockSubCommand {
+    Unlock = 0x01,
+}
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EnterpriseAttestationSubCommand {
@@ -239,6 +367,7 @@ pub enum MemoryResponseKey {
 }
 
 bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct PinUvAuthTokenPermissions: u8 {
         const MAKE_CREDENTIAL = 0x01;
         const GET_ASSERTION = 0x02;
@@ -304,6 +433,98 @@ pub enum CoseCurve {
     BP512R1 = 11,
 }
 
+impl CoseCurve {
+    pub fn from_id(id: i128) -> Option<Self> {
+        Some(match id {
+            1 => Self::P256,
+            2 => Self::P384,
+            3 => Self::P521,
+            4 => Self::X25519,
+            5 => Self::X448,
+            6 => Self::Ed25519,
+            7 => Self::Ed448,
+            8 => Self::P256K1,
+            9 => Self::BP256R1,
+            10 => Self::BP384R1,
+            11 => Self::BP512R1,
+            _ => return None,
+        })
+    }
+
+    /// The curve name registered with IANA (also the JWK `crv` where one is registered).
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::P256 => "P-256",
+            Self::P384 => "P-384",
+            Self::P521 => "P-521",
+            Self::X25519 => "X25519",
+            Self::X448 => "X448",
+            Self::Ed25519 => "Ed25519",
+            Self::Ed448 => "Ed448",
+            Self::P256K1 => "secp256k1",
+            Self::BP256R1 => "brainpoolP256r1",
+            Self::BP384R1 => "brainpoolP384r1",
+            Self::BP512R1 => "brainpoolP512r1",
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoseKeyType {
+    Okp = 1,
+    Ec2 = 2,
+    Rsa = 3,
+}
+
+impl CoseAlgorithm {
+    pub fn from_id(id: i128) -> Option<Self> {
+        Some(match id {
+            -7 => Self::ES256,
+            -8 => Self::EdDSA,
+            -9 => Self::ESP256,
+            -19 => Self::Ed25519,
+            -25 => Self::EcdhEsHkdf256,
+            -35 => Self::ES384,
+            -36 => Self::ES512,
+            -47 => Self::ES256K,
+            -51 => Self::ESP384,
+            -52 => Self::ESP512,
+            -53 => Self::Ed448,
+            -257 => Self::RS256,
+            -258 => Self::RS384,
+            -259 => Self::RS512,
+            -265 => Self::ESB256,
+            -267 => Self::ESB384,
+            -268 => Self::ESB512,
+            _ => return None,
+        })
+    }
+
+    /// The algorithm name registered with IANA (also the JWK `alg`).
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ES256 => "ES256",
+            Self::EdDSA => "EdDSA",
+            Self::ESP256 => "ESP256",
+            Self::Ed25519 => "Ed25519",
+            Self::EcdhEsHkdf256 => "ECDH-ES+HKDF-256",
+            Self::ES384 => "ES384",
+            Self::ES512 => "ES512",
+            Self::ES256K => "ES256K",
+            Self::ESP384 => "ESP384",
+            Self::ESP512 => "ESP512",
+            Self::Ed448 => "Ed448",
+            Self::RS256 => "RS256",
+            Self::RS384 => "RS384",
+            Self::RS512 => "RS512",
+            Self::ESB256 => "ESB256",
+            Self::ESB384 => "ESB384",
+            Self::ESB512 => "ESB512",
+        }
+    }
+}
+
 #[repr(i32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CoseKeyParam {
@@ -322,10 +543,19 @@ pub enum CoseKeyParam {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Ctap2Error {
     Success = 0x00,
+    InvalidCommand = 0x01,
+    InvalidParameter = 0x02,
+    InvalidLength = 0x03,
+    InvalidSeq = 0x04,
+    Timeout = 0x05,
+    ChannelBusy = 0x06,
+    LockRequired = 0x0A,
+    InvalidChannel = 0x0B,
     CborUnexpectedType = 0x11,
     InvalidCbor = 0x12,
     MissingParameter = 0x14,
     LimitExceeded = 0x15,
+    UnsupportedExtension = 0x16,
     FpDatabaseFull = 0x17,
     LargeBlobStorageFull = 0x18,
     CredentialExcluded = 0x19,
@@ -358,8 +588,236 @@ pub enum Ctap2Error {
     InvalidSubcommand = 0x3E,
     UvInvalid = 0x3F,
     UnauthorizedPermission = 0x40,
+    Other = 0x7F,
+}
+
+impl Ctap2Error {
+    /// Decodes a CTAP status byte or `CTAPHID_ERROR` code. Codes the spec leaves to extensions
+    /// and vendors decode as [`Ctap2Error::Other`].
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x00 => Self::Success,
+            0x01 => Self::InvalidCommand,
+            0x02 => Self::InvalidParameter,
+            0x03 => Self::InvalidLength,
+            0x04 => Self::InvalidSeq,
+            0x05 => Self::Timeout,
+            0x06 => Self::ChannelBusy,
+            0x0A => Self::LockRequired,
+            0x0B => Self::InvalidChannel,
+            0x11 => Self::CborUnexpectedType,
+            0x12 => Self::InvalidCbor,
+            0x14 => Self::MissingParameter,
+            0x15 => Self::LimitExceeded,
+            0x16 => Self::UnsupportedExtension,
+            0x17 => Self::FpDatabaseFull,
+            0x18 => Self::LargeBlobStorageFull,
+            0x19 => Self::CredentialExcluded,
+            0x21 => Self::Processing,
+            0x22 => Self::InvalidCredential,
+            0x23 => Self::UserActionPending,
+            0x24 => Self::OperationPending,
+            0x25 => Self::NoOperations,
+            0x26 => Self::UnsupportedAlgorithm,
+            0x27 => Self::OperationDenied,
+            0x28 => Self::KeyStoreFull,
+            0x2B => Self::UnsupportedOption,
+            0x2C => Self::InvalidOption,
+            0x2D => Self::KeepaliveCancel,
+            0x2E => Self::NoCredentials,
+            0x2F => Self::UserActionTimeout,
+            0x30 => Self::NotAllowed,
+            0x31 => Self::PinInvalid,
+            0x32 => Self::PinBlocked,
+            0x33 => Self::PinAuthInvalid,
+            0x34 => Self::PinAuthBlocked,
+            0x35 => Self::PinNotSet,
+            0x36 => Self::PuatRequired,
+            0x37 => Self::PinPolicyViolation,
+            0x39 => Self::RequestTooLarge,
+            0x3A => Self::ActionTimeout,
+            0x3B => Self::UpRequired,
+            0x3C => Self::UvBlocked,
+            0x3D => Self::IntegrityFailure,
+            0x3E => Self::InvalidSubcommand,
+            0x3F => Self::UvInvalid,
+            0x40 => Self::UnauthorizedPermission,
+            0x7F => Self::Other,
+            _ => {
+                log::warn!("Unknown CTAP status 0x{:02X}", code);
+                Self::Other
+            }
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// Name from the CTAP specification, e.g. `CTAP2_ERR_PIN_INVALID`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Success => "CTAP1_ERR_SUCCESS",
+            Self::InvalidCommand => "CTAP1_ERR_INVALID_COMMAND",
+            Self::InvalidParameter => "CTAP1_ERR_INVALID_PARAMETER",
+            Self::InvalidLength => "CTAP1_ERR_INVALID_LENGTH",
+            Self::InvalidSeq => "CTAP1_ERR_INVALID_SEQ",
+            Self::Timeout => "CTAP1_ERR_TIMEOUT",
+            Self::ChannelBusy => "CTAP1_ERR_CHANNEL_BUSY",
+            Self::LockRequired => "CTAP1_ERR_LOCK_REQUIRED",
+            Self::InvalidChannel => "CTAP1_ERR_INVALID_CHANNEL",
+            Self::CborUnexpectedType => "CTAP2_ERR_CBOR_UNEXPECTED_TYPE",
+            Self::InvalidCbor => "CTAP2_ERR_INVALID_CBOR",
+            Self::MissingParameter => "CTAP2_ERR_MISSING_PARAMETER",
+            Self::LimitExceeded => "CTAP2_ERR_LIMIT_EXCEEDED",
+            Self::UnsupportedExtension => "CTAP2_ERR_UNSUPPORTED_EXTENSION",
+            Self::FpDatabaseFull => "CTAP2_ERR_FP_DATABASE_FULL",
+            Self::LargeBlobStorageFull => "CTAP2_ERR_LARGE_BLOB_STORAGE_FULL",
+            Self::CredentialExcluded => "CTAP2_ERR_CREDENTIAL_EXCLUDED",
+            Self::Processing => "CTAP2_ERR_PROCESSING",
+            Self::InvalidCredential => "CTAP2_ERR_INVALID_CREDENTIAL",
+            Self::UserActionPending => "CTAP2_ERR_USER_ACTION_PENDING",
+            Self::OperationPending => "CTAP2_ERR_OPERATION_PENDING",
+            Self::NoOperations => "CTAP2_ERR_NO_OPERATIONS",
+            Self::UnsupportedAlgorithm => "CTAP2_ERR_UNSUPPORTED_ALGORITHM",
+            Self::OperationDenied => "CTAP2_ERR_OPERATION_DENIED",
+            Self::KeyStoreFull => "CTAP2_ERR_KEY_STORE_FULL",
+            Self::UnsupportedOption => "CTAP2_ERR_UNSUPPORTED_OPTION",
+            Self::InvalidOption => "CTAP2_ERR_INVALID_OPTION",
+            Self::KeepaliveCancel => "CTAP2_ERR_KEEPALIVE_CANCEL",
+            Self::NoCredentials => "CTAP2_ERR_NO_CREDENTIALS",
+            Self::UserActionTimeout => "CTAP2_ERR_USER_ACTION_TIMEOUT",
+            Self::NotAllowed => "CTAP2_ERR_NOT_ALLOWED",
+            Self::PinInvalid => "CTAP2_ERR_PIN_INVALID",
+            Self::PinBlocked => "CTAP2_ERR_PIN_BLOCKED",
+            Self::PinAuthInvalid => "CTAP2_ERR_PIN_AUTH_INVALID",
+            Self::PinAuthBlocked => "CTAP2_ERR_PIN_AUTH_BLOCKED",
+            Self::PinNotSet => "CTAP2_ERR_PIN_NOT_SET",
+            Self::PuatRequired => "CTAP2_ERR_PUAT_REQUIRED",
+            Self::PinPolicyViolation => "CTAP2_ERR_PIN_POLICY_VIOLATION",
+            Self::RequestTooLarge => "CTAP2_ERR_REQUEST_TOO_LARGE",
+            Self::ActionTimeout => "CTAP2_ERR_ACTION_TIMEOUT",
+            Self::UpRequired => "CTAP2_ERR_UP_REQUIRED",
+            Self::UvBlocked => "CTAP2_ERR_UV_BLOCKED",
+            Self::IntegrityFailure => "CTAP2_ERR_INTEGRITY_FAILURE",
+            Self::InvalidSubcommand => "CTAP2_ERR_INVALID_SUBCOMMAND",
+            Self::UvInvalid => "CTAP2_ERR_UV_INVALID",
+            Self::UnauthorizedPermission => "CTAP2_ERR_UNAUTHORIZED_PERMISSION",
+            Self::Other => "CTAP1_ERR_OTHER",
+        }
+    }
+
+    /// What went wrong, in words a user understands.
+    pub fn explanation(self) -> &'static str {
+        match self {
+            Self::Success => "The operation succeeded.",
+            Self::InvalidCommand => "The key does not support this command.",
+            Self::InvalidParameter => "The key rejected a parameter of the request.",
+            Self::InvalidLength => "The request had an invalid length.",
+            Self::InvalidSeq => "USB packets arrived out of order.",
+            Self::Timeout => "The key timed out waiting for the rest of a USB message.",
+            Self::ChannelBusy => "The key is busy with a request from another application.",
+            Self::LockRequired => "The key is locked by another application.",
+            Self::InvalidChannel => "The USB channel to the key is no longer valid.",
+            Self::CborUnexpectedType => "The key could not decode the request.",
+            Self::InvalidCbor => "The key could not decode the request.",
+            Self::MissingParameter => "The request is missing a required parameter.",
+            Self::LimitExceeded => "A limit of the key was exceeded.",
+            Self::UnsupportedExtension => "The key does not support this extension.",
+            Self::FpDatabaseFull => "The fingerprint database is full.",
+            Self::LargeBlobStorageFull => "The large blob storage is full.",
+            Self::CredentialExcluded => "This key already holds a credential for this account.",
+            Self::Processing => "The key is still processing the request.",
+            Self::InvalidCredential => "The credential is not valid for this key.",
+            Self::UserActionPending => "The key is waiting for you.",
+            Self::OperationPending => "Another operation is in progress on the key.",
+            Self::NoOperations => "There is no operation to continue.",
+            Self::UnsupportedAlgorithm => "The key does not support the requested algorithm.",
+            Self::OperationDenied => "The operation was denied.",
+            Self::KeyStoreFull => "The key has no room for more passkeys.",
+            Self::UnsupportedOption => "The key does not support this option.",
+            Self::InvalidOption => "The request used an invalid option.",
+            Self::KeepaliveCancel => "The operation was cancelled.",
+            Self::NoCredentials => "No matching credentials are stored on the key.",
+            Self::UserActionTimeout => "The key timed out waiting for a touch.",
+            Self::NotAllowed => "The key does not allow this operation in its current state.",
+            Self::PinInvalid => "The PIN is incorrect.",
+            Self::PinBlocked => "The PIN is blocked after too many wrong attempts.",
+            Self::PinAuthInvalid => "The key rejected the PIN authentication.",
+            Self::PinAuthBlocked => {
+                "Too many wrong PINs in a row, PIN entry is blocked until the key is power cycled."
+            }
+            Self::PinNotSet => "No PIN is set on the key.",
+            Self::PuatRequired => "This operation requires the PIN.",
+            Self::PinPolicyViolation => "The PIN does not meet the key's PIN policy.",
+            Self::RequestTooLarge => "The request is too large for the key.",
+            Self::ActionTimeout => "The key timed out.",
+            Self::UpRequired => "This operation requires a touch.",
+            Self::UvBlocked => "Built-in user verification is blocked.",
+            Self::IntegrityFailure => "The data failed an integrity check.",
+            Self::InvalidSubcommand => "The key does not support this subcommand.",
+            Self::UvInvalid => "User verification failed.",
+            Self::UnauthorizedPermission => {
+                "The PIN token does not grant permission for this operation."
+            }
+            Self::Other => "The key reported an unspecified error.",
+        }
+    }
+
+    /// What the user can do about it, if anything.
+    pub fn remediation(self) -> Option<&'static str> {
+        match self {
+            Self::InvalidCommand => {
+                Some("Update the firmware of your key, this feature needs a newer version.")
+            }
+            Self::InvalidSeq => Some("Connect the key directly or try another USB port or cable."),
+            Self::Timeout => Some("Connect the key directly or try another USB port or cable."),
+            Self::ChannelBusy => {
+                Some("Close other applications using the key (e.g. browsers) and try again.")
+            }
+            Self::LockRequired => Some("Close other applications using the key and try again."),
+            Self::InvalidChannel => Some("Refresh the device list and try again."),
+            Self::FpDatabaseFull => Some("Remove an enrolled fingerprint first."),
+            Self::LargeBlobStorageFull => Some("Delete some large blob entries first."),
+            Self::Processing => Some("Wait a moment and try again."),
+            Self::UserActionPending => Some("Touch your key."),
+            Self::OperationPending => Some("Wait for it to finish and try again."),
+            Self::KeyStoreFull => Some("Delete passkeys you no longer need."),
+            Self::UserActionTimeout => Some("Try again and touch your key when it blinks."),
+            Self::PinInvalid => Some(
+                "Check the PIN and try again. The key blocks it after too many wrong attempts.",
+            ),
+            Self::PinBlocked => Some("The key has to be reset, which deletes all passkeys on it."),
+            Self::PinAuthInvalid => {
+                Some("Try again. If it keeps failing, unplug and replug the key.")
+            }
+            Self::PinAuthBlocked => Some("Unplug the key, plug it back in and try again."),
+            Self::PinNotSet => Some("Set up a PIN first."),
+            Self::PuatRequired => Some("Set up a PIN and enter it when asked."),
+            Self::PinPolicyViolation => Some(
+                "Choose a longer or more complex PIN. The minimum PIN length can only be raised, lowering it needs a reset.",
+            ),
+            Self::ActionTimeout => Some("Try again."),
+            Self::UpRequired => Some("Touch your key when it blinks."),
+            Self::UvBlocked => Some("Use the PIN instead."),
+            Self::InvalidSubcommand => {
+                Some("Update the firmware of your key, this feature needs a newer version.")
+            }
+            Self::UvInvalid => Some("Try again."),
+            Self::UnauthorizedPermission => Some("Try again, PicoForge will request a new token."),
+            _ => None,
+        }
+    }
 }
 
+impl fmt::Display for Ctap2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.explanation(), self.name())
+    }
+}
+
+impl std::error::Error for Ctap2Error {}
+
 pub const CTAP_VENDOR_CBOR_CMD: u8 = 0xC1;
 pub const CTAP_VENDOR_CONFIG_CMD: u8 = 0xC2;
 
@@ -373,6 +831,10 @@ pub const CTAP_MAX_EC_SIG_SIZE: usize = 72;
 pub const CTAP_CTR_SIZE: usize = 4;
 
 pub const MAX_PIN_RETRIES: u8 = 8;
+/// Wrong PINs in a row after which the key answers PIN_AUTH_BLOCKED until it is power cycled.
+pub const MAX_CONSECUTIVE_PIN_FAILURES: u8 = 3;
+/// authenticatorReset is only accepted this many seconds after power-up.
+pub const RESET_WINDOW_SECS: u64 = 10;
 pub const MAX_CREDENTIAL_COUNT_IN_LIST: usize = 16;
 pub const MAX_CRED_ID_LENGTH: usize = 1024;
 pub const MAX_RESIDENT_CREDENTIALS: usize = 256;
@@ -384,3 +846,40 @@ pub const MAX_LARGE_BLOB_SIZE: usize = 2048;
 pub const AAGUID: [u8; 16] = [
     0x89, 0xFB, 0x94, 0xB7, 0x06, 0xC9, 0x36, 0x73, 0x9B, 0x7E, 0x30, 0x52, 0x6D, 0x96, 0x81, 0x45,
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every status the CTAP 2.2 specification and CTAPHID define.
+    const KNOWN_CODES: &[u8] = &[
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x0A, 0x0B, 0x11, 0x12, 0x14, 0x15, 0x16, 0x17,
+        0x18, 0x19, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x2B, 0x2C, 0x2D, 0x2E, 0x2F,
+        0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F,
+        0x40, 0x7F,
+    ];
+
+    #[test]
+    fn known_codes_round_trip() {
+        for &code in KNOWN_CODES {
+            let error = Ctap2Error::from_code(code);
+            assert_eq!(error.code(), code, "{:?}", error);
+            assert!(error.name().starts_with("CTAP"), "{:?}", error);
+            assert!(!error.explanation().is_empty(), "{:?}", error);
+        }
+        assert_eq!(Ctap2Error::from_code(0x31), Ctap2Error::PinInvalid);
+        assert_eq!(Ctap2Error::PinInvalid.name(), "CTAP2_ERR_PIN_INVALID");
+    }
+
+    #[test]
+    fn unknown_codes_are_other() {
+        for code in (0..=u8::MAX).filter(|c| !KNOWN_CODES.contains(c)) {
+            assert_eq!(
+                Ctap2Error::from_code(code),
+                Ctap2Error::Other,
+                "0x{:02X}",
+                code
+            );
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//! Credential public keys as the key reports them (COSE_Key), and the formats relying party
+//! tooling expects them in: PEM SubjectPublicKeyInfo and JWK.
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use serde_cbor_2::Value;
+
+use crate::device::fido::cbor::{self, CborMap};
+use crate::device::fido::constants::*;
+use crate::device::fido::pem;
+use crate::error::PFError;
+
+/// id-ecPublicKey (1.2.840.10045.2.1)
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+
+/// A decoded credential public key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CredentialPublicKey {
+    /// COSE algorithm identifier, kept as is so unknown algorithms can still be shown.
+    pub algorithm: i128,
+    pub params: KeyParams,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyParams {
+    Ec2 {
+        curve: i128,
+        x: Vec<u8>,
+        y: Vec<u8>,
+    },
+    Okp {
+        curve: i128,
+        x: Vec<u8>,
+    },
+    /// A key type PicoForge cannot export (e.g. RSA).
+    Other {
+        key_type: i128,
+    },
+}
+
+impl CredentialPublicKey {
+    pub fn from_cose(key: &CborMap) -> Result<Self, PFError> {
+        let algorithm = cbor::get_int(key, CoseKeyParam::Alg as i128)
+            .ok_or_else(|| PFError::Device("Public key is missing its algorithm".into()))?;
+        let key_type = cbor::get_int(key, CoseKeyParam::Kty as i128)
+            .ok_or_else(|| PFError::Device("Public key is missing its key type".into()))?;
+        let curve = || {
+            cbor::get_int(key, CoseKeyParam::Crv as i128)
+                .ok_or_else(|| PFError::Device("Public key is missing its curve".into()))
+        };
+        let coordinate = |param: CoseKeyParam| {
+            cbor::get_bytes(key, param as i128)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| PFError::Device(format!("Public key is missing {:?}", param)))
+        };
+
+        let params = match key_type {
+            k if k == CoseKeyType::Ec2 as i128 => KeyParams::Ec2 {
+                curve: curve()?,
+                x: coordinate(CoseKeyParam::X)?,
+                y: coordinate(CoseKeyParam::Y)?,
+            },
+            k if k == CoseKeyType::Okp as i128 => KeyParams::Okp {
+                curve: curve()?,
+                x: coordinate(CoseKeyParam::X)?,
+            },
+            key_type => KeyParams::Other { key_type },
+        };
+        Ok(Self { algorithm, params })
+    }
+
+    pub fn algorithm_name(&self) -> String {
+        CoseAlgorithm::from_id(self.algorithm)
+            .map(|alg| alg.name().to_string())
+            .unwrap_or_else(|| format!("COSE algorithm {}", self.algorithm))
+    }
+
+    /// Name of the curve, for EC2 and OKP keys.
+    pub fn curve_name(&self) -> Option<String> {
+        let curve = match self.params {
+            KeyParams::Ec2 { curve, .. } | KeyParams::Okp { curve, .. } => curve,
+            KeyParams::Other { .. } => return None,
+        };
+        Some(
+            CoseCurve::from_id(curve)
+                .map(|crv| crv.name().to_string())
+                .unwrap_or_else(|| format!("COSE curve {}", curve)),
+        )
+    }
+
+    /// DER SubjectPublicKeyInfo.
+    pub fn to_spki(&self) -> Result<Vec<u8>, PFError> {
+        let (algorithm, public_key) = match &self.params {
+            KeyParams::Ec2 { curve, x, y } => {
+                let curve_oid = CoseCurve::from_id(*curve)
+                    .and_then(ec_curve_oid)
+                    .ok_or_else(|| self.unsupported("PEM"))?;
+                let mut algorithm = der(0x06, OID_EC_PUBLIC_KEY);
+                algorithm.extend(der(0x06, curve_oid));
+                let mut point = vec![0x04];
+                point.extend(x);
+                point.extend(y);
+                (algorithm, point)
+            }
+            KeyParams::Okp { curve, x } => {
+                let oid = CoseCurve::from_id(*curve)
+                    .and_then(okp_oid)
+                    .ok_or_else(|| self.unsupported("PEM"))?;
+                (der(0x06, oid), x.clone())
+            }
+            KeyParams::Other { .. } => return Err(self.unsupported("PEM")),
+        };
+
+        let mut bit_string = vec![0x00];
+        bit_string.extend(public_key);
+        let mut spki = der(0x30, &algorithm);
+        spki.extend(der(0x03, &bit_string));
+        Ok(der(0x30, &spki))
+    }
+
+    pub fn to_pem(&self) -> Result<String, PFError> {
+        Ok(pem::encode("PUBLIC KEY", &self.to_spki()?))
+    }
+
+    /// JSON Web Key (RFC 7517), pretty printed.
+    pub fn to_jwk(&self) -> Result<String, PFError> {
+        let mut jwk = match &self.params {
+            KeyParams::Ec2 { curve, x, y } => {
+                let crv = CoseCurve::from_id(*curve)
+                    .filter(|crv| ec_curve_oid(*crv).is_some() && !is_brainpool(*crv))
+                    .ok_or_else(|| self.unsupported("JWK"))?;
+                serde_json::json!({
+                    "kty": "EC",
+                    "crv": crv.name(),
+                    "x": URL_SAFE_NO_PAD.encode(x),
+                    "y": URL_SAFE_NO_PAD.encode(y),
+                })
+            }
+            KeyParams::Okp { curve, x } => {
+                let crv = CoseCurve::from_id(*curve)
+                    .filter(|crv| okp_oid(*crv).is_some())
+                    .ok_or_else(|| self.unsupported("JWK"))?;
+                serde_json::json!({
+                    "kty": "OKP",
+                    "crv": crv.name(),
+                    "x": URL_SAFE_NO_PAD.encode(x),
+                })
+            }
+            KeyParams::Other { .. } => return Err(self.unsupported("JWK")),
+        };
+        if let Some(alg) = CoseAlgorithm::from_id(self.algorithm) {
+            jwk["alg"] = alg.name().into();
+        }
+        serde_json::to_string_pretty(&jwk).map_err(|e| PFError::Io(e.to_string()))
+    }
+
+    fn unsupported(&self, format: &str) -> PFError {
+        PFError::Device(format!(
+            "A {} key{} cannot be exported as {}",
+            self.algorithm_name(),
+            self.curve_name()
+                .map(|crv| format!(" on {}", crv))
+                .unwrap_or_default(),
+            format
+        ))
+    }
+}
+
+/// Reads the credential public key out of a credential management response, if it has one.
+pub fn from_response(value: Option<&Value>) -> Option<CredentialPublicKey> {
+    let Some(Value::Map(key)) = value else {
+        return None;
+    };
+    CredentialPublicKey::from_cose(key)
+        .inspect_err(|e| log::warn!("Ignoring undecodable credential public key: {}", e))
+        .ok()
+}
+
+fn ec_curve_oid(curve: CoseCurve) -> Option<&'static [u8]> {
+    Some(match curve {
+        CoseCurve::P256 => &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07],
+        CoseCurve::P384 => &[0x2B, 0x81, 0x04, 0x00, 0x22],
+        CoseCurve::P521 => &[0x2B, 0x81, 0x04, 0x00, 0x23],
+        CoseCurve::P256K1 => &[0x2B, 0x81, 0x04, 0x00, 0x0A],
+        CoseCurve::BP256R1 => &[0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x07],
+        CoseCurve::BP384R1 => &[0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x0B],
+        CoseCurve::BP512R1 => &[0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x0D],
+        _ => return None,
+    })
+}
+
+fn okp_oid(curve: CoseCurve) -> Option<&'static [u8]> {
+    Some(match curve {
+        CoseCurve::X25519 => &[0x2B, 0x65, 0x6E],
+        CoseCurve::X448 => &[0x2B, 0x65, 0x6F],
+        CoseCurve::Ed25519 => &[0x2B, 0x65, 0x70],
+        CoseCurve::Ed448 => &[0x2B, 0x65, 0x71],
+        _ => return None,
+    })
+}
+
+/// Brainpool curves have no registered JWK `crv` name.
+fn is_brainpool(curve: CoseCurve) -> bool {
+    matches!(
+        curve,
+        CoseCurve::BP256R1 | CoseCurve::BP384R1 | CoseCurve::BP512R1
+    )
+}
+
+/// A DER TLV. Lengths above 0xFFFF do not occur in public keys.
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match content.len() {
+        len @ 0..=0x7F => out.push(len as u8),
+        len @ 0x80..=0xFF => out.extend([0x81, len as u8]),
+        len => out.extend([0x82, (len >> 8) as u8, len as u8]),
+    }
+    out.extend(content);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::fido::cbor::int;
+    use std::collections::BTreeMap;
+
+    // The public keys of private key 1, i.e. the curve generators, and their SubjectPublicKeyInfo
+    // as Python's `cryptography` encodes it.
+    const P256_X: &str = "6b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296";
+    const P256_Y: &str = "4fe342e2fe1a7f9b8ee7eb4a7c0f9e162bce33576b315ececbb6406837bf51f5";
+    const P256_SPKI: &str = "3059301306072a8648ce3d020106082a8648ce3d030107034200046b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c2964fe342e2fe1a7f9b8ee7eb4a7c0f9e162bce33576b315ececbb6406837bf51f5";
+    const P384_X: &str = "aa87ca22be8b05378eb1c71ef320ad746e1d3b628ba79b9859f741e082542a385502f25dbf55296c3a545e3872760ab7";
+    const P384_Y: &str = "3617de4a96262c6f5d9e98bf9292dc29f8f41dbd289a147ce9da3113b5f0b8c00a60b1ce1d7e819d7a431d7c90ea0e5f";
+    const P384_SPKI: &str = "3076301006072a8648ce3d020106052b8104002203620004aa87ca22be8b05378eb1c71ef320ad746e1d3b628ba79b9859f741e082542a385502f25dbf55296c3a545e3872760ab73617de4a96262c6f5d9e98bf9292dc29f8f41dbd289a147ce9da3113b5f0b8c00a60b1ce1d7e819d7a431d7c90ea0e5f";
+    const BP256_X: &str = "8bd2aeb9cb7e57cb2c4b482ffc81b7afb9de27e1e3bd23c23a4453bd9ace3262";
+    const BP256_Y: &str = "547ef835c3dac4fd97f8461a14611dc9c27745132ded8e545c1d54c72f046997";
+    const BP256_SPKI: &str = "305a301406072a8648ce3d020106092b2403030208010107034200048bd2aeb9cb7e57cb2c4b482ffc81b7afb9de27e1e3bd23c23a4453bd9ace3262547ef835c3dac4fd97f8461a14611dc9c27745132ded8e545c1d54c72f046997";
+    // Ed25519 key of the all-zero seed
+    const ED25519_X: &str = "3b6a27bcceb6a42d62a3a8d02a6f0d73653215771de243a63ac048a18b59da29";
+    const ED25519_SPKI: &str =
+        "302a300506032b65700321003b6a27bcceb6a42d62a3a8d02a6f0d73653215771de243a63ac048a18b59da29";
+
+    fn cose_key(params: &[(CoseKeyParam, Value)]) -> CborMap {
+        params
+            .iter()
+            .map(|(param, value)| (int(*param as i8), value.clone()))
+            .collect::<BTreeMap<_, _>>()
+    }
+
+    fn ec2(alg: CoseAlgorithm, curve: CoseCurve, x: &str, y: &str) -> CredentialPublicKey {
+        CredentialPublicKey::from_cose(&cose_key(&[
+            (CoseKeyParam::Kty, int(CoseKeyType::Ec2 as u8)),
+            (CoseKeyParam::Alg, int(alg as i32)),
+            (CoseKeyParam::Crv, int(curve as u8)),
+            (CoseKeyParam::X, Value::Bytes(hex::decode(x).unwrap())),
+            (CoseKeyParam::Y, Value::Bytes(hex::decode(y).unwrap())),
+        ]))
+        .unwrap()
+    }
+
+    fn ed25519() -> CredentialPublicKey {
+        CredentialPublicKey::from_cose(&cose_key(&[
+            (CoseKeyParam::Kty, int(CoseKeyType::Okp as u8)),
+            (CoseKeyParam::Alg, int(CoseAlgorithm::EdDSA as i32)),
+            (CoseKeyParam::Crv, int(CoseCurve::Ed25519 as u8)),
+            (
+                CoseKeyParam::X,
+                Value::Bytes(hex::decode(ED25519_X).unwrap()),
+            ),
+        ]))
+        .unwrap()
+    }
+
+    fn jwk(key: &CredentialPublicKey) -> serde_json::Value {
+        serde_json::from_str(&key.to_jwk().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn exports_p256() {
+        let key = ec2(CoseAlgorithm::ES256, CoseCurve::P256, P256_X, P256_Y);
+        assert_eq!(hex::encode(key.to_spki().unwrap()), P256_SPKI);
+        assert!(
+            key.to_pem()
+                .unwrap()
+                .starts_with("-----BEGIN PUBLIC KEY-----\nMFkwEwYHKoZIzj0CAQYI")
+        );
+
+        let x = URL_SAFE_NO_PAD.encode(hex::decode(P256_X).unwrap());
+        let y = URL_SAFE_NO_PAD.encode(hex::decode(P256_Y).unwrap());
+        assert_eq!(
+            jwk(&key),
+            serde_json::json!({"kty": "EC", "crv": "P-256", "x": x, "y": y, "alg": "ES256"})
+        );
+    }
+
+    #[test]
+    fn exports_p384_and_ed25519() {
+        let key = ec2(CoseAlgorithm::ES384, CoseCurve::P384, P384_X, P384_Y);
+        assert_eq!(hex::encode(key.to_spki().unwrap()), P384_SPKI);
+        assert_eq!(jwk(&key)["crv"], "P-384");
+
+        let key = ed25519();
+        assert_eq!(hex::encode(key.to_spki().unwrap()), ED25519_SPKI);
+        let x = URL_SAFE_NO_PAD.encode(hex::decode(ED25519_X).unwrap());
+        assert_eq!(
+            jwk(&key),
+            serde_json::json!({"kty": "OKP", "crv": "Ed25519", "x": x, "alg": "EdDSA"})
+        );
+    }
+
+    #[test]
+    fn brainpool_has_no_jwk() {
+        let key = ec2(CoseAlgorithm::ES256, CoseCurve::BP256R1, BP256_X, BP256_Y);
+        assert_eq!(hex::encode(key.to_spki().unwrap()), BP256_SPKI);
+        assert!(key.to_jwk().is_err());
+    }
+
+    #[test]
+    fn rejects_keys_it_cannot_export() {
+        let rsa = CredentialPublicKey::from_cose(&cose_key(&[
+            (CoseKeyParam::Kty, int(CoseKeyType::Rsa as u8)),
+            (CoseKeyParam::Alg, int(CoseAlgorithm::RS256 as i32)),
+        ]))
+        .unwrap();
+        assert_eq!(rsa.params, KeyParams::Other { key_type: 3 });
+        assert!(rsa.to_spki().is_err());
+        assert!(rsa.to_jwk().is_err());
+
+        let unknown_curve = CredentialPublicKey {
+            algorithm: CoseAlgorithm::ES256 as i128,
+            params: KeyParams::Ec2 {
+                curve: 99,
+                x: vec![0; 32],
+                y: vec![0; 32],
+            },
+        };
+        let err = unknown_curve.to_spki().unwrap_err();
+        assert!(err.to_string().contains("COSE curve 99"));
+        assert!(unknown_curve.to_jwk().is_err());
+    }
+
+    #[test]
+    fn rejects_incomplete_cose_keys() {
+        let missing_y = cose_key(&[
+            (CoseKeyParam::Kty, int(CoseKeyType::Ec2 as u8)),
+            (CoseKeyParam::Alg, int(CoseAlgorithm::ES256 as i32)),
+            (CoseKeyParam::Crv, int(CoseCurve::P256 as u8)),
+            (CoseKeyParam::X, Value::Bytes(vec![0; 32])),
+        ]);
+        assert!(CredentialPublicKey::from_cose(&missing_y).is_err());
+        assert!(from_response(Some(&Value::Map(missing_y))).is_none());
+        assert!(from_response(Some(&Value::Bytes(vec![]))).is_none());
+    }
+}
@@ -0,0 +1,100 @@
+//! credBlob: a few bytes a relying party stored alongside a credential at registration.
+//!
+//! Credential management does not return the blob. The only way to read it is a getAssertion
+//! for that credential with the `credBlob` extension, which PicoForge sends silently (`up`
+//! false) so the key does not ask for a touch. The blob comes back in the extension outputs
+//! of the authenticator data.
+
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_cbor_2::{Value, from_slice};
+use std::collections::BTreeMap;
+
+use crate::device::fido::cbor::{self, CborMap, int};
+use crate::device::fido::constants::*;
+use crate::device::fido::pin::PinUvAuthProtocol;
+use crate::device::transport::CtapHidTransport;
+use crate::error::PFError;
+
+const EXTENSION: &str = "credBlob";
+/// rpIdHash (32) || flags (1) || signCount (4)
+const AUTH_DATA_HEADER_LEN: usize = 37;
+
+/// Reads the credBlob of one credential. `pin_token` must have the `GET_ASSERTION` permission
+/// for `rp_id`. Returns `None` if the credential has no blob.
+pub fn read<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+    rp_id: &str,
+    credential_id: &[u8],
+) -> Result<Option<Vec<u8>>, PFError> {
+    // Nothing verifies the signature, so any client data hash will do.
+    let mut client_data_hash = vec![0u8; 32];
+    SystemRandom::new()
+        .fill(&mut client_data_hash)
+        .map_err(|_| PFError::Io("Failed to generate a client data hash".into()))?;
+
+    let mut descriptor = BTreeMap::new();
+    descriptor.insert(
+        Value::Text("id".into()),
+        Value::Bytes(credential_id.to_vec()),
+    );
+    descriptor.insert(Value::Text("type".into()), Value::Text("public-key".into()));
+
+    let mut extensions = BTreeMap::new();
+    extensions.insert(Value::Text(EXTENSION.into()), Value::Bool(true));
+    let mut options = BTreeMap::new();
+    options.insert(Value::Text("up".into()), Value::Bool(false));
+
+    let mut params = BTreeMap::new();
+    params.insert(
+        int(GetAssertionParam::RpId as u8),
+        Value::Text(rp_id.to_string()),
+    );
+    params.insert(
+        int(GetAssertionParam::ClientDataHash as u8),
+        Value::Bytes(client_data_hash.clone()),
+    );
+    params.insert(
+        int(GetAssertionParam::AllowList as u8),
+        Value::Array(vec![Value::Map(descriptor)]),
+    );
+    params.insert(
+        int(GetAssertionParam::Extensions as u8),
+        Value::Map(extensions),
+    );
+    params.insert(int(GetAssertionParam::Options as u8), Value::Map(options));
+    params.insert(
+        int(GetAssertionParam::PinUvAuthParam as u8),
+        Value::Bytes(protocol.authenticate(pin_token, &client_data_hash)),
+    );
+    params.insert(
+        int(GetAssertionParam::PinUvAuthProtocol as u8),
+        int(protocol.version()),
+    );
+
+    log::debug!("Reading credBlob for {}...", rp_id);
+    let response = cbor::send_command(transport, CtapCommand::GetAssertion, Some(params))?;
+    let auth_data = cbor::get_bytes(&response, GetAssertionResponse::AuthData as i128)
+        .ok_or_else(|| PFError::Device("Assertion is missing the authenticator data".into()))?;
+    parse_auth_data(auth_data)
+}
+
+/// Pulls the credBlob extension output out of getAssertion authenticator data. Assertions carry
+/// no attested credential data, so the extension map directly follows the header.
+fn parse_auth_data(auth_data: &[u8]) -> Result<Option<Vec<u8>>, PFError> {
+    let (header, rest) = auth_data
+        .split_at_checked(AUTH_DATA_HEADER_LEN)
+        .ok_or_else(|| PFError::Device("Authenticator data is truncated".into()))?;
+    let flags = AuthenticatorFlags::from_bits_truncate(header[32]);
+    if !flags.contains(AuthenticatorFlags::EXTENSION_DATA) {
+        return Ok(None);
+    }
+
+    let extensions: CborMap = from_slice(rest)
+        .map_err(|e| PFError::Device(format!("Invalid extension outputs: {}", e)))?;
+    match extensions.get(&Value::Text(EXTENSION.into())) {
+        Some(Value::Bytes(blob)) if !blob.is_empty() => Ok(Some(blob.clone())),
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,253 @@
+//! Native authenticatorCredentialManagement (0x0A) client.
+
+use serde_cbor_2::{Value, to_vec};
+use std::collections::BTreeMap;
+
+use crate::device::fido::cbor::{self, CborMap, int};
+use crate::device::fido::constants::*;
+use crate::device::fido::cose;
+use crate::device::fido::pin::PinUvAuthProtocol;
+use crate::device::transport::CtapHidTransport;
+use crate::device::types::{CredsMetadata, StoredCredential};
+use crate::error::PFError;
+
+/// Sends a credential management sub command. Commands that need authentication are signed
+/// with `pin_token` over `subCommand || subCommandParams`.
+fn credential_mgmt<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    auth: Option<(PinUvAuthProtocol, &[u8])>,
+    sub_command: CredentialMgmtSubCommand,
+    sub_params: Option<CborMap>,
+) -> Result<CborMap, PFError> {
+    let mut params = BTreeMap::new();
+    params.insert(
+        int(CredentialMgmtParam::SubCommand as u8),
+        int(sub_command as u8),
+    );
+
+    let mut message = vec![sub_command as u8];
+    if let Some(sub_params) = sub_params {
+        let sub_params = Value::Map(sub_params);
+        message.extend(to_vec(&sub_params).map_err(|e| PFError::Io(e.to_string()))?);
+        params.insert(int(CredentialMgmtParam::SubCommandParams as u8), sub_params);
+    }
+
+    if let Some((protocol, pin_token)) = auth {
+        params.insert(
+            int(CredentialMgmtParam::PinUvAuthProtocol as u8),
+            int(protocol.version()),
+        );
+        params.insert(
+            int(CredentialMgmtParam::PinUvAuthParam as u8),
+            Value::Bytes(protocol.authenticate(pin_token, &message)),
+        );
+    }
+
+    cbor::send_command(transport, CtapCommand::CredentialMgmt, Some(params))
+}
+
+/// Reads how many discoverable credentials are stored and how many more fit.
+pub fn get_creds_metadata<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+) -> Result<CredsMetadata, PFError> {
+    let response = credential_mgmt(
+        transport,
+        Some((protocol, pin_token)),
+        CredentialMgmtSubCommand::GetCredsMetadata,
+        None,
+    )?;
+    let count = |key: CredentialMgmtResponse| {
+        cbor::get_int(&response, key as i128)
+            .map(|v| v as u32)
+            .ok_or_else(|| PFError::Device(format!("Credential metadata is missing {:?}", key)))
+    };
+    Ok(CredsMetadata {
+        existing: count(CredentialMgmtResponse::ExistingResidentCredentialsCount)?,
+        remaining: count(CredentialMgmtResponse::MaxPossibleRemainingResidentCredentialsCount)?,
+    })
+}
+
+/// Lists every discoverable credential, grouped by relying party.
+pub fn enumerate_credentials<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+) -> Result<Vec<StoredCredential>, PFError> {
+    let first_rp = match credential_mgmt(
+        transport,
+        Some((protocol, pin_token)),
+        CredentialMgmtSubCommand::EnumerateRpsBegin,
+        None,
+    ) {
+        Ok(rp) => rp,
+        Err(PFError::Ctap(Ctap2Error::NoCredentials)) => {
+            log::info!("No credentials stored on device (CTAP2_ERR_NO_CREDENTIALS)");
+            return Ok(Vec::new());
+        }
+        Err(e) => return Err(e),
+    };
+
+    let total_rps = cbor::get_int(&first_rp, CredentialMgmtResponse::TotalRps as i128).unwrap_or(1);
+    let mut rps = vec![first_rp];
+    for _ in 1..total_rps {
+        rps.push(credential_mgmt(
+            transport,
+            None,
+            CredentialMgmtSubCommand::EnumerateRpsGetNextRp,
+            None,
+        )?);
+    }
+
+    let mut all_credentials = Vec::new();
+    for rp in rps {
+        let rp_entity = cbor::get_map(&rp, CredentialMgmtResponse::Rp as i128)
+            .ok_or_else(|| PFError::Device("RP entry is missing the rp entity".into()))?;
+        let rp_id_hash = cbor::get_bytes(&rp, CredentialMgmtResponse::RpIdHash as i128)
+            .ok_or_else(|| PFError::Device("RP entry is missing rpIDHash".into()))?;
+        let rp_id = cbor::get_text(rp_entity, "id")
+            .unwrap_or_default()
+            .to_string();
+        let rp_name = cbor::get_text(rp_entity, "name")
+            .unwrap_or_default()
+            .to_string();
+
+        let mut sub_params = BTreeMap::new();
+        sub_params.insert(
+            int(CredentialMgmtSubParam::RpIdHash as u8),
+            Value::Bytes(rp_id_hash.to_vec()),
+        );
+        let first_cred = credential_mgmt(
+            transport,
+            Some((protocol, pin_token)),
+            CredentialMgmtSubCommand::EnumerateCredentialsBegin,
+            Some(sub_params),
+        )?;
+
+        let total_creds = cbor::get_int(
+            &first_cred,
+            CredentialMgmtResponse::TotalCredentials as i128,
+        )
+        .unwrap_or(1);
+        let mut creds = vec![first_cred];
+        for _ in 1..total_creds {
+            creds.push(credential_mgmt(
+                transport,
+                None,
+                CredentialMgmtSubCommand::EnumerateCredentialsGetNextCredential,
+                None,
+            )?);
+        }
+
+        for cred in creds {
+            all_credentials.push(parse_credential(&rp_id, &rp_name, &cred)?);
+        }
+    }
+
+    Ok(all_credentials)
+}
+
+fn parse_credential(
+    rp_id: &str,
+    rp_name: &str,
+    cred: &CborMap,
+) -> Result<StoredCredential, PFError> {
+    let user = cbor::get_map(cred, CredentialMgmtResponse::User as i128)
+        .ok_or_else(|| PFError::Device("Credential entry is missing the user entity".into()))?;
+    let descriptor = cbor::get_map(cred, CredentialMgmtResponse::CredentialId as i128)
+        .ok_or_else(|| PFError::Device("Credential entry is missing the credential ID".into()))?;
+
+    Ok(StoredCredential {
+        rp_id: rp_id.to_string(),
+        rp_name: rp_name.to_string(),
+        user_name: cbor::get_text(user, "name").unwrap_or_default().to_string(),
+        user_display_name: cbor::get_text(user, "displayName")
+            .unwrap_or_default()
+            .to_string(),
+        user_id: hex::encode(cbor::get_text_keyed_bytes(user, "id").unwrap_or_default()),
+        credential_id: hex::encode(
+            cbor::get_text_keyed_bytes(descriptor, "id").unwrap_or_default(),
+        ),
+        public_key: cose::from_response(cred.get(&int(CredentialMgmtResponse::PublicKey as u8))),
+        large_blob_key: cbor::get_bytes(cred, CredentialMgmtResponse::LargeBlobKey as i128)
+            .map(<[u8]>::to_vec),
+        cred_protect: cbor::get_int(cred, CredentialMgmtResponse::CredProtect as i128)
+            .and_then(|level| u8::try_from(level).ok()),
+        third_party_payment: matches!(
+            cred.get(&int(CredentialMgmtResponse::ThirdPartyPayment as u8)),
+            Some(Value::Bool(true))
+        ),
+    })
+}
+
+/// Builds a `PublicKeyCredentialDescriptor` for the given credential ID.
+fn credential_descriptor(credential_id: &[u8]) -> Value {
+    let mut descriptor = BTreeMap::new();
+    descriptor.insert(
+        Value::Text("id".into()),
+        Value::Bytes(credential_id.to_vec()),
+    );
+    descriptor.insert(Value::Text("type".into()), Value::Text("public-key".into()));
+    Value::Map(descriptor)
+}
+
+pub fn delete_credential<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+    credential_id: &[u8],
+) -> Result<(), PFError> {
+    let mut sub_params = BTreeMap::new();
+    sub_params.insert(
+        int(CredentialMgmtSubParam::CredentialId as u8),
+        credential_descriptor(credential_id),
+    );
+
+    credential_mgmt(
+        transport,
+        Some((protocol, pin_token)),
+        CredentialMgmtSubCommand::DeleteCredential,
+        Some(sub_params),
+    )?;
+    Ok(())
+}
+
+/// Replaces the user name and display name stored with a credential. The user ID must match
+/// the one already stored. Empty names are left out, which removes them from the credential.
+pub fn update_user_information<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+    credential_id: &[u8],
+    user_id: &[u8],
+    user_name: &str,
+    user_display_name: &str,
+) -> Result<(), PFError> {
+    let mut user = BTreeMap::new();
+    user.insert(Value::Text("id".into()), Value::Bytes(user_id.to_vec()));
+    if !user_name.is_empty() {
+        user.insert(Value::Text("name".into()), Value::Text(user_name.into()));
+    }
+    if !user_display_name.is_empty() {
+        user.insert(
+            Value::Text("displayName".into()),
+            Value::Text(user_display_name.into()),
+        );
+    }
+
+    let mut sub_params = BTreeMap::new();
+    sub_params.insert(
+        int(CredentialMgmtSubParam::CredentialId as u8),
+        credential_descriptor(credential_id),
+    );
+    sub_params.insert(int(CredentialMgmtSubParam::User as u8), Value::Map(user));
+
+    credential_mgmt(
+        transport,
+        Some((protocol, pin_token)),
+        CredentialMgmtSubCommand::UpdateUserInformation,
+        Some(sub_params),
+    )?;
+    Ok(())
+}
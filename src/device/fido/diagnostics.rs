@@ -0,0 +1,511 @@
+//! CTAPHID transport diagnostics, for tracking down flaky USB hubs and cables.
+//!
+//! Everything here runs on `CTAPHID_PING`, which the key echoes back without touching its
+//! storage, so it is safe to run on a key in daily use.
+
+use rand::RngExt;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use super::hid::{CTAPHID_MAX_PAYLOAD, HidTransport};
+use crate::device::transport::CtapHidTransport;
+use crate::device::types::DeviceHandle;
+
+/// Payload sizes to ping with: empty, a single init packet, the first continuation packet, a
+/// few sizes in between and the largest message CTAPHID allows.
+pub const PING_SIZES: &[usize] = &[0, 1, 57, 58, 116, 512, 1024, 4096, CTAPHID_MAX_PAYLOAD];
+const PINGS_PER_SIZE: usize = 10;
+const CHANNEL_COUNT: usize = 4;
+const PINGS_PER_CHANNEL: usize = 5;
+const CHANNEL_PING_SIZE: usize = 128;
+
+/// Results of pinging with one payload size.
+#[derive(Debug, Clone)]
+pub struct PingStats {
+    pub size: usize,
+    pub attempts: usize,
+    pub round_trips: Vec<Duration>,
+    pub sequence_mismatches: usize,
+    pub echo_mismatches: usize,
+    pub errors: Vec<String>,
+}
+
+impl PingStats {
+    fn new(size: usize) -> Self {
+        Self {
+            size,
+            attempts: 0,
+            round_trips: Vec::new(),
+            sequence_mismatches: 0,
+            echo_mismatches: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        percentile(&self.round_trips, p)
+    }
+}
+
+/// Results of pinging on one of several channels used in turn.
+#[derive(Debug, Clone)]
+pub struct ChannelCheck {
+    pub cid: u32,
+    pub successes: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiagnosticsReport {
+    pub device: String,
+    pub hid_path: String,
+    pub vid: u16,
+    pub pid: u16,
+    pub pings: Vec<PingStats>,
+    pub channels: Vec<ChannelCheck>,
+    /// Set when not every channel could be allocated.
+    pub channel_error: Option<String>,
+    pub duration: Duration,
+}
+
+impl DiagnosticsReport {
+    /// Round trip time over every successful ping, regardless of size.
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        let all: Vec<Duration> = self
+            .pings
+            .iter()
+            .flat_map(|s| s.round_trips.iter().copied())
+            .collect();
+        percentile(&all, p)
+    }
+
+    /// Payload bytes moved per second (both directions) with the largest payload.
+    pub fn throughput(&self) -> Option<f64> {
+        let stats = self
+            .pings
+            .iter()
+            .rev()
+            .find(|s| !s.round_trips.is_empty())?;
+        let total: Duration = stats.round_trips.iter().sum();
+        if total.is_zero() {
+            return None;
+        }
+        let bytes = 2 * stats.size * stats.round_trips.len();
+        Some(bytes as f64 / total.as_secs_f64())
+    }
+
+    pub fn sequence_mismatches(&self) -> usize {
+        self.pings.iter().map(|s| s.sequence_mismatches).sum()
+    }
+
+    pub fn failed_pings(&self) -> usize {
+        self.pings
+            .iter()
+            .map(|s| s.attempts - s.round_trips.len())
+            .sum()
+    }
+
+    /// Whether every ping and channel check succeeded.
+    pub fn passed(&self) -> bool {
+        self.failed_pings() == 0
+            && self.channel_error.is_none()
+            && self.channels.iter().all(|c| c.error.is_none())
+    }
+
+    /// Plain text summary meant to be attached to a support ticket.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let ms = |d: Option<Duration>| {
+            d.map(|d| format!("{:.2} ms", d.as_secs_f64() * 1000.0))
+                .unwrap_or_else(|| "-".into())
+        };
+
+        let _ = writeln!(out, "PicoForge CTAPHID diagnostics");
+        let _ = writeln!(out, "=============================");
+        let _ = writeln!(out, "PicoForge version: {}", env!("CARGO_PKG_VERSION"));
+        let _ = writeln!(
+            out,
+            "Platform: {} ({})",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        );
+        let _ = writeln!(out, "Device: {}", self.device);
+        let _ = writeln!(out, "VID:PID: {:04X}:{:04X}", self.vid, self.pid);
+        let _ = writeln!(out, "HID path: {}", self.hid_path);
+        let _ = writeln!(out, "Duration: {:.1} s", self.duration.as_secs_f64());
+        let _ = writeln!(
+            out,
+            "Result: {}",
+            if self.passed() { "PASS" } else { "FAIL" }
+        );
+        let _ = writeln!(out);
+
+        let _ = writeln!(out, "Ping round trips");
+        let _ = writeln!(out, "----------------");
+        let _ = writeln!(
+            out,
+            "{:>6}  {:>7}  {:>10}  {:>10}  {:>10}  {:>8}  {:>6}",
+            "bytes", "ok", "p50", "p90", "max", "seq err", "echo"
+        );
+        for stats in &self.pings {
+            let _ = writeln!(
+                out,
+                "{:>6}  {:>7}  {:>10}  {:>10}  {:>10}  {:>8}  {:>6}",
+                stats.size,
+                format!("{}/{}", stats.round_trips.len(), stats.attempts),
+                ms(stats.percentile(50.0)),
+                ms(stats.percentile(90.0)),
+                ms(stats.round_trips.iter().max().copied()),
+                stats.sequence_mismatches,
+                stats.echo_mismatches,
+            );
+        }
+        let _ = writeln!(out);
+        let _ = writeln!(
+            out,
+            "Latency (all sizes): p50 {}, p95 {}, p99 {}",
+            ms(self.percentile(50.0)),
+            ms(self.percentile(95.0)),
+            ms(self.percentile(99.0))
+        );
+        let _ = writeln!(
+            out,
+            "Throughput: {}",
+            self.throughput()
+                .map(|b| format!("{:.1} KB/s", b / 1024.0))
+                .unwrap_or_else(|| "-".into())
+        );
+        let _ = writeln!(out, "Sequence mismatches: {}", self.sequence_mismatches());
+        let _ = writeln!(out);
+
+        let _ = writeln!(out, "Channels");
+        let _ = writeln!(out, "--------");
+        for channel in &self.channels {
+            let _ = writeln!(
+                out,
+                "CID 0x{:08X}: {}/{} pings{}",
+                channel.cid,
+                channel.successes,
+                PINGS_PER_CHANNEL,
+                channel
+                    .error
+                    .as_ref()
+                    .map(|e| format!(", {}", e))
+                    .unwrap_or_default()
+            );
+        }
+        if let Some(e) = &self.channel_error {
+            let _ = writeln!(out, "Channel allocation failed: {}", e);
+        }
+
+        let errors: Vec<String> = self
+            .pings
+            .iter()
+            .flat_map(|s| {
+                s.errors
+                    .iter()
+                    .map(move |e| format!("{} bytes: {}", s.size, e))
+            })
+            .collect();
+        if !errors.is_empty() {
+            let _ = writeln!(out);
+            let _ = writeln!(out, "Errors");
+            let _ = writeln!(out, "------");
+            for e in errors {
+                let _ = writeln!(out, "{}", e);
+            }
+        }
+
+        out
+    }
+}
+
+/// Nearest-rank percentile.
+fn percentile(samples: &[Duration], p: f64) -> Option<Duration> {
+    if samples.is_empty() {
+        return None;
+    }
+    let mut sorted = samples.to_vec();
+    sorted.sort();
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+fn random_payload(size: usize) -> Vec<u8> {
+    let mut payload = vec![0u8; size];
+    rand::rng().fill(&mut payload[..]);
+    payload
+}
+
+/// Pings with every size in [`PING_SIZES`], then pings on several channels in turn.
+pub fn run<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    device: &DeviceHandle,
+) -> DiagnosticsReport {
+    log::info!("Running CTAPHID diagnostics on {}", device.label());
+    let start = Instant::now();
+
+    let pings = PING_SIZES
+        .iter()
+        .map(|&size| ping_size(transport, size))
+        .collect();
+    let (channels, channel_error) = check_channels(transport);
+
+    let report = DiagnosticsReport {
+        device: device.label(),
+        hid_path: device.hid_path.clone().unwrap_or_default(),
+        vid: device.vid,
+        pid: device.pid,
+        pings,
+        channels,
+        channel_error,
+        duration: start.elapsed(),
+    };
+    log::info!(
+        "Diagnostics finished: {} failed pings, {} sequence mismatches",
+        report.failed_pings(),
+        report.sequence_mismatches()
+    );
+    report
+}
+
+fn ping_size<T: CtapHidTransport + ?Sized>(transport: &T, size: usize) -> PingStats {
+    let mut stats = PingStats::new(size);
+
+    for _ in 0..PINGS_PER_SIZE {
+        let payload = random_payload(size);
+        stats.attempts += 1;
+
+        let sent = Instant::now();
+        match transport.ping(&payload) {
+            Ok(echo) if echo == payload => stats.round_trips.push(sent.elapsed()),
+            Ok(echo) => {
+                log::warn!(
+                    "PING with {} bytes echoed {} different bytes",
+                    size,
+                    echo.len()
+                );
+                stats.echo_mismatches += 1;
+                stats
+                    .errors
+                    .push(format!("echo mismatch ({} bytes back)", echo.len()));
+            }
+            Err(e) => {
+                log::warn!("PING with {} bytes failed: {}", size, e);
+                if HidTransport::is_sequence_mismatch(&e) {
+                    stats.sequence_mismatches += 1;
+                }
+                stats.errors.push(e.to_string());
+                // Don't let the rest of a broken response end up in the next ping
+                transport.drain();
+            }
+        }
+    }
+
+    stats
+}
+
+/// Pings round robin on [`CHANNEL_COUNT`] channels, our own and the ones it allocates next to
+/// it, checking that every response comes back on the channel it was sent on.
+fn check_channels<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+) -> (Vec<ChannelCheck>, Option<String>) {
+    let mut channels = vec![ChannelCheck {
+        cid: transport.cid(),
+        successes: 0,
+        error: None,
+    }];
+    let mut channel_error = None;
+
+    while channels.len() < CHANNEL_COUNT {
+        match transport.allocate_channel() {
+            Ok(cid) if channels.iter().any(|c| c.cid == cid) => {
+                channel_error = Some(format!("Device handed out CID 0x{:08X} twice", cid));
+                break;
+            }
+            Ok(cid) => channels.push(ChannelCheck {
+                cid,
+                successes: 0,
+                error: None,
+            }),
+            Err(e) => {
+                channel_error = Some(e.to_string());
+                break;
+            }
+        }
+    }
+
+    for _ in 0..PINGS_PER_CHANNEL {
+        for channel in channels.iter_mut().filter(|c| c.error.is_none()) {
+            let payload = random_payload(CHANNEL_PING_SIZE);
+            match transport.ping_on(channel.cid, &payload) {
+                Ok(echo) if echo == payload => channel.successes += 1,
+                Ok(_) => channel.error = Some("echo mismatch".into()),
+                Err(e) => {
+                    log::warn!("PING on CID 0x{:08X} failed: {}", channel.cid, e);
+                    channel.error = Some(e.to_string());
+                    transport.drain();
+                }
+            }
+        }
+    }
+
+    (channels, channel_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::fido::hid::{CTAPHID_INIT, CTAPHID_PING, SEQUENCE_MISMATCH};
+    use crate::device::simulator::Simulator;
+    use crate::device::transport::scripted::{SCRIPTED_CID, ScriptedCtapHidTransport};
+    use crate::error::PFError;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn stats(size: usize, attempts: usize, round_trips: &[u64]) -> PingStats {
+        PingStats {
+            attempts,
+            round_trips: round_trips.iter().copied().map(ms).collect(),
+            ..PingStats::new(size)
+        }
+    }
+
+    fn report_of(pings: Vec<PingStats>, channels: Vec<ChannelCheck>) -> DiagnosticsReport {
+        DiagnosticsReport {
+            device: "Scripted Key".into(),
+            hid_path: "/dev/hidraw0".into(),
+            vid: 0xCAFE,
+            pid: 0x4242,
+            pings,
+            channels,
+            channel_error: None,
+            duration: Duration::from_secs(2),
+        }
+    }
+
+    fn scripted() -> ScriptedCtapHidTransport {
+        ScriptedCtapHidTransport::new(0xCAFE, 0x4242, "Scripted Key")
+    }
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let samples: Vec<Duration> = (1..=10).map(ms).collect();
+        assert_eq!(percentile(&samples, 50.0), Some(ms(5)));
+        assert_eq!(percentile(&samples, 90.0), Some(ms(9)));
+        assert_eq!(percentile(&samples, 99.0), Some(ms(10)));
+        assert_eq!(percentile(&samples, 0.0), Some(ms(1)));
+        assert_eq!(percentile(&[ms(3)], 50.0), Some(ms(3)));
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+
+    #[test]
+    fn throughput_uses_the_largest_size_that_got_through() {
+        let report = report_of(
+            vec![
+                stats(0, 2, &[1, 1]),
+                stats(1000, 2, &[1000, 1000]),
+                stats(7609, 2, &[]),
+            ],
+            Vec::new(),
+        );
+        // 1000 bytes out and back, twice, in two seconds
+        assert_eq!(report.throughput(), Some(2000.0));
+        assert_eq!(report.percentile(50.0), Some(ms(1)));
+        assert_eq!(report.failed_pings(), 2);
+        assert!(!report.passed());
+
+        assert_eq!(
+            report_of(vec![stats(0, 1, &[0])], Vec::new()).throughput(),
+            None
+        );
+    }
+
+    #[test]
+    fn passes_only_without_failures() {
+        let channel = |error: Option<&str>| ChannelCheck {
+            cid: 1,
+            successes: PINGS_PER_CHANNEL,
+            error: error.map(str::to_string),
+        };
+        assert!(report_of(vec![stats(64, 2, &[1, 2])], vec![channel(None)]).passed());
+        assert!(!report_of(vec![stats(64, 2, &[1, 2])], vec![channel(Some("busy"))]).passed());
+
+        let mut failed = report_of(vec![stats(64, 2, &[1, 2])], vec![channel(None)]);
+        failed.channel_error = Some("Device handed out CID 0x00000001 twice".into());
+        assert!(!failed.passed());
+        assert!(
+            failed
+                .to_text()
+                .contains("Channel allocation failed: Device handed out")
+        );
+    }
+
+    #[test]
+    fn text_summary_lists_results_and_errors() {
+        let mut pings = stats(57, 2, &[2]);
+        pings.sequence_mismatches = 1;
+        pings.errors.push(SEQUENCE_MISMATCH.into());
+        let text = report_of(
+            vec![pings],
+            vec![ChannelCheck {
+                cid: 0xDEAD_BEEF,
+                successes: 3,
+                error: Some("echo mismatch".into()),
+            }],
+        )
+        .to_text();
+
+        assert!(text.contains("Result: FAIL"));
+        assert!(text.contains("VID:PID: CAFE:4242"));
+        assert!(text.contains("Sequence mismatches: 1"));
+        assert!(text.contains("CID 0xDEADBEEF: 3/5 pings, echo mismatch"));
+        assert!(text.contains("57 bytes: Sequence mismatch"));
+    }
+
+    #[test]
+    fn passes_on_a_healthy_key() {
+        let sim = Simulator::new();
+        let report = run(&sim.open_hid(), &sim.device_handle());
+        assert!(report.passed(), "{}", report.to_text());
+        assert_eq!(report.pings.len(), PING_SIZES.len());
+        assert_eq!(report.channels.len(), CHANNEL_COUNT);
+        assert!(
+            report
+                .channels
+                .iter()
+                .all(|c| c.successes == PINGS_PER_CHANNEL)
+        );
+    }
+
+    #[test]
+    fn counts_sequence_and_echo_mismatches() {
+        let mut hid = scripted()
+            .fail(CTAPHID_PING, PFError::Device(SEQUENCE_MISMATCH.into()))
+            .respond(CTAPHID_PING, [0xFF; 3]);
+        for _ in 2..PINGS_PER_SIZE {
+            hid = hid.fail(CTAPHID_PING, PFError::NoResponse("Timeout".into()));
+        }
+
+        let stats = ping_size(&hid, 4);
+        assert!(hid.is_finished());
+        assert_eq!(stats.attempts, PINGS_PER_SIZE);
+        assert!(stats.round_trips.is_empty());
+        assert_eq!(stats.sequence_mismatches, 1);
+        assert_eq!(stats.echo_mismatches, 1);
+        assert_eq!(stats.errors.len(), PINGS_PER_SIZE);
+    }
+
+    #[test]
+    fn reports_a_channel_handed_out_twice() {
+        let hid = scripted().respond(CTAPHID_INIT, SCRIPTED_CID.to_be_bytes());
+        let (channels, error) = check_channels(&hid);
+        assert_eq!(channels.len(), 1);
+        assert_eq!(
+            error.as_deref(),
+            Some("Device handed out CID 0x01020304 twice")
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! Enterprise attestation provisioning: the key generates an attestation key pair and returns
+//! a CSR for it, the RP operator signs that CSR, and the resulting certificate goes back in
+//! through a vendor authenticatorConfig command.
+
+use serde_cbor_2::Value;
+
+use crate::device::fido::cbor;
+use crate::device::fido::config;
+use crate::device::fido::constants::*;
+use crate::device::fido::pin::PinUvAuthProtocol;
+use crate::device::transport::CtapHidTransport;
+use crate::error::PFError;
+
+pub const CSR_PEM_LABEL: &str = "CERTIFICATE REQUEST";
+pub const CERTIFICATE_PEM_LABEL: &str = "CERTIFICATE";
+
+/// Has the key generate a new attestation key pair and returns the DER encoded CSR.
+pub fn generate_csr<T: CtapHidTransport + ?Sized>(transport: &T) -> Result<Vec<u8>, PFError> {
+    log::debug!("Requesting enterprise attestation CSR...");
+    let response = cbor::send_vendor_command(
+        transport,
+        VendorCommand::EnterpriseAttestation,
+        EnterpriseAttestationSubCommand::GenerateCsr as u8,
+        None,
+    )?;
+    cbor::get_bytes(&response, 0x01)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| PFError::Device("CSR response is missing the request".into()))
+}
+
+/// Stores the signed attestation certificate (DER) on the key.
+pub fn upload_certificate<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+    certificate: &[u8],
+) -> Result<(), PFError> {
+    log::debug!(
+        "Uploading enterprise attestation certificate ({} bytes)...",
+        certificate.len()
+    );
+    config::send_vendor_config(
+        transport,
+        protocol,
+        pin_token,
+        VendorConfigCommand::EnterpriseAttestationUpload,
+        Value::Bytes(certificate.to_vec()),
+    )
+}
@@ -1,48 +1,130 @@
 use rand::RngExt;
-use serde_cbor_2::{Value, to_vec};
-use std::collections::BTreeMap;
-use std::time::Duration;
-
-use crate::device::fido::constants::*;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::device::fido::constants::Ctap2Error;
+use crate::device::transport::{
+    CtapHidTransport, KeepaliveStatus, OperationMonitor, strip_ctap_status,
+};
+use crate::device::types::DeviceHandle;
 use crate::error::PFError;
 
 // HID Transport Constants
 const HID_REPORT_SIZE: usize = 64;
-const HID_USAGE_PAGE_FIDO: u16 = 0xF1D0;
+pub(crate) const HID_USAGE_PAGE_FIDO: u16 = 0xF1D0;
 const CTAPHID_CID_BROADCAST: u32 = 0xFFFFFFFF;
-const CTAPHID_INIT: u8 = 0x86;
+pub(crate) const CTAPHID_PING: u8 = 0x81;
+pub(crate) const CTAPHID_INIT: u8 = 0x86;
+const CTAPHID_WINK: u8 = 0x88;
 pub const CTAPHID_CBOR: u8 = 0x90;
+const CTAPHID_CANCEL: u8 = 0x91;
 const CTAPHID_ERROR: u8 = 0xBF;
 const CTAPHID_KEEPALIVE: u8 = 0xBB;
 
+/// Largest message CTAPHID can carry: one init packet and 128 continuation packets.
+pub const CTAPHID_MAX_PAYLOAD: usize = (HID_REPORT_SIZE - 7) + 128 * (HID_REPORT_SIZE - 5);
+
+pub(crate) const SEQUENCE_MISMATCH: &str = "Sequence mismatch";
+
+// CTAPHID_INIT capability flags
+const CAPABILITY_WINK: u8 = 0x01;
+
 // Timeouts
 const HID_READ_TIMEOUT_MS: i32 = 10;
 const HID_INIT_READ_TIMEOUT_MS: i32 = 100;
 const HID_RESP_READ_TIMEOUT_MS: i32 = 2000;
 const HID_CONT_READ_TIMEOUT_MS: i32 = 500;
+/// How long a key may stay silent while working on a request. Keys send a KEEPALIVE about every
+/// 100 ms while busy or waiting for a touch, and each one restarts the wait.
+const HID_RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a key gets to answer a request after CTAPHID_CANCEL, keepalives or not.
+const HID_CANCEL_GRACE: Duration = Duration::from_secs(1);
+
+/// Raw report I/O on a HID interface: `hidapi::HidDevice`, or a scripted device in tests.
+pub trait HidReportDevice: Send {
+    /// Writes one output report, prefixed with its report ID.
+    fn write(&self, report: &[u8]) -> hidapi::HidResult<usize>;
+
+    /// Reads one input report. Returns `Ok(0)` if none arrived within `timeout_ms`.
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> hidapi::HidResult<usize>;
+}
+
+impl HidReportDevice for hidapi::HidDevice {
+    fn write(&self, report: &[u8]) -> hidapi::HidResult<usize> {
+        hidapi::HidDevice::write(self, report)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> hidapi::HidResult<usize> {
+        hidapi::HidDevice::read_timeout(self, buf, timeout_ms)
+    }
+}
 
 pub struct HidTransport {
-    device: hidapi::HidDevice,
+    device: Box<dyn HidReportDevice>,
     cid: u32,
+    capabilities: u8,
+    monitor: Mutex<Option<OperationMonitor>>,
     pub vid: u16,
     pub pid: u16,
     pub product_name: String,
 }
 
 impl HidTransport {
-    pub fn open() -> Result<Self, PFError> {
+    /// Lists every HID interface on the FIDO usage page (0xF1D0).
+    pub fn list_devices() -> Result<Vec<DeviceHandle>, PFError> {
+        let api = hidapi::HidApi::new().map_err(|e| {
+            log::error!("Failed to initialize HidApi: {}", e);
+            PFError::Device(format!("Failed to initialize HidApi: {}", e))
+        })?;
+
+        let mut devices: Vec<DeviceHandle> = Vec::new();
+        for info in api
+            .device_list()
+            .filter(|d| d.usage_page() == HID_USAGE_PAGE_FIDO)
+        {
+            let path = info.path().to_string_lossy().into_owned();
+            if devices
+                .iter()
+                .any(|d| d.hid_path.as_deref() == Some(path.as_str()))
+            {
+                continue;
+            }
+
+            // pico-keys report the firmware version as bcdDevice
+            let release = info.release_number();
+
+            devices.push(DeviceHandle {
+                reader_name: None,
+                hid_path: Some(path),
+                vid: info.vendor_id(),
+                pid: info.product_id(),
+                product_name: info
+                    .product_string()
+                    .unwrap_or("Unknown FIDO Device")
+                    .to_string(),
+                serial: info
+                    .serial_number()
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.to_uppercase()),
+                firmware_version: Some(format!("{}.{}", release >> 8, release & 0xFF)),
+            });
+        }
+
+        Ok(devices)
+    }
+
+    pub fn open(path: &str) -> Result<Self, PFError> {
         log::info!("Attempting to open HID transport for FIDO device...");
         let api = hidapi::HidApi::new().map_err(|e| {
             log::error!("Failed to initialize HidApi: {}", e);
             PFError::Device(format!("Failed to initialize HidApi: {}", e))
         })?;
 
-        // Find device with FIDO Usage Page (0xF1D0)
         let info = api
             .device_list()
-            .find(|d| d.usage_page() == HID_USAGE_PAGE_FIDO)
+            .find(|d| d.usage_page() == HID_USAGE_PAGE_FIDO && d.path().to_string_lossy() == path)
             .ok_or_else(|| {
-                log::warn!("No FIDO device found with Usage Page 0xF1D0.");
+                log::warn!("FIDO device at {} is no longer connected.", path);
                 PFError::NoDevice
             })?;
 
@@ -65,33 +147,30 @@ impl HidTransport {
         })?;
 
         // Negotiate Channel ID (CID)
-        let cid = Self::init_channel(&device).map_err(|e| {
+        let (cid, capabilities) = Self::init_channel(&device).map_err(|e| {
             log::error!("Failed to negotiate Channel ID: {}", e);
             PFError::Device(format!("Failed to negotiate Channel ID: {}", e))
         })?;
 
         log::info!("HID Transport established successfully. CID: 0x{:08X}", cid);
         Ok(Self {
-            device,
+            device: Box::new(device),
             cid,
+            capabilities,
+            monitor: Mutex::new(None),
             vid,
             pid,
             product_name,
         })
     }
 
-    fn init_channel(device: &hidapi::HidDevice) -> Result<u32, PFError> {
+    /// Returns the new channel ID and the capability flags the device reported.
+    fn init_channel(device: &dyn HidReportDevice) -> Result<(u32, u8), PFError> {
         log::debug!("Initializing CTAPHID channel...");
 
         // --- Drain Step ---
         // Read and discard any pending packets to avoid using a stale response for CID negotiation.
-        let mut drain_buf = [0u8; HID_REPORT_SIZE];
-        while let Ok(n) = device.read_timeout(&mut drain_buf[..], HID_READ_TIMEOUT_MS) {
-            if n == 0 {
-                break;
-            }
-            log::trace!("Drained stale HID packet: {:02X?}", &drain_buf[0..16]);
-        }
+        Self::drain_device(device);
 
         let mut nonce = [0u8; 8];
         rand::rng().fill(&mut nonce);
@@ -125,8 +204,14 @@ impl HidTransport {
                 {
                     // New CID is at bytes 16..20
                     let new_cid = u32::from_be_bytes([buf[15], buf[16], buf[17], buf[18]]);
-                    log::debug!("Channel negotiation successful. New CID: 0x{:08X}", new_cid);
-                    return Ok(new_cid);
+                    // Followed by protocol version, major, minor and build version, capabilities
+                    let capabilities = buf[23];
+                    log::debug!(
+                        "Channel negotiation successful. New CID: 0x{:08X}, capabilities: 0x{:02X}",
+                        new_cid,
+                        capabilities
+                    );
+                    return Ok((new_cid, capabilities));
                 } else {
                     log::trace!(
                         "Received ignoreable HID packet during CID negotiation: {:02X?}",
@@ -141,12 +226,40 @@ impl HidTransport {
         ))
     }
 
-    pub fn send_cbor(&self, cmd: u8, payload: &[u8]) -> Result<Vec<u8>, PFError> {
-        self.write_cbor_request(cmd, payload)?;
-        self.read_cbor_response(cmd)
+    fn drain_device(device: &dyn HidReportDevice) {
+        let mut drain_buf = [0u8; HID_REPORT_SIZE];
+        while let Ok(n) = device.read_timeout(&mut drain_buf[..], HID_READ_TIMEOUT_MS) {
+            if n == 0 {
+                break;
+            }
+            log::trace!("Drained stale HID packet: {:02X?}", &drain_buf[0..16]);
+        }
+    }
+
+    /// A transport on an already negotiated channel of `device`, for driving it from tests.
+    #[cfg(test)]
+    pub(crate) fn scripted(device: impl HidReportDevice + 'static, cid: u32) -> Self {
+        Self {
+            device: Box::new(device),
+            cid,
+            capabilities: CAPABILITY_WINK,
+            monitor: Mutex::new(None),
+            vid: 0,
+            pid: 0,
+            product_name: String::new(),
+        }
+    }
+
+    /// Whether `e` is the error a response with out of order continuation packets produces.
+    pub fn is_sequence_mismatch(e: &PFError) -> bool {
+        matches!(e, PFError::Device(msg) if msg == SEQUENCE_MISMATCH)
     }
 
     fn write_cbor_request(&self, cmd: u8, payload: &[u8]) -> Result<(), PFError> {
+        self.write_request(self.cid, cmd, payload)
+    }
+
+    fn write_request(&self, cid: u32, cmd: u8, payload: &[u8]) -> Result<(), PFError> {
         log::debug!(
             "Sending CBOR Command: 0x{:02X}, Payload Size: {} bytes",
             cmd,
@@ -159,7 +272,7 @@ impl HidTransport {
 
         // 1. Init Packet
         let mut report = [0u8; HID_REPORT_SIZE + 1];
-        report[1..5].copy_from_slice(&self.cid.to_be_bytes());
+        report[1..5].copy_from_slice(&cid.to_be_bytes());
         report[5] = cmd;
         report[6] = (total_len >> 8) as u8;
         report[7] = (total_len & 0xFF) as u8;
@@ -171,7 +284,7 @@ impl HidTransport {
         // log::trace!("Writing Init Packet (Sent: {}/{})", sent, total_len);
         if let Err(e) = self.device.write(&report[..]) {
             log::error!("Failed to write initial HID packet: {}", e);
-            return Err(PFError::Io(format!(
+            return Err(PFError::Disconnected(format!(
                 "Failed to write initial HID packet: {}",
                 e,
             )));
@@ -182,7 +295,7 @@ impl HidTransport {
         // 2. Continuation Packets
         while sent < total_len {
             let mut report = [0u8; HID_REPORT_SIZE + 1];
-            report[1..5].copy_from_slice(&self.cid.to_be_bytes());
+            report[1..5].copy_from_slice(&cid.to_be_bytes());
             report[5] = 0x7F & sequence; // SEQ
             sequence += 1;
 
@@ -197,7 +310,7 @@ impl HidTransport {
                     sequence - 1,
                     e
                 );
-                return Err(PFError::Io(format!(
+                return Err(PFError::Disconnected(format!(
                     "Failed to write continuation HID packet: {}",
                     e,
                 )));
@@ -212,9 +325,43 @@ impl HidTransport {
         Ok(())
     }
 
+    fn monitor(&self) -> Option<OperationMonitor> {
+        self.monitor
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Asks the key to abandon the request pending on our channel. The key still answers it,
+    /// with `CTAP2_ERR_KEEPALIVE_CANCEL`.
+    fn write_cancel(&self, cid: u32) -> Result<(), PFError> {
+        log::debug!("Sending CTAPHID_CANCEL on CID 0x{:08X}", cid);
+        let mut report = [0u8; HID_REPORT_SIZE + 1];
+        report[1..5].copy_from_slice(&cid.to_be_bytes());
+        report[5] = CTAPHID_CANCEL;
+        // The request itself already went out, so this is no reason to send it again
+        self.device.write(&report[..]).map_err(|e| {
+            log::error!("Failed to write CANCEL packet: {}", e);
+            PFError::NoResponse(format!("Failed to write CANCEL packet: {}", e))
+        })?;
+        Ok(())
+    }
+
     fn read_cbor_response(&self, cmd: u8) -> Result<Vec<u8>, PFError> {
+        let response_data = self.read_response(self.cid, cmd)?;
+
+        // Check CTAP Status Byte (First byte of payload) and return payload without it
+        strip_ctap_status(cmd, &response_data)
+    }
+
+    /// Reads the complete response to `cmd` on `cid`, skipping keepalives and packets for other
+    /// channels.
+    fn read_response(&self, cid: u32, cmd: u8) -> Result<Vec<u8>, PFError> {
         log::debug!("Waiting for response...");
 
+        let monitor = self.monitor();
+        let mut cancel_sent = false;
+
         let mut buf = [0u8; HID_REPORT_SIZE];
         let mut response_data = Vec::new();
         let expected_len: usize;
@@ -222,20 +369,42 @@ impl HidTransport {
         let mut last_seq = 0;
 
         // 1. Read First Packet (Loop to handle Keepalives)
+        let mut deadline = Instant::now() + HID_RESPONSE_TIMEOUT;
         loop {
-            if let Err(e) = self
-                .device
-                .read_timeout(&mut buf[..], HID_RESP_READ_TIMEOUT_MS)
-            {
-                log::error!("Timeout reading response packet: {}", e);
-                return Err(PFError::Io(format!(
-                    "Timeout reading response packet: {}",
-                    e
-                )));
+            if !cancel_sent && monitor.as_ref().is_some_and(|m| m.is_cancelled()) {
+                self.write_cancel(cid)?;
+                cancel_sent = true;
+                deadline = deadline.min(Instant::now() + HID_CANCEL_GRACE);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                if cancel_sent {
+                    log::warn!("Device did not acknowledge the cancel of 0x{:02X}", cmd);
+                    return Err(PFError::Cancelled);
+                }
+                log::error!("Device stopped responding to command 0x{:02X}", cmd);
+                return Err(PFError::NoResponse(
+                    "Timeout waiting for the device to respond".into(),
+                ));
+            }
+
+            let timeout_ms = remaining.as_millis().min(HID_RESP_READ_TIMEOUT_MS as u128) as i32;
+            match self.device.read_timeout(&mut buf[..], timeout_ms) {
+                // Nothing arrived in time, go back and check the deadline
+                Ok(0) => continue,
+                Ok(_) => {}
+                Err(e) => {
+                    log::error!("Failed to read response packet: {}", e);
+                    return Err(PFError::NoResponse(format!(
+                        "Failed to read response packet: {}",
+                        e
+                    )));
+                }
             }
 
             // Check CID mismatch
-            if u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) != self.cid {
+            if u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) != cid {
                 log::warn!("Received packet from different CID, ignoring...");
                 continue;
             }
@@ -247,6 +416,12 @@ impl HidTransport {
                     "Device sent KEEPALIVE (Status: 0x{:02X}), waiting...",
                     status
                 );
+                if let Some(monitor) = &monitor {
+                    monitor.set_status(KeepaliveStatus::from_u8(status));
+                }
+                if !cancel_sent {
+                    deadline = Instant::now() + HID_RESPONSE_TIMEOUT;
+                }
                 continue; // Go back to start of loop and read again
             }
 
@@ -254,12 +429,14 @@ impl HidTransport {
             break;
         }
 
+        if let Some(monitor) = &monitor {
+            monitor.set_status(None);
+        }
+
+        // The error code is the one byte payload, after the length
         if buf[4] == CTAPHID_ERROR {
-            log::error!("Device returned CTAP Error code: 0x{:02X}", buf[5]);
-            return Err(PFError::Device(format!(
-                "Device returned CTAP Error: 0x{:02X}",
-                buf[5],
-            )));
+            log::error!("Device returned CTAPHID Error code: 0x{:02X}", buf[7]);
+            return Err(PFError::Ctap(Ctap2Error::from_code(buf[7])));
         } else {
             log::trace!("Packet received is not a CTAP Error");
         }
@@ -284,18 +461,25 @@ impl HidTransport {
 
         // 2. Read Continuation Packets
         while read_len < expected_len {
-            if let Err(e) = self
+            match self
                 .device
                 .read_timeout(&mut buf[..], HID_CONT_READ_TIMEOUT_MS)
             {
-                log::error!("Timeout reading continuation packet: {}", e);
-                return Err(PFError::Io(format!(
-                    "Timeout reading continuation packet: {}",
-                    e
-                )));
+                Ok(0) => {
+                    log::error!("Timeout waiting for continuation packet {}", last_seq);
+                    return Err(PFError::Io("Timeout reading continuation packet".into()));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    log::error!("Timeout reading continuation packet: {}", e);
+                    return Err(PFError::Io(format!(
+                        "Timeout reading continuation packet: {}",
+                        e
+                    )));
+                }
             }
 
-            if u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) != self.cid {
+            if u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) != cid {
                 continue; // Ignore packets from other channels
             }
 
@@ -306,7 +490,7 @@ impl HidTransport {
                     last_seq,
                     seq
                 );
-                return Err(PFError::Device("Sequence mismatch".into()));
+                return Err(PFError::Device(SEQUENCE_MISMATCH.into()));
             }
             last_seq += 1;
 
@@ -315,207 +499,159 @@ impl HidTransport {
             read_len += in_pkt;
         }
 
-        // 3. Check CTAP Status Byte (First byte of payload)
-        if response_data.is_empty() {
-            log::error!("Device sent empty payload response.");
-            return Err(PFError::Device("Empty response".into()));
-        }
-        let status = response_data[0];
-        if status != 0x00 {
-            log::error!("FIDO Operation returned failure status: 0x{:02X}", status);
-            return Err(PFError::Device(format!(
-                "FIDO Operation Failed with Status: 0x{:02X}",
-                status
-            )));
-        }
+        Ok(response_data)
+    }
+}
 
-        log::debug!(
-            "Command 0x{:02X} successful. Response payload len: {}",
-            cmd,
-            response_data.len() - 1
-        );
-        // Return payload without status byte
-        Ok(response_data[1..].to_vec())
-    }
-
-    pub fn send_vendor_config(
-        &self,
-        pin_token: &[u8],
-        vendor_cmd: VendorConfigCommand,
-        param: Value,
-    ) -> Result<(), PFError> {
-        log::debug!("Sending vendor config command: {}...", vendor_cmd);
-
-        // Build subCommandParams (Key 0x02)
-        // This map contains:
-        // 0x01: vendorCommandId (u64)
-        // 0x02/0x03/0x04: param
-        let mut sub_params_inner = BTreeMap::new();
-        sub_params_inner.insert(
-            Value::Integer(0x01),
-            Value::Integer(vendor_cmd.to_u64() as i128),
-        );
+impl CtapHidTransport for HidTransport {
+    fn send_cbor(&self, cmd: u8, payload: &[u8]) -> Result<Vec<u8>, PFError> {
+        self.write_cbor_request(cmd, payload)?;
+        self.read_cbor_response(cmd)
+    }
 
-        match param {
-            Value::Bytes(_) => {
-                sub_params_inner.insert(Value::Integer(0x02), param.clone());
-            }
-            Value::Integer(_) => {
-                sub_params_inner.insert(Value::Integer(0x03), param.clone());
-            }
-            Value::Text(_) => {
-                sub_params_inner.insert(Value::Integer(0x04), param.clone());
-            }
-            _ => return Err(PFError::Io("Unsupported parameter type".into())),
+    fn vid(&self) -> u16 {
+        self.vid
+    }
+
+    fn pid(&self) -> u16 {
+        self.pid
+    }
+
+    fn product_name(&self) -> &str {
+        &self.product_name
+    }
+
+    fn wink(&self) -> Result<bool, PFError> {
+        if self.capabilities & CAPABILITY_WINK == 0 {
+            log::debug!("Device does not advertise CTAPHID_WINK");
+            return Ok(false);
         }
+        self.write_cbor_request(CTAPHID_WINK, &[])?;
+        self.read_response(self.cid, CTAPHID_WINK)?;
+        Ok(true)
+    }
 
-        let sub_params = Value::Map(sub_params_inner);
-        let sub_params_bytes = to_vec(&sub_params).map_err(|e| PFError::Io(e.to_string()))?;
+    fn set_monitor(&self, monitor: Option<OperationMonitor>) {
+        *self.monitor.lock().unwrap_or_else(PoisonError::into_inner) = monitor;
+    }
 
-        // Calculate PIN Auth
-        let pin_auth = self.sign_config_command(
-            pin_token,
-            ConfigSubCommand::VendorPrototype as u8,
-            &sub_params_bytes,
-        );
+    /// The channel ID negotiated when the transport was opened.
+    fn cid(&self) -> u32 {
+        self.cid
+    }
 
-        // Build full authenticatorConfig map
-        let mut config_map = BTreeMap::new();
-        config_map.insert(
-            Value::Integer(ConfigParam::SubCommand as i128),
-            Value::Integer(ConfigSubCommand::VendorPrototype as i128),
-        );
-        config_map.insert(
-            Value::Integer(ConfigParam::SubCommandParams as i128),
-            sub_params,
-        );
-        config_map.insert(
-            Value::Integer(ConfigParam::PinUvAuthProtocol as i128),
-            Value::Integer(1),
-        );
-        config_map.insert(
-            Value::Integer(ConfigParam::PinUvAuthParam as i128),
-            Value::Bytes(pin_auth),
-        );
+    fn allocate_channel(&self) -> Result<u32, PFError> {
+        Self::init_channel(self.device.as_ref()).map(|(cid, _)| cid)
+    }
 
-        let config_payload_cbor =
-            to_vec(&Value::Map(config_map)).map_err(|e| PFError::Io(e.to_string()))?;
+    fn ping_on(&self, cid: u32, payload: &[u8]) -> Result<Vec<u8>, PFError> {
+        self.write_request(cid, CTAPHID_PING, payload)?;
+        self.read_response(cid, CTAPHID_PING)
+    }
 
-        // Encapsulate for CTAP
-        let mut payload = vec![CtapCommand::Config as u8];
-        payload.extend(config_payload_cbor);
+    fn drain(&self) {
+        Self::drain_device(self.device.as_ref());
+    }
+}
 
-        // Send via HID
-        self.send_cbor(CTAPHID_CBOR, &payload).map_err(|e| {
-            log::error!("Failed to send FIDO config: {}", e);
-            PFError::Device(format!("FIDO config failed: {}", e))
-        })?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::transport::scripted::ScriptedHidDevice;
 
-        Ok(())
+    const CID: u32 = 0x0102_0304;
+
+    /// An initialisation packet of a `len` byte response to `cmd`, carrying `payload`.
+    fn init_packet(cid: u32, cmd: u8, len: u16, payload: &[u8]) -> Vec<u8> {
+        let mut packet = cid.to_be_bytes().to_vec();
+        packet.push(cmd);
+        packet.extend(len.to_be_bytes());
+        packet.extend(payload);
+        packet
     }
 
-    /// Send authenticatorConfig command to set minimum PIN length.
-    ///
-    /// This bypasses the ctap-hid-fido2 library which has a bug where it sends
-    /// CBOR map keys out of order (0x01, 0x03, 0x04, 0x02) instead of the required
-    /// ascending order (0x01, 0x02, 0x03, 0x04). The pico-fido firmware strictly
-    /// enforces canonical CBOR ordering per CTAP2 spec.
-    pub fn send_config_set_min_pin_length(
-        &self,
-        pin_token: &[u8],
-        new_min_pin_length: u8,
-    ) -> Result<(), PFError> {
-        log::debug!(
-            "Sending setMinPINLength config command (new length: {})...",
-            new_min_pin_length
-        );
+    fn cont_packet(cid: u32, seq: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = cid.to_be_bytes().to_vec();
+        packet.push(seq);
+        packet.extend(payload);
+        packet
+    }
 
-        // Build subCommandParams (Key 0x02): { 0x01: newMinPINLength }
-        let mut sub_params_map = BTreeMap::new();
-        sub_params_map.insert(
-            Value::Integer(ConfigSubCommandParam::NewMinPinLength as i128),
-            Value::Integer(new_min_pin_length as i128),
-        );
-        let sub_params = Value::Map(sub_params_map);
-        let sub_params_bytes = to_vec(&sub_params).map_err(|e| PFError::Io(e.to_string()))?;
-
-        // Calculate PIN Auth
-        let pin_auth = self.sign_config_command(
-            pin_token,
-            ConfigSubCommand::SetMinPinLength as u8,
-            &sub_params_bytes,
+    #[test]
+    fn reassembles_response_around_keepalives_and_other_channels() {
+        // Status byte followed by 99 bytes of CBOR, split 57 + 43
+        let mut response = vec![0x00];
+        response.extend((1..100).map(|i| i as u8));
+        let device = ScriptedHidDevice::new()
+            .report(init_packet(CID, CTAPHID_KEEPALIVE, 1, &[0x02]))
+            .report(init_packet(0xDEAD_BEEF, CTAPHID_CBOR, 1, &[0x00]))
+            .report(init_packet(CID, CTAPHID_CBOR, 100, &response[..57]))
+            .report(cont_packet(0xDEAD_BEEF, 0, &[0xFF; 59]))
+            .report(cont_packet(CID, 0, &response[57..]));
+        let transport = HidTransport::scripted(device.clone(), CID);
+
+        let payload = transport.send_cbor(CTAPHID_CBOR, &[0x04]).unwrap();
+        assert_eq!(payload, response[1..]);
+
+        let written = device.written();
+        assert_eq!(written.len(), 1);
+        assert_eq!(
+            written[0][..9],
+            [0x00, 0x01, 0x02, 0x03, 0x04, 0x90, 0x00, 0x01, 0x04]
         );
+    }
 
-        // Build full authenticatorConfig map with keys in ASCENDING ORDER
-        // Keeping the map item in the correct order is critical - the firmware parser rejects out-of-order keys with CTAP2_ERR_INVALID_CBOR
-        let mut config_map = BTreeMap::new();
-        config_map.insert(
-            Value::Integer(ConfigParam::SubCommand as i128), // 0x01
-            Value::Integer(ConfigSubCommand::SetMinPinLength as i128), // 0x03
-        );
-        config_map.insert(
-            Value::Integer(ConfigParam::SubCommandParams as i128), // 0x02
-            sub_params,
-        );
-        config_map.insert(
-            Value::Integer(ConfigParam::PinUvAuthProtocol as i128), // 0x03
-            Value::Integer(1),                                      // PIN protocol version 1
-        );
-        config_map.insert(
-            Value::Integer(ConfigParam::PinUvAuthParam as i128), // 0x04
-            Value::Bytes(pin_auth),
-        );
+    #[test]
+    fn out_of_order_continuation_is_a_sequence_mismatch() {
+        let device = ScriptedHidDevice::new()
+            .report(init_packet(CID, CTAPHID_CBOR, 100, &[0x00; 57]))
+            .report(cont_packet(CID, 1, &[0x00; 43]));
+        let transport = HidTransport::scripted(device, CID);
 
-        let config_payload_cbor =
-            to_vec(&Value::Map(config_map)).map_err(|e| PFError::Io(e.to_string()))?;
+        let err = transport.send_cbor(CTAPHID_CBOR, &[0x04]).unwrap_err();
+        assert!(HidTransport::is_sequence_mismatch(&err), "{:?}", err);
+    }
 
-        // Prepend CTAP command byte
-        let mut payload = vec![CtapCommand::Config as u8];
-        payload.extend(config_payload_cbor);
+    #[test]
+    fn missing_continuation_is_a_timeout() {
+        let device =
+            ScriptedHidDevice::new().report(init_packet(CID, CTAPHID_CBOR, 100, &[0x00; 57]));
+        let transport = HidTransport::scripted(device, CID);
 
-        // Send via HID
-        match self.send_cbor(CTAPHID_CBOR, &payload) {
-            Ok(_) => {
-                log::info!(
-                    "Successfully set minimum PIN length to {}",
-                    new_min_pin_length
-                );
-                Ok(())
-            }
-            Err(e) => {
-                let err_str = e.to_string();
-                log::error!("Failed to send setMinPINLength config: {}", err_str);
-
-                // Check for PIN policy violation (0x37) - cannot decrease min PIN length
-                if err_str.contains("0x37") {
-                    return Err(PFError::Device(
-                        "Cannot decrease minimum PIN length. The FIDO2 security policy only allows increasing the minimum PIN length, not decreasing it. A device reset is required to lower the minimum.".into()
-                    ));
-                }
+        let err = transport.send_cbor(CTAPHID_CBOR, &[0x04]).unwrap_err();
+        assert!(!HidTransport::is_sequence_mismatch(&err), "{:?}", err);
+        assert!(matches!(err, PFError::Io(_)), "{:?}", err);
+    }
 
-                Err(PFError::Device(format!("setMinPINLength failed: {}", e)))
-            }
-        }
+    #[test]
+    fn unanswered_cancel_is_a_cancel() {
+        let device = ScriptedHidDevice::new();
+        let transport = HidTransport::scripted(device.clone(), CID);
+        let monitor = OperationMonitor::new();
+        monitor.cancel();
+        transport.set_monitor(Some(monitor));
+
+        let err = transport.send_cbor(CTAPHID_CBOR, &[0x07]).unwrap_err();
+        assert!(matches!(err, PFError::Cancelled), "{:?}", err);
+        assert_eq!(device.written()[1][5], CTAPHID_CANCEL);
     }
 
-    /// Helper to sign the authenticatorConfig command
-    fn sign_config_command(
-        &self,
-        pin_token: &[u8],
-        sub_cmd: u8,
-        sub_params_bytes: &[u8],
-    ) -> Vec<u8> {
-        // Build HMAC message for signing
-        // According to FIDO 2.1: authenticate(pinUvAuthToken, 32×0xff || 0x0d || uint8(subCommand) || subCommandParams)
-        let mut message = vec![0xff; 32];
-        message.push(CtapCommand::Config as u8);
-        message.push(sub_cmd);
-        message.extend(sub_params_bytes);
-
-        // Sign using provided PIN token
-        use ring::hmac;
-        let hmac_key = hmac::Key::new(hmac::HMAC_SHA256, pin_token);
-        let sig = hmac::sign(&hmac_key, &message);
-        sig.as_ref()[0..16].to_vec()
+    #[test]
+    fn ctaphid_error_maps_to_ctap_error() {
+        // ERR_CHANNEL_BUSY
+        let device = ScriptedHidDevice::new().report(init_packet(CID, CTAPHID_ERROR, 1, &[0x06]));
+        let transport = HidTransport::scripted(device, CID);
+
+        let err = transport.send_cbor(CTAPHID_CBOR, &[0x04]).unwrap_err();
+        assert_eq!(err.ctap_error(), Some(Ctap2Error::from_code(0x06)));
+    }
+
+    #[test]
+    fn wink_waits_for_its_response() {
+        let device = ScriptedHidDevice::new().report(init_packet(CID, CTAPHID_WINK, 0, &[]));
+        let transport = HidTransport::scripted(device.clone(), CID);
+
+        assert!(transport.wink().unwrap());
+        assert_eq!(device.written()[0][5], CTAPHID_WINK);
     }
 }
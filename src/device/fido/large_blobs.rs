@@ -0,0 +1,311 @@
+//! authenticatorLargeBlobs (0x0C): the large-blob array shared by every credential on the key.
+//!
+//! The key stores the array serialized as CBOR followed by the first 16 bytes of its SHA-256,
+//! and only ever hands it out and takes it back in fragments. Each entry is encrypted with the
+//! largeBlobKey of the credential it belongs to, so an entry can only be attributed to a
+//! credential by trying that credential's key on it.
+
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::digest;
+use serde_cbor_2::{Value, from_slice, to_vec};
+use std::collections::BTreeMap;
+
+use crate::device::fido::cbor::{self, CborMap, int};
+use crate::device::fido::constants::*;
+use crate::device::fido::pin::PinUvAuthProtocol;
+use crate::device::transport::CtapHidTransport;
+use crate::error::PFError;
+
+/// Length of the truncated SHA-256 that follows the serialized array.
+pub const TRAILER_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Reads the serialized array, `max_fragment` bytes at a time.
+pub fn read_array<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    max_fragment: usize,
+) -> Result<Vec<u8>, PFError> {
+    let mut serialized = Vec::new();
+    loop {
+        let mut params = BTreeMap::new();
+        params.insert(int(LargeBlobsParam::Get as u8), int(max_fragment as u64));
+        params.insert(
+            int(LargeBlobsParam::Offset as u8),
+            int(serialized.len() as u64),
+        );
+
+        let response = cbor::send_command(transport, CtapCommand::LargeBlobs, Some(params))?;
+        let fragment = cbor::get_bytes(&response, LargeBlobsResponse::Config as i128)
+            .ok_or_else(|| PFError::Device("Large blob response is missing the data".into()))?;
+        serialized.extend_from_slice(fragment);
+
+        // A short fragment marks the end of the array.
+        if fragment.len() < max_fragment {
+            break;
+        }
+    }
+    log::debug!("Read {} bytes of large-blob array", serialized.len());
+    Ok(serialized)
+}
+
+/// Replaces the whole array with `serialized` (as built by [`serialize_array`]). Every fragment
+/// is signed with a pinUvAuthToken that has the `LARGE_BLOB_WRITE` permission.
+pub fn write_array<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+    serialized: &[u8],
+    max_fragment: usize,
+) -> Result<(), PFError> {
+    log::debug!("Writing {} bytes of large-blob array", serialized.len());
+    for (index, fragment) in serialized.chunks(max_fragment).enumerate() {
+        let offset = index * max_fragment;
+
+        let mut params = BTreeMap::new();
+        params.insert(
+            int(LargeBlobsParam::Set as u8),
+            Value::Bytes(fragment.to_vec()),
+        );
+        params.insert(int(LargeBlobsParam::Offset as u8), int(offset as u64));
+        if offset == 0 {
+            params.insert(
+                int(LargeBlobsParam::Length as u8),
+                int(serialized.len() as u64),
+            );
+        }
+        params.insert(
+            int(LargeBlobsParam::PinUvAuthParam as u8),
+            Value::Bytes(protocol.authenticate(pin_token, &write_message(offset, fragment))),
+        );
+        params.insert(
+            int(LargeBlobsParam::PinUvAuthProtocol as u8),
+            int(protocol.version()),
+        );
+
+        cbor::send_command(transport, CtapCommand::LargeBlobs, Some(params))?;
+    }
+    Ok(())
+}
+
+/// What a fragment write is signed over: 32 × 0xff || 0x0c 0x00 || uint32LE(offset) ||
+/// SHA-256(fragment).
+pub fn write_message(offset: usize, fragment: &[u8]) -> Vec<u8> {
+    let mut message = vec![0xFF; 32];
+    message.extend([CtapCommand::LargeBlobs as u8, 0x00]);
+    message.extend((offset as u32).to_le_bytes());
+    message.extend(digest::digest(&digest::SHA256, fragment).as_ref());
+    message
+}
+
+/// Decodes a serialized array. Returns `None` if the trailer does not match or the data is not
+/// a CBOR array, in which case the only way forward is to reset it.
+pub fn parse_array(serialized: &[u8]) -> Option<Vec<Value>> {
+    if serialized.len() < TRAILER_LEN {
+        return None;
+    }
+    let (array, trailer) = serialized.split_at(serialized.len() - TRAILER_LEN);
+    if trailer != &digest::digest(&digest::SHA256, array).as_ref()[..TRAILER_LEN] {
+        log::warn!("Large-blob array trailer does not match its contents");
+        return None;
+    }
+    match from_slice(array) {
+        Ok(Value::Array(entries)) => Some(entries),
+        _ => {
+            log::warn!("Large-blob array is not a CBOR array");
+            None
+        }
+    }
+}
+
+/// Serializes entries with their SHA-256 trailer. No entries gives the empty array every key
+/// starts with.
+pub fn serialize_array(entries: &[Value]) -> Result<Vec<u8>, PFError> {
+    let mut serialized =
+        to_vec(&Value::Array(entries.to_vec())).map_err(|e| PFError::Io(e.to_string()))?;
+    let hash = digest::digest(&digest::SHA256, &serialized);
+    serialized.extend(&hash.as_ref()[..TRAILER_LEN]);
+    Ok(serialized)
+}
+
+/// The nonce of an entry, which is random per entry and so identifies it within the array.
+pub fn entry_nonce(entry: &Value) -> Option<&[u8]> {
+    match entry {
+        Value::Map(map) => cbor::get_bytes(map, LargeBlobEntryKey::Nonce as i128),
+        _ => None,
+    }
+}
+
+/// Size of the encrypted data and of the original (uncompressed) blob.
+pub fn entry_sizes(entry: &Value) -> (usize, u64) {
+    let Value::Map(map) = entry else {
+        return (0, 0);
+    };
+    (
+        cbor::get_bytes(map, LargeBlobEntryKey::Ciphertext as i128).map_or(0, <[u8]>::len),
+        cbor::get_int(map, LargeBlobEntryKey::OrigSize as i128).unwrap_or(0) as u64,
+    )
+}
+
+/// Whether `large_blob_key` decrypts the entry, i.e. the entry belongs to that credential.
+pub fn entry_decrypts(entry: &Value, large_blob_key: &[u8]) -> bool {
+    let Value::Map(map) = entry else {
+        return false;
+    };
+    let (Some(ciphertext), Some(nonce), Some(orig_size)) = (
+        cbor::get_bytes(map, LargeBlobEntryKey::Ciphertext as i128),
+        cbor::get_bytes(map, LargeBlobEntryKey::Nonce as i128),
+        cbor::get_int(map, LargeBlobEntryKey::OrigSize as i128),
+    ) else {
+        return false;
+    };
+    let (Ok(key), Ok(nonce)) = (
+        UnboundKey::new(&AES_256_GCM, large_blob_key),
+        Nonce::try_assume_unique_for_key(nonce),
+    ) else {
+        return false;
+    };
+
+    let mut buf = ciphertext.to_vec();
+    LessSafeKey::new(key)
+        .open_in_place(nonce, Aad::from(entry_aad(orig_size as u64)), &mut buf)
+        .is_ok()
+}
+
+/// Encrypts `data` (already compressed) into an entry for the credential with
+/// `large_blob_key`.
+pub fn encrypt_entry(large_blob_key: &[u8], data: &[u8], orig_size: u64) -> Result<Value, PFError> {
+    let key = UnboundKey::new(&AES_256_GCM, large_blob_key)
+        .map_err(|_| PFError::Io("Invalid largeBlobKey".into()))?;
+    let mut nonce = [0u8; NONCE_LEN];
+    ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut nonce)
+        .map_err(|_| PFError::Io("Failed to generate a nonce".into()))?;
+
+    let mut ciphertext = data.to_vec();
+    LessSafeKey::new(key)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(entry_aad(orig_size)),
+            &mut ciphertext,
+        )
+        .map_err(|_| PFError::Io("Failed to encrypt large blob".into()))?;
+
+    let mut entry: CborMap = BTreeMap::new();
+    entry.insert(
+        int(LargeBlobEntryKey::Ciphertext as u8),
+        Value::Bytes(ciphertext),
+    );
+    entry.insert(
+        int(LargeBlobEntryKey::Nonce as u8),
+        Value::Bytes(nonce.to_vec()),
+    );
+    entry.insert(int(LargeBlobEntryKey::OrigSize as u8), int(orig_size));
+    Ok(Value::Map(entry))
+}
+
+/// "blob" || uint64LE(origSize)
+fn entry_aad(orig_size: u64) -> Vec<u8> {
+    let mut aad = b"blob".to_vec();
+    aad.extend(orig_size.to_le_bytes());
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::fido::hid::CTAPHID_CBOR;
+    use crate::device::transport::scripted::ScriptedCtapHidTransport;
+
+    const KEY: [u8; 32] = [0x42; 32];
+
+    /// The initial value from the CTAP 2.1 spec: an empty CBOR array and its truncated hash.
+    const EMPTY_ARRAY: &str = "8076be8b528d0075f7aae98d6fa57a6d3c";
+
+    #[test]
+    fn serializes_the_empty_array() {
+        assert_eq!(hex::encode(serialize_array(&[]).unwrap()), EMPTY_ARRAY);
+        assert_eq!(
+            parse_array(&hex::decode(EMPTY_ARRAY).unwrap()),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let entry = encrypt_entry(&KEY, b"compressed blob", 40).unwrap();
+        let serialized = serialize_array(std::slice::from_ref(&entry)).unwrap();
+        let entries = parse_array(&serialized).unwrap();
+
+        assert_eq!(entries, [entry]);
+        assert!(entry_decrypts(&entries[0], &KEY));
+        assert!(!entry_decrypts(&entries[0], &[0x43; 32]));
+        assert_eq!(entry_nonce(&entries[0]).map(<[u8]>::len), Some(NONCE_LEN));
+        // 16 byte AES-GCM tag
+        assert_eq!(entry_sizes(&entries[0]), (15 + 16, 40));
+    }
+
+    #[test]
+    fn entry_is_bound_to_its_original_size() {
+        let Value::Map(mut map) = encrypt_entry(&KEY, b"data", 4).unwrap() else {
+            unreachable!();
+        };
+        map.insert(int(LargeBlobEntryKey::OrigSize as u8), int(5));
+        assert!(!entry_decrypts(&Value::Map(map), &KEY));
+    }
+
+    #[test]
+    fn rejects_a_bad_hash_or_truncated_array() {
+        let entry = encrypt_entry(&KEY, b"data", 4).unwrap();
+        let serialized = serialize_array(&[entry]).unwrap();
+
+        let mut bad_hash = serialized.clone();
+        *bad_hash.last_mut().unwrap() ^= 1;
+        assert_eq!(parse_array(&bad_hash), None);
+
+        assert_eq!(parse_array(&serialized[..serialized.len() - 1]), None);
+        assert_eq!(parse_array(&serialized[..TRAILER_LEN - 1]), None);
+
+        // A hash that matches, over a CBOR array cut short
+        let array = &serialized[..serialized.len() - TRAILER_LEN - 1];
+        let mut truncated = array.to_vec();
+        truncated.extend(&digest::digest(&digest::SHA256, array).as_ref()[..TRAILER_LEN]);
+        assert_eq!(parse_array(&truncated), None);
+    }
+
+    #[test]
+    fn rejects_data_that_is_not_an_array() {
+        let map = to_vec(&Value::Map(BTreeMap::new())).unwrap();
+        let mut serialized = map.clone();
+        serialized.extend(&digest::digest(&digest::SHA256, &map).as_ref()[..TRAILER_LEN]);
+        assert_eq!(parse_array(&serialized), None);
+    }
+
+    #[test]
+    fn write_message_matches_spec() {
+        let mut expected = "ff".repeat(32);
+        expected.push_str("0c00");
+        expected.push_str("02010000");
+        expected.push_str("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(hex::encode(write_message(0x0102, b"abc")), expected);
+    }
+
+    #[test]
+    fn reads_fragments_until_a_short_one() {
+        let fragment = |data: &[u8]| {
+            let mut response = vec![0x00];
+            let map = BTreeMap::from([(
+                int(LargeBlobsResponse::Config as u8),
+                Value::Bytes(data.to_vec()),
+            )]);
+            response.extend(to_vec(&Value::Map(map)).unwrap());
+            response
+        };
+        let serialized = hex::decode(EMPTY_ARRAY).unwrap();
+        let hid = ScriptedCtapHidTransport::new(0xCAFE, 0x4242, "Scripted Key")
+            .respond(CTAPHID_CBOR, fragment(&serialized[..8]))
+            .respond(CTAPHID_CBOR, fragment(&serialized[8..16]))
+            .respond(CTAPHID_CBOR, fragment(&serialized[16..]));
+
+        assert_eq!(read_array(&hid, 8).unwrap(), serialized);
+        assert!(hid.is_finished());
+    }
+}
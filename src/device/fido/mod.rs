@@ -1,116 +1,232 @@
+pub mod auth_encryption;
+pub mod backup;
+pub mod cbor;
+pub mod client_pin;
+pub mod config;
 pub mod constants;
+pub mod cose;
+pub mod cred_blob;
+pub mod credential_management;
+pub mod diagnostics;
+pub mod enterprise_attestation;
 pub mod hid;
+pub mod large_blobs;
+pub mod pem;
+pub mod pin;
+pub mod pin_policy;
 
 use crate::{
+    device::rescue,
+    device::session::{self, DeviceSession},
+    device::transport::{CtapHidTransport, OperationMonitor},
     device::types::{
-        AppConfig, AppConfigInput, DeviceInfo, DeviceMethod, FidoDeviceInfo, FullDeviceStatus,
-        StoredCredential,
+        AppConfig, AppConfigInput, BulkDeleteReport, DeviceHandle, DeviceInfo, DeviceMethod,
+        FidoDeviceInfo, FullDeviceStatus, LargeBlobArray, LargeBlobEntry, StoredCredential,
     },
     error::PFError,
 };
 use constants::*;
-use ctap_hid_fido2::{
-    Cfg, FidoKeyHidFactory,
-    fidokey::{FidoKeyHid, pin::Permission},
-    public_key_credential_descriptor::PublicKeyCredentialDescriptor,
-};
 use hid::*;
+use pin::PinUvAuthProtocol;
+use pin_policy::PinComplexityPolicy;
 use serde_cbor_2::{Value, from_slice, to_vec};
 use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Opens the FIDO HID interface of the given device handle and negotiates a channel.
+pub(crate) fn open_transport(
+    device: &DeviceHandle,
+) -> Result<Box<dyn CtapHidTransport + Send>, PFError> {
+    let path = device.hid_path.as_deref().ok_or_else(|| {
+        log::info!("Selected device has no FIDO HID interface");
+        PFError::NoDevice
+    })?;
+    if device.is_simulated() {
+        return Ok(Box::new(crate::device::simulator::global().open_hid()));
+    }
+    Ok(Box::new(HidTransport::open(path)?))
+}
 
-// Fido functions that require pin: ( Uses ctap_hid_fido2 crate)
+// Fido functions that require pin: (native ClientPIN over our own HID channel, so every
+// command goes out as canonical CBOR)
+
+/// Obtains a pinUvAuthToken limited to `permissions`, using the PIN/UV auth protocol
+/// negotiated from GetInfo. Keys without CTAP 2.1 token permissions (no `pinUvAuthToken`
+/// option) get an unrestricted getPinToken token instead.
+fn obtain_pin_token(
+    session: &mut DeviceSession,
+    pin: &str,
+    permissions: PinUvAuthTokenPermissions,
+) -> Result<(PinUvAuthProtocol, Vec<u8>), PFError> {
+    obtain_rp_pin_token(session, pin, permissions, None)
+}
 
-fn get_device() -> Result<FidoKeyHid, String> {
-    let cfg = Cfg::init();
-    FidoKeyHidFactory::create(&cfg).map_err(|e| {
-        format!(
-            "Could not connect to FIDO device. Is it plugged in? Error: {:?}",
-            e
-        )
+/// Like [`obtain_pin_token`], but also limits the token to `rp_id`, as getAssertion requires.
+fn obtain_rp_pin_token(
+    session: &mut DeviceSession,
+    pin: &str,
+    permissions: PinUvAuthTokenPermissions,
+    rp_id: Option<&str>,
+) -> Result<(PinUvAuthProtocol, Vec<u8>), PFError> {
+    let protocol = pin_protocol(session)?;
+    let with_permissions = session
+        .fido_info()?
+        .options
+        .get("pinUvAuthToken")
+        .copied()
+        .unwrap_or(false);
+
+    let token = session.with_hid(|t| {
+        if with_permissions {
+            client_pin::get_pin_uv_auth_token(t, protocol, pin, permissions, rp_id)
+        } else {
+            log::debug!("Key has no pinUvAuthToken support, using getPinToken");
+            client_pin::get_pin_token(t, protocol, pin)
+        }
+    });
+    // Every attempt changes the PIN retry counters in GetInfo.
+    session.invalidate_fido_info();
+    Ok((protocol, token?))
+}
+
+/// The PIN/UV auth protocol to sign commands with, as negotiated from GetInfo.
+fn pin_protocol(session: &mut DeviceSession) -> Result<PinUvAuthProtocol, PFError> {
+    let protocol = PinUvAuthProtocol::negotiate(&session.fido_info()?.pin_protocols);
+    log::debug!("Using PIN/UV auth protocol {}", protocol.version());
+    Ok(protocol)
+}
+
+pub(crate) fn get_fido_info(session: &mut DeviceSession) -> Result<FidoDeviceInfo, PFError> {
+    session.fido_info().inspect_err(|e| {
+        log::error!("Error reading device info: {}", e);
     })
 }
 
-pub(crate) fn get_fido_info() -> Result<FidoDeviceInfo, String> {
-    let device = get_device()?;
+/// Parses the authenticatorGetInfo response without going through ctap-hid-fido2.
+pub fn read_fido_info<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+) -> Result<FidoDeviceInfo, PFError> {
+    let info = cbor::send_command(transport, CtapCommand::GetInfo, None)?;
+
+    let text_list = |key: i128| -> Vec<String> {
+        match info.get(&cbor::int(key)) {
+            Some(Value::Array(items)) => items
+                .iter()
+                .filter_map(|v| match v {
+                    Value::Text(s) => Some(s.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    };
 
-    let info = device
-        .get_info()
-        .map_err(|e| format!("Error reading device info: {:?}", e))?;
+    let options = match info.get(&cbor::int(0x04)) {
+        Some(Value::Map(m)) => m
+            .iter()
+            .filter_map(|(k, v)| match (k, v) {
+                (Value::Text(name), Value::Bool(enabled)) => Some((name.clone(), *enabled)),
+                _ => None,
+            })
+            .collect(),
+        _ => HashMap::new(),
+    };
 
-    let options_map: HashMap<String, bool> = info.options.into_iter().collect();
+    let pin_protocols = match info.get(&cbor::int(0x06)) {
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(|v| match v {
+                Value::Integer(i) => Some(*i as u32),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let auth_encryption_state = options.get(auth_encryption::STATE_OPTION).copied();
+    let firmware = cbor::get_int(&info, 0x0E).unwrap_or(0);
+    // Plain CTAP 2.2 keys only report whether a policy applies, not its rules.
+    let pin_complexity_policy = cbor::get_int(&info, 0x1B)
+        .map(|bits| PinComplexityPolicy::from_bits_truncate(bits as u8))
+        .unwrap_or_default();
+
+    // The retry counters are only meaningful on keys that support a PIN / built-in UV. They are
+    // informational, so a key that refuses to report them still shows its other details.
+    let protocol = PinUvAuthProtocol::negotiate(&pin_protocols);
+    let pin_retries = if options.contains_key("clientPin") {
+        client_pin::get_pin_retries(transport, protocol)
+            .inspect_err(|e| log::warn!("Failed to read PIN retries: {}", e))
+            .ok()
+    } else {
+        None
+    };
+    let uv_retries = if options.contains_key("uv") {
+        client_pin::get_uv_retries(transport, protocol)
+            .inspect_err(|e| log::warn!("Failed to read UV retries: {}", e))
+            .ok()
+    } else {
+        None
+    };
 
     Ok(FidoDeviceInfo {
-        versions: info.versions,
-        extensions: info.extensions,
-        aaguid: hex::encode_upper(info.aaguid),
-        options: options_map,
-        max_msg_size: info.max_msg_size,
-        pin_protocols: info.pin_uv_auth_protocols,
-        min_pin_length: info.min_pin_length,
-        firmware_version: format!(
-            "{}.{}",
-            (info.firmware_version >> 8) & 0xFF,
-            info.firmware_version & 0xFF
-        ),
+        versions: text_list(0x01),
+        extensions: text_list(0x02),
+        aaguid: hex::encode_upper(cbor::get_bytes(&info, 0x03).unwrap_or_default()),
+        options,
+        max_msg_size: cbor::get_int(&info, 0x05).unwrap_or(0) as i32,
+        pin_protocols,
+        min_pin_length: cbor::get_int(&info, 0x0D).unwrap_or(4) as u32,
+        firmware_version: format!("{}.{}", (firmware >> 8) & 0xFF, firmware & 0xFF),
+        pin_retries: pin_retries.map(|r| r.retries),
+        uv_retries,
+        power_cycle_required: pin_retries.is_some_and(|r| r.power_cycle_required),
+        auth_encryption: auth_encryption_state.is_some(),
+        auth_encryption_locked: auth_encryption_state.unwrap_or(false),
+        pin_complexity_policy,
+        remaining_disc_creds: cbor::get_int(&info, 0x14).map(|count| count as u32),
+        creds_metadata: None,
+        max_large_blob_array: cbor::get_int(&info, 0x0B).map(|size| size as u32),
     })
 }
 
 pub(crate) fn change_fido_pin(
+    session: &mut DeviceSession,
     current_pin: Option<String>,
     new_pin: String,
-) -> Result<String, String> {
-    let device = get_device()?;
+) -> Result<String, PFError> {
+    let protocol = pin_protocol(session)?;
+    // The clientPin option and minPINLength in GetInfo change with the PIN.
+    session.invalidate_fido_info();
 
     match current_pin {
         Some(old) => {
-            device
-                .change_pin(&old, &new_pin)
-                .map_err(|e| format!("Failed to change PIN: {:?}", e))?;
+            session.with_hid(|t| client_pin::change_pin(t, protocol, &old, &new_pin))?;
             Ok("PIN Changed Successfully".into())
         }
         None => {
-            device
-                .set_new_pin(&new_pin)
-                .map_err(|e| format!("Failed to set PIN: {:?}", e))?;
+            session.with_hid(|t| client_pin::set_pin(t, protocol, &new_pin))?;
             Ok("PIN Set Successfully".into())
         }
     }
 }
 
 pub(crate) fn set_min_pin_length(
+    session: &mut DeviceSession,
     current_pin: String,
     min_pin_length: u8,
-) -> Result<String, String> {
-    log::info!("Starting set_min_pin_length (custom implementation)...");
-
-    // 1. Obtain PIN token using the library handle
-    let pin_token = {
-        let device = get_device()?;
-
-        // Obtain a token with AuthenticatorConfiguration permission (CTAP 2.1)
-        match device.get_pinuv_auth_token_with_permission(
-            &current_pin,
-            Permission::AuthenticatorConfiguration,
-        ) {
-            Ok(token) => {
-                log::debug!("Successfully obtained PIN token with ACFG permission.");
-                token.key
-            }
-            Err(e) => {
-                log::error!("Failed to get PIN token with ACFG permission: {:?}", e);
-                return Err(format!("Failed to obtain PIN token: {:?}", e));
-            }
-        }
-        // Library handle 'device' is dropped here, closing the HID session.
-    };
-
-    // 2. Open custom HidTransport and send command using the token because ctap-hid-fido2 has a bug where it sends CBOR map keys out of order (0x01, 0x03, 0x04, 0x02) instead of the required ascending order (0x01, 0x02, 0x03, 0x04). The pico-fido firmware strictly requires ascending order.
-    let transport =
-        HidTransport::open().map_err(|e| format!("Could not open HID transport: {}", e))?;
+) -> Result<String, PFError> {
+    log::info!("Starting set_min_pin_length...");
+    let (protocol, pin_token) = obtain_pin_token(
+        session,
+        &current_pin,
+        PinUvAuthTokenPermissions::AUTHENTICATOR_CONFIG,
+    )?;
+    session.invalidate_fido_info();
 
-    transport
-        .send_config_set_min_pin_length(&pin_token, min_pin_length)
-        .map_err(|e| format!("Failed to set minimum PIN length: {}", e))?;
+    session.with_hid(|t| {
+        config::send_config_set_min_pin_length(t, protocol, &pin_token, min_pin_length)
+    })?;
 
     Ok(format!(
         "Minimum PIN length successfully set to {}",
@@ -118,82 +234,634 @@ pub(crate) fn set_min_pin_length(
     ))
 }
 
-pub(crate) fn get_credentials(pin: String) -> Result<Vec<StoredCredential>, String> {
-    let device = get_device()?;
+pub(crate) fn set_pin_complexity_policy(
+    session: &mut DeviceSession,
+    pin: String,
+    policy: PinComplexityPolicy,
+) -> Result<String, PFError> {
+    log::info!("Starting set_pin_complexity_policy...");
+    let (protocol, pin_token) = obtain_pin_token(
+        session,
+        &pin,
+        PinUvAuthTokenPermissions::AUTHENTICATOR_CONFIG,
+    )?;
 
-    let rps = match device.credential_management_enumerate_rps(Some(&pin)) {
-        Ok(rps) => rps,
-        Err(e) => {
-            // CTAP2_ERR_NO_CREDENTIALS (0x2E) means no credentials exist - return empty list
-            let err_str = format!("{:?}", e);
-            if err_str.contains("0x2E") || err_str.contains("NO_CREDENTIALS") {
-                log::info!("No credentials stored on device (CTAP2_ERR_NO_CREDENTIALS)");
-                return Ok(Vec::new());
+    session.with_hid(|t| pin_policy::set_policy(t, protocol, &pin_token, policy))?;
+    session.invalidate_fido_info();
+
+    Ok(if policy.is_empty() {
+        "PIN complexity policy removed".into()
+    } else {
+        "PIN complexity policy updated".into()
+    })
+}
+
+/// Flips the `alwaysUv` option and reports the state the key ended up in.
+pub(crate) fn toggle_always_uv(session: &mut DeviceSession, pin: String) -> Result<bool, PFError> {
+    log::info!("Starting toggle_always_uv...");
+    let (protocol, pin_token) = obtain_pin_token(
+        session,
+        &pin,
+        PinUvAuthTokenPermissions::AUTHENTICATOR_CONFIG,
+    )?;
+
+    session.with_hid(|t| config::send_config_toggle_always_uv(t, protocol, &pin_token))?;
+    session.invalidate_fido_info();
+
+    let enabled = session
+        .fido_info()?
+        .options
+        .get("alwaysUv")
+        .copied()
+        .unwrap_or(false);
+    Ok(enabled)
+}
+
+/// Verifies the PIN, which unlocks the device secret, and returns the encrypted backup of it
+/// as recovery words.
+///
+/// The vendor Backup command carries no pinUvAuthParam. pico-fido only hands the secret out
+/// while it holds it decrypted, which is what a correct PIN since power-up gets it to do, so
+/// the token is obtained for that alone and dropped. A token needs some permission, and
+/// `AUTHENTICATOR_CONFIG` is the one every other key-wide setting asks for.
+pub(crate) fn get_backup_words(
+    session: &mut DeviceSession,
+    pin: String,
+) -> Result<Vec<String>, PFError> {
+    log::info!("Starting get_backup_words...");
+    obtain_pin_token(
+        session,
+        &pin,
+        PinUvAuthTokenPermissions::AUTHENTICATOR_CONFIG,
+    )?;
+    let secret = session.with_hid(|t| backup::get_encrypted_backup(t))?;
+    backup::to_words(&secret)
+}
+
+/// Writes the secret encoded by the recovery words to the key, replacing its own. A key with
+/// a PIN has to be unlocked with it first, so holding the key is not enough to swap its secret
+/// out. Keys without one (fresh or reset) have nothing to protect.
+pub(crate) fn restore_backup(
+    session: &mut DeviceSession,
+    words: String,
+    pin: Option<String>,
+) -> Result<String, PFError> {
+    log::info!("Starting restore_backup...");
+    let secret = backup::from_words(&words)?;
+
+    let pin_set = session
+        .fido_info()?
+        .options
+        .get("clientPin")
+        .copied()
+        .unwrap_or(false);
+    if pin_set {
+        let pin = pin.ok_or(PFError::Ctap(Ctap2Error::PuatRequired))?;
+        obtain_pin_token(
+            session,
+            &pin,
+            PinUvAuthTokenPermissions::AUTHENTICATOR_CONFIG,
+        )?;
+    }
+
+    session.with_hid(|t| backup::restore_encrypted_backup(t, &secret))?;
+    session.invalidate_fido_info();
+    Ok("Backup restored".into())
+}
+
+/// Turns authentication encryption on or off with the host key stored on this computer, and
+/// returns the new state.
+pub(crate) fn set_auth_encryption(
+    session: &mut DeviceSession,
+    pin: String,
+    enabled: bool,
+) -> Result<bool, PFError> {
+    log::info!("Starting set_auth_encryption (enabled: {})...", enabled);
+    let host_key = if enabled {
+        auth_encryption::load_or_create_host_key()?
+    } else {
+        auth_encryption::require_host_key()?
+    };
+    let (protocol, pin_token) = obtain_pin_token(
+        session,
+        &pin,
+        PinUvAuthTokenPermissions::AUTHENTICATOR_CONFIG,
+    )?;
+
+    session.with_hid(|t| {
+        if enabled {
+            auth_encryption::enable(t, protocol, &pin_token, &host_key)
+        } else {
+            auth_encryption::disable(t, protocol, &pin_token, &host_key)
+        }
+    })?;
+    session.invalidate_fido_info();
+    Ok(enabled)
+}
+
+/// Unlocks a key whose GetInfo `info` reports its device secret as encrypted and locked, with
+/// the host key stored on this computer, and updates `info` to match. Other keys are left alone.
+pub(crate) fn unlock_auth_encryption<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    info: &mut FidoDeviceInfo,
+) -> Result<(), PFError> {
+    if !info.auth_encryption_locked {
+        return Ok(());
+    }
+    let host_key = auth_encryption::require_host_key()?;
+    auth_encryption::unlock(transport, &host_key)?;
+    info.auth_encryption_locked = false;
+    log::info!("Unlocked key with authentication encryption");
+    Ok(())
+}
+
+/// Has the key generate an enterprise attestation key pair and returns its CSR (DER).
+pub(crate) fn generate_attestation_csr(session: &mut DeviceSession) -> Result<Vec<u8>, PFError> {
+    log::info!("Generating enterprise attestation CSR...");
+    session.with_hid(|t| enterprise_attestation::generate_csr(t))
+}
+
+/// Uploads the signed attestation certificate, enables enterprise attestation and checks that
+/// GetInfo reports `ep` afterwards.
+pub(crate) fn provision_enterprise_attestation(
+    session: &mut DeviceSession,
+    pin: String,
+    certificate: Vec<u8>,
+) -> Result<String, PFError> {
+    log::info!("Starting provision_enterprise_attestation...");
+    let (protocol, pin_token) = obtain_pin_token(
+        session,
+        &pin,
+        PinUvAuthTokenPermissions::AUTHENTICATOR_CONFIG,
+    )?;
+
+    session.with_hid(|t| {
+        enterprise_attestation::upload_certificate(t, protocol, &pin_token, &certificate)?;
+        config::send_config_enable_enterprise_attestation(t, protocol, &pin_token)
+    })?;
+    session.invalidate_fido_info();
+
+    let enabled = session.fido_info()?.options.get("ep").copied();
+    if enabled != Some(true) {
+        log::error!("Key does not report ep after enabling: {:?}", enabled);
+        return Err(PFError::Device(
+            "The key accepted the certificate but does not report enterprise attestation as enabled"
+                .into(),
+        ));
+    }
+
+    Ok("Enterprise attestation enabled".into())
+}
+
+pub(crate) fn get_credentials(
+    session: &mut DeviceSession,
+    pin: String,
+) -> Result<Vec<StoredCredential>, PFError> {
+    let (protocol, pin_token) = obtain_pin_token(
+        session,
+        &pin,
+        PinUvAuthTokenPermissions::CREDENTIAL_MANAGEMENT,
+    )?;
+    let (credentials, metadata) = session.with_hid(|t| {
+        let credentials = credential_management::enumerate_credentials(t, protocol, &pin_token)?;
+        let metadata = credential_management::get_creds_metadata(t, protocol, &pin_token)
+            .inspect_err(|e| log::warn!("Failed to read credential metadata: {}", e))
+            .ok();
+        Ok((credentials, metadata))
+    })?;
+    session.set_creds_metadata(metadata);
+    Ok(credentials)
+}
+
+pub(crate) fn delete_credential(
+    session: &mut DeviceSession,
+    pin: String,
+    credential_id_hex: String,
+) -> Result<String, PFError> {
+    let cred_id_bytes = hex::decode(&credential_id_hex)
+        .map_err(|_| PFError::Io("Invalid Credential ID Hex string".into()))?;
+
+    let (protocol, pin_token) = obtain_pin_token(
+        session,
+        &pin,
+        PinUvAuthTokenPermissions::CREDENTIAL_MANAGEMENT,
+    )?;
+    session.with_hid(|t| {
+        credential_management::delete_credential(t, protocol, &pin_token, &cred_id_bytes)
+    })?;
+
+    Ok("Credential deleted successfully".into())
+}
+
+/// Deletes several credentials under a single pinUvAuthToken, reporting progress through
+/// `monitor` and stopping early when it is cancelled. A failed deletion does not stop the
+/// batch unless the key stops accepting the token.
+pub(crate) fn delete_credentials(
+    session: &mut DeviceSession,
+    pin: String,
+    credentials: Vec<StoredCredential>,
+    monitor: &OperationMonitor,
+) -> Result<BulkDeleteReport, PFError> {
+    let total = credentials.len();
+    log::info!("Deleting {} credentials...", total);
+    monitor.set_progress(0, total);
+
+    let (protocol, pin_token) = obtain_pin_token(
+        session,
+        &pin,
+        PinUvAuthTokenPermissions::CREDENTIAL_MANAGEMENT,
+    )?;
+
+    let mut report = BulkDeleteReport::default();
+    let mut abort: Option<String> = None;
+    for (index, credential) in credentials.into_iter().enumerate() {
+        if abort.is_none() && monitor.is_cancelled() {
+            abort = Some("Cancelled".into());
+        }
+        if let Some(reason) = &abort {
+            report.failed.push((credential, reason.clone()));
+            continue;
+        }
+
+        let result = hex::decode(&credential.credential_id)
+            .map_err(|_| PFError::Io("Invalid Credential ID Hex string".into()))
+            .and_then(|id| {
+                session.with_hid(|t| {
+                    credential_management::delete_credential(t, protocol, &pin_token, &id)
+                })
+            });
+        match result {
+            Ok(()) => report.deleted += 1,
+            // Already gone, e.g. when retrying a batch that partly went through.
+            Err(e) if e.ctap_error() == Some(Ctap2Error::NoCredentials) => report.deleted += 1,
+            Err(e) => {
+                log::warn!(
+                    "Failed to delete credential for {}: {}",
+                    credential.rp_id,
+                    e
+                );
+                if e.invalidates_pin() || e.ctap_error() == Some(Ctap2Error::PinAuthInvalid) {
+                    abort = Some(e.user_message());
+                }
+                report.failed.push((credential, e.user_message()));
             }
-            return Err(format!("Failed to enumerate Relying Parties: {:?}", e));
         }
+        monitor.set_progress(index + 1, total);
+    }
+
+    log::info!(
+        "Deleted {} of {} credentials, {} failed",
+        report.deleted,
+        total,
+        report.failed.len()
+    );
+    Ok(report)
+}
+
+/// Renames the user of a credential. The user ID stays the same, so the relying party still
+/// recognises the account.
+pub(crate) fn update_credential_user(
+    session: &mut DeviceSession,
+    pin: String,
+    credential: StoredCredential,
+    user_name: String,
+    user_display_name: String,
+) -> Result<String, PFError> {
+    let credential_id = hex::decode(&credential.credential_id)
+        .map_err(|_| PFError::Io("Invalid Credential ID Hex string".into()))?;
+    let user_id = hex::decode(&credential.user_id)
+        .map_err(|_| PFError::Io("Invalid User ID Hex string".into()))?;
+
+    let (protocol, pin_token) = obtain_pin_token(
+        session,
+        &pin,
+        PinUvAuthTokenPermissions::CREDENTIAL_MANAGEMENT,
+    )?;
+    session.with_hid(|t| {
+        credential_management::update_user_information(
+            t,
+            protocol,
+            &pin_token,
+            &credential_id,
+            &user_id,
+            &user_name,
+            &user_display_name,
+        )
+    })?;
+
+    Ok("Passkey user updated".into())
+}
+
+/// Reads a credential's credBlob through a silent getAssertion. Returns `None` if the
+/// credential has no blob.
+pub(crate) fn read_cred_blob(
+    session: &mut DeviceSession,
+    pin: String,
+    credential: StoredCredential,
+) -> Result<Option<Vec<u8>>, PFError> {
+    if !session
+        .fido_info()?
+        .extensions
+        .iter()
+        .any(|e| e == "credBlob")
+    {
+        return Err(PFError::Device(
+            "This key does not support the credBlob extension".into(),
+        ));
+    }
+    let credential_id = hex::decode(&credential.credential_id)
+        .map_err(|_| PFError::Io("Invalid Credential ID Hex string".into()))?;
+
+    let (protocol, pin_token) = obtain_rp_pin_token(
+        session,
+        &pin,
+        PinUvAuthTokenPermissions::GET_ASSERTION,
+        Some(&credential.rp_id),
+    )?;
+    session
+        .with_hid(|t| cred_blob::read(t, protocol, &pin_token, &credential.rp_id, &credential_id))
+}
+
+/// Largest fragment of the large-blob array a single command may carry.
+fn max_fragment_length(session: &mut DeviceSession) -> Result<usize, PFError> {
+    let max_msg_size = session.fido_info()?.max_msg_size as usize;
+    Ok(max_msg_size
+        .checked_sub(64)
+        .filter(|&len| len > 0)
+        .unwrap_or(MAX_FRAGMENT_LENGTH))
+}
+
+fn require_large_blobs(session: &mut DeviceSession) -> Result<(), PFError> {
+    let supported = session.fido_info()?.options.get("largeBlobs").copied();
+    if supported != Some(true) {
+        return Err(PFError::Device(
+            "This key does not support large blobs".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads the large-blob array and attributes each entry to the stored credential whose
+/// largeBlobKey decrypts it. Reading the array needs no PIN, only the owners do.
+pub(crate) fn get_large_blobs(
+    session: &mut DeviceSession,
+    pin: String,
+) -> Result<LargeBlobArray, PFError> {
+    log::info!("Reading large-blob array...");
+    require_large_blobs(session)?;
+    let max_fragment = max_fragment_length(session)?;
+    let serialized = session.with_hid(|t| large_blobs::read_array(t, max_fragment))?;
+
+    let Some(entries) = large_blobs::parse_array(&serialized) else {
+        return Ok(LargeBlobArray {
+            entries: Vec::new(),
+            size: serialized.len(),
+            intact: false,
+        });
+    };
+    let credentials = if entries.is_empty() {
+        Vec::new()
+    } else {
+        large_blob_owners(session, &pin)?
     };
 
-    let mut all_credentials = Vec::new();
-
-    for rp in rps {
-        let creds = device
-            .credential_management_enumerate_credentials(Some(&pin), &rp.rpid_hash)
-            .map_err(|e| {
-                format!(
-                    "Failed to enumerate credentials for RP {}: {:?}",
-                    rp.public_key_credential_rp_entity.id, e
-                )
-            })?;
-
-        for cred in creds {
-            all_credentials.push(StoredCredential {
-                credential_id: hex::encode(&cred.public_key_credential_descriptor.id),
-                rp_id: rp.public_key_credential_rp_entity.id.clone(),
-                rp_name: rp.public_key_credential_rp_entity.name.clone(),
-                user_name: cred.public_key_credential_user_entity.name.clone(),
-                user_display_name: cred.public_key_credential_user_entity.display_name.clone(),
-                user_id: hex::encode(&cred.public_key_credential_user_entity.id).clone(),
-            });
+    let entries = entries
+        .iter()
+        .map(|entry| {
+            let (size, original_size) = large_blobs::entry_sizes(entry);
+            LargeBlobEntry {
+                id: hex::encode(large_blobs::entry_nonce(entry).unwrap_or_default()),
+                size,
+                original_size,
+                owner: credentials
+                    .iter()
+                    .find(|c| {
+                        c.large_blob_key
+                            .as_deref()
+                            .is_some_and(|key| large_blobs::entry_decrypts(entry, key))
+                    })
+                    .cloned(),
+            }
+        })
+        .collect();
+
+    Ok(LargeBlobArray {
+        entries,
+        size: serialized.len(),
+        intact: true,
+    })
+}
+
+/// The stored credentials, to name the owners of large-blob entries. Without credential
+/// management, or if the lookup fails, the entries are listed without owners. A PIN the key
+/// rejects is still reported, so the user can correct it.
+fn large_blob_owners(
+    session: &mut DeviceSession,
+    pin: &str,
+) -> Result<Vec<StoredCredential>, PFError> {
+    let options = &session.fido_info()?.options;
+    let supported = options.get("credMgmt").copied().unwrap_or(false)
+        && options.get("clientPin").copied().unwrap_or(false);
+    if !supported {
+        log::debug!("Key has no credential management, large blobs are shown without owners");
+        return Ok(Vec::new());
+    }
+
+    let credentials = obtain_pin_token(
+        session,
+        pin,
+        PinUvAuthTokenPermissions::CREDENTIAL_MANAGEMENT,
+    )
+    .and_then(|(protocol, pin_token)| {
+        session.with_hid(|t| credential_management::enumerate_credentials(t, protocol, &pin_token))
+    });
+    match credentials {
+        Ok(credentials) => Ok(credentials),
+        Err(e) if e.invalidates_pin() => Err(e),
+        Err(e) => {
+            log::warn!("Failed to look up large blob owners: {}", e);
+            Ok(Vec::new())
         }
     }
+}
 
-    Ok(all_credentials)
+/// Removes one entry, identified by [`LargeBlobEntry::id`], from the large-blob array.
+pub(crate) fn delete_large_blob(
+    session: &mut DeviceSession,
+    pin: String,
+    entry_id: String,
+) -> Result<String, PFError> {
+    log::info!("Deleting large-blob entry {}...", entry_id);
+    let nonce =
+        hex::decode(&entry_id).map_err(|_| PFError::Io("Invalid large blob entry ID".into()))?;
+    let max_fragment = max_fragment_length(session)?;
+    let (protocol, pin_token) =
+        obtain_pin_token(session, &pin, PinUvAuthTokenPermissions::LARGE_BLOB_WRITE)?;
+
+    session.with_hid(|t| {
+        // Re-read so entries written since the list was shown are kept.
+        let serialized = large_blobs::read_array(t, max_fragment)?;
+        let mut entries = large_blobs::parse_array(&serialized).ok_or_else(|| {
+            PFError::Device("The large-blob array is corrupt, reset it instead".into())
+        })?;
+        let index = entries
+            .iter()
+            .position(|e| large_blobs::entry_nonce(e) == Some(nonce.as_slice()))
+            .ok_or_else(|| PFError::Device("The large blob entry no longer exists".into()))?;
+        entries.remove(index);
+
+        let serialized = large_blobs::serialize_array(&entries)?;
+        large_blobs::write_array(t, protocol, &pin_token, &serialized, max_fragment)
+    })?;
+
+    Ok("Large blob deleted".into())
 }
 
-pub(crate) fn delete_credential(pin: String, credential_id_hex: String) -> Result<String, String> {
-    let device = get_device()?;
+/// Replaces the large-blob array with an empty one, dropping every entry.
+pub(crate) fn reset_large_blobs(
+    session: &mut DeviceSession,
+    pin: String,
+) -> Result<String, PFError> {
+    log::info!("Resetting large-blob array...");
+    let max_fragment = max_fragment_length(session)?;
+    let (protocol, pin_token) =
+        obtain_pin_token(session, &pin, PinUvAuthTokenPermissions::LARGE_BLOB_WRITE)?;
+
+    let empty = large_blobs::serialize_array(&[])?;
+    session
+        .with_hid(|t| large_blobs::write_array(t, protocol, &pin_token, &empty, max_fragment))?;
+
+    Ok("Large-blob array reset".into())
+}
 
-    let cred_id_bytes = hex::decode(&credential_id_hex)
-        .map_err(|_| "Invalid Credential ID Hex string".to_string())?;
+/// How long a key may take to drop off the bus after a rescue reboot.
+pub(crate) const REBOOT_GRACE: Duration = Duration::from_secs(3);
+/// How long to wait for the key to come back after a reboot or replug.
+pub(crate) const RECONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Erases every passkey and the PIN with authenticatorReset. The key only accepts the reset
+/// shortly after power-up, so it is first power cycled: through a rescue reboot if `reboot`,
+/// otherwise by the user replugging it. The session is left unlocked while waiting for that.
+pub(crate) fn reset_authenticator(
+    shared: &Mutex<DeviceSession>,
+    reboot: bool,
+    monitor: &OperationMonitor,
+) -> Result<String, PFError> {
+    {
+        let mut session = session::lock(shared);
+        if session.device().hid_path.is_none() {
+            return Err(PFError::Device(
+                "Resetting a key requires its FIDO HID interface".into(),
+            ));
+        }
+        if reboot {
+            log::info!("Rebooting {} for reset...", session.device().label());
+            rescue::reboot_device(&mut session, false)?;
+        }
+    }
 
-    let descriptor = PublicKeyCredentialDescriptor {
-        ctype: "public-key".to_string(),
-        id: cred_id_bytes,
+    let gone_within = if reboot {
+        REBOOT_GRACE
+    } else {
+        RECONNECT_TIMEOUT
     };
+    session::wait_for_reconnect(shared, gone_within, RECONNECT_TIMEOUT, monitor)?;
+
+    let mut session = session::lock(shared);
+    log::info!("Sending authenticatorReset, waiting for touch...");
+    let result = session.with_hid_monitored(monitor, |t| {
+        t.send_cbor(CTAPHID_CBOR, &[CtapCommand::Reset as u8])
+    });
+    // The reset also drops authentication encryption and every PIN setting.
+    session.invalidate_fido_info();
+
+    match result {
+        Ok(_) => {
+            log::info!("Authenticator reset");
+            Ok("The key was reset. All passkeys and the PIN have been erased.".into())
+        }
+        Err(e) if e.ctap_error() == Some(Ctap2Error::NotAllowed) => {
+            log::error!("Reset refused, power-up window has passed");
+            Err(PFError::Device(format!(
+                "The key only accepts a reset within {} seconds of being plugged in, and that \
+                 time had passed. Try again and touch the key as soon as it asks.",
+                RESET_WINDOW_SECS
+            )))
+        }
+        Err(e)
+            if matches!(
+                e.ctap_error(),
+                Some(Ctap2Error::UserActionTimeout | Ctap2Error::ActionTimeout)
+            ) =>
+        {
+            log::error!("Reset timed out waiting for touch");
+            Err(PFError::Device(
+                "The key was not touched in time. Nothing was erased.".into(),
+            ))
+        }
+        Err(e) => Err(e),
+    }
+}
 
-    device
-        .credential_management_delete_credential(Some(&pin), descriptor)
-        .map_err(|e| format!("Failed to delete credential: {:?}", e))?;
+/// Makes the key identify itself: blinks it with `CTAPHID_WINK` if supported, otherwise sends
+/// authenticatorSelection and waits for the user to touch it.
+pub fn identify(
+    session: &mut DeviceSession,
+    monitor: &OperationMonitor,
+) -> Result<String, PFError> {
+    if session.device().hid_path.is_none() {
+        return Err(PFError::Device(
+            "Identifying a key requires its FIDO HID interface".into(),
+        ));
+    }
 
-    Ok("Credential deleted successfully".into())
+    if session.with_hid(|t| t.wink())? {
+        log::info!("Sent CTAPHID_WINK to {}", session.device().label());
+        return Ok("The key's LED is blinking.".into());
+    }
+
+    log::info!("Falling back to authenticatorSelection, waiting for touch...");
+    session.with_hid_monitored(monitor, |t| {
+        t.send_cbor(CTAPHID_CBOR, &[CtapCommand::Selection as u8])
+    })?;
+    Ok("You touched this key.".into())
+}
+
+/// Runs the CTAPHID diagnostics on a channel of its own. The session's channel is closed first
+/// so no other handle competes for the responses.
+pub fn run_diagnostics(
+    session: &mut DeviceSession,
+) -> Result<diagnostics::DiagnosticsReport, PFError> {
+    let device = session.device().clone();
+    if device.hid_path.is_none() {
+        return Err(PFError::Device(
+            "Selected device has no FIDO HID interface".into(),
+        ));
+    }
+
+    session.disconnect();
+    let transport = open_transport(&device)?;
+    Ok(diagnostics::run(transport.as_ref(), &device))
 }
 
 // Custom Fido functions ( works only with pico-fido firmware )
 
-pub fn read_device_details() -> Result<FullDeviceStatus, PFError> {
+pub fn read_device_details(session: &mut DeviceSession) -> Result<FullDeviceStatus, PFError> {
     log::info!("Starting FIDO device details read...");
 
-    let transport = HidTransport::open().map_err(|e| {
-        if matches!(e, PFError::NoDevice) {
-            PFError::NoDevice
-        } else {
-            log::error!("Failed to open HID transport: {}", e);
-            PFError::Device(e.to_string())
+    session.with_hid(|t| read_status(t)).inspect_err(|e| {
+        if !matches!(e, PFError::NoDevice) {
+            log::error!("Failed to read FIDO device details: {}", e);
         }
-    })?;
+    })
+}
 
-    let (aaguid_str, fw_version) = read_device_info(&transport)?;
+/// Reads firmware version, flash usage and the physical options through the pico-fido vendor
+/// commands.
+pub fn read_status<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+) -> Result<FullDeviceStatus, PFError> {
+    let (aaguid_str, fw_version) = read_device_info(transport)?;
 
     log::info!(
         "Device identified: AAGUID={}, FW={}",
@@ -201,14 +869,14 @@ pub fn read_device_details() -> Result<FullDeviceStatus, PFError> {
         fw_version
     );
 
-    let (used, total) = read_memory_stats(&transport)?;
+    let (used, total) = read_memory_stats(transport)?;
     log::debug!(
         "Memory Stats: Used={}KB, Total={}KB",
         used / 1024,
         total / 1024
     );
 
-    let config = read_physical_config(&transport)?;
+    let config = read_physical_config(transport)?;
 
     log::info!("Successfully read all device details.");
 
@@ -226,15 +894,14 @@ pub fn read_device_details() -> Result<FullDeviceStatus, PFError> {
     })
 }
 
-fn read_device_info(transport: &HidTransport) -> Result<(String, String), PFError> {
+fn read_device_info<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+) -> Result<(String, String), PFError> {
     log::debug!("Sending GetInfo command (0x04)...");
     let info_payload = [CtapCommand::GetInfo as u8];
     let info_res = transport
         .send_cbor(CTAPHID_CBOR, &info_payload)
-        .map_err(|e| {
-            log::error!("GetInfo CTAP command failed: {}", e);
-            PFError::Device(format!("GetInfo failed: {}", e))
-        })?;
+        .inspect_err(|e| log::error!("GetInfo CTAP command failed: {}", e))?;
 
     log::debug!("GetInfo response received ({} bytes)", info_res.len());
 
@@ -281,7 +948,7 @@ fn read_device_info(transport: &HidTransport) -> Result<(String, String), PFErro
     Ok((aaguid_str, fw_version))
 }
 
-fn read_memory_stats(transport: &HidTransport) -> Result<(u32, u32), PFError> {
+fn read_memory_stats<T: CtapHidTransport + ?Sized>(transport: &T) -> Result<(u32, u32), PFError> {
     log::debug!("Preparing Memory Stats vendor command...");
 
     let mut mem_req = BTreeMap::new();
@@ -301,10 +968,7 @@ fn read_memory_stats(transport: &HidTransport) -> Result<(u32, u32), PFError> {
     log::debug!("Sending Memory Stats command...");
     let mem_res = transport
         .send_cbor(CTAP_VENDOR_CBOR_CMD, &mem_payload)
-        .map_err(|e| {
-            log::warn!("Failed to fetch memory stats (Vendor Cmd): {}", e);
-            PFError::Device(format!("Failed to fetch memory stats: {}", e))
-        })?;
+        .inspect_err(|e| log::warn!("Failed to fetch memory stats (Vendor Cmd): {}", e))?;
 
     let mem_map: BTreeMap<i128, i128> = if !mem_res.is_empty() {
         from_slice(&mem_res).map_err(|e| {
@@ -327,7 +991,7 @@ fn read_memory_stats(transport: &HidTransport) -> Result<(u32, u32), PFError> {
     Ok((used, total))
 }
 
-fn read_physical_config(transport: &HidTransport) -> Result<AppConfig, PFError> {
+fn read_physical_config<T: CtapHidTransport + ?Sized>(transport: &T) -> Result<AppConfig, PFError> {
     log::debug!("Preparing Physical Config vendor command...");
 
     // FIX: Only arguments in CBOR map
@@ -354,9 +1018,9 @@ fn read_physical_config(transport: &HidTransport) -> Result<AppConfig, PFError>
         });
 
     let mut config = AppConfig {
-        vid: format!("{:04X}", transport.vid),
-        pid: format!("{:04X}", transport.pid),
-        product_name: transport.product_name.clone(),
+        vid: format!("{:04X}", transport.vid()),
+        pid: format!("{:04X}", transport.pid()),
+        product_name: transport.product_name().to_string(),
         ..Default::default()
     };
 
@@ -382,7 +1046,12 @@ fn read_physical_config(transport: &HidTransport) -> Result<AppConfig, PFError>
     Ok(config)
 }
 
-pub fn write_config(config: AppConfigInput, pin: Option<String>) -> Result<String, PFError> {
+pub fn write_config(
+    session: &mut DeviceSession,
+    config: AppConfigInput,
+    pin: Option<String>,
+    monitor: &OperationMonitor,
+) -> Result<String, PFError> {
     log::info!("Starting FIDO write_config...");
 
     let pin_val = pin.as_deref().ok_or_else(|| {
@@ -392,48 +1061,36 @@ pub fn write_config(config: AppConfigInput, pin: Option<String>) -> Result<Strin
         )
     })?;
 
-    // 1. Obtain PIN token using the library handle
-    let pin_token = {
-        let device = get_device().map_err(PFError::Device)?;
-
-        // Try to obtain a token with AuthenticatorConfiguration permission (CTAP 2.1)
-        match device
-            .get_pinuv_auth_token_with_permission(pin_val, Permission::AuthenticatorConfiguration)
-        {
-            Ok(token) => {
-                log::debug!("Successfully obtained PIN token with ACFG permission.");
-                token.key
-            }
-            Err(e) => {
-                log::warn!(
-                    "Failed to get PIN token with ACFG permission (Error: {:?}). Falling back to standard token.",
-                    e
-                );
-                // Fallback to standard PIN token (Subcommand 0x05)
-                let token = device.get_pin_token(pin_val).map_err(|e2| {
-                    log::error!("Failed to obtain even a standard PIN token: {:?}", e2);
-                    PFError::Device(format!("PIN token acquisition failed: {:?}", e2))
-                })?;
-                log::debug!("Successfully obtained standard PIN token (fallback).");
-                token.key
-            }
-        }
-        // Library handle 'device' is dropped here, closing the HID session.
-    };
+    // 1. Obtain a PIN token with the authenticatorConfiguration permission
+    let (protocol, pin_token) = obtain_pin_token(
+        session,
+        pin_val,
+        PinUvAuthTokenPermissions::AUTHENTICATOR_CONFIG,
+    )?;
 
-    // 2. Open custom HidTransport and send vendor commands using the token
-    let transport = HidTransport::open().map_err(|e| {
-        log::error!("Failed to open HID transport: {}", e);
-        PFError::Device(format!("Could not open HID transport: {}", e))
-    })?;
+    // 2. Send vendor commands over the same HID channel using the token
+    session.with_hid_monitored(monitor, |t| {
+        apply_config(t, protocol, &pin_token, config.clone())
+    })
+}
 
+/// Sends the given changes as pico-fido vendor config commands, authenticated with a PIN token
+/// that carries the authenticatorConfiguration permission.
+pub fn apply_config<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+    config: AppConfigInput,
+) -> Result<String, PFError> {
     // VID/PID config
     if let (Some(vid_str), Some(pid_str)) = (&config.vid, &config.pid) {
         let vid = u16::from_str_radix(vid_str, 16).map_err(|e| PFError::Io(e.to_string()))?;
         let pid = u16::from_str_radix(pid_str, 16).map_err(|e| PFError::Io(e.to_string()))?;
         let vidpid = ((vid as u32) << 16) | (pid as u32);
-        transport.send_vendor_config(
-            &pin_token,
+        config::send_vendor_config(
+            transport,
+            protocol,
+            pin_token,
             VendorConfigCommand::PhysicalVidPid,
             Value::Integer(vidpid as i128),
         )?;
@@ -443,8 +1100,10 @@ pub fn write_config(config: AppConfigInput, pin: Option<String>) -> Result<Strin
 
     // LED GPIO config
     if let Some(gpio) = config.led_gpio {
-        transport.send_vendor_config(
-            &pin_token,
+        config::send_vendor_config(
+            transport,
+            protocol,
+            pin_token,
             VendorConfigCommand::PhysicalLedGpio,
             Value::Integer(gpio as i128),
         )?;
@@ -454,8 +1113,10 @@ pub fn write_config(config: AppConfigInput, pin: Option<String>) -> Result<Strin
 
     // LED brightness config
     if let Some(brightness) = config.led_brightness {
-        transport.send_vendor_config(
-            &pin_token,
+        config::send_vendor_config(
+            transport,
+            protocol,
+            pin_token,
             VendorConfigCommand::PhysicalLedBrightness,
             Value::Integer(brightness as i128),
         )?;
@@ -476,19 +1137,22 @@ pub fn write_config(config: AppConfigInput, pin: Option<String>) -> Result<Strin
     }
     // Touch_timeout config
     if let Some(timeout) = config.touch_timeout {
-        transport
-            .send_vendor_config(
-                &pin_token,
-                VendorConfigCommand::PhysicalOptions,
-                Value::Integer(timeout as i128),
-            )
-            .ok();
+        config::send_vendor_config(
+            transport,
+            protocol,
+            pin_token,
+            VendorConfigCommand::PhysicalOptions,
+            Value::Integer(timeout as i128),
+        )
+        .ok();
     } else {
         log::info!("Touch timeout configuration not provided, skipping update.");
     }
 
-    transport.send_vendor_config(
-        &pin_token,
+    config::send_vendor_config(
+        transport,
+        protocol,
+        pin_token,
         VendorConfigCommand::PhysicalOptions,
         Value::Integer(opts as i128),
     )?;
@@ -500,3 +1164,128 @@ pub fn write_config(config: AppConfigInput, pin: Option<String>) -> Result<Strin
       .to_string(),
   )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::rescue;
+    use crate::device::simulator::{DEMO_PIN, SimulatedHid, Simulator};
+    use crate::device::transport::scripted::ScriptedCtapHidTransport;
+
+    fn session(sim: &Simulator) -> DeviceSession {
+        DeviceSession::scripted(sim.device_handle(), None, Some(Box::new(sim.open_hid())))
+    }
+
+    /// Reads the secret of the simulated key as recovery words.
+    fn backup_words(sim: &Simulator) -> String {
+        get_backup_words(&mut session(sim), DEMO_PIN.into())
+            .unwrap()
+            .join(" ")
+    }
+
+    #[test]
+    fn restore_requires_the_pin_of_a_protected_key() {
+        let source = Simulator::new();
+        let target = Simulator::new();
+        let words = backup_words(&source);
+        let original = target.device_secret();
+        // A nonce, the 32 byte secret and a tag, too long for a plain BIP-39 mnemonic
+        assert_eq!(words.split(' ').count(), 1 + 44 + 1);
+
+        let err = restore_backup(&mut session(&target), words.clone(), None).unwrap_err();
+        assert_eq!(err.ctap_error(), Some(Ctap2Error::PuatRequired));
+        let err = restore_backup(&mut session(&target), words.clone(), Some("000000".into()))
+            .unwrap_err();
+        assert_eq!(err.ctap_error(), Some(Ctap2Error::PinInvalid));
+        assert_eq!(target.device_secret(), original);
+
+        restore_backup(&mut session(&target), words.clone(), Some(DEMO_PIN.into())).unwrap();
+        assert_eq!(target.device_secret(), source.device_secret());
+    }
+
+    #[test]
+    fn backup_needs_the_right_pin() {
+        let sim = Simulator::new();
+        let err = get_backup_words(&mut session(&sim), "000000".into()).unwrap_err();
+        assert_eq!(err.ctap_error(), Some(Ctap2Error::PinInvalid));
+    }
+
+    #[test]
+    fn leaves_keys_without_locked_encryption_alone() {
+        let mut info = read_fido_info(&Simulator::new().open_hid()).unwrap();
+        // Nothing may be sent
+        let hid = ScriptedCtapHidTransport::new(0xCAFE, 0x4242, "Scripted Key");
+        unlock_auth_encryption(&hid, &mut info).unwrap();
+        assert!(!info.auth_encryption_locked);
+    }
+
+    #[test]
+    fn reads_the_encryption_state_from_get_info() {
+        let sim = Simulator::new();
+        let hid = sim.open_hid();
+        assert!(!read_fido_info(&hid).unwrap().auth_encryption);
+
+        let protocol = PinUvAuthProtocol::Two;
+        let token = client_pin::get_pin_token(&hid, protocol, DEMO_PIN).unwrap();
+        auth_encryption::enable(&hid, protocol, &token, &[7; 32]).unwrap();
+        let info = read_fido_info(&hid).unwrap();
+        assert!(info.auth_encryption && !info.auth_encryption_locked);
+
+        rescue::reboot(&sim, false).unwrap();
+        let info = read_fido_info(&hid).unwrap();
+        assert!(info.auth_encryption && info.auth_encryption_locked);
+    }
+
+    /// Forwards to the simulator but refuses credential management, like a key without it.
+    struct WithoutCredentialManagement(SimulatedHid);
+
+    impl CtapHidTransport for WithoutCredentialManagement {
+        fn send_cbor(&self, cmd: u8, payload: &[u8]) -> Result<Vec<u8>, PFError> {
+            if payload.first() == Some(&(CtapCommand::CredentialMgmt as u8)) {
+                return Err(PFError::Ctap(Ctap2Error::InvalidCommand));
+            }
+            self.0.send_cbor(cmd, payload)
+        }
+        fn vid(&self) -> u16 {
+            self.0.vid()
+        }
+        fn pid(&self) -> u16 {
+            self.0.pid()
+        }
+        fn product_name(&self) -> &str {
+            self.0.product_name()
+        }
+        fn cid(&self) -> u32 {
+            self.0.cid()
+        }
+        fn allocate_channel(&self) -> Result<u32, PFError> {
+            self.0.allocate_channel()
+        }
+        fn ping_on(&self, cid: u32, payload: &[u8]) -> Result<Vec<u8>, PFError> {
+            self.0.ping_on(cid, payload)
+        }
+    }
+
+    #[test]
+    fn names_the_owners_of_large_blobs() {
+        let sim = Simulator::new();
+        let array = get_large_blobs(&mut session(&sim), DEMO_PIN.into()).unwrap();
+        assert!(array.intact);
+        assert_eq!(array.entries.len(), 2);
+        assert!(array.entries[0].owner.is_some());
+        assert!(array.entries[1].owner.is_none());
+
+        let err = get_large_blobs(&mut session(&sim), "000000".into()).unwrap_err();
+        assert_eq!(err.ctap_error(), Some(Ctap2Error::PinInvalid));
+    }
+
+    #[test]
+    fn lists_large_blobs_when_the_owners_cannot_be_looked_up() {
+        let sim = Simulator::new();
+        let hid = WithoutCredentialManagement(sim.open_hid());
+        let mut session = DeviceSession::scripted(sim.device_handle(), None, Some(Box::new(hid)));
+        let array = get_large_blobs(&mut session, DEMO_PIN.into()).unwrap();
+        assert_eq!(array.entries.len(), 2);
+        assert!(array.entries.iter().all(|entry| entry.owner.is_none()));
+    }
+}
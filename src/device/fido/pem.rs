@@ -0,0 +1,102 @@
+//! PEM armour for the DER blobs users exchange with their own tooling (CSRs, certificates).
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use crate::error::PFError;
+
+/// Wraps `der` in a PEM block with the given label, e.g. `CERTIFICATE REQUEST`.
+pub fn encode(label: &str, der: &[u8]) -> String {
+    let body = STANDARD.encode(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in body.as_bytes().chunks(64) {
+        pem.extend(line.iter().map(|&b| b as char));
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+/// Reads a file the user picked, which may hold either a PEM block with the given label or
+/// the raw DER encoding.
+pub fn decode_pem_or_der(label: &str, data: &[u8]) -> Result<Vec<u8>, PFError> {
+    let text = std::str::from_utf8(data)
+        .ok()
+        .filter(|t| t.contains("-----BEGIN "));
+    let Some(text) = text else {
+        // DER structures we accept are all SEQUENCEs
+        return match data.first() {
+            Some(0x30) => Ok(data.to_vec()),
+            _ => Err(PFError::Io(format!(
+                "File is neither PEM nor DER encoded {}",
+                label.to_lowercase()
+            ))),
+        };
+    };
+
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+    let start = text
+        .find(&begin)
+        .map(|i| i + begin.len())
+        .ok_or_else(|| PFError::Io(format!("PEM file does not contain a {}", label)))?;
+    let stop = text[start..]
+        .find(&end)
+        .ok_or_else(|| PFError::Io(format!("PEM block is missing \"{}\"", end)))?;
+
+    let body: String = text[start..start + stop]
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    STANDARD
+        .decode(body)
+        .map_err(|e| PFError::Io(format!("Invalid PEM encoding: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LABEL: &str = "CERTIFICATE";
+
+    #[test]
+    fn round_trips_through_pem() {
+        let der: Vec<u8> = std::iter::once(0x30).chain(0..=200).collect();
+        let pem = encode(LABEL, &der);
+
+        assert!(pem.starts_with("-----BEGIN CERTIFICATE-----\n"));
+        assert!(pem.ends_with("-----END CERTIFICATE-----\n"));
+        assert!(pem.lines().all(|line| line.len() <= 64));
+        assert_eq!(decode_pem_or_der(LABEL, pem.as_bytes()).unwrap(), der);
+    }
+
+    #[test]
+    fn decodes_pem_with_surrounding_text_and_crlf() {
+        let pem = "Subject: test\r\n-----BEGIN CERTIFICATE-----\r\nMAMCAQE=\r\n-----END CERTIFICATE-----\r\n";
+        assert_eq!(
+            decode_pem_or_der(LABEL, pem.as_bytes()).unwrap(),
+            [0x30, 0x03, 0x02, 0x01, 0x01]
+        );
+    }
+
+    #[test]
+    fn passes_der_through() {
+        let der = [0x30, 0x03, 0x02, 0x01, 0x01];
+        assert_eq!(decode_pem_or_der(LABEL, &der).unwrap(), der);
+    }
+
+    #[test]
+    fn rejects_other_input() {
+        // Neither a SEQUENCE nor PEM
+        assert!(decode_pem_or_der(LABEL, &[0x04, 0x00]).is_err());
+        assert!(decode_pem_or_der(LABEL, &[]).is_err());
+
+        let other = encode("CERTIFICATE REQUEST", &[0x30, 0x00]);
+        assert!(decode_pem_or_der(LABEL, other.as_bytes()).is_err());
+
+        let unterminated = "-----BEGIN CERTIFICATE-----\nMAA=\n";
+        assert!(decode_pem_or_der(LABEL, unterminated.as_bytes()).is_err());
+
+        let bad_base64 = "-----BEGIN CERTIFICATE-----\nMA*=\n-----END CERTIFICATE-----\n";
+        assert!(decode_pem_or_der(LABEL, bad_base64.as_bytes()).is_err());
+    }
+}
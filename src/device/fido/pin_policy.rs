@@ -0,0 +1,168 @@
+//! PIN complexity policy enforced by pico-fido whenever a PIN is set or changed.
+//!
+//! The policy is sent as the integer parameter of the `PinComplexityPolicy` vendor config
+//! command, and the key reports the rules it enforces in GetInfo `pinComplexityPolicy` (0x1B).
+//! PicoForge checks the same rules before sending a new PIN, so users see which rule a PIN
+//! breaks instead of a bare PIN_POLICY_VIOLATION.
+
+use serde_cbor_2::Value;
+
+use crate::device::fido::config;
+use crate::device::fido::constants::*;
+use crate::device::fido::pin::PinUvAuthProtocol;
+use crate::device::transport::CtapHidTransport;
+use crate::error::PFError;
+
+/// Length of the runs the sequence and repetition rules reject.
+const MAX_RUN: usize = 3;
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct PinComplexityPolicy: u8 {
+        /// No three ascending or descending characters in a row (`123`, `cba`).
+        const NO_SEQUENCES = 0x01;
+        /// No character three times in a row (`111`).
+        const NO_REPEATS = 0x02;
+        /// At least two of digits, letters and symbols.
+        const MIXED_CLASSES = 0x04;
+    }
+}
+
+impl PinComplexityPolicy {
+    /// Short name and explanation of each rule, for the policy editor.
+    pub fn describe(rule: Self) -> (&'static str, &'static str) {
+        match rule {
+            Self::NO_SEQUENCES => ("No Sequences", "Reject runs like 123, 987 or abc"),
+            Self::NO_REPEATS => ("No Repeats", "Reject runs like 111 or aaa"),
+            Self::MIXED_CLASSES => (
+                "Mixed Characters",
+                "Require at least two of digits, letters and symbols",
+            ),
+            _ => ("Unknown Rule", ""),
+        }
+    }
+
+    /// The first rule `pin` breaks, as a message for the user.
+    pub fn violation(self, pin: &str) -> Option<&'static str> {
+        let chars: Vec<char> = pin.chars().collect();
+        let runs = || chars.windows(MAX_RUN);
+
+        if self.contains(Self::NO_SEQUENCES)
+            && runs().any(|w| {
+                let step = |d: i64| w.windows(2).all(|p| p[1] as i64 - p[0] as i64 == d);
+                step(1) || step(-1)
+            })
+        {
+            return Some("PIN must not contain sequences like 123 or cba");
+        }
+
+        if self.contains(Self::NO_REPEATS) && runs().any(|w| w.iter().all(|&c| c == w[0])) {
+            return Some("PIN must not repeat a character three times in a row");
+        }
+
+        if self.contains(Self::MIXED_CLASSES) {
+            let classes = [
+                chars.iter().any(|c| c.is_numeric()),
+                chars.iter().any(|c| c.is_alphabetic()),
+                chars.iter().any(|c| !c.is_alphanumeric()),
+            ];
+            if classes.iter().filter(|&&present| present).count() < 2 {
+                return Some("PIN must mix at least two of digits, letters and symbols");
+            }
+        }
+
+        None
+    }
+}
+
+/// Replaces the policy the key enforces on new PINs.
+pub fn set_policy<T: CtapHidTransport + ?Sized>(
+    transport: &T,
+    protocol: PinUvAuthProtocol,
+    pin_token: &[u8],
+    policy: PinComplexityPolicy,
+) -> Result<(), PFError> {
+    log::debug!("Setting PIN complexity policy to {:?}...", policy);
+    config::send_vendor_config(
+        transport,
+        protocol,
+        pin_token,
+        VendorConfigCommand::PinComplexityPolicy,
+        Value::Integer(policy.bits() as i128),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_policy_allows_anything() {
+        let policy = PinComplexityPolicy::empty();
+        for pin in ["1234", "1111", "aaaa", ""] {
+            assert_eq!(policy.violation(pin), None);
+        }
+    }
+
+    #[test]
+    fn rejects_ascending_and_descending_runs() {
+        let policy = PinComplexityPolicy::NO_SEQUENCES;
+        assert!(policy.violation("90123").is_some());
+        assert!(policy.violation("x987").is_some());
+        assert!(policy.violation("pabc").is_some());
+        assert_eq!(policy.violation("1357"), None);
+        assert_eq!(policy.violation("12a34"), None);
+        assert_eq!(policy.violation("12"), None);
+    }
+
+    #[test]
+    fn rejects_repeated_characters() {
+        let policy = PinComplexityPolicy::NO_REPEATS;
+        assert!(policy.violation("40001").is_some());
+        assert_eq!(policy.violation("400100"), None);
+    }
+
+    #[test]
+    fn requires_two_character_classes() {
+        let policy = PinComplexityPolicy::MIXED_CLASSES;
+        assert!(policy.violation("482915").is_some());
+        assert!(policy.violation("secret").is_some());
+        assert!(policy.violation("#!%&").is_some());
+        assert_eq!(policy.violation("secret42"), None);
+        assert_eq!(policy.violation("4829#"), None);
+    }
+
+    #[test]
+    fn reports_the_first_rule_broken() {
+        let policy = PinComplexityPolicy::all();
+        assert_eq!(
+            policy.violation("1234"),
+            Some("PIN must not contain sequences like 123 or cba")
+        );
+        assert_eq!(
+            policy.violation("1111"),
+            Some("PIN must not repeat a character three times in a row")
+        );
+        assert_eq!(
+            policy.violation("1357"),
+            Some("PIN must mix at least two of digits, letters and symbols")
+        );
+        assert_eq!(policy.violation("13x57"), None);
+    }
+
+    #[test]
+    fn works_on_characters_not_bytes() {
+        let policy = PinComplexityPolicy::all();
+        // U+03B1..U+03B3, consecutive code points
+        assert!(policy.violation("7αβγ").is_some());
+        assert!(policy.violation("7ééé").is_some());
+        // Non-ASCII letters count as letters, and é (C3 A9) is not a run of bytes
+        assert_eq!(policy.violation("ñandú42"), None);
+        assert_eq!(policy.violation("é1é2"), None);
+        assert!(
+            PinComplexityPolicy::MIXED_CLASSES
+                .violation("ñandú")
+                .is_some()
+        );
+    }
+}
@@ -1,60 +1,341 @@
 //! Tauri Commands to interact with the pico-fido firmware via rescue and fido protocols.
 #![allow(unused)]
 
-use crate::{device::fido, device::rescue, device::types::*, error::PFError};
+use crate::{
+    device::discovery, device::fido, device::rescue, device::session,
+    device::session::DeviceSession, device::transport::OperationMonitor, device::types::*,
+    error::PFError,
+};
 
-pub fn read_device_details() -> Result<FullDeviceStatus, PFError> {
-    match rescue::read_device_details() {
+pub fn list_devices() -> Result<Vec<DeviceHandle>, PFError> {
+    let devices = discovery::list_devices()?;
+    session::retain(&devices);
+    Ok(devices)
+}
+
+pub fn read_device_details(device: &DeviceHandle) -> Result<FullDeviceStatus, PFError> {
+    read_session_details(&mut session::lock(&session::get(device)))
+}
+
+/// Reads the status through the Rescue Applet, or over FIDO if the key does not answer it.
+fn read_session_details(session: &mut DeviceSession) -> Result<FullDeviceStatus, PFError> {
+    match rescue::read_device_details(session) {
         Ok(status) => Ok(status),
         Err(e) => {
             log::warn!("Rescue method failed: {}. Falling back to FIDO...", e);
-            fido::read_device_details()
+            fido::read_device_details(session)
         }
     }
 }
 
 pub fn write_config(
+    device: &DeviceHandle,
     config: AppConfigInput,
     method: DeviceMethod,
     pin: Option<String>,
+    monitor: &OperationMonitor,
 ) -> Result<String, PFError> {
+    let _running = monitor.track();
+    let session = session::get(device);
+    let mut session = session::lock(&session);
     if method == DeviceMethod::Fido {
-        fido::write_config(config, pin)
+        fido::write_config(&mut session, config, pin, monitor)
     } else {
-        rescue::write_config(config)
+        rescue::write_config(&mut session, config)
     }
 }
 
-pub fn enable_secure_boot(lock: bool) -> Result<String, PFError> {
-    rescue::enable_secure_boot(lock)
+pub fn enable_secure_boot(device: &DeviceHandle, lock: bool) -> Result<String, PFError> {
+    rescue::enable_secure_boot(&mut session::lock(&session::get(device)), lock)
 }
 
-pub(crate) fn get_fido_info() -> Result<FidoDeviceInfo, String> {
-    fido::get_fido_info()
+pub(crate) fn get_fido_info(device: &DeviceHandle) -> Result<FidoDeviceInfo, PFError> {
+    fido::get_fido_info(&mut session::lock(&session::get(device)))
 }
 
 pub(crate) fn change_fido_pin(
+    device: &DeviceHandle,
     current_pin: Option<String>,
     new_pin: String,
-) -> Result<String, String> {
-    fido::change_fido_pin(current_pin, new_pin)
+) -> Result<String, PFError> {
+    fido::change_fido_pin(
+        &mut session::lock(&session::get(device)),
+        current_pin,
+        new_pin,
+    )
 }
 
 pub(crate) fn set_min_pin_length(
+    device: &DeviceHandle,
     current_pin: String,
     min_pin_length: u8,
-) -> Result<String, String> {
-    fido::set_min_pin_length(current_pin, min_pin_length)
+) -> Result<String, PFError> {
+    fido::set_min_pin_length(
+        &mut session::lock(&session::get(device)),
+        current_pin,
+        min_pin_length,
+    )
+}
+
+pub(crate) fn set_pin_complexity_policy(
+    device: &DeviceHandle,
+    pin: String,
+    policy: fido::pin_policy::PinComplexityPolicy,
+) -> Result<String, PFError> {
+    fido::set_pin_complexity_policy(&mut session::lock(&session::get(device)), pin, policy)
+}
+
+pub(crate) fn toggle_always_uv(device: &DeviceHandle, pin: String) -> Result<bool, PFError> {
+    fido::toggle_always_uv(&mut session::lock(&session::get(device)), pin)
+}
+
+pub(crate) fn set_auth_encryption(
+    device: &DeviceHandle,
+    pin: String,
+    enabled: bool,
+) -> Result<bool, PFError> {
+    fido::set_auth_encryption(&mut session::lock(&session::get(device)), pin, enabled)
 }
 
-pub fn reboot(to_bootsel: bool) -> Result<String, PFError> {
-    rescue::reboot_device(to_bootsel)
+pub(crate) fn get_backup_words(device: &DeviceHandle, pin: String) -> Result<Vec<String>, PFError> {
+    fido::get_backup_words(&mut session::lock(&session::get(device)), pin)
 }
 
-pub fn get_credentials(pin: String) -> Result<Vec<StoredCredential>, String> {
-    fido::get_credentials(pin)
+pub(crate) fn restore_backup(
+    device: &DeviceHandle,
+    words: String,
+    pin: Option<String>,
+) -> Result<String, PFError> {
+    fido::restore_backup(&mut session::lock(&session::get(device)), words, pin)
+}
+
+pub(crate) fn generate_attestation_csr(device: &DeviceHandle) -> Result<Vec<u8>, PFError> {
+    fido::generate_attestation_csr(&mut session::lock(&session::get(device)))
+}
+
+pub(crate) fn provision_enterprise_attestation(
+    device: &DeviceHandle,
+    pin: String,
+    certificate: Vec<u8>,
+) -> Result<String, PFError> {
+    fido::provision_enterprise_attestation(
+        &mut session::lock(&session::get(device)),
+        pin,
+        certificate,
+    )
+}
+
+pub fn identify(device: &DeviceHandle, monitor: &OperationMonitor) -> Result<String, PFError> {
+    let _running = monitor.track();
+    fido::identify(&mut session::lock(&session::get(device)), monitor)
+}
+
+pub fn run_diagnostics(
+    device: &DeviceHandle,
+) -> Result<fido::diagnostics::DiagnosticsReport, PFError> {
+    fido::run_diagnostics(&mut session::lock(&session::get(device)))
+}
+
+/// Reboots the key and returns once it is back, so hot-plug refreshes do not run while it is
+/// re-enumerating. In BOOTSEL, or without a FIDO interface to wait for, it only waits for the
+/// key to drop off the bus.
+pub fn reboot(
+    device: &DeviceHandle,
+    to_bootsel: bool,
+    monitor: &OperationMonitor,
+) -> Result<String, PFError> {
+    let _running = monitor.track();
+    let shared = session::get(device);
+    let message = rescue::reboot_device(&mut session::lock(&shared), to_bootsel)?;
+    if to_bootsel || device.hid_path.is_none() {
+        std::thread::sleep(fido::REBOOT_GRACE);
+    } else if let Err(e) = session::wait_for_reconnect(
+        &shared,
+        fido::REBOOT_GRACE,
+        fido::RECONNECT_TIMEOUT,
+        monitor,
+    ) {
+        // The reboot itself went through, the next refresh shows whether the key is back.
+        log::warn!(
+            "{} did not come back after rebooting: {}",
+            device.label(),
+            e
+        );
+    }
+    Ok(message)
+}
+
+pub(crate) fn reset_authenticator(
+    device: &DeviceHandle,
+    reboot: bool,
+    monitor: &OperationMonitor,
+) -> Result<String, PFError> {
+    let _running = monitor.track();
+    fido::reset_authenticator(&session::get(device), reboot, monitor)
 }
 
-pub fn delete_credential(pin: String, credential_id: String) -> Result<String, String> {
-    fido::delete_credential(pin, credential_id)
+pub fn get_credentials(
+    device: &DeviceHandle,
+    pin: String,
+) -> Result<Vec<StoredCredential>, PFError> {
+    fido::get_credentials(&mut session::lock(&session::get(device)), pin)
+}
+
+pub fn delete_credential(
+    device: &DeviceHandle,
+    pin: String,
+    credential_id: String,
+) -> Result<String, PFError> {
+    fido::delete_credential(
+        &mut session::lock(&session::get(device)),
+        pin,
+        credential_id,
+    )
+}
+
+pub(crate) fn delete_credentials(
+    device: &DeviceHandle,
+    pin: String,
+    credentials: Vec<StoredCredential>,
+    monitor: &OperationMonitor,
+) -> Result<BulkDeleteReport, PFError> {
+    let _running = monitor.track();
+    fido::delete_credentials(
+        &mut session::lock(&session::get(device)),
+        pin,
+        credentials,
+        monitor,
+    )
+}
+
+pub(crate) fn update_credential_user(
+    device: &DeviceHandle,
+    pin: String,
+    credential: StoredCredential,
+    user_name: String,
+    user_display_name: String,
+) -> Result<String, PFError> {
+    fido::update_credential_user(
+        &mut session::lock(&session::get(device)),
+        pin,
+        credential,
+        user_name,
+        user_display_name,
+    )
+}
+
+pub(crate) fn read_cred_blob(
+    device: &DeviceHandle,
+    pin: String,
+    credential: StoredCredential,
+) -> Result<Option<Vec<u8>>, PFError> {
+    fido::read_cred_blob(&mut session::lock(&session::get(device)), pin, credential)
+}
+
+pub(crate) fn get_large_blobs(
+    device: &DeviceHandle,
+    pin: String,
+) -> Result<LargeBlobArray, PFError> {
+    fido::get_large_blobs(&mut session::lock(&session::get(device)), pin)
+}
+
+pub(crate) fn delete_large_blob(
+    device: &DeviceHandle,
+    pin: String,
+    entry_id: String,
+) -> Result<String, PFError> {
+    fido::delete_large_blob(&mut session::lock(&session::get(device)), pin, entry_id)
+}
+
+pub(crate) fn reset_large_blobs(device: &DeviceHandle, pin: String) -> Result<String, PFError> {
+    fido::reset_large_blobs(&mut session::lock(&session::get(device)), pin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::fido::constants::CTAP_VENDOR_CBOR_CMD;
+    use crate::device::fido::hid::CTAPHID_CBOR;
+    use crate::device::transport::scripted::{ScriptedApduTransport, ScriptedCtapHidTransport};
+    use serde_cbor_2::{Value, to_vec};
+    use std::collections::BTreeMap;
+
+    fn device() -> DeviceHandle {
+        DeviceHandle {
+            reader_name: Some("Scripted Reader".into()),
+            hid_path: Some("scripted-hid".into()),
+            vid: 0xCAFE,
+            pid: 0x4242,
+            product_name: "Scripted Key".into(),
+            serial: None,
+            firmware_version: None,
+        }
+    }
+
+    /// A successful CTAP response: status 0x00 followed by `map` as CBOR.
+    fn ok_response(map: Vec<(Value, Value)>) -> Vec<u8> {
+        let mut response = vec![0x00];
+        response.extend(to_vec(&Value::Map(map.into_iter().collect::<BTreeMap<_, _>>())).unwrap());
+        response
+    }
+
+    #[test]
+    fn read_details_falls_back_to_fido() {
+        let card = ScriptedApduTransport::new().respond([0x6A, 0x82]);
+        let hid = ScriptedCtapHidTransport::new(0xCAFE, 0x4242, "Scripted Key")
+            .expect(
+                CTAPHID_CBOR,
+                [0x04],
+                ok_response(vec![
+                    (Value::Integer(0x03), Value::Bytes(vec![0xAB; 16])),
+                    (Value::Integer(0x0E), Value::Integer(0x0702)),
+                ]),
+            )
+            .expect(
+                CTAP_VENDOR_CBOR_CMD,
+                [0x06, 0xA1, 0x01, 0x01],
+                ok_response(vec![
+                    (Value::Integer(0x02), Value::Integer(0x2000)),
+                    (Value::Integer(0x03), Value::Integer(0x10_0000)),
+                ]),
+            )
+            .expect(
+                CTAP_VENDOR_CBOR_CMD,
+                [0x05, 0xA1, 0x01, 0x01],
+                ok_response(vec![
+                    (Value::Text("gpio".into()), Value::Integer(25)),
+                    (Value::Text("brightness".into()), Value::Integer(128)),
+                ]),
+            );
+        let mut session = DeviceSession::scripted(
+            device(),
+            Some(Box::new(card.clone())),
+            Some(Box::new(hid.clone())),
+        );
+
+        let status = read_session_details(&mut session).unwrap();
+        assert!(card.is_finished());
+        assert!(hid.is_finished());
+        assert_eq!(status.method, DeviceMethod::Fido);
+        assert_eq!(status.info.firmware_version, "7.2");
+        assert_eq!(status.info.flash_used, 8);
+        assert_eq!(status.info.flash_total, 1024);
+        assert_eq!(status.config.vid, "CAFE");
+        assert_eq!(status.config.product_name, "Scripted Key");
+        assert_eq!(status.config.led_gpio, 25);
+        assert_eq!(status.config.led_brightness, 128);
+    }
+
+    #[test]
+    fn read_details_reports_fido_error_when_both_fail() {
+        let card = ScriptedApduTransport::new().respond([0x6A, 0x82]);
+        // CTAP1_ERR_INVALID_COMMAND
+        let hid = ScriptedCtapHidTransport::new(0xCAFE, 0x4242, "Scripted Key")
+            .respond(CTAPHID_CBOR, [0x01]);
+        let mut session =
+            DeviceSession::scripted(device(), Some(Box::new(card)), Some(Box::new(hid)));
+
+        let err = read_session_details(&mut session).unwrap_err();
+        assert!(err.ctap_error().is_some(), "{:?}", err);
+    }
 }
@@ -1,4 +1,9 @@
+pub mod discovery;
 pub mod fido;
 pub mod io;
 pub mod rescue;
+pub mod session;
+pub mod simulator;
+pub mod transport;
 pub mod types;
+pub mod watcher;
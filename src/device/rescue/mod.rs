@@ -4,45 +4,61 @@
 
 pub mod constants;
 
-use crate::device::{rescue::constants::*, types::*};
+use crate::device::{
+    rescue::constants::*,
+    session::DeviceSession,
+    simulator,
+    transport::{
+        ApduTransport, PcscCard,
+        apdu::{self, Command, SHORT_MAX_LE},
+    },
+    types::*,
+};
 use crate::error::PFError;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use pcsc::{Context, Protocols, Scope, ShareMode};
+use std::ffi::CString;
 use std::io::Cursor;
 
-/// Connects to the first available reader and selects the Rescue Applet
-fn connect_and_select() -> Result<(pcsc::Card, Vec<u8>), PFError> {
+/// Connects to the reader of the given device handle
+pub(crate) fn connect_device(
+    device: &DeviceHandle,
+) -> Result<Box<dyn ApduTransport + Send>, PFError> {
+    let reader_name = device.reader_name.as_deref().ok_or_else(|| {
+        log::info!("Selected device has no Smart Card Reader interface");
+        PFError::NoDevice
+    })?;
+
+    if device.is_simulated() {
+        return Ok(Box::new(simulator::global().clone()));
+    }
+
     let ctx = Context::establish(Scope::User).map_err(|e| {
         log::error!("Failed to establish PCSC context: {}", e);
         PFError::Pcsc(e)
     })?;
 
-    let mut readers_buf = [0; 2048];
-    let mut readers = ctx.list_readers(&mut readers_buf)?;
+    let reader =
+        CString::new(reader_name).map_err(|_| PFError::Io("Invalid reader name".into()))?;
+    let card = ctx.connect(&reader, ShareMode::Shared, Protocols::ANY)?;
 
-    // Use the first reader found
-    let reader = readers.next().ok_or_else(|| {
-        log::info!("No Smart Card Reader found");
-        PFError::NoDevice
-    })?;
-
-    let card = ctx.connect(reader, ShareMode::Shared, Protocols::ANY)?;
+    log::info!("Successfully connected to reader {}", reader_name);
+    Ok(Box::new(PcscCard::new(card)))
+}
 
+fn select_rescue_applet<T: ApduTransport + ?Sized>(transport: &T) -> Result<Vec<u8>, PFError> {
     // Select Applet APDU: 00 A4 04 04 [Len] [AID]
-    let mut apdu = vec![
+    let command = Command::new(
         APDU_CLA_ISO,
         APDU_INS_SELECT,
         APDU_P1_SELECT_BY_DF_NAME,
         APDU_P2_RETURN_FCI,
-        RESCUE_AID.len() as u8,
-    ];
-    apdu.extend_from_slice(RESCUE_AID);
+    )
+    .data(RESCUE_AID);
 
-    let mut rx_buf = [0; 256];
-    let rx = card.transmit(&apdu, &mut rx_buf)?;
+    let response = apdu::send(transport, &command)?;
 
-    // Check Success (0x90 0x00)
-    if !rx.ends_with(&[0x90, 0x00]) {
+    if !response.sw.is_success() {
         log::error!("Rescue Applet not found on the device!");
         return Err(PFError::Device(
             // There is no such mode as fido, i tink the rescue applet stays active and at the same time fido mode works?
@@ -51,19 +67,14 @@ fn connect_and_select() -> Result<(pcsc::Card, Vec<u8>), PFError> {
         ));
     }
 
-    log::info!("Successfully connected to Rescue Applet");
-    Ok((card, rx.to_vec()))
+    Ok(response.data)
 }
 
-pub fn read_device_details() -> Result<FullDeviceStatus, PFError> {
-    log::info!("Reading full device details");
-    let (card, select_resp) = connect_and_select()?;
-
-    log::info!("Select Response: {:?}", select_resp);
-
+/// Extracts `(serial, firmware_version)` from the data of a Rescue Applet select response.
+fn parse_select_response(select_resp: &[u8]) -> Result<(Option<String>, String), PFError> {
     // FIX: Relax the length check.
-    // Minimum valid response is 4 bytes data + 2 bytes SW = 6 bytes.
-    if select_resp.len() < 6 {
+    // Minimum valid response is 4 bytes of data.
+    if select_resp.len() < 4 {
         log::error!("Invalid select response length: {}", select_resp.len());
         return Err(PFError::Device("Invalid select response".into()));
     }
@@ -72,37 +83,114 @@ pub fn read_device_details() -> Result<FullDeviceStatus, PFError> {
     let version_minor = select_resp[3];
 
     // FIX: Handle missing Serial Number safely
-    // If the firmware sends 14 bytes, we have a serial. If it sends 6, we don't.
-    let serial_str = if select_resp.len() >= 14 {
-        hex::encode_upper(&select_resp[4..12])
+    // If the firmware sends 12 bytes, we have a serial. If it sends 4, we don't.
+    let serial = if select_resp.len() >= 12 {
+        Some(hex::encode_upper(&select_resp[4..12]))
     } else {
+        None
+    };
+
+    Ok((serial, format!("{}.{}", version_major, version_minor)))
+}
+
+/// Lists every PC/SC reader that answers the Rescue Applet select.
+///
+/// Readers belonging to other tokens (e.g. a YubiKey CCID interface) are skipped.
+pub fn list_devices() -> Result<Vec<DeviceHandle>, PFError> {
+    let ctx = match Context::establish(Scope::User) {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            log::warn!("PC/SC not available, skipping reader enumeration: {}", e);
+            return Ok(Vec::new());
+        }
+    };
+
+    let mut readers_buf = [0; 2048];
+    let readers = match ctx.list_readers(&mut readers_buf) {
+        Ok(readers) => readers,
+        Err(pcsc::Error::NoReadersAvailable) => return Ok(Vec::new()),
+        Err(e) => return Err(PFError::Pcsc(e)),
+    };
+
+    let mut devices = Vec::new();
+    for reader in readers {
+        let reader_name = reader.to_string_lossy().into_owned();
+
+        let select_resp = match ctx
+            .connect(reader, ShareMode::Shared, Protocols::ANY)
+            .map_err(PFError::Pcsc)
+            .and_then(|card| select_rescue_applet(&PcscCard::new(card)))
+        {
+            Ok(resp) => resp,
+            Err(e) => {
+                log::debug!("Skipping reader {}: {}", reader_name, e);
+                continue;
+            }
+        };
+
+        let (serial, firmware_version) = match parse_select_response(&select_resp) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                log::debug!("Skipping reader {}: {}", reader_name, e);
+                continue;
+            }
+        };
+
+        log::debug!("Found Rescue Applet on reader {}", reader_name);
+        devices.push(DeviceHandle {
+            product_name: reader_name.clone(),
+            reader_name: Some(reader_name),
+            hid_path: None,
+            vid: 0,
+            pid: 0,
+            serial,
+            firmware_version: Some(firmware_version),
+        });
+    }
+
+    Ok(devices)
+}
+
+pub fn read_device_details(session: &mut DeviceSession) -> Result<FullDeviceStatus, PFError> {
+    session.with_card(|t| read_status(t))
+}
+
+/// `80 1E [param] 00 00`: reads one of the status blocks of the Rescue Applet.
+fn read_command(param: ReadParam) -> Command {
+    Command::new(
+        APDU_CLA_PROPRIETARY,
+        RescueInstruction::Read as u8,
+        param as u8,
+        P2_UNUSED,
+    )
+    .le(SHORT_MAX_LE)
+}
+
+/// Selects the Rescue Applet and reads serial, flash usage, secure boot state and the PHY
+/// configuration.
+pub fn read_status<T: ApduTransport + ?Sized>(transport: &T) -> Result<FullDeviceStatus, PFError> {
+    log::info!("Reading full device details");
+    let select_resp = select_rescue_applet(transport)?;
+
+    log::info!("Select Response: {:?}", select_resp);
+
+    let (serial, firmware_version) = parse_select_response(&select_resp)?;
+    let serial_str = serial.unwrap_or_else(|| {
         log::warn!(
             "Device did not return a Serial Number (Firmware mismatch?). Using placeholder."
         );
         "00000000".to_string()
-    };
+    });
 
-    log::info!("Device Version: {}.{}", version_major, version_minor);
+    log::info!("Device Version: {}", firmware_version);
     log::info!("Device Serial: {}", serial_str);
 
     // 2. Read Flash Info
-    let mut rx_buf = [0; 256];
-    let rx_flash = card.transmit(
-        &[
-            APDU_CLA_PROPRIETARY,
-            RescueInstruction::Read as u8,
-            ReadParam::FlashInfo as u8,
-            P2_UNUSED,
-            0x00, // Le
-        ],
-        &mut rx_buf,
-    )?;
-
-    if !rx_flash.ends_with(&SW_SUCCESS) {
-        return Err(PFError::Device("Failed to read flash".into()));
-    }
+    let rx_flash = apdu::send(transport, &read_command(ReadParam::FlashInfo))?
+        .into_success("Reading flash info")
+        .map_err(|_| PFError::Device("Failed to read flash".into()))?;
 
-    let mut rdr = Cursor::new(&rx_flash[..rx_flash.len() - 2]);
+    let mut rdr = Cursor::new(&rx_flash[..]);
     let _free = rdr.read_u32::<BigEndian>().unwrap_or(0);
     let used = rdr.read_u32::<BigEndian>().unwrap_or(0);
     let total = rdr.read_u32::<BigEndian>().unwrap_or(0);
@@ -112,40 +200,26 @@ pub fn read_device_details() -> Result<FullDeviceStatus, PFError> {
     let _chip_size = rdr.read_u32::<BigEndian>().unwrap_or(0);
 
     // --- Read Secure Boot Status ---
-    let rx_secure = card.transmit(
-        &[
-            APDU_CLA_PROPRIETARY,
-            RescueInstruction::Read as u8,
-            ReadParam::SecureBootStatus as u8,
-            P2_UNUSED,
-            0x00,
-        ],
-        &mut rx_buf,
-    )?;
-
-    let (sb_enabled, sb_locked) = if rx_secure.ends_with(&[0x90, 0x00]) && rx_secure.len() >= 4 {
-        (rx_secure[0] != 0, rx_secure[1] != 0)
+    let rx_secure = apdu::send(transport, &read_command(ReadParam::SecureBootStatus))?;
+
+    let (sb_enabled, sb_locked) = if rx_secure.sw.is_success() && rx_secure.data.len() >= 2 {
+        (rx_secure.data[0] != 0, rx_secure.data[1] != 0)
     } else {
         (false, false)
-    }; // --- Read PHY Config ---
-    let rx_phy = card.transmit(
-        &[
-            APDU_CLA_PROPRIETARY,
-            RescueInstruction::Read as u8,
-            ReadParam::PhyConfig as u8,
-            0x01,
-            0x00,
-        ],
-        &mut rx_buf,
-    )?;
-
-    if !rx_phy.ends_with(&[0x90, 0x00]) {
-        return Err(PFError::Device("Failed to read config".into()));
-    }
+    };
+
+    // --- Read PHY Config ---
+    let phy_command = Command {
+        p2: 0x01,
+        ..read_command(ReadParam::PhyConfig)
+    };
+    let rx_phy = apdu::send(transport, &phy_command)?
+        .into_success("Reading PHY config")
+        .map_err(|_| PFError::Device("Failed to read config".into()))?;
 
     // Parse TLV
     let mut config = AppConfig::default();
-    let data = &rx_phy[..rx_phy.len() - 2];
+    let data = &rx_phy[..];
     let mut i = 0;
     while i < data.len() {
         if i + 2 > data.len() {
@@ -219,10 +293,9 @@ pub fn read_device_details() -> Result<FullDeviceStatus, PFError> {
     }
 
     log::info!(
-        "Successfully read device details - Serial: {}, Firmware: {}.{}",
+        "Successfully read device details - Serial: {}, Firmware: {}",
         serial_str,
-        version_major,
-        version_minor
+        firmware_version
     );
 
     Ok(FullDeviceStatus {
@@ -230,7 +303,7 @@ pub fn read_device_details() -> Result<FullDeviceStatus, PFError> {
             serial: serial_str,
             flash_used: used / 1024,
             flash_total: total / 1024,
-            firmware_version: format!("{}.{}", version_major, version_minor),
+            firmware_version,
         },
         config,
         secure_boot: sb_enabled,
@@ -239,7 +312,18 @@ pub fn read_device_details() -> Result<FullDeviceStatus, PFError> {
     })
 }
 
-pub fn write_config(config: AppConfigInput) -> Result<String, PFError> {
+pub fn write_config(
+    session: &mut DeviceSession,
+    config: AppConfigInput,
+) -> Result<String, PFError> {
+    session.with_card(|t| apply_config(t, config.clone()))
+}
+
+/// Encodes the given changes as PHY config TLVs and writes them through the Rescue Applet.
+pub fn apply_config<T: ApduTransport + ?Sized>(
+    transport: &T,
+    config: AppConfigInput,
+) -> Result<String, PFError> {
     log::info!("Writing configuration to device");
     log::debug!("Config input: {:?}", config);
 
@@ -343,32 +427,40 @@ pub fn write_config(config: AppConfigInput) -> Result<String, PFError> {
 
     log::debug!("TLV payload size: {} bytes", tlv.len());
 
-    let (card, _) = connect_and_select()?;
+    select_rescue_applet(transport)?;
 
-    // APDU: 80 1C 01 00 [Lc] [Data]
-    let mut apdu = vec![
+    // APDU: 80 1C 01 00 [Lc] [Data], with an extended Lc for large blobs
+    let command = Command::new(
         APDU_CLA_PROPRIETARY,
         RescueInstruction::Write as u8,
         WriteParam::PhyConfig as u8,
         P2_UNUSED,
-        tlv.len() as u8, // Lc
-    ];
-    apdu.extend_from_slice(&tlv);
+    )
+    .data(tlv);
 
-    let mut rx_buf = [0; 256];
-    let rx = card.transmit(&apdu, &mut rx_buf)?;
+    let response = apdu::send(transport, &command)?;
 
-    if rx.ends_with(&[0x90, 0x00]) {
+    if response.sw.is_success() {
         log::info!("Configuration applied successfully");
         Ok("Configuration Applied Successfully".into())
     } else {
-        log::error!("Configuration write failed: {:02X?}", rx);
-        Err(PFError::Device(format!("Write failed: {:02X?}", rx)))
+        log::error!("Configuration write failed: SW {}", response.sw);
+        Err(PFError::Device(format!("Write failed: SW {}", response.sw)))
     }
 }
 
-pub fn reboot_device(to_bootsel: bool) -> Result<String, PFError> {
-    let (card, _) = connect_and_select()?;
+pub fn reboot_device(session: &mut DeviceSession, to_bootsel: bool) -> Result<String, PFError> {
+    let result = session.with_card(|t| reboot(t, to_bootsel));
+    // The key drops off the bus while rebooting, none of the open handles survive that.
+    session.disconnect();
+    result
+}
+
+pub fn reboot<T: ApduTransport + ?Sized>(
+    transport: &T,
+    to_bootsel: bool,
+) -> Result<String, PFError> {
+    select_rescue_applet(transport)?;
 
     let param = if to_bootsel {
         RebootParam::Bootsel
@@ -376,46 +468,184 @@ pub fn reboot_device(to_bootsel: bool) -> Result<String, PFError> {
         RebootParam::Normal
     };
 
-    let apdu = [
+    let command = Command::new(
         APDU_CLA_PROPRIETARY,
         RescueInstruction::Reboot as u8,
         param as u8,
         P2_UNUSED,
-        0x00,
-    ];
+    )
+    .le(SHORT_MAX_LE);
 
-    let mut rx_buf = [0; 256];
-    let rx = card.transmit(&apdu, &mut rx_buf)?;
+    let response = apdu::send(transport, &command)?;
 
-    if rx.ends_with(&SW_SUCCESS) {
+    if response.sw.is_success() {
         Ok("Reboot command sent".into())
     } else {
-        Err(PFError::Device(format!("Reboot failed: {:02X?}", rx)))
+        Err(PFError::Device(format!(
+            "Reboot failed: SW {}",
+            response.sw
+        )))
     }
 }
 
 /// UNSTABLE! (WIP)
-pub fn enable_secure_boot(lock: bool) -> Result<String, PFError> {
-    let (card, _) = connect_and_select()?;
+pub fn enable_secure_boot(session: &mut DeviceSession, lock: bool) -> Result<String, PFError> {
+    session.with_card(|t| secure_boot(t, lock))
+}
+
+/// UNSTABLE! (WIP)
+pub fn secure_boot<T: ApduTransport + ?Sized>(
+    transport: &T,
+    lock: bool,
+) -> Result<String, PFError> {
+    select_rescue_applet(transport)?;
 
     // APDU: 80 1D [KeyIndex] [LockBool] 00
     // KeyIndex = 0 (Default), LockBool = 1 if true
     let lock_byte = if lock { 0x01 } else { 0x00 };
 
-    let apdu = [
+    let command = Command::new(
         APDU_CLA_PROPRIETARY,
         RescueInstruction::Secure as u8,
         0x00, // Boot Key Index (0 = Default)
         lock_byte as u8,
-        0x00,
-    ];
+    )
+    .le(SHORT_MAX_LE);
 
-    let mut rx_buf = [0; 256];
-    let rx = card.transmit(&apdu, &mut rx_buf)?;
+    let response = apdu::send(transport, &command)?;
 
-    if rx.ends_with(&[0x90, 0x00]) {
+    if response.sw.is_success() {
         Ok("Secure Boot Enabled".into())
     } else {
-        Err(PFError::Device(format!("Secure Boot failed: {:02X?}", rx)))
+        Err(PFError::Device(format!(
+            "Secure Boot failed: SW {}",
+            response.sw
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::transport::scripted::ScriptedApduTransport;
+
+    const SELECT: [u8; 13] = [
+        0x00, 0xA4, 0x04, 0x04, 0x08, 0xA0, 0x58, 0x3F, 0xC1, 0x9B, 0x7E, 0x4F, 0x21,
+    ];
+    /// Version 7.2 with serial 0102030405060708.
+    const SELECT_RESPONSE: [u8; 14] = [0, 0, 7, 2, 1, 2, 3, 4, 5, 6, 7, 8, 0x90, 0x00];
+
+    fn session(card: &ScriptedApduTransport) -> DeviceSession {
+        let device = DeviceHandle {
+            reader_name: Some("Scripted Reader".into()),
+            hid_path: None,
+            vid: 0xCAFE,
+            pid: 0x4242,
+            product_name: "Scripted Key".into(),
+            serial: None,
+            firmware_version: None,
+        };
+        DeviceSession::scripted(device, Some(Box::new(card.clone())), None)
+    }
+
+    fn config_input() -> AppConfigInput {
+        AppConfigInput {
+            vid: None,
+            pid: None,
+            product_name: None,
+            led_gpio: None,
+            led_brightness: None,
+            touch_timeout: None,
+            led_driver: None,
+            led_dimmable: None,
+            power_cycle_on_reset: None,
+            led_steady: None,
+            enable_secp256k1: None,
+        }
+    }
+
+    #[test]
+    fn read_device_details_parses_status() {
+        // free, used, total, nfiles, chip size
+        let flash_info = [
+            0, 0, 0, 0, 0, 0, 0x20, 0, 0, 0x10, 0, 0, 0, 0, 0, 3, 0, 0x40, 0, 0, 0x90, 0x00,
+        ];
+        let phy_config = [
+            0x00, 0x04, 0xCA, 0xFE, 0x42, 0x42, // VID:PID
+            0x04, 0x01, 0x19, // LED GPIO
+            0x05, 0x01, 0x80, // LED brightness
+            0x90, 0x00,
+        ];
+        let card = ScriptedApduTransport::new()
+            .expect(SELECT, SELECT_RESPONSE)
+            .expect([0x80, 0x1E, 0x02, 0x00, 0x00], flash_info)
+            .expect([0x80, 0x1E, 0x03, 0x00, 0x00], [1, 0, 0x90, 0x00])
+            .expect([0x80, 0x1E, 0x01, 0x01, 0x00], phy_config);
+
+        let status = read_device_details(&mut session(&card)).unwrap();
+        assert!(card.is_finished());
+        assert_eq!(status.method, DeviceMethod::Rescue);
+        assert_eq!(status.info.serial, "0102030405060708");
+        assert_eq!(status.info.firmware_version, "7.2");
+        assert_eq!(status.info.flash_used, 8);
+        assert_eq!(status.info.flash_total, 1024);
+        assert!(status.secure_boot);
+        assert!(!status.secure_lock);
+        assert_eq!(status.config.vid, "CAFE");
+        assert_eq!(status.config.pid, "4242");
+        assert_eq!(status.config.led_gpio, 0x19);
+        assert_eq!(status.config.led_brightness, 0x80);
+    }
+
+    #[test]
+    fn read_device_details_fails_without_rescue_applet() {
+        let card = ScriptedApduTransport::new().expect(SELECT, [0x6A, 0x82]);
+
+        let err = read_device_details(&mut session(&card)).unwrap_err();
+        assert!(matches!(err, PFError::Device(_)), "{:?}", err);
+        assert!(card.is_finished());
+    }
+
+    #[test]
+    fn write_config_sends_phy_tlvs() {
+        let mut write = vec![0x80, 0x1C, 0x01, 0x00, 0x0F];
+        write.extend([0x00, 0x04, 0xCA, 0xFE, 0x42, 0x42]); // VID:PID
+        write.extend([0x04, 0x01, 0x03]); // LED GPIO
+        write.extend([0x09, 0x04, b'K', b'e', b'y', 0x00]); // USB product name
+        let card = ScriptedApduTransport::new()
+            .expect(SELECT, SELECT_RESPONSE)
+            .expect(write, [0x90, 0x00]);
+        let config = AppConfigInput {
+            vid: Some("CAFE".into()),
+            pid: Some("4242".into()),
+            product_name: Some("Key".into()),
+            led_gpio: Some(3),
+            ..config_input()
+        };
+
+        write_config(&mut session(&card), config).unwrap();
+        assert!(card.is_finished());
+    }
+
+    #[test]
+    fn write_config_reports_rejected_write() {
+        let card = ScriptedApduTransport::new()
+            .expect(SELECT, SELECT_RESPONSE)
+            .respond([0x6A, 0x80]);
+        let config = AppConfigInput {
+            led_brightness: Some(10),
+            ..config_input()
+        };
+
+        let err = write_config(&mut session(&card), config).unwrap_err();
+        assert!(matches!(err, PFError::Device(_)), "{:?}", err);
+        assert!(card.is_finished());
+    }
+
+    #[test]
+    fn write_config_without_changes_sends_nothing() {
+        let card = ScriptedApduTransport::new();
+
+        write_config(&mut session(&card), config_input()).unwrap();
     }
 }
@@ -0,0 +1,611 @@
+//! Long lived connections to a key.
+//!
+//! Opening a PC/SC context, negotiating a CTAPHID channel and reading GetInfo each take a
+//! noticeable amount of time (and make the key's LED flicker), so a [`DeviceSession`] keeps them
+//! around for as long as the key stays connected. Sessions live in a process wide registry keyed
+//! by [`DeviceHandle::id`]; `device::io` looks them up for every operation.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::device::transport::{ApduTransport, CtapHidTransport, OperationMonitor};
+use crate::device::types::{CredsMetadata, DeviceHandle, FidoDeviceInfo};
+use crate::device::{discovery, fido, rescue};
+use crate::error::PFError;
+
+const RECONNECT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+static SESSIONS: LazyLock<Mutex<HashMap<String, Arc<Mutex<DeviceSession>>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Returns the session for `device`, creating it on first use.
+///
+/// If the key re-enumerated under a different reader name or HID path, the session picks up the
+/// new handle and drops its old connections.
+pub fn get(device: &DeviceHandle) -> Arc<Mutex<DeviceSession>> {
+    let mut sessions = SESSIONS.lock().unwrap_or_else(PoisonError::into_inner);
+    let session = sessions
+        .entry(device.id())
+        .or_insert_with(|| Arc::new(Mutex::new(DeviceSession::new(device.clone()))))
+        .clone();
+    drop(sessions);
+
+    lock(&session).update_handle(device);
+    session
+}
+
+/// Locks a session, ignoring poisoning: a panic mid operation leaves at worst a stale
+/// connection, which the session replaces on the next error.
+pub fn lock(session: &Mutex<DeviceSession>) -> MutexGuard<'_, DeviceSession> {
+    session.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Closes the sessions of every key that is no longer connected.
+pub fn retain(connected: &[DeviceHandle]) {
+    let mut sessions = SESSIONS.lock().unwrap_or_else(PoisonError::into_inner);
+    sessions.retain(|id, _| {
+        let keep = connected.iter().any(|d| &d.id() == id);
+        if !keep {
+            log::info!("Closing session for disconnected device {}", id);
+        }
+        keep
+    });
+}
+
+/// Waits for the key of `session` to drop off the bus and come back, e.g. while the user
+/// replugs it, and picks up its new HID interface. A key still listed after `gone_within` is
+/// assumed to have re-enumerated faster than the poll. Fails after `timeout` or when `monitor`
+/// is cancelled.
+///
+/// The session is only locked to drop its connections and to take the new handle, so status
+/// reads for the same key are not stuck behind the wait.
+pub fn wait_for_reconnect(
+    session: &Mutex<DeviceSession>,
+    gone_within: Duration,
+    timeout: Duration,
+    monitor: &OperationMonitor,
+) -> Result<(), PFError> {
+    wait_for_reconnect_in(session, gone_within, timeout, monitor, || {
+        discovery::list_hid_devices().unwrap_or_default()
+    })
+}
+
+/// [`wait_for_reconnect`] with the keys currently attached coming from `listed`.
+fn wait_for_reconnect_in(
+    session: &Mutex<DeviceSession>,
+    gone_within: Duration,
+    timeout: Duration,
+    monitor: &OperationMonitor,
+    listed: impl Fn() -> Vec<DeviceHandle>,
+) -> Result<(), PFError> {
+    let device = {
+        let mut session = lock(session);
+        session.disconnect();
+        session.device.clone()
+    };
+    let id = device.id();
+    let start = Instant::now();
+
+    log::info!("Waiting for {} to disconnect...", device.label());
+    while start.elapsed() < gone_within && listed().iter().any(|d| d.id() == id) {
+        if monitor.is_cancelled() {
+            return Err(PFError::Cancelled);
+        }
+        std::thread::sleep(RECONNECT_POLL_INTERVAL);
+    }
+
+    log::info!("Waiting for {} to come back...", device.label());
+    loop {
+        if monitor.is_cancelled() {
+            return Err(PFError::Cancelled);
+        }
+        let devices = listed();
+        let mut found = devices.iter().find(|d| d.id() == id);
+        // Without a serial number the HID path is the only identity, and it usually changes on
+        // replug, so a key of the same model counts, as long as it is the only one.
+        if found.is_none() && device.serial.is_none() {
+            let mut same_model = devices
+                .iter()
+                .filter(|d| d.vid == device.vid && d.pid == device.pid);
+            found = same_model.next();
+            if found.is_some() && same_model.next().is_some() {
+                return Err(PFError::Device(
+                    "Several keys of this model are connected and it has no serial number to \
+                     tell them apart. Leave only the key being set up plugged in and try again."
+                        .into(),
+                ));
+            }
+        }
+        if let Some(hid) = found {
+            log::info!("Device {} reconnected", hid.label());
+            // The HID list knows nothing about the rescue reader, which with_card looks up
+            // again if it moved.
+            let mut session = lock(session);
+            let reconnected = DeviceHandle {
+                hid_path: hid.hid_path.clone(),
+                ..session.device.clone()
+            };
+            session.update_handle(&reconnected);
+            return Ok(());
+        }
+        if start.elapsed() >= timeout {
+            return Err(PFError::Disconnected(
+                "The key did not come back in time".into(),
+            ));
+        }
+        std::thread::sleep(RECONNECT_POLL_INTERVAL);
+    }
+}
+
+/// Whether an error means the underlying connection is gone (unplugged, USB reset, PC/SC
+/// service restart) rather than the key rejecting a command.
+fn is_connection_lost(e: &PFError) -> bool {
+    match e {
+        PFError::Disconnected(_) => true,
+        PFError::Pcsc(e) => matches!(
+            e,
+            pcsc::Error::ResetCard
+                | pcsc::Error::RemovedCard
+                | pcsc::Error::NoSmartcard
+                | pcsc::Error::InvalidHandle
+                | pcsc::Error::ReaderUnavailable
+                | pcsc::Error::UnknownReader
+                | pcsc::Error::NoService
+                | pcsc::Error::ServiceStopped
+                | pcsc::Error::CommError
+        ),
+        _ => false,
+    }
+}
+
+/// Reads GetInfo on a newly opened channel and unlocks a key whose authentication encryption
+/// reports it as locked, as it is after every power-up.
+///
+/// Failures are only logged: the operation that opened the channel still runs, so a key that
+/// cannot be unlocked here (e.g. encrypted on another computer) can still be inspected or reset.
+/// The returned GetInfo then keeps reporting it as locked, which the UI shows.
+fn info_after_unlock(hid: &dyn CtapHidTransport) -> Option<FidoDeviceInfo> {
+    let mut info = fido::read_fido_info(hid)
+        .inspect_err(|e| log::warn!("Failed to read GetInfo on the new channel: {}", e))
+        .ok()?;
+    if let Err(e) = fido::unlock_auth_encryption(hid, &mut info) {
+        log::warn!("Failed to unlock authentication encryption: {}", e);
+    }
+    Some(info)
+}
+
+/// Passes requests through to a channel, noting whether any of them may have reached the key.
+/// Only an operation none of whose requests got that far is safe to run again.
+struct DeliveryTracker<'a> {
+    hid: &'a dyn CtapHidTransport,
+    delivered: Cell<bool>,
+}
+
+impl<'a> DeliveryTracker<'a> {
+    fn new(hid: &'a dyn CtapHidTransport) -> Self {
+        Self {
+            hid,
+            delivered: Cell::new(false),
+        }
+    }
+
+    /// A `Disconnected` error means the request could not be written; anything else means it
+    /// went out.
+    fn track<R>(&self, result: Result<R, PFError>) -> Result<R, PFError> {
+        if !matches!(result, Err(PFError::Disconnected(_))) {
+            self.delivered.set(true);
+        }
+        result
+    }
+}
+
+impl CtapHidTransport for DeliveryTracker<'_> {
+    fn send_cbor(&self, cmd: u8, payload: &[u8]) -> Result<Vec<u8>, PFError> {
+        self.track(self.hid.send_cbor(cmd, payload))
+    }
+
+    fn vid(&self) -> u16 {
+        self.hid.vid()
+    }
+
+    fn pid(&self) -> u16 {
+        self.hid.pid()
+    }
+
+    fn product_name(&self) -> &str {
+        self.hid.product_name()
+    }
+
+    fn wink(&self) -> Result<bool, PFError> {
+        self.track(self.hid.wink())
+    }
+
+    fn set_monitor(&self, monitor: Option<OperationMonitor>) {
+        self.hid.set_monitor(monitor);
+    }
+
+    fn cid(&self) -> u32 {
+        self.hid.cid()
+    }
+
+    fn allocate_channel(&self) -> Result<u32, PFError> {
+        self.hid.allocate_channel()
+    }
+
+    fn ping_on(&self, cid: u32, payload: &[u8]) -> Result<Vec<u8>, PFError> {
+        self.track(self.hid.ping_on(cid, payload))
+    }
+
+    fn drain(&self) {
+        self.hid.drain();
+    }
+}
+
+/// The open connections to one key.
+pub struct DeviceSession {
+    device: DeviceHandle,
+    card: Option<Box<dyn ApduTransport + Send>>,
+    hid: Option<Box<dyn CtapHidTransport + Send>>,
+    fido_info: Option<FidoDeviceInfo>,
+    /// Credential counts read along with the last credential listing.
+    creds_metadata: Option<CredsMetadata>,
+}
+
+impl DeviceSession {
+    fn new(device: DeviceHandle) -> Self {
+        Self {
+            device,
+            card: None,
+            hid: None,
+            fido_info: None,
+            creds_metadata: None,
+        }
+    }
+
+    /// A session over already open transports, for driving the device layer in tests.
+    #[cfg(test)]
+    pub(crate) fn scripted(
+        device: DeviceHandle,
+        card: Option<Box<dyn ApduTransport + Send>>,
+        hid: Option<Box<dyn CtapHidTransport + Send>>,
+    ) -> Self {
+        Self {
+            card,
+            hid,
+            ..Self::new(device)
+        }
+    }
+
+    pub fn device(&self) -> &DeviceHandle {
+        &self.device
+    }
+
+    fn update_handle(&mut self, device: &DeviceHandle) {
+        if self.device.reader_name != device.reader_name {
+            self.card = None;
+        }
+        if self.device.hid_path != device.hid_path {
+            self.hid = None;
+            self.fido_info = None;
+        }
+        self.device = device.clone();
+    }
+
+    /// Drops every connection and cached value, e.g. after the key was rebooted.
+    pub fn disconnect(&mut self) {
+        log::debug!("Dropping connections to {}", self.device.label());
+        self.card = None;
+        self.hid = None;
+        self.fido_info = None;
+        self.creds_metadata = None;
+    }
+
+    /// Forgets the cached GetInfo response. Call after anything that changes PIN state or
+    /// authenticator options.
+    pub fn invalidate_fido_info(&mut self) {
+        self.fido_info = None;
+    }
+
+    /// Records the credential counts read with the PIN, so GetInfo can be shown with them.
+    pub fn set_creds_metadata(&mut self, metadata: Option<CredsMetadata>) {
+        self.creds_metadata = metadata;
+        if let Some(info) = &mut self.fido_info {
+            info.creds_metadata = metadata;
+        }
+    }
+
+    /// Runs `op` against the Smart Card interface, connecting first if needed. If the connection
+    /// turns out to be dead, it is re-established and `op` retried once.
+    pub fn with_card<R>(
+        &mut self,
+        mut op: impl FnMut(&dyn ApduTransport) -> Result<R, PFError>,
+    ) -> Result<R, PFError> {
+        if let Some(card) = &self.card {
+            match op(card.as_ref()) {
+                Err(e) if is_connection_lost(&e) => {
+                    log::warn!("Smart Card connection lost ({}), reconnecting...", e);
+                    self.card = None;
+                }
+                result => return result,
+            }
+        }
+
+        let card = match rescue::connect_device(&self.device) {
+            Err(e) if is_connection_lost(&e) && self.rediscover() => {
+                rescue::connect_device(&self.device)?
+            }
+            result => result?,
+        };
+        op(self.card.insert(card).as_ref())
+    }
+
+    /// Runs `op` against the FIDO HID interface, negotiating a channel first if needed. If the
+    /// channel turns out to be dead before any request of `op` was written, a new one is
+    /// negotiated and `op` retried once. Requests that may have reached the key are never sent
+    /// again, since the key may already have acted on them (a reset, a deletion).
+    pub fn with_hid<R>(
+        &mut self,
+        mut op: impl FnMut(&dyn CtapHidTransport) -> Result<R, PFError>,
+    ) -> Result<R, PFError> {
+        if let Some(hid) = &self.hid {
+            let tracker = DeliveryTracker::new(hid.as_ref());
+            match op(&tracker) {
+                Err(e) if is_connection_lost(&e) || matches!(e, PFError::NoResponse(_)) => {
+                    let delivered = tracker.delivered.get();
+                    self.hid = None;
+                    self.fido_info = None;
+                    if delivered {
+                        log::warn!("HID channel lost mid request ({}), not retrying", e);
+                        return Err(e);
+                    }
+                    log::warn!("HID channel lost ({}), reopening...", e);
+                }
+                result => return result,
+            }
+        }
+
+        let hid = self.open_hid()?;
+        op(hid)
+    }
+
+    /// Negotiates a new channel and caches the GetInfo response read on it.
+    fn open_hid(&mut self) -> Result<&dyn CtapHidTransport, PFError> {
+        let hid = match fido::open_transport(&self.device) {
+            Err(PFError::NoDevice) if self.device.hid_path.is_some() && self.rediscover() => {
+                fido::open_transport(&self.device)?
+            }
+            result => result?,
+        };
+        let hid = self.hid.insert(hid);
+        self.fido_info = info_after_unlock(hid.as_ref()).map(|mut info| {
+            info.creds_metadata = self.creds_metadata;
+            info
+        });
+        Ok(&**hid)
+    }
+
+    /// Like [`with_hid`](Self::with_hid), with `monitor` attached to the channel so it receives
+    /// keepalive status and can cancel the request.
+    pub fn with_hid_monitored<R>(
+        &mut self,
+        monitor: &OperationMonitor,
+        mut op: impl FnMut(&dyn CtapHidTransport) -> Result<R, PFError>,
+    ) -> Result<R, PFError> {
+        self.with_hid(|t| {
+            t.set_monitor(Some(monitor.clone()));
+            let result = op(t);
+            t.set_monitor(None);
+            result
+        })
+    }
+
+    /// Returns the authenticatorGetInfo response, reading it only once per connection.
+    pub fn fido_info(&mut self) -> Result<FidoDeviceInfo, PFError> {
+        // A new channel comes with a fresh GetInfo
+        if self.hid.is_none() {
+            self.open_hid()?;
+        }
+        if let Some(info) = &self.fido_info {
+            return Ok(info.clone());
+        }
+        let mut info = self.with_hid(|t| fido::read_fido_info(t))?;
+        info.creds_metadata = self.creds_metadata;
+        self.fido_info = Some(info.clone());
+        Ok(info)
+    }
+
+    /// Looks the key up again after a USB reset, since the OS may have assigned it a new reader
+    /// name or HID path. Returns whether it was found.
+    fn rediscover(&mut self) -> bool {
+        let id = self.device.id();
+        let Some(device) = discovery::list_devices()
+            .ok()
+            .and_then(|devices| devices.into_iter().find(|d| d.id() == id))
+        else {
+            log::warn!("Device {} did not come back", id);
+            return false;
+        };
+        log::info!("Device {} re-enumerated, reconnecting", id);
+        self.update_handle(&device);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::fido::hid::CTAPHID_CBOR;
+    use crate::device::fido::pin::PinUvAuthProtocol;
+    use crate::device::fido::{auth_encryption, client_pin};
+    use crate::device::simulator::{self, DEMO_PIN, Simulator};
+    use crate::device::transport::scripted::ScriptedCtapHidTransport;
+
+    /// A session for the simulated key whose cached channel is `hid`.
+    fn scripted_session(hid: ScriptedCtapHidTransport) -> DeviceSession {
+        DeviceSession::scripted(
+            simulator::global().device_handle(),
+            None,
+            Some(Box::new(hid)),
+        )
+    }
+
+    #[test]
+    fn does_not_resend_a_request_without_an_answer() {
+        let hid = ScriptedCtapHidTransport::new(0xCAFE, 0x4242, "Scripted Key")
+            .fail(CTAPHID_CBOR, PFError::NoResponse("Timeout".into()));
+        let mut session = scripted_session(hid);
+        let mut attempts = 0;
+
+        let err = session
+            .with_hid(|t| {
+                attempts += 1;
+                t.send_cbor(CTAPHID_CBOR, &[0x07])
+            })
+            .unwrap_err();
+        assert!(matches!(err, PFError::NoResponse(_)), "{:?}", err);
+        assert_eq!(attempts, 1);
+        // The late answer must not be taken for the next request's
+        assert!(session.hid.is_none());
+    }
+
+    #[test]
+    fn does_not_repeat_requests_that_went_out() {
+        let hid = ScriptedCtapHidTransport::new(0xCAFE, 0x4242, "Scripted Key")
+            .respond(CTAPHID_CBOR, [0x00])
+            .fail(CTAPHID_CBOR, PFError::Disconnected("Unplugged".into()));
+        let mut session = scripted_session(hid);
+        let mut attempts = 0;
+
+        let err = session
+            .with_hid(|t| {
+                attempts += 1;
+                t.send_cbor(CTAPHID_CBOR, &[0x07])?;
+                t.send_cbor(CTAPHID_CBOR, &[0x07])
+            })
+            .unwrap_err();
+        assert!(matches!(err, PFError::Disconnected(_)), "{:?}", err);
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn retries_on_a_new_channel_when_nothing_went_out() {
+        let hid = ScriptedCtapHidTransport::new(0xCAFE, 0x4242, "Scripted Key")
+            .fail(CTAPHID_CBOR, PFError::Disconnected("Unplugged".into()));
+        let mut session = scripted_session(hid);
+        let mut attempts = 0;
+
+        session
+            .with_hid(|t| {
+                attempts += 1;
+                fido::read_fido_info(t)
+            })
+            .unwrap();
+        assert_eq!(attempts, 2);
+    }
+
+    #[test]
+    fn keeps_a_channel_whose_key_stays_locked() {
+        let sim = Simulator::new();
+        let hid = sim.open_hid();
+        let protocol = PinUvAuthProtocol::Two;
+        let token = client_pin::get_pin_token(&hid, protocol, DEMO_PIN).unwrap();
+        // A host key this computer does not have, stored or not
+        auth_encryption::enable(&hid, protocol, &token, &[9; auth_encryption::HOST_KEY_LEN])
+            .unwrap();
+        rescue::reboot(&sim, false).unwrap();
+
+        let info = info_after_unlock(&hid).unwrap();
+        assert!(info.auth_encryption && info.auth_encryption_locked);
+        // The key still answers, it just cannot use its secret
+        assert!(fido::read_fido_info(&hid).unwrap().auth_encryption_locked);
+    }
+
+    /// A key without a serial number, known by its HID path alone.
+    fn serial_less(path: &str) -> DeviceHandle {
+        DeviceHandle {
+            reader_name: None,
+            hid_path: Some(path.into()),
+            vid: 0xCAFE,
+            pid: 0x4242,
+            product_name: "Scripted Key".into(),
+            serial: None,
+            firmware_version: None,
+        }
+    }
+
+    fn wait_among(
+        device: DeviceHandle,
+        attached: Vec<DeviceHandle>,
+    ) -> (Mutex<DeviceSession>, Result<(), PFError>) {
+        let session = Mutex::new(DeviceSession::new(device));
+        let result = wait_for_reconnect_in(
+            &session,
+            Duration::ZERO,
+            Duration::ZERO,
+            &OperationMonitor::new(),
+            || attached.clone(),
+        );
+        (session, result)
+    }
+
+    #[test]
+    fn reconnect_wait_leaves_the_session_unlocked() {
+        let device = Simulator::new().device_handle();
+        let shared = Arc::new(Mutex::new(DeviceSession::new(device.clone())));
+        let monitor = OperationMonitor::new();
+        let waiter = {
+            let shared = shared.clone();
+            let monitor = monitor.clone();
+            // The key never drops off
+            std::thread::spawn(move || {
+                wait_for_reconnect_in(
+                    &shared,
+                    Duration::from_secs(30),
+                    Duration::from_secs(30),
+                    &monitor,
+                    || vec![device.clone()],
+                )
+            })
+        };
+
+        std::thread::sleep(RECONNECT_POLL_INTERVAL * 2);
+        assert!(shared.try_lock().is_ok());
+        monitor.cancel();
+        assert!(matches!(waiter.join().unwrap(), Err(PFError::Cancelled)));
+    }
+
+    #[test]
+    fn serial_less_key_comes_back_under_a_new_path() {
+        let (session, result) = wait_among(
+            serial_less("/dev/hidraw1"),
+            vec![serial_less("/dev/hidraw2")],
+        );
+        result.unwrap();
+        assert_eq!(
+            lock(&session).device().hid_path.as_deref(),
+            Some("/dev/hidraw2")
+        );
+    }
+
+    #[test]
+    fn serial_less_key_is_not_confused_with_a_twin() {
+        let (session, result) = wait_among(
+            serial_less("/dev/hidraw1"),
+            vec![serial_less("/dev/hidraw2"), serial_less("/dev/hidraw3")],
+        );
+        assert!(matches!(result, Err(PFError::Device(_))), "{:?}", result);
+        assert_eq!(
+            lock(&session).device().hid_path.as_deref(),
+            Some("/dev/hidraw1")
+        );
+
+        // Unless it came back where it was
+        let (_, result) = wait_among(
+            serial_less("/dev/hidraw1"),
+            vec![serial_less("/dev/hidraw1"), serial_less("/dev/hidraw3")],
+        );
+        result.unwrap();
+    }
+}
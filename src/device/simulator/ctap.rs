@@ -0,0 +1,997 @@
+//! CTAP2 and pico-fido vendor command side of the simulator.
+
+use rand::RngExt;
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, Nonce, UnboundKey};
+use ring::digest;
+use serde_cbor_2::{Value, from_slice, to_vec};
+use std::collections::BTreeMap;
+
+use super::{SimCredential, State, random_token};
+use crate::device::fido::auth_encryption::{self, SecureChannel};
+use crate::device::fido::cbor::{self, CborMap, int};
+use crate::device::fido::constants::*;
+use crate::device::fido::hid::CTAPHID_CBOR;
+use crate::device::fido::large_blobs;
+use crate::device::fido::pin::{KeyAgreementKey, PinUvAuthProtocol, pin_hash};
+use crate::device::fido::pin_policy::PinComplexityPolicy;
+
+type CtapResult = Result<Option<CborMap>, Ctap2Error>;
+
+/// Stands in for the key the firmware encrypts backups with.
+const BACKUP_KEY: [u8; 32] = [0x5B; 32];
+const BACKUP_NONCE_LEN: usize = 12;
+
+impl State {
+    /// Handles one CTAPHID message and returns the CTAP response, status byte first.
+    pub(super) fn process_ctaphid(&mut self, cmd: u8, payload: &[u8]) -> Vec<u8> {
+        let result = match (cmd, payload.split_first()) {
+            (CTAPHID_CBOR, Some((&ctap_cmd, params))) => self.process_ctap(ctap_cmd, params),
+            (CTAP_VENDOR_CBOR_CMD, Some((&vendor_cmd, params))) => {
+                self.process_vendor(vendor_cmd, params)
+            }
+            _ => Err(Ctap2Error::InvalidCommand),
+        };
+
+        match result {
+            Ok(None) => vec![Ctap2Error::Success as u8],
+            Ok(Some(map)) => {
+                let mut response = vec![Ctap2Error::Success as u8];
+                response.extend(to_vec(&Value::Map(map)).unwrap_or_default());
+                response
+            }
+            Err(e) => {
+                log::debug!("Simulator returning CTAP error {:?}", e);
+                vec![e as u8]
+            }
+        }
+    }
+
+    fn process_ctap(&mut self, ctap_cmd: u8, params: &[u8]) -> CtapResult {
+        match ctap_cmd {
+            c if c == CtapCommand::GetInfo as u8 => Ok(Some(self.get_info())),
+            c if c == CtapCommand::ClientPin as u8 => self.client_pin(&parse_params(params)?),
+            c if c == CtapCommand::CredentialMgmt as u8 => {
+                self.credential_mgmt(&parse_params(params)?)
+            }
+            c if c == CtapCommand::Config as u8 => self.config(&parse_params(params)?),
+            c if c == CtapCommand::LargeBlobs as u8 => self.large_blobs(&parse_params(params)?),
+            c if c == CtapCommand::GetAssertion as u8 => self.get_assertion(&parse_params(params)?),
+            c if c == CtapCommand::Reset as u8 => {
+                if self.powered_up.elapsed().as_secs() >= RESET_WINDOW_SECS {
+                    return Err(Ctap2Error::NotAllowed);
+                }
+                log::info!("Simulator reset: PIN and credentials erased");
+                self.pin_hash = None;
+                self.pin_retries = MAX_PIN_RETRIES;
+                self.min_pin_length = 4;
+                self.always_uv = false;
+                self.pin_policy = PinComplexityPolicy::empty();
+                self.enterprise_attestation = false;
+                self.auth_encryption_key = None;
+                self.credentials.clear();
+                self.large_blobs = large_blobs::serialize_array(&[]).unwrap_or_default();
+                self.power_cycle();
+                Ok(None)
+            }
+            c if c == CtapCommand::Selection as u8 => Ok(None),
+            _ => Err(Ctap2Error::InvalidCommand),
+        }
+    }
+
+    fn get_info(&self) -> CborMap {
+        let text_array = |items: &[&str]| {
+            Value::Array(items.iter().map(|s| Value::Text(s.to_string())).collect())
+        };
+
+        let mut options = BTreeMap::new();
+        for (name, value) in [
+            ("rk", true),
+            ("up", true),
+            ("clientPin", self.pin_hash.is_some()),
+            ("credMgmt", true),
+            ("authnrCfg", true),
+            ("setMinPINLength", true),
+            ("alwaysUv", self.always_uv),
+            ("ep", self.enterprise_attestation),
+            ("makeCredUvNotRqd", !self.always_uv),
+            ("pinUvAuthToken", true),
+            ("largeBlobs", true),
+        ] {
+            options.insert(Value::Text(name.into()), Value::Bool(value));
+        }
+        if self.auth_encryption_key.is_some() {
+            options.insert(
+                Value::Text(auth_encryption::STATE_OPTION.into()),
+                Value::Bool(self.device_locked),
+            );
+        }
+
+        let mut info = BTreeMap::new();
+        info.insert(int(0x01), text_array(&["U2F_V2", "FIDO_2_0", "FIDO_2_1"]));
+        info.insert(
+            int(0x02),
+            text_array(&[
+                "credProtect",
+                "hmac-secret",
+                "credBlob",
+                "largeBlobKey",
+                "minPinLength",
+            ]),
+        );
+        info.insert(int(0x03), Value::Bytes(AAGUID.to_vec()));
+        info.insert(int(0x04), Value::Map(options));
+        info.insert(int(0x05), int(MAX_MSG_SIZE as i128));
+        info.insert(int(0x06), Value::Array(vec![int(2), int(1)]));
+        info.insert(int(0x0B), int(MAX_LARGE_BLOB_SIZE as i128));
+        info.insert(int(0x0D), int(self.min_pin_length));
+        info.insert(int(0x0F), int(MAX_CREDBLOB_LENGTH as i128));
+        info.insert(
+            int(0x14),
+            int((MAX_RESIDENT_CREDENTIALS - self.credentials.len()) as i128),
+        );
+        info.insert(int(0x1B), int(self.pin_policy.bits()));
+        info.insert(
+            int(0x0E),
+            int(((self.firmware.0 as i128) << 8) | self.firmware.1 as i128),
+        );
+        info
+    }
+
+    // --- ClientPIN ---
+
+    fn client_pin(&mut self, params: &CborMap) -> CtapResult {
+        let sub_command = cbor::get_int(params, ClientPinParam::SubCommand as i128)
+            .ok_or(Ctap2Error::MissingParameter)?;
+
+        if sub_command == ClientPinSubCommand::GetPinRetries as i128 {
+            let mut response = BTreeMap::new();
+            response.insert(int(0x03), int(self.pin_retries));
+            response.insert(
+                int(0x04),
+                Value::Bool(self.consecutive_pin_failures >= MAX_CONSECUTIVE_PIN_FAILURES),
+            );
+            return Ok(Some(response));
+        }
+
+        let protocol = cbor::get_int(params, ClientPinParam::PinUvAuthProtocol as i128)
+            .and_then(|v| PinUvAuthProtocol::from_version(v as u32))
+            .ok_or(Ctap2Error::InvalidParameter)?;
+
+        match sub_command {
+            s if s == ClientPinSubCommand::GetKeyAgreement as i128 => {
+                let key = KeyAgreementKey::generate().map_err(|_| Ctap2Error::Processing)?;
+                let mut response = BTreeMap::new();
+                response.insert(int(0x01), key.cose_key());
+                self.key_agreement = Some(key);
+                Ok(Some(response))
+            }
+            s if s == ClientPinSubCommand::SetPin as i128 => {
+                if self.pin_hash.is_some() {
+                    return Err(Ctap2Error::NotAllowed);
+                }
+                let new_pin_enc = required_bytes(params, ClientPinParam::NewPinEnc as i128)?;
+                let pin_auth = required_bytes(params, ClientPinParam::PinUvAuthParam as i128)?;
+                let shared = self.shared_secret(protocol, params)?;
+                if !protocol.verify(&shared, new_pin_enc, pin_auth) {
+                    return Err(Ctap2Error::PinAuthInvalid);
+                }
+                self.store_new_pin(protocol, &shared, new_pin_enc)?;
+                Ok(None)
+            }
+            s if s == ClientPinSubCommand::ChangePin as i128 => {
+                let new_pin_enc = required_bytes(params, ClientPinParam::NewPinEnc as i128)?;
+                let pin_hash_enc = required_bytes(params, ClientPinParam::PinHashEnc as i128)?;
+                let pin_auth = required_bytes(params, ClientPinParam::PinUvAuthParam as i128)?;
+                let shared = self.shared_secret(protocol, params)?;
+
+                let mut message = new_pin_enc.to_vec();
+                message.extend_from_slice(pin_hash_enc);
+                if !protocol.verify(&shared, &message, pin_auth) {
+                    return Err(Ctap2Error::PinAuthInvalid);
+                }
+                self.check_pin_hash(protocol, &shared, pin_hash_enc)?;
+                self.store_new_pin(protocol, &shared, new_pin_enc)?;
+                Ok(None)
+            }
+            s if s == ClientPinSubCommand::GetPinToken as i128
+                || s == ClientPinSubCommand::GetPinUvAuthTokenUsingPinWithPermissions as i128 =>
+            {
+                if s == ClientPinSubCommand::GetPinUvAuthTokenUsingPinWithPermissions as i128
+                    && cbor::get_int(params, ClientPinParam::Permissions as i128).unwrap_or(0) == 0
+                {
+                    return Err(Ctap2Error::MissingParameter);
+                }
+                let pin_hash_enc = required_bytes(params, ClientPinParam::PinHashEnc as i128)?;
+                let shared = self.shared_secret(protocol, params)?;
+                self.check_pin_hash(protocol, &shared, pin_hash_enc)?;
+
+                self.pin_token = random_token();
+                let token_enc = protocol
+                    .encrypt(&shared, &self.pin_token)
+                    .map_err(|_| Ctap2Error::Processing)?;
+                let mut response = BTreeMap::new();
+                response.insert(int(0x02), Value::Bytes(token_enc));
+                Ok(Some(response))
+            }
+            _ => Err(Ctap2Error::InvalidSubcommand),
+        }
+    }
+
+    fn shared_secret(
+        &mut self,
+        protocol: PinUvAuthProtocol,
+        params: &CborMap,
+    ) -> Result<Vec<u8>, Ctap2Error> {
+        let platform_key = params
+            .get(&int(ClientPinParam::KeyAgreement as u8))
+            .ok_or(Ctap2Error::MissingParameter)?;
+        let key = self
+            .key_agreement
+            .take()
+            .ok_or(Ctap2Error::PinAuthInvalid)?;
+        key.shared_secret(protocol, platform_key)
+            .map_err(|_| Ctap2Error::InvalidParameter)
+    }
+
+    fn check_pin_hash(
+        &mut self,
+        protocol: PinUvAuthProtocol,
+        shared: &[u8],
+        pin_hash_enc: &[u8],
+    ) -> Result<(), Ctap2Error> {
+        let Some(stored) = &self.pin_hash else {
+            return Err(Ctap2Error::PinNotSet);
+        };
+        if self.pin_retries == 0 {
+            return Err(Ctap2Error::PinBlocked);
+        }
+        if self.consecutive_pin_failures >= MAX_CONSECUTIVE_PIN_FAILURES {
+            return Err(Ctap2Error::PinAuthBlocked);
+        }
+
+        let received = protocol
+            .decrypt(shared, pin_hash_enc)
+            .map_err(|_| Ctap2Error::PinInvalid)?;
+        if &received != stored {
+            self.pin_retries -= 1;
+            self.consecutive_pin_failures += 1;
+            self.key_agreement = None;
+            log::info!("Simulator: wrong PIN, {} retries left", self.pin_retries);
+            return Err(if self.pin_retries == 0 {
+                Ctap2Error::PinBlocked
+            } else if self.consecutive_pin_failures >= MAX_CONSECUTIVE_PIN_FAILURES {
+                Ctap2Error::PinAuthBlocked
+            } else {
+                Ctap2Error::PinInvalid
+            });
+        }
+
+        self.pin_retries = MAX_PIN_RETRIES;
+        self.consecutive_pin_failures = 0;
+        self.secret_unlocked = true;
+        Ok(())
+    }
+
+    fn store_new_pin(
+        &mut self,
+        protocol: PinUvAuthProtocol,
+        shared: &[u8],
+        new_pin_enc: &[u8],
+    ) -> Result<(), Ctap2Error> {
+        let padded = protocol
+            .decrypt(shared, new_pin_enc)
+            .map_err(|_| Ctap2Error::InvalidParameter)?;
+        let pin_len = padded.iter().position(|&b| b == 0).unwrap_or(padded.len());
+        let pin =
+            std::str::from_utf8(&padded[..pin_len]).map_err(|_| Ctap2Error::PinPolicyViolation)?;
+
+        if pin.chars().count() < self.min_pin_length as usize
+            || self.pin_policy.violation(pin).is_some()
+        {
+            return Err(Ctap2Error::PinPolicyViolation);
+        }
+
+        self.pin_hash = Some(pin_hash(pin));
+        self.pin_retries = MAX_PIN_RETRIES;
+        self.consecutive_pin_failures = 0;
+        self.pin_token = random_token();
+        log::info!("Simulator PIN updated");
+        Ok(())
+    }
+
+    /// Checks `pinUvAuthParam` against the current pinUvAuthToken. credentialManagement and
+    /// authenticatorConfig both carry the protocol at 0x03 and the signature at 0x04.
+    fn verify_token(&self, params: &CborMap, message: &[u8]) -> Result<(), Ctap2Error> {
+        let protocol = cbor::get_int(params, ConfigParam::PinUvAuthProtocol as i128)
+            .and_then(|v| PinUvAuthProtocol::from_version(v as u32))
+            .ok_or(Ctap2Error::MissingParameter)?;
+        let pin_auth = cbor::get_bytes(params, ConfigParam::PinUvAuthParam as i128)
+            .ok_or(Ctap2Error::PuatRequired)?;
+        if protocol.verify(&self.pin_token, message, pin_auth) {
+            Ok(())
+        } else {
+            Err(Ctap2Error::PinAuthInvalid)
+        }
+    }
+
+    // --- Credential management ---
+
+    fn credential_mgmt(&mut self, params: &CborMap) -> CtapResult {
+        let sub_command = cbor::get_int(params, CredentialMgmtParam::SubCommand as i128)
+            .ok_or(Ctap2Error::MissingParameter)?;
+        let sub_params = cbor::get_map(params, CredentialMgmtParam::SubCommandParams as i128);
+
+        if self.device_locked {
+            return Err(Ctap2Error::NotAllowed);
+        }
+
+        let needs_auth = [
+            CredentialMgmtSubCommand::GetCredsMetadata,
+            CredentialMgmtSubCommand::EnumerateRpsBegin,
+            CredentialMgmtSubCommand::EnumerateCredentialsBegin,
+            CredentialMgmtSubCommand::DeleteCredential,
+            CredentialMgmtSubCommand::UpdateUserInformation,
+        ]
+        .iter()
+        .any(|s| *s as i128 == sub_command);
+
+        if needs_auth {
+            let mut message = vec![sub_command as u8];
+            if let Some(sub_params) = sub_params {
+                message.extend(to_vec(&Value::Map(sub_params.clone())).unwrap_or_default());
+            }
+            self.verify_token(params, &message)?;
+        }
+
+        let mut response = BTreeMap::new();
+        match sub_command {
+            s if s == CredentialMgmtSubCommand::GetCredsMetadata as i128 => {
+                response.insert(
+                    int(CredentialMgmtResponse::ExistingResidentCredentialsCount as u8),
+                    int(self.credentials.len() as i128),
+                );
+                response.insert(
+                    int(CredentialMgmtResponse::MaxPossibleRemainingResidentCredentialsCount as u8),
+                    int((MAX_RESIDENT_CREDENTIALS - self.credentials.len()) as i128),
+                );
+            }
+            s if s == CredentialMgmtSubCommand::EnumerateRpsBegin as i128 => {
+                let mut rp_ids: Vec<String> = Vec::new();
+                for cred in &self.credentials {
+                    if !rp_ids.contains(&cred.rp_id) {
+                        rp_ids.push(cred.rp_id.clone());
+                    }
+                }
+                if rp_ids.is_empty() {
+                    return Err(Ctap2Error::NoCredentials);
+                }
+                response.insert(
+                    int(CredentialMgmtResponse::TotalRps as u8),
+                    int(rp_ids.len() as i128),
+                );
+                let first = rp_ids.remove(0);
+                self.rp_cursor = rp_ids;
+                self.insert_rp(&mut response, &first);
+            }
+            s if s == CredentialMgmtSubCommand::EnumerateRpsGetNextRp as i128 => {
+                if self.rp_cursor.is_empty() {
+                    return Err(Ctap2Error::NotAllowed);
+                }
+                let next = self.rp_cursor.remove(0);
+                self.insert_rp(&mut response, &next);
+            }
+            s if s == CredentialMgmtSubCommand::EnumerateCredentialsBegin as i128 => {
+                let rp_id_hash = sub_params
+                    .and_then(|p| cbor::get_bytes(p, CredentialMgmtSubParam::RpIdHash as i128))
+                    .ok_or(Ctap2Error::MissingParameter)?;
+                let mut creds: Vec<SimCredential> = self
+                    .credentials
+                    .iter()
+                    .filter(|c| rp_id_hash_of(&c.rp_id) == rp_id_hash)
+                    .cloned()
+                    .collect();
+                if creds.is_empty() {
+                    return Err(Ctap2Error::NoCredentials);
+                }
+                response.insert(
+                    int(CredentialMgmtResponse::TotalCredentials as u8),
+                    int(creds.len() as i128),
+                );
+                let first = creds.remove(0);
+                self.credential_cursor = creds;
+                insert_credential(&mut response, &first);
+            }
+            s if s == CredentialMgmtSubCommand::EnumerateCredentialsGetNextCredential as i128 => {
+                if self.credential_cursor.is_empty() {
+                    return Err(Ctap2Error::NotAllowed);
+                }
+                let next = self.credential_cursor.remove(0);
+                insert_credential(&mut response, &next);
+            }
+            s if s == CredentialMgmtSubCommand::DeleteCredential as i128 => {
+                let credential_id = sub_params
+                    .and_then(|p| descriptor_id(p))
+                    .ok_or(Ctap2Error::MissingParameter)?;
+                let before = self.credentials.len();
+                self.credentials
+                    .retain(|c| c.credential_id != credential_id);
+                if self.credentials.len() == before {
+                    return Err(Ctap2Error::NoCredentials);
+                }
+                log::info!(
+                    "Simulator deleted credential {}",
+                    hex::encode(credential_id)
+                );
+                return Ok(None);
+            }
+            s if s == CredentialMgmtSubCommand::UpdateUserInformation as i128 => {
+                let sub_params = sub_params.ok_or(Ctap2Error::MissingParameter)?;
+                let credential_id =
+                    descriptor_id(sub_params).ok_or(Ctap2Error::MissingParameter)?;
+                let user = cbor::get_map(sub_params, CredentialMgmtSubParam::User as i128)
+                    .ok_or(Ctap2Error::MissingParameter)?;
+                let cred = self
+                    .credentials
+                    .iter_mut()
+                    .find(|c| c.credential_id == credential_id)
+                    .ok_or(Ctap2Error::NoCredentials)?;
+                if cbor::get_text_keyed_bytes(user, "id") != Some(cred.user_id.as_slice()) {
+                    return Err(Ctap2Error::InvalidParameter);
+                }
+                cred.user_name = cbor::get_text(user, "name").unwrap_or_default().to_string();
+                cred.user_display_name = cbor::get_text(user, "displayName")
+                    .unwrap_or_default()
+                    .to_string();
+                return Ok(None);
+            }
+            _ => return Err(Ctap2Error::InvalidSubcommand),
+        }
+        Ok(Some(response))
+    }
+
+    fn insert_rp(&self, response: &mut CborMap, rp_id: &str) {
+        let rp_name = self
+            .credentials
+            .iter()
+            .find(|c| c.rp_id == rp_id)
+            .map(|c| c.rp_name.clone())
+            .unwrap_or_default();
+
+        let mut rp = BTreeMap::new();
+        rp.insert(Value::Text("id".into()), Value::Text(rp_id.into()));
+        rp.insert(Value::Text("name".into()), Value::Text(rp_name));
+        response.insert(int(CredentialMgmtResponse::Rp as u8), Value::Map(rp));
+        response.insert(
+            int(CredentialMgmtResponse::RpIdHash as u8),
+            Value::Bytes(rp_id_hash_of(rp_id)),
+        );
+    }
+
+    // --- authenticatorConfig ---
+
+    // --- getAssertion ---
+
+    /// Just enough of getAssertion to hand out credBlobs: one credential from the allow list,
+    /// a token for user verification, and no signature.
+    fn get_assertion(&mut self, params: &CborMap) -> CtapResult {
+        if self.device_locked {
+            return Err(Ctap2Error::NotAllowed);
+        }
+        let rp_id = params
+            .get(&int(GetAssertionParam::RpId as u8))
+            .and_then(|v| match v {
+                Value::Text(rp_id) => Some(rp_id.as_str()),
+                _ => None,
+            })
+            .ok_or(Ctap2Error::MissingParameter)?;
+        let client_data_hash = required_bytes(params, GetAssertionParam::ClientDataHash as i128)?;
+
+        let protocol = cbor::get_int(params, GetAssertionParam::PinUvAuthProtocol as i128)
+            .and_then(|v| PinUvAuthProtocol::from_version(v as u32))
+            .ok_or(Ctap2Error::MissingParameter)?;
+        let pin_auth = cbor::get_bytes(params, GetAssertionParam::PinUvAuthParam as i128)
+            .ok_or(Ctap2Error::PuatRequired)?;
+        if !protocol.verify(&self.pin_token, client_data_hash, pin_auth) {
+            return Err(Ctap2Error::PinAuthInvalid);
+        }
+
+        let allowed: Vec<&[u8]> = match params.get(&int(GetAssertionParam::AllowList as u8)) {
+            Some(Value::Array(list)) => list
+                .iter()
+                .filter_map(|d| match d {
+                    Value::Map(d) => cbor::get_text_keyed_bytes(d, "id"),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let cred = self
+            .credentials
+            .iter()
+            .find(|c| c.rp_id == rp_id && allowed.contains(&c.credential_id.as_slice()))
+            .ok_or(Ctap2Error::NoCredentials)?;
+
+        let wants_cred_blob = matches!(
+            cbor::get_map(params, GetAssertionParam::Extensions as i128)
+                .and_then(|e| e.get(&Value::Text("credBlob".into()))),
+            Some(Value::Bool(true))
+        );
+        let mut flags = AuthenticatorFlags::USER_VERIFIED;
+        let mut extensions = BTreeMap::new();
+        if wants_cred_blob {
+            flags |= AuthenticatorFlags::EXTENSION_DATA;
+            extensions.insert(
+                Value::Text("credBlob".into()),
+                Value::Bytes(cred.cred_blob.clone().unwrap_or_default()),
+            );
+        }
+
+        let mut auth_data = rp_id_hash_of(rp_id);
+        auth_data.push(flags.bits());
+        auth_data.extend(0u32.to_be_bytes());
+        if !extensions.is_empty() {
+            auth_data.extend(to_vec(&Value::Map(extensions)).unwrap_or_default());
+        }
+
+        let mut descriptor = BTreeMap::new();
+        descriptor.insert(
+            Value::Text("id".into()),
+            Value::Bytes(cred.credential_id.clone()),
+        );
+        descriptor.insert(Value::Text("type".into()), Value::Text("public-key".into()));
+
+        let mut response = BTreeMap::new();
+        response.insert(
+            int(GetAssertionResponse::Credential as u8),
+            Value::Map(descriptor),
+        );
+        response.insert(
+            int(GetAssertionResponse::AuthData as u8),
+            Value::Bytes(auth_data),
+        );
+        response.insert(
+            int(GetAssertionResponse::Signature as u8),
+            Value::Bytes(Vec::new()),
+        );
+        Ok(Some(response))
+    }
+
+    // --- Large blobs ---
+
+    fn large_blobs(&mut self, params: &CborMap) -> CtapResult {
+        let offset = cbor::get_int(params, LargeBlobsParam::Offset as i128)
+            .ok_or(Ctap2Error::MissingParameter)? as usize;
+        let get = cbor::get_int(params, LargeBlobsParam::Get as i128);
+        let set = cbor::get_bytes(params, LargeBlobsParam::Set as i128);
+
+        match (get, set) {
+            (Some(length), None) => {
+                let length = length as usize;
+                if length > MAX_FRAGMENT_LENGTH {
+                    return Err(Ctap2Error::InvalidLength);
+                }
+                if offset > self.large_blobs.len() {
+                    return Err(Ctap2Error::InvalidParameter);
+                }
+                let end = (offset + length).min(self.large_blobs.len());
+                let mut response = BTreeMap::new();
+                response.insert(
+                    int(LargeBlobsResponse::Config as u8),
+                    Value::Bytes(self.large_blobs[offset..end].to_vec()),
+                );
+                Ok(Some(response))
+            }
+            (None, Some(fragment)) => {
+                if fragment.len() > MAX_FRAGMENT_LENGTH {
+                    return Err(Ctap2Error::InvalidLength);
+                }
+                if self.pin_hash.is_some() || self.always_uv {
+                    let protocol =
+                        cbor::get_int(params, LargeBlobsParam::PinUvAuthProtocol as i128)
+                            .and_then(|v| PinUvAuthProtocol::from_version(v as u32))
+                            .ok_or(Ctap2Error::MissingParameter)?;
+                    let pin_auth = cbor::get_bytes(params, LargeBlobsParam::PinUvAuthParam as i128)
+                        .ok_or(Ctap2Error::PuatRequired)?;
+                    let message = large_blobs::write_message(offset, fragment);
+                    if !protocol.verify(&self.pin_token, &message, pin_auth) {
+                        return Err(Ctap2Error::PinAuthInvalid);
+                    }
+                }
+
+                if offset == 0 {
+                    let length = cbor::get_int(params, LargeBlobsParam::Length as i128)
+                        .ok_or(Ctap2Error::InvalidParameter)?
+                        as usize;
+                    if length > MAX_LARGE_BLOB_SIZE {
+                        return Err(Ctap2Error::LargeBlobStorageFull);
+                    }
+                    if length <= large_blobs::TRAILER_LEN {
+                        return Err(Ctap2Error::InvalidParameter);
+                    }
+                    self.large_blob_write = Some((Vec::new(), length));
+                }
+                let (buffer, expected) = self
+                    .large_blob_write
+                    .as_mut()
+                    .ok_or(Ctap2Error::InvalidSeq)?;
+                if offset != buffer.len() {
+                    return Err(Ctap2Error::InvalidSeq);
+                }
+                if buffer.len() + fragment.len() > *expected {
+                    return Err(Ctap2Error::InvalidParameter);
+                }
+                buffer.extend_from_slice(fragment);
+
+                if buffer.len() == *expected {
+                    let (serialized, _) = self.large_blob_write.take().unwrap_or_default();
+                    if large_blobs::parse_array(&serialized).is_none() {
+                        return Err(Ctap2Error::IntegrityFailure);
+                    }
+                    log::info!(
+                        "Simulator large-blob array replaced ({} bytes)",
+                        serialized.len()
+                    );
+                    self.large_blobs = serialized;
+                }
+                Ok(None)
+            }
+            _ => Err(Ctap2Error::InvalidParameter),
+        }
+    }
+
+    fn config(&mut self, params: &CborMap) -> CtapResult {
+        let sub_command = cbor::get_int(params, ConfigParam::SubCommand as i128)
+            .ok_or(Ctap2Error::MissingParameter)?;
+        let sub_params = cbor::get_map(params, ConfigParam::SubCommandParams as i128);
+
+        // authenticate(pinUvAuthToken, 32×0xff || 0x0d || uint8(subCommand) || subCommandParams)
+        let mut message = vec![0xff; 32];
+        message.push(CtapCommand::Config as u8);
+        message.push(sub_command as u8);
+        if let Some(sub_params) = sub_params {
+            message.extend(to_vec(&Value::Map(sub_params.clone())).unwrap_or_default());
+        }
+        self.verify_token(params, &message)?;
+
+        match sub_command {
+            s if s == ConfigSubCommand::ToggleAlwaysUv as i128 => {
+                self.always_uv = !self.always_uv;
+                log::info!("Simulator alwaysUv is now {}", self.always_uv);
+                Ok(None)
+            }
+            s if s == ConfigSubCommand::EnableEnterpriseAttestation as i128 => {
+                if self.ea_certificate.is_none() {
+                    return Err(Ctap2Error::NotAllowed);
+                }
+                self.enterprise_attestation = true;
+                Ok(None)
+            }
+            s if s == ConfigSubCommand::SetMinPinLength as i128 => {
+                let new_length = sub_params
+                    .and_then(|p| cbor::get_int(p, ConfigSubCommandParam::NewMinPinLength as i128))
+                    .unwrap_or(self.min_pin_length as i128);
+                if new_length < self.min_pin_length as i128 || new_length > 63 {
+                    return Err(Ctap2Error::PinPolicyViolation);
+                }
+                self.min_pin_length = new_length as u8;
+                Ok(None)
+            }
+            s if s == ConfigSubCommand::VendorPrototype as i128 => {
+                let sub_params = sub_params.ok_or(Ctap2Error::MissingParameter)?;
+                let vendor_cmd = cbor::get_int(sub_params, VendorSubParam::VendorParam as i128)
+                    .and_then(|v| VendorConfigCommand::from_u64(v as u64))
+                    .ok_or(Ctap2Error::InvalidParameter)?;
+
+                if matches!(
+                    vendor_cmd,
+                    VendorConfigCommand::AuthEncryptionEnable
+                        | VendorConfigCommand::AuthEncryptionDisable
+                ) {
+                    let sealed = required_bytes(sub_params, 0x02)?;
+                    let host_key = self
+                        .secure_channel
+                        .take()
+                        .ok_or(Ctap2Error::NotAllowed)?
+                        .open(sealed)
+                        .map_err(|_| Ctap2Error::InvalidParameter)?;
+                    return self.set_auth_encryption(vendor_cmd, host_key);
+                }
+
+                if vendor_cmd == VendorConfigCommand::EnterpriseAttestationUpload {
+                    // Byte string parameters travel at 0x02
+                    let certificate = required_bytes(sub_params, 0x02)?;
+                    if !self.ea_key_generated || certificate.first() != Some(&0x30) {
+                        return Err(Ctap2Error::InvalidParameter);
+                    }
+                    self.ea_certificate = Some(certificate.to_vec());
+                    log::info!("Simulator stored enterprise attestation certificate");
+                    return Ok(None);
+                }
+
+                let value = cbor::get_int(sub_params, VendorSubParam::VendorParamInt as i128)
+                    .ok_or(Ctap2Error::MissingParameter)?;
+
+                match vendor_cmd {
+                    VendorConfigCommand::PhysicalVidPid => {
+                        self.vid = (value >> 16) as u16;
+                        self.pid = (value & 0xFFFF) as u16;
+                    }
+                    VendorConfigCommand::PhysicalLedGpio => self.led_gpio = value as u8,
+                    VendorConfigCommand::PhysicalLedBrightness => self.led_brightness = value as u8,
+                    VendorConfigCommand::PhysicalOptions => self.opts = value as u16,
+                    VendorConfigCommand::PinComplexityPolicy => {
+                        self.pin_policy = PinComplexityPolicy::from_bits(value as u8)
+                            .ok_or(Ctap2Error::InvalidParameter)?
+                    }
+                    _ => return Err(Ctap2Error::InvalidParameter),
+                }
+                log::info!("Simulator applied vendor config {}", vendor_cmd);
+                Ok(None)
+            }
+            _ => Err(Ctap2Error::InvalidSubcommand),
+        }
+    }
+
+    fn set_auth_encryption(
+        &mut self,
+        vendor_cmd: VendorConfigCommand,
+        host_key: Vec<u8>,
+    ) -> CtapResult {
+        if vendor_cmd == VendorConfigCommand::AuthEncryptionEnable {
+            if self.auth_encryption_key.is_some() {
+                return Err(Ctap2Error::NotAllowed);
+            }
+            if host_key.len() != 32 {
+                return Err(Ctap2Error::InvalidLength);
+            }
+            self.auth_encryption_key = Some(host_key);
+        } else {
+            if self.device_locked || self.auth_encryption_key.as_ref() != Some(&host_key) {
+                return Err(Ctap2Error::NotAllowed);
+            }
+            self.auth_encryption_key = None;
+        }
+        log::info!(
+            "Simulator authentication encryption is now {}",
+            self.auth_encryption_key.is_some()
+        );
+        Ok(None)
+    }
+
+    // --- pico-fido vendor commands ---
+
+    fn process_vendor(&mut self, vendor_cmd: u8, params: &[u8]) -> CtapResult {
+        let params = parse_params(params)?;
+        let sub_command = cbor::get_int(&params, 0x01).ok_or(Ctap2Error::MissingParameter)?;
+
+        match vendor_cmd {
+            c if c == VendorCommand::Memory as u8
+                && sub_command == MemorySubCommand::GetStats as i128 =>
+            {
+                let mut response = BTreeMap::new();
+                for (key, value) in [
+                    (
+                        MemoryResponseKey::FreeSpace,
+                        self.flash_total - self.flash_used,
+                    ),
+                    (MemoryResponseKey::UsedSpace, self.flash_used),
+                    (MemoryResponseKey::TotalSpace, self.flash_total),
+                    (MemoryResponseKey::NumFiles, 12),
+                    (MemoryResponseKey::FlashSize, 2 * 1024 * 1024),
+                ] {
+                    response.insert(int(key as u8), int(value));
+                }
+                Ok(Some(response))
+            }
+            c if c == VendorCommand::PhysicalOptions as u8
+                && sub_command == PhysicalOptionsSubCommand::GetOptions as i128 =>
+            {
+                let mut response = BTreeMap::new();
+                response.insert(Value::Text("gpio".into()), int(self.led_gpio));
+                response.insert(Value::Text("brightness".into()), int(self.led_brightness));
+                Ok(Some(response))
+            }
+            c if c == VendorCommand::Backup as u8
+                && sub_command == BackupSubCommand::GetEncryptedBackup as i128 =>
+            {
+                if self.device_locked {
+                    return Err(Ctap2Error::NotAllowed);
+                }
+                if !self.secret_unlocked {
+                    return Err(Ctap2Error::PinAuthInvalid);
+                }
+                let mut response = BTreeMap::new();
+                response.insert(int(0x01), Value::Bytes(seal_backup(&self.device_secret)));
+                Ok(Some(response))
+            }
+            c if c == VendorCommand::Backup as u8
+                && sub_command == BackupSubCommand::RestoreEncryptedBackup as i128 =>
+            {
+                let backup = cbor::get_map(&params, 0x02)
+                    .and_then(|p| cbor::get_bytes(p, VendorSubParam::VendorParam as i128))
+                    .ok_or(Ctap2Error::MissingParameter)?;
+                let secret = open_backup(backup).ok_or(Ctap2Error::InvalidParameter)?;
+                if secret.len() != self.device_secret.len() {
+                    return Err(Ctap2Error::InvalidLength);
+                }
+                self.device_secret = secret;
+                log::info!("Simulator restored device secret from backup");
+                Ok(None)
+            }
+            c if c == VendorCommand::ManageSecurityEnvironment as u8
+                && sub_command == MseSubCommand::KeyAgreement as i128 =>
+            {
+                let platform_key = cbor::get_map(&params, 0x02)
+                    .and_then(|p| p.get(&int(VendorSubParam::CoseKey as u8)))
+                    .ok_or(Ctap2Error::MissingParameter)?;
+                let key = KeyAgreementKey::generate().map_err(|_| Ctap2Error::Other)?;
+                let cose_key = key.cose_key();
+                self.secure_channel = Some(
+                    key.agree(platform_key, SecureChannel::derive)
+                        .map_err(|_| Ctap2Error::InvalidParameter)?,
+                );
+                let mut response = BTreeMap::new();
+                response.insert(int(0x01), cose_key);
+                Ok(Some(response))
+            }
+            c if c == VendorCommand::Unlock as u8
+                && sub_command == UnlockSubCommand::Unlock as i128 =>
+            {
+                let Some(expected) = &self.auth_encryption_key else {
+                    return Err(Ctap2Error::NotAllowed);
+                };
+                let sealed = cbor::get_map(&params, 0x02)
+                    .and_then(|p| cbor::get_bytes(p, VendorSubParam::VendorParam as i128))
+                    .ok_or(Ctap2Error::MissingParameter)?;
+                let host_key = self
+                    .secure_channel
+                    .take()
+                    .ok_or(Ctap2Error::NotAllowed)?
+                    .open(sealed)
+                    .map_err(|_| Ctap2Error::InvalidParameter)?;
+                if &host_key != expected {
+                    return Err(Ctap2Error::PinAuthInvalid);
+                }
+                self.device_locked = false;
+                log::info!("Simulator device secret unlocked");
+                Ok(None)
+            }
+            c if c == VendorCommand::EnterpriseAttestation as u8
+                && sub_command == EnterpriseAttestationSubCommand::GenerateCsr as i128 =>
+            {
+                self.ea_key_generated = true;
+                self.ea_certificate = None;
+                let mut response = BTreeMap::new();
+                response.insert(int(0x01), Value::Bytes(simulated_csr(&self.serial)));
+                Ok(Some(response))
+            }
+            _ => Err(Ctap2Error::InvalidCommand),
+        }
+    }
+}
+
+/// Encrypts the device secret the way a backup leaves the key: a random nonce, then the
+/// ChaCha20-Poly1305 ciphertext and tag. The key is shared by every simulator, as the firmware's
+/// is by every pico-fido key, so a backup restores on another one.
+fn seal_backup(secret: &[u8]) -> Vec<u8> {
+    let mut nonce = [0u8; BACKUP_NONCE_LEN];
+    rand::rng().fill(&mut nonce);
+    let mut sealed = secret.to_vec();
+    backup_key()
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut sealed,
+        )
+        .expect("secret fits in one ChaCha20-Poly1305 message");
+    let mut blob = nonce.to_vec();
+    blob.extend(sealed);
+    blob
+}
+
+/// Decrypts a backup made by [`seal_backup`], or returns `None` if it was altered.
+fn open_backup(blob: &[u8]) -> Option<Vec<u8>> {
+    if blob.len() < BACKUP_NONCE_LEN {
+        return None;
+    }
+    let (nonce, sealed) = blob.split_at(BACKUP_NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+    let mut sealed = sealed.to_vec();
+    let secret = backup_key()
+        .open_in_place(nonce, Aad::empty(), &mut sealed)
+        .ok()?;
+    Some(secret.to_vec())
+}
+
+fn backup_key() -> LessSafeKey {
+    LessSafeKey::new(
+        UnboundKey::new(&CHACHA20_POLY1305, &BACKUP_KEY)
+            .expect("ChaCha20-Poly1305 key is 32 bytes"),
+    )
+}
+
+fn parse_params(params: &[u8]) -> Result<CborMap, Ctap2Error> {
+    match from_slice(params) {
+        Ok(Value::Map(map)) => Ok(map),
+        Ok(_) => Err(Ctap2Error::CborUnexpectedType),
+        Err(_) => Err(Ctap2Error::InvalidCbor),
+    }
+}
+
+/// Stand-in for the CSR the firmware builds: a DER SEQUENCE naming the key, followed by a
+/// random "public key". Good enough to exercise the PEM export, not to be signed.
+fn simulated_csr(serial: &[u8]) -> Vec<u8> {
+    let der = |tag: u8, content: &[u8]| {
+        let mut out = vec![tag];
+        if content.len() > 0x7F {
+            out.push(0x81);
+        }
+        out.push(content.len() as u8);
+        out.extend(content);
+        out
+    };
+    let mut public_key = vec![0x04; 65];
+    rand::rng().fill(&mut public_key[1..]);
+
+    let mut body = der(
+        0x0C,
+        format!("Pico Fido EE Serial {}", hex::encode_upper(serial)).as_bytes(),
+    );
+    body.extend(der(0x04, &public_key));
+    der(0x30, &body)
+}
+
+fn required_bytes(params: &CborMap, key: i128) -> Result<&[u8], Ctap2Error> {
+    cbor::get_bytes(params, key).ok_or(Ctap2Error::MissingParameter)
+}
+
+fn rp_id_hash_of(rp_id: &str) -> Vec<u8> {
+    digest::digest(&digest::SHA256, rp_id.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+fn descriptor_id(sub_params: &CborMap) -> Option<&[u8]> {
+    cbor::get_map(sub_params, CredentialMgmtSubParam::CredentialId as i128)
+        .and_then(|d| cbor::get_text_keyed_bytes(d, "id"))
+}
+
+fn insert_credential(response: &mut CborMap, cred: &SimCredential) {
+    let mut user = BTreeMap::new();
+    user.insert(Value::Text("id".into()), Value::Bytes(cred.user_id.clone()));
+    user.insert(
+        Value::Text("name".into()),
+        Value::Text(cred.user_name.clone()),
+    );
+    user.insert(
+        Value::Text("displayName".into()),
+        Value::Text(cred.user_display_name.clone()),
+    );
+
+    let mut descriptor = BTreeMap::new();
+    descriptor.insert(
+        Value::Text("id".into()),
+        Value::Bytes(cred.credential_id.clone()),
+    );
+    descriptor.insert(Value::Text("type".into()), Value::Text("public-key".into()));
+
+    response.insert(int(CredentialMgmtResponse::User as u8), Value::Map(user));
+    response.insert(
+        int(CredentialMgmtResponse::CredentialId as u8),
+        Value::Map(descriptor),
+    );
+    response.insert(
+        int(CredentialMgmtResponse::PublicKey as u8),
+        cred.public_key.clone(),
+    );
+    response.insert(
+        int(CredentialMgmtResponse::CredProtect as u8),
+        int(cred.cred_protect),
+    );
+    if let Some(key) = &cred.large_blob_key {
+        response.insert(
+            int(CredentialMgmtResponse::LargeBlobKey as u8),
+            Value::Bytes(key.clone()),
+        );
+    }
+}
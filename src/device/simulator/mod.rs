@@ -0,0 +1,438 @@
+//! Software emulation of a pico-fido key.
+//!
+//! The simulator answers the Rescue Applet APDUs and the CTAP/vendor commands PicoForge uses,
+//! keeping all state in memory. It backs the `--demo` mode of the GUI and can be driven directly
+//! through [`ApduTransport`] / [`CtapHidTransport`] in end-to-end tests.
+
+mod ctap;
+mod rescue;
+
+use rand::RngExt;
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair, Ed25519KeyPair, KeyPair};
+use serde_cbor_2::Value;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+use crate::device::fido::auth_encryption::SecureChannel;
+use crate::device::fido::constants::{
+    CoseAlgorithm, CoseCurve, CoseKeyParam, CoseKeyType, CredProtectPolicy, Ctap2Error,
+    MAX_PIN_RETRIES,
+};
+use crate::device::fido::large_blobs;
+use crate::device::fido::pin::{KeyAgreementKey, pin_hash};
+use crate::device::fido::pin_policy::PinComplexityPolicy;
+use crate::device::transport::{ApduTransport, CtapHidTransport, apdu, strip_ctap_status};
+use crate::device::types::DeviceHandle;
+use crate::error::PFError;
+
+/// Reader name reported for the simulated Rescue interface.
+pub const SIMULATOR_READER: &str = "PicoForge Simulator";
+/// HID path reported for the simulated FIDO interface.
+pub const SIMULATOR_HID_PATH: &str = "simulator://pico-fido";
+/// ATR of the simulated card: T=1, with card capabilities advertising extended Lc and Le fields,
+/// since the simulator does not implement command chaining.
+const SIMULATOR_ATR: &[u8] = &[0x3B, 0x85, 0x01, 0x80, 0x73, 0xC0, 0x21, 0xC0, 0x56];
+/// PIN the demo key is provisioned with.
+pub const DEMO_PIN: &str = "123456";
+
+static DEMO_MODE: AtomicBool = AtomicBool::new(false);
+static SIMULATOR: OnceLock<Simulator> = OnceLock::new();
+
+/// Makes device discovery report only the simulated key.
+pub fn enable_demo_mode() {
+    log::info!(
+        "Demo mode enabled: using the built-in simulator (PIN: {})",
+        DEMO_PIN
+    );
+    DEMO_MODE.store(true, Ordering::Relaxed);
+}
+
+pub fn is_demo_mode() -> bool {
+    DEMO_MODE.load(Ordering::Relaxed)
+}
+
+/// The simulator instance shared by the whole application in demo mode.
+pub fn global() -> &'static Simulator {
+    SIMULATOR.get_or_init(Simulator::new)
+}
+
+/// A simulated credential stored on the key.
+#[derive(Debug, Clone)]
+struct SimCredential {
+    rp_id: String,
+    rp_name: String,
+    user_id: Vec<u8>,
+    user_name: String,
+    user_display_name: String,
+    credential_id: Vec<u8>,
+    /// COSE_Key of a freshly generated key pair. The simulator never signs, so the private
+    /// half is dropped.
+    public_key: Value,
+    large_blob_key: Option<Vec<u8>>,
+    cred_protect: u8,
+    cred_blob: Option<Vec<u8>>,
+}
+
+/// Everything the simulated key remembers.
+struct State {
+    serial: [u8; 8],
+    firmware: (u8, u8),
+
+    // PHY configuration (Rescue TLVs / FIDO vendor config)
+    vid: u16,
+    pid: u16,
+    product_name: String,
+    led_gpio: u8,
+    led_brightness: u8,
+    touch_timeout: u8,
+    led_driver: Option<u8>,
+    opts: u16,
+    curves: u32,
+
+    flash_used: u32,
+    flash_total: u32,
+    secure_boot: bool,
+    secure_lock: bool,
+
+    // FIDO state
+    pin_hash: Option<Vec<u8>>,
+    pin_retries: u8,
+    /// Wrong PINs since the last power cycle or correct PIN.
+    consecutive_pin_failures: u8,
+    min_pin_length: u8,
+    always_uv: bool,
+    /// When the key was last powered up, for the authenticatorReset window.
+    powered_up: Instant,
+    pin_policy: PinComplexityPolicy,
+    /// Whether an enterprise attestation key pair (and CSR) has been generated.
+    ea_key_generated: bool,
+    /// Stands in for the firmware's device key. Only handed out (as a backup) once a correct
+    /// PIN has unlocked it.
+    device_secret: Vec<u8>,
+    secret_unlocked: bool,
+    /// Host key the device secret is encrypted with, while authentication encryption is on.
+    auth_encryption_key: Option<Vec<u8>>,
+    /// The encrypted device secret has not been unlocked since power-up.
+    device_locked: bool,
+    secure_channel: Option<SecureChannel>,
+    ea_certificate: Option<Vec<u8>>,
+    enterprise_attestation: bool,
+    key_agreement: Option<KeyAgreementKey>,
+    pin_token: Vec<u8>,
+    credentials: Vec<SimCredential>,
+    /// Serialized large-blob array, trailer included.
+    large_blobs: Vec<u8>,
+    /// Fragments of a large-blob array being written, and its announced length.
+    large_blob_write: Option<(Vec<u8>, usize)>,
+
+    // Cursors for credential management `getNext*` sub commands
+    rp_cursor: Vec<String>,
+    credential_cursor: Vec<SimCredential>,
+
+    /// The CTAPHID channel ID handed out next.
+    next_cid: u32,
+}
+
+impl State {
+    fn demo() -> Self {
+        let demo_credential =
+            |rp_id: &str, rp_name: &str, user: &str, display: &str, algorithm: CoseAlgorithm| {
+                let mut credential_id = vec![0u8; 32];
+                rand::rng().fill(&mut credential_id[..]);
+                SimCredential {
+                    rp_id: rp_id.into(),
+                    rp_name: rp_name.into(),
+                    user_id: user.as_bytes().to_vec(),
+                    user_name: user.into(),
+                    user_display_name: display.into(),
+                    credential_id,
+                    public_key: generate_public_key(algorithm),
+                    large_blob_key: Some(random_token()),
+                    cred_protect: CredProtectPolicy::UserVerificationOptional as u8,
+                    cred_blob: None,
+                }
+            };
+        let mut credentials = vec![
+            demo_credential(
+                "github.com",
+                "GitHub",
+                "octocat",
+                "The Octocat",
+                CoseAlgorithm::ES256,
+            ),
+            demo_credential(
+                "google.com",
+                "Google",
+                "jane.doe@gmail.com",
+                "Jane Doe",
+                CoseAlgorithm::ES256,
+            ),
+            demo_credential(
+                "example.com",
+                "Example",
+                "jane",
+                "Jane (Example)",
+                CoseAlgorithm::EdDSA,
+            ),
+            demo_credential(
+                "example.com",
+                "Example",
+                "admin",
+                "Administrator",
+                CoseAlgorithm::ES256,
+            ),
+        ];
+        // Sensitive accounts as a security review would want them.
+        credentials[1].cred_protect = CredProtectPolicy::UserVerificationRequired as u8;
+        credentials[1].cred_blob = Some(b"recovery-hint: blue notebook".to_vec());
+        credentials[3].cred_protect =
+            CredProtectPolicy::UserVerificationOptionalWithCredentialIdList as u8;
+
+        // One blob per GitHub credential, plus one left behind by a deleted credential.
+        let demo_blob = |key: &[u8], size: usize| {
+            large_blobs::encrypt_entry(key, &vec![0x5A; size], size as u64 * 3)
+                .expect("demo large blob")
+        };
+        let large_blob_entries = [
+            demo_blob(credentials[0].large_blob_key.as_deref().unwrap(), 180),
+            demo_blob(&random_token(), 64),
+        ];
+
+        Self {
+            serial: [0xDE, 0x40, 0x00, 0x00, 0x12, 0x34, 0x56, 0x78],
+            firmware: (7, 2),
+            vid: 0x2E8A,
+            pid: 0x10FE,
+            product_name: "Pico Key (Demo)".into(),
+            led_gpio: 25,
+            led_brightness: 128,
+            touch_timeout: 15,
+            led_driver: Some(1),
+            opts: 0x02,
+            curves: 0x08,
+            flash_used: 96 * 1024,
+            flash_total: 1024 * 1024,
+            secure_boot: false,
+            secure_lock: false,
+            pin_hash: Some(pin_hash(DEMO_PIN)),
+            pin_retries: MAX_PIN_RETRIES,
+            consecutive_pin_failures: 0,
+            min_pin_length: 4,
+            always_uv: false,
+            powered_up: Instant::now(),
+            pin_policy: PinComplexityPolicy::empty(),
+            ea_key_generated: false,
+            device_secret: random_token(),
+            secret_unlocked: false,
+            auth_encryption_key: None,
+            device_locked: false,
+            secure_channel: None,
+            ea_certificate: None,
+            enterprise_attestation: false,
+            key_agreement: None,
+            pin_token: random_token(),
+            credentials,
+            large_blobs: large_blobs::serialize_array(&large_blob_entries)
+                .expect("demo large-blob array"),
+            large_blob_write: None,
+            rp_cursor: Vec::new(),
+            credential_cursor: Vec::new(),
+            next_cid: 1,
+        }
+    }
+
+    fn allocate_cid(&mut self) -> u32 {
+        let cid = self.next_cid;
+        self.next_cid += 1;
+        cid
+    }
+
+    fn is_allocated(&self, cid: u32) -> bool {
+        (1..self.next_cid).contains(&cid)
+    }
+
+    /// Forgets everything that does not survive a power cycle.
+    fn power_cycle(&mut self) {
+        self.key_agreement = None;
+        self.consecutive_pin_failures = 0;
+        self.secret_unlocked = false;
+        self.powered_up = Instant::now();
+        self.device_locked = self.auth_encryption_key.is_some();
+        self.secure_channel = None;
+        self.large_blob_write = None;
+        self.pin_token = random_token();
+        self.rp_cursor.clear();
+        self.credential_cursor.clear();
+    }
+}
+
+/// Generates an ES256 (P-256) or EdDSA (Ed25519) key pair and returns its public COSE_Key.
+fn generate_public_key(algorithm: CoseAlgorithm) -> Value {
+    let rng = SystemRandom::new();
+    let param = |p: CoseKeyParam| Value::Integer(p as i128);
+    let mut key = BTreeMap::new();
+    key.insert(param(CoseKeyParam::Alg), Value::Integer(algorithm as i128));
+
+    if algorithm == CoseAlgorithm::EdDSA {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).expect("Ed25519 key generation");
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).expect("fresh Ed25519 key");
+        key.insert(
+            param(CoseKeyParam::Kty),
+            Value::Integer(CoseKeyType::Okp as i128),
+        );
+        key.insert(
+            param(CoseKeyParam::Crv),
+            Value::Integer(CoseCurve::Ed25519 as i128),
+        );
+        key.insert(
+            param(CoseKeyParam::X),
+            Value::Bytes(pair.public_key().as_ref().to_vec()),
+        );
+    } else {
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            .expect("P-256 key generation");
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+            .expect("fresh P-256 key");
+        // Uncompressed point: 0x04 || x || y
+        let point = pair.public_key().as_ref();
+        key.insert(
+            param(CoseKeyParam::Kty),
+            Value::Integer(CoseKeyType::Ec2 as i128),
+        );
+        key.insert(
+            param(CoseKeyParam::Crv),
+            Value::Integer(CoseCurve::P256 as i128),
+        );
+        key.insert(param(CoseKeyParam::X), Value::Bytes(point[1..33].to_vec()));
+        key.insert(param(CoseKeyParam::Y), Value::Bytes(point[33..65].to_vec()));
+    }
+    Value::Map(key)
+}
+
+fn random_token() -> Vec<u8> {
+    let mut token = vec![0u8; 32];
+    rand::rng().fill(&mut token[..]);
+    token
+}
+
+/// Handle to a simulated key. Clones share the same state.
+#[derive(Clone)]
+pub struct Simulator {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulator {
+    /// Creates a key provisioned with a PIN and a few demo passkeys.
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State::demo())),
+        }
+    }
+
+    /// Enumeration entry for this key, exposing both the Rescue and FIDO interfaces.
+    pub fn device_handle(&self) -> DeviceHandle {
+        let state = self.state.lock().unwrap();
+        DeviceHandle {
+            reader_name: Some(SIMULATOR_READER.into()),
+            hid_path: Some(SIMULATOR_HID_PATH.into()),
+            vid: state.vid,
+            pid: state.pid,
+            product_name: state.product_name.clone(),
+            serial: Some(hex::encode_upper(state.serial)),
+            firmware_version: Some(format!("{}.{}", state.firmware.0, state.firmware.1)),
+        }
+    }
+
+    /// The device secret backups carry, for checking them in tests.
+    #[cfg(test)]
+    pub(crate) fn device_secret(&self) -> Vec<u8> {
+        self.state.lock().unwrap().device_secret.clone()
+    }
+
+    /// Opens the simulated FIDO HID interface. Like a real device, the USB identity is captured
+    /// when the interface is opened.
+    pub fn open_hid(&self) -> SimulatedHid {
+        let mut state = self.state.lock().unwrap();
+        SimulatedHid {
+            simulator: self.clone(),
+            cid: state.allocate_cid(),
+            vid: state.vid,
+            pid: state.pid,
+            product_name: state.product_name.clone(),
+        }
+    }
+}
+
+impl ApduTransport for Simulator {
+    fn transmit(&self, apdu: &[u8]) -> Result<Vec<u8>, PFError> {
+        log::trace!("Simulator APDU: {:02X?}", apdu);
+        Ok(self.state.lock().unwrap().process_apdu(apdu))
+    }
+
+    fn supports_extended_length(&self) -> bool {
+        apdu::atr_supports_extended_length(SIMULATOR_ATR)
+    }
+}
+
+/// The FIDO HID interface of a [`Simulator`].
+pub struct SimulatedHid {
+    simulator: Simulator,
+    cid: u32,
+    vid: u16,
+    pid: u16,
+    product_name: String,
+}
+
+impl CtapHidTransport for SimulatedHid {
+    fn send_cbor(&self, cmd: u8, payload: &[u8]) -> Result<Vec<u8>, PFError> {
+        log::trace!("Simulator CTAPHID 0x{:02X}: {:02X?}", cmd, payload);
+        let response = self
+            .simulator
+            .state
+            .lock()
+            .unwrap()
+            .process_ctaphid(cmd, payload);
+        strip_ctap_status(cmd, &response)
+    }
+
+    fn wink(&self) -> Result<bool, PFError> {
+        log::info!("Simulated key blinks its LED");
+        Ok(true)
+    }
+
+    fn vid(&self) -> u16 {
+        self.vid
+    }
+
+    fn pid(&self) -> u16 {
+        self.pid
+    }
+
+    fn product_name(&self) -> &str {
+        &self.product_name
+    }
+
+    fn cid(&self) -> u32 {
+        self.cid
+    }
+
+    fn allocate_channel(&self) -> Result<u32, PFError> {
+        Ok(self.simulator.state.lock().unwrap().allocate_cid())
+    }
+
+    fn ping_on(&self, cid: u32, payload: &[u8]) -> Result<Vec<u8>, PFError> {
+        if !self.simulator.state.lock().unwrap().is_allocated(cid) {
+            return Err(PFError::Ctap(Ctap2Error::InvalidChannel));
+        }
+        Ok(payload.to_vec())
+    }
+}
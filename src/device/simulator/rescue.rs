@@ -0,0 +1,160 @@
+//! Rescue Applet side of the simulator.
+
+use byteorder::{BigEndian, WriteBytesExt};
+
+use super::State;
+use crate::device::rescue::constants::*;
+
+const SW_WRONG_LENGTH: [u8; 2] = [0x67, 0x00];
+const SW_FILE_NOT_FOUND: [u8; 2] = [0x6A, 0x82];
+const SW_INCORRECT_P1P2: [u8; 2] = [0x6A, 0x86];
+const SW_INS_NOT_SUPPORTED: [u8; 2] = [0x6D, 0x00];
+const SW_CLA_NOT_SUPPORTED: [u8; 2] = [0x6E, 0x00];
+
+impl State {
+    pub(super) fn process_apdu(&mut self, apdu: &[u8]) -> Vec<u8> {
+        if apdu.len() < 4 {
+            return SW_WRONG_LENGTH.to_vec();
+        }
+        let (cla, ins, p1, p2) = (apdu[0], apdu[1], apdu[2], apdu[3]);
+        // Lc is either one byte, or 00 followed by two bytes for extended APDUs. A lone Le
+        // (one byte, or three for extended) carries no data.
+        let (offset, lc) = match apdu.len() {
+            0..=5 => (5, 0),
+            7 if apdu[4] == 0 => (7, 0),
+            _ if apdu[4] == 0 => (7, u16::from_be_bytes([apdu[5], apdu[6]]) as usize),
+            _ => (5, apdu[4] as usize),
+        };
+        let Some(data) = apdu.get(offset..offset + lc) else {
+            return SW_WRONG_LENGTH.to_vec();
+        };
+
+        let result = match (cla, ins) {
+            (APDU_CLA_ISO, APDU_INS_SELECT) => self.select(data),
+            (APDU_CLA_PROPRIETARY, ins) if ins == RescueInstruction::Read as u8 => self.read(p1),
+            (APDU_CLA_PROPRIETARY, ins) if ins == RescueInstruction::Write as u8 => {
+                self.write(p1, data)
+            }
+            (APDU_CLA_PROPRIETARY, ins) if ins == RescueInstruction::Reboot as u8 => {
+                log::info!("Simulator rebooting (bootsel: {})", p1 != 0);
+                self.power_cycle();
+                Ok(Vec::new())
+            }
+            (APDU_CLA_PROPRIETARY, ins) if ins == RescueInstruction::Secure as u8 => {
+                self.secure_boot = true;
+                self.secure_lock = p2 == SecureLockParam::Lock as u8;
+                Ok(Vec::new())
+            }
+            (APDU_CLA_ISO | APDU_CLA_PROPRIETARY, _) => Err(SW_INS_NOT_SUPPORTED),
+            _ => Err(SW_CLA_NOT_SUPPORTED),
+        };
+
+        match result {
+            Ok(mut response) => {
+                response.extend_from_slice(&SW_SUCCESS);
+                response
+            }
+            Err(sw) => sw.to_vec(),
+        }
+    }
+
+    fn select(&self, aid: &[u8]) -> Result<Vec<u8>, [u8; 2]> {
+        if aid != RESCUE_AID {
+            return Err(SW_FILE_NOT_FOUND);
+        }
+        // [?, ?, major, minor, serial (8 bytes)]
+        let mut response = vec![0x01, 0x00, self.firmware.0, self.firmware.1];
+        response.extend_from_slice(&self.serial);
+        Ok(response)
+    }
+
+    fn read(&self, p1: u8) -> Result<Vec<u8>, [u8; 2]> {
+        let mut response = Vec::new();
+        match p1 {
+            p1 if p1 == ReadParam::FlashInfo as u8 => {
+                let free = self.flash_total - self.flash_used;
+                for value in [free, self.flash_used, self.flash_total, 12, 2 * 1024 * 1024] {
+                    response.write_u32::<BigEndian>(value).unwrap();
+                }
+            }
+            p1 if p1 == ReadParam::SecureBootStatus as u8 => {
+                response.push(self.secure_boot as u8);
+                response.push(self.secure_lock as u8);
+            }
+            p1 if p1 == ReadParam::PhyConfig as u8 => {
+                response = self.phy_tlv();
+            }
+            _ => return Err(SW_INCORRECT_P1P2),
+        }
+        Ok(response)
+    }
+
+    fn phy_tlv(&self) -> Vec<u8> {
+        let mut tlv = Vec::new();
+
+        tlv.extend([PhyTag::VidPid as u8, 0x04]);
+        tlv.write_u16::<BigEndian>(self.vid).unwrap();
+        tlv.write_u16::<BigEndian>(self.pid).unwrap();
+
+        tlv.extend([PhyTag::LedGpio as u8, 0x01, self.led_gpio]);
+        tlv.extend([PhyTag::LedBrightness as u8, 0x01, self.led_brightness]);
+        tlv.extend([PhyTag::PresenceTimeout as u8, 0x01, self.touch_timeout]);
+
+        tlv.extend([PhyTag::Opts as u8, 0x02]);
+        tlv.write_u16::<BigEndian>(self.opts).unwrap();
+
+        tlv.extend([PhyTag::Curves as u8, 0x04]);
+        tlv.write_u32::<BigEndian>(self.curves).unwrap();
+
+        if let Some(driver) = self.led_driver {
+            tlv.extend([PhyTag::LedDriver as u8, 0x01, driver]);
+        }
+
+        let name = self.product_name.as_bytes();
+        tlv.extend([PhyTag::UsbProduct as u8, (name.len() + 1) as u8]);
+        tlv.extend_from_slice(name);
+        tlv.push(0x00);
+
+        tlv
+    }
+
+    fn write(&mut self, p1: u8, data: &[u8]) -> Result<Vec<u8>, [u8; 2]> {
+        if p1 != WriteParam::PhyConfig as u8 {
+            return Err(SW_INCORRECT_P1P2);
+        }
+
+        let mut i = 0;
+        while i + 2 <= data.len() {
+            let (tag, len) = (data[i], data[i + 1] as usize);
+            i += 2;
+            let Some(val) = data.get(i..i + len) else {
+                return Err(SW_WRONG_LENGTH);
+            };
+            i += len;
+
+            match PhyTag::from_u8(tag) {
+                Some(PhyTag::VidPid) if len == 4 => {
+                    self.vid = u16::from_be_bytes([val[0], val[1]]);
+                    self.pid = u16::from_be_bytes([val[2], val[3]]);
+                }
+                Some(PhyTag::LedGpio) if len == 1 => self.led_gpio = val[0],
+                Some(PhyTag::LedBrightness) if len == 1 => self.led_brightness = val[0],
+                Some(PhyTag::PresenceTimeout) if len == 1 => self.touch_timeout = val[0],
+                Some(PhyTag::LedDriver) if len == 1 => self.led_driver = Some(val[0]),
+                Some(PhyTag::Opts) if len == 2 => self.opts = u16::from_be_bytes([val[0], val[1]]),
+                Some(PhyTag::Curves) if len == 4 => {
+                    self.curves = u32::from_be_bytes([val[0], val[1], val[2], val[3]]);
+                }
+                Some(PhyTag::UsbProduct) => {
+                    self.product_name = String::from_utf8_lossy(val)
+                        .trim_matches(char::from(0))
+                        .to_string();
+                }
+                _ => return Err(SW_WRONG_LENGTH),
+            }
+        }
+
+        log::info!("Simulator PHY config updated");
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,477 @@
+//! ISO 7816-4 command/response handling on top of [`ApduTransport`].
+//!
+//! Takes care of short vs extended length encoding, command chaining for transports without
+//! extended length support, fetching the rest of a response with GET RESPONSE (`61xx`) and
+//! re-sending with the corrected Le (`6Cxx`).
+
+use std::fmt;
+
+use super::ApduTransport;
+use crate::error::PFError;
+
+const CLA_CHAINING: u8 = 0x10;
+const INS_GET_RESPONSE: u8 = 0xC0;
+
+/// Category indicator of historical bytes made of compact-TLV objects only.
+const HISTORICAL_COMPACT_TLV: u8 = 0x80;
+/// Category indicator of compact-TLV objects followed by a three byte status indicator.
+const HISTORICAL_COMPACT_TLV_WITH_STATUS: u8 = 0x00;
+const STATUS_INDICATOR_LEN: usize = 3;
+/// Compact-TLV tag of the card capabilities (ISO 7816-4, 8.1.1.2.7).
+const TAG_CARD_CAPABILITIES: u8 = 0x7;
+/// Third software function table, b7: "Extended Lc and Le fields".
+const CAPABILITY_EXTENDED_LENGTH: u8 = 0x40;
+
+/// Largest data field of a short APDU.
+pub const SHORT_MAX_LC: usize = 255;
+/// Largest response length a short APDU can ask for (encoded as `Le = 00`).
+pub const SHORT_MAX_LE: usize = 256;
+/// Largest response length an extended APDU can ask for (encoded as `Le = 00 00`).
+pub const EXTENDED_MAX_LE: usize = 65536;
+
+/// `SW1 SW2` of a response APDU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusWord(pub u16);
+
+impl StatusWord {
+    pub const SUCCESS: StatusWord = StatusWord(0x9000);
+
+    pub fn new(sw1: u8, sw2: u8) -> Self {
+        Self(u16::from_be_bytes([sw1, sw2]))
+    }
+
+    pub fn sw1(self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    pub fn sw2(self) -> u8 {
+        self.0 as u8
+    }
+
+    pub fn is_success(self) -> bool {
+        self == Self::SUCCESS
+    }
+}
+
+impl fmt::Display for StatusWord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X}", self.0)
+    }
+}
+
+/// A command APDU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    pub cla: u8,
+    pub ins: u8,
+    pub p1: u8,
+    pub p2: u8,
+    pub data: Vec<u8>,
+    /// Expected response length. `None` means no response data is expected (no Le field).
+    pub le: Option<usize>,
+}
+
+impl Command {
+    pub fn new(cla: u8, ins: u8, p1: u8, p2: u8) -> Self {
+        Self {
+            cla,
+            ins,
+            p1,
+            p2,
+            data: Vec::new(),
+            le: None,
+        }
+    }
+
+    pub fn data(mut self, data: impl Into<Vec<u8>>) -> Self {
+        self.data = data.into();
+        self
+    }
+
+    /// Sets the expected response length, capped at what an extended APDU can express.
+    pub fn le(mut self, le: usize) -> Self {
+        self.le = Some(le.min(EXTENDED_MAX_LE));
+        self
+    }
+
+    /// Whether this command needs the extended length encoding.
+    pub fn is_extended(&self) -> bool {
+        self.data.len() > SHORT_MAX_LC || self.le.is_some_and(|le| le > SHORT_MAX_LE)
+    }
+
+    /// Serialises the command, using the extended length encoding when the data or Le do not
+    /// fit a short APDU.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut apdu = vec![self.cla, self.ins, self.p1, self.p2];
+        let extended = self.is_extended();
+
+        if !self.data.is_empty() {
+            if extended {
+                apdu.push(0x00);
+                apdu.extend_from_slice(&(self.data.len() as u16).to_be_bytes());
+            } else {
+                apdu.push(self.data.len() as u8);
+            }
+            apdu.extend_from_slice(&self.data);
+        }
+
+        if let Some(le) = self.le {
+            if extended {
+                // Le is preceded by a zero byte only when there is no Lc field
+                if self.data.is_empty() {
+                    apdu.push(0x00);
+                }
+                apdu.extend_from_slice(&((le % EXTENDED_MAX_LE) as u16).to_be_bytes());
+            } else {
+                apdu.push((le % SHORT_MAX_LE) as u8);
+            }
+        }
+
+        apdu
+    }
+}
+
+/// A parsed response APDU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub data: Vec<u8>,
+    pub sw: StatusWord,
+}
+
+impl Response {
+    /// Splits a raw response into data and status word.
+    pub fn parse(raw: &[u8]) -> Result<Self, PFError> {
+        if raw.len() < 2 {
+            return Err(PFError::Device(format!(
+                "Response APDU too short: {:02X?}",
+                raw
+            )));
+        }
+        let (data, sw) = raw.split_at(raw.len() - 2);
+        Ok(Self {
+            data: data.to_vec(),
+            sw: StatusWord::new(sw[0], sw[1]),
+        })
+    }
+
+    /// Returns the data if the status word is `9000`, otherwise an error naming `what` failed.
+    pub fn into_success(self, what: &str) -> Result<Vec<u8>, PFError> {
+        if self.sw.is_success() {
+            Ok(self.data)
+        } else {
+            log::error!("{} failed with status {}", what, self.sw);
+            Err(PFError::Device(format!("{} failed: SW {}", what, self.sw)))
+        }
+    }
+}
+
+/// Whether an ATR advertises extended Lc and Le fields in the card capabilities of its
+/// historical bytes. Cards that do not say so get chained short APDUs.
+pub fn atr_supports_extended_length(atr: &[u8]) -> bool {
+    historical_bytes(atr)
+        .and_then(card_capabilities)
+        .and_then(|capabilities| capabilities.get(2))
+        .is_some_and(|functions| functions & CAPABILITY_EXTENDED_LENGTH != 0)
+}
+
+/// Skips TS, T0 and the interface bytes of an ATR (ISO 7816-3, 8.2) and returns the historical
+/// bytes.
+fn historical_bytes(atr: &[u8]) -> Option<&[u8]> {
+    let t0 = *atr.get(1)?;
+    let count = (t0 & 0x0F) as usize;
+    let mut offset = 2;
+    // The high nibble of T0 and of each TDi flags which of TA, TB, TC, TD follow
+    let mut indicator = t0 >> 4;
+    loop {
+        let present = indicator.count_ones() as usize;
+        let td = (indicator & 0x08 != 0).then(|| atr.get(offset + present - 1).copied());
+        offset += present;
+        match td {
+            Some(td) => indicator = td? >> 4,
+            None => break,
+        }
+    }
+    atr.get(offset..offset + count)
+}
+
+/// Finds the card capabilities object in compact-TLV historical bytes (ISO 7816-4, 8.1.1).
+fn card_capabilities(historical: &[u8]) -> Option<&[u8]> {
+    let objects = match historical.split_first()? {
+        (&HISTORICAL_COMPACT_TLV, rest) => rest,
+        (&HISTORICAL_COMPACT_TLV_WITH_STATUS, rest) => {
+            rest.get(..rest.len().checked_sub(STATUS_INDICATOR_LEN)?)?
+        }
+        _ => return None,
+    };
+
+    let mut offset = 0;
+    while let Some(&header) = objects.get(offset) {
+        let len = (header & 0x0F) as usize;
+        let value = objects.get(offset + 1..offset + 1 + len)?;
+        if header >> 4 == TAG_CARD_CAPABILITIES {
+            return Some(value);
+        }
+        offset += 1 + len;
+    }
+    None
+}
+
+/// Sends `command`, chaining or using extended lengths as needed, and collects the complete
+/// response.
+pub fn send<T: ApduTransport + ?Sized>(
+    transport: &T,
+    command: &Command,
+) -> Result<Response, PFError> {
+    let mut response = dispatch(transport, command)?;
+
+    // Wrong Le: the card tells us the exact length, ask again with that
+    if response.sw.sw1() == 0x6C {
+        let le = match response.sw.sw2() {
+            0 => SHORT_MAX_LE,
+            n => n as usize,
+        };
+        log::debug!("Card asked for Le = {}, re-sending", le);
+        let retry = Command {
+            le: Some(le),
+            ..command.clone()
+        };
+        response = dispatch(transport, &retry)?;
+    }
+
+    // More data available: fetch it with GET RESPONSE until the card is done
+    let mut data = std::mem::take(&mut response.data);
+    while response.sw.sw1() == 0x61 {
+        let le = match response.sw.sw2() {
+            0 => SHORT_MAX_LE,
+            n => n as usize,
+        };
+        log::trace!("{} more response bytes available", le);
+        let get_response = Command::new(0x00, INS_GET_RESPONSE, 0x00, 0x00).le(le);
+        response = exchange(transport, &get_response)?;
+        data.append(&mut response.data);
+    }
+
+    Ok(Response {
+        data,
+        sw: response.sw,
+    })
+}
+
+fn dispatch<T: ApduTransport + ?Sized>(
+    transport: &T,
+    command: &Command,
+) -> Result<Response, PFError> {
+    if transport.supports_extended_length() || !command.is_extended() {
+        exchange(transport, command)
+    } else {
+        send_chained(transport, command)
+    }
+}
+
+/// Sends an extended command as short APDUs: the data is split over several commands linked
+/// with the chaining bit, and Le is capped so the rest of a long response comes back via `61xx`.
+fn send_chained<T: ApduTransport + ?Sized>(
+    transport: &T,
+    command: &Command,
+) -> Result<Response, PFError> {
+    let chunks: Vec<&[u8]> = if command.data.is_empty() {
+        vec![&[]]
+    } else {
+        command.data.chunks(SHORT_MAX_LC).collect()
+    };
+    let last = chunks.len() - 1;
+    log::debug!(
+        "Chaining {} bytes of command data over {} APDUs",
+        command.data.len(),
+        chunks.len()
+    );
+
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let is_last = i == last;
+        let part = Command {
+            cla: if is_last {
+                command.cla
+            } else {
+                command.cla | CLA_CHAINING
+            },
+            data: chunk.to_vec(),
+            le: if is_last {
+                command.le.map(|le| le.min(SHORT_MAX_LE))
+            } else {
+                None
+            },
+            ..command.clone()
+        };
+        let response = exchange(transport, &part)?;
+        if is_last || !response.sw.is_success() {
+            return Ok(response);
+        }
+    }
+
+    unreachable!("there is always a last chunk")
+}
+
+fn exchange<T: ApduTransport + ?Sized>(
+    transport: &T,
+    command: &Command,
+) -> Result<Response, PFError> {
+    Response::parse(&transport.transmit(&command.encode())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::transport::scripted::ScriptedApduTransport;
+
+    #[test]
+    fn encodes_short_lengths() {
+        let command = Command::new(0x80, 0x1C, 0x01, 0x00).data([0xAA, 0xBB]);
+        assert_eq!(command.encode(), [0x80, 0x1C, 0x01, 0x00, 0x02, 0xAA, 0xBB]);
+        assert_eq!(
+            command.le(256).encode(),
+            [0x80, 0x1C, 0x01, 0x00, 0x02, 0xAA, 0xBB, 0x00]
+        );
+        assert_eq!(
+            Command::new(0x00, 0xC0, 0x00, 0x00).le(0x10).encode(),
+            [0x00, 0xC0, 0x00, 0x00, 0x10]
+        );
+        assert_eq!(
+            Command::new(0x80, 0x10, 0x00, 0x00).encode(),
+            [0x80, 0x10, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn encodes_extended_lengths() {
+        let mut expected = vec![0x80, 0x1C, 0x01, 0x00, 0x00, 0x01, 0x2C];
+        expected.extend([0xAB; 300]);
+        assert_eq!(
+            Command::new(0x80, 0x1C, 0x01, 0x00)
+                .data([0xAB; 300])
+                .encode(),
+            expected
+        );
+
+        expected.extend([0x02, 0x00]);
+        assert_eq!(
+            Command::new(0x80, 0x1C, 0x01, 0x00)
+                .data([0xAB; 300])
+                .le(512)
+                .encode(),
+            expected
+        );
+
+        // Without data, Le gets its own leading zero; 65536 wraps to 00 00
+        assert_eq!(
+            Command::new(0x00, 0xB0, 0x00, 0x00).le(300).encode(),
+            [0x00, 0xB0, 0x00, 0x00, 0x00, 0x01, 0x2C]
+        );
+        assert_eq!(
+            Command::new(0x00, 0xB0, 0x00, 0x00).le(usize::MAX).encode(),
+            [0x00, 0xB0, 0x00, 0x00, 0x00, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn chains_long_commands_and_collects_get_response() {
+        let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let mut first = vec![0x90, 0x1C, 0x01, 0x00, 0xFF];
+        first.extend(&data[..255]);
+        let mut second = vec![0x80, 0x1C, 0x01, 0x00, 0x2D];
+        second.extend(&data[255..]);
+        second.push(0x00);
+        let transport = ScriptedApduTransport::new()
+            .expect(first, [0x90, 0x00])
+            .expect(second, [0x01, 0x02, 0x61, 0x02])
+            .expect([0x00, 0xC0, 0x00, 0x00, 0x02], [0x03, 0x04, 0x61, 0x00])
+            .expect([0x00, 0xC0, 0x00, 0x00, 0x00], [0x05, 0x90, 0x00]);
+
+        let command = Command::new(0x80, 0x1C, 0x01, 0x00).data(data).le(1024);
+        let response = send(&transport, &command).unwrap();
+        assert_eq!(response.data, [0x01, 0x02, 0x03, 0x04, 0x05]);
+        assert!(response.sw.is_success());
+        assert!(transport.is_finished());
+    }
+
+    #[test]
+    fn stops_chaining_on_error() {
+        let transport = ScriptedApduTransport::new().respond([0x6A, 0x80]);
+
+        let command = Command::new(0x80, 0x1C, 0x01, 0x00).data([0xAB; 600]);
+        let response = send(&transport, &command).unwrap();
+        assert_eq!(response.sw, StatusWord(0x6A80));
+        assert!(transport.is_finished());
+    }
+
+    #[test]
+    fn sends_extended_commands_unchained_when_supported() {
+        let mut extended = vec![0x80, 0x1C, 0x01, 0x00, 0x00, 0x01, 0x2C];
+        extended.extend([0xAB; 300]);
+        let transport = ScriptedApduTransport::new()
+            .extended_length(true)
+            .expect(extended, [0x90, 0x00]);
+
+        let command = Command::new(0x80, 0x1C, 0x01, 0x00).data([0xAB; 300]);
+        assert!(send(&transport, &command).unwrap().sw.is_success());
+        assert!(transport.is_finished());
+    }
+
+    #[test]
+    fn retries_with_the_le_the_card_asks_for() {
+        let transport = ScriptedApduTransport::new()
+            .expect([0x80, 0x1E, 0x01, 0x00, 0x00], [0x6C, 0x05])
+            .expect([0x80, 0x1E, 0x01, 0x00, 0x05], [1, 2, 3, 4, 5, 0x90, 0x00]);
+
+        let command = Command::new(0x80, 0x1E, 0x01, 0x00).le(SHORT_MAX_LE);
+        let response = send(&transport, &command).unwrap();
+        assert_eq!(response.data, [1, 2, 3, 4, 5]);
+        assert!(transport.is_finished());
+    }
+
+    #[test]
+    fn rejects_truncated_responses() {
+        let transport = ScriptedApduTransport::new().respond([0x90]);
+
+        let command = Command::new(0x80, 0x1E, 0x01, 0x00);
+        assert!(matches!(
+            send(&transport, &command),
+            Err(PFError::Device(_))
+        ));
+    }
+
+    #[test]
+    fn reads_extended_length_support_from_atr() {
+        // T=1 with TA1..TD1, TD2 and TA3/TB3 ahead of the historical bytes
+        let with_interface_bytes = [
+            0x3B, 0xFD, 0x13, 0x00, 0x00, 0x81, 0x31, 0xFE, 0x15, 0x80, 0x73, 0xC0, 0x21, 0xC0,
+            0x57, 0x59, 0x75, 0x62, 0x69, 0x4B, 0x65, 0x79, 0x40,
+        ];
+        assert!(atr_supports_extended_length(&with_interface_bytes));
+        assert!(atr_supports_extended_length(&[
+            0x3B, 0x85, 0x01, 0x80, 0x73, 0xC0, 0x21, 0xC0, 0x56
+        ]));
+        // Category 00: the last three historical bytes are a status indicator, not a TLV
+        assert!(atr_supports_extended_length(&[
+            0x3B, 0x88, 0x01, 0x00, 0x73, 0xC0, 0x21, 0xC0, 0x00, 0x90, 0x00, 0x00
+        ]));
+    }
+
+    #[test]
+    fn defaults_to_chaining_without_capabilities() {
+        // Capabilities without b7 of the third byte
+        assert!(!atr_supports_extended_length(&[
+            0x3B, 0x85, 0x01, 0x80, 0x73, 0xC0, 0x21, 0x80, 0x16
+        ]));
+        // No card capabilities object
+        assert!(!atr_supports_extended_length(&[
+            0x3B, 0x83, 0x01, 0x80, 0x52, 0x01, 0x02, 0x51
+        ]));
+        // Historical bytes cut short
+        assert!(!atr_supports_extended_length(&[
+            0x3B, 0x85, 0x01, 0x80, 0x73
+        ]));
+        // TD1 announces interface bytes that are missing
+        assert!(!atr_supports_extended_length(&[0x3B, 0xF0, 0x13]));
+        assert!(!atr_supports_extended_length(&[]));
+    }
+}
@@ -0,0 +1,136 @@
+//! Transport abstractions used by the rescue and FIDO device layers.
+//!
+//! The protocol code in `device::rescue` and `device::fido` only talks to these traits, so it
+//! can run against real hardware (PC/SC cards, `hidapi` devices) or, in tests, against the
+//! in-memory `scripted` backend.
+
+pub mod apdu;
+mod monitor;
+#[cfg(test)]
+pub mod scripted;
+
+pub use monitor::{KeepaliveStatus, OperationMonitor};
+
+use crate::device::fido::constants::Ctap2Error;
+use crate::error::PFError;
+
+/// Sends ISO 7816-4 command APDUs to a smart card interface.
+pub trait ApduTransport {
+    /// Transmits a raw command APDU and returns the raw response, including `SW1 SW2`.
+    ///
+    /// Most callers want [`apdu::send`], which deals with chaining and `61xx`/`6Cxx`.
+    fn transmit(&self, apdu: &[u8]) -> Result<Vec<u8>, PFError>;
+
+    /// Whether extended length APDUs can be sent as is. Otherwise [`apdu::send`] falls back to
+    /// command chaining.
+    fn supports_extended_length(&self) -> bool {
+        false
+    }
+}
+
+/// Sends CTAPHID messages to a FIDO HID interface.
+pub trait CtapHidTransport {
+    /// Sends a CTAPHID command (e.g. `CTAPHID_CBOR`) and returns the response payload with
+    /// the CTAP status byte already checked and stripped.
+    fn send_cbor(&self, cmd: u8, payload: &[u8]) -> Result<Vec<u8>, PFError>;
+
+    /// USB Vendor ID reported by the interface.
+    fn vid(&self) -> u16;
+
+    /// USB Product ID reported by the interface.
+    fn pid(&self) -> u16;
+
+    /// USB product string reported by the interface.
+    fn product_name(&self) -> &str;
+
+    /// Asks the key to identify itself with `CTAPHID_WINK` (usually by blinking its LED).
+    /// Returns `false` without sending anything if the key does not advertise the capability.
+    fn wink(&self) -> Result<bool, PFError> {
+        Ok(false)
+    }
+
+    /// The CTAPHID channel requests are sent on.
+    fn cid(&self) -> u32;
+
+    /// Negotiates an additional channel on the same device, for use with [`Self::ping_on`].
+    fn allocate_channel(&self) -> Result<u32, PFError>;
+
+    /// Sends `CTAPHID_PING` on `cid` and returns the echoed payload.
+    fn ping_on(&self, cid: u32, payload: &[u8]) -> Result<Vec<u8>, PFError>;
+
+    /// Sends `CTAPHID_PING` on our own channel and returns the echoed payload.
+    fn ping(&self, payload: &[u8]) -> Result<Vec<u8>, PFError> {
+        self.ping_on(self.cid(), payload)
+    }
+
+    /// Discards any packets still queued, e.g. the rest of a response abandoned after an error.
+    /// Transports that hand over whole messages have nothing to discard.
+    fn drain(&self) {}
+
+    /// Attaches a monitor that receives keepalive status and can cancel the requests sent
+    /// while it is attached. Transports that never block on the user ignore it.
+    fn set_monitor(&self, _monitor: Option<OperationMonitor>) {}
+}
+
+/// A PC/SC card connection.
+pub struct PcscCard {
+    card: pcsc::Card,
+    extended_length: bool,
+}
+
+impl PcscCard {
+    /// Wraps `card`, reading from its ATR whether it takes extended length APDUs.
+    pub fn new(card: pcsc::Card) -> Self {
+        let extended_length = card
+            .get_attribute_owned(pcsc::Attribute::AtrString)
+            .inspect_err(|e| log::warn!("Failed to read the ATR: {}", e))
+            .is_ok_and(|atr| apdu::atr_supports_extended_length(&atr));
+        log::debug!("Card supports extended length APDUs: {}", extended_length);
+        Self {
+            card,
+            extended_length,
+        }
+    }
+}
+
+impl ApduTransport for PcscCard {
+    fn transmit(&self, apdu: &[u8]) -> Result<Vec<u8>, PFError> {
+        let mut rx_buf = vec![0; pcsc::MAX_BUFFER_SIZE_EXTENDED];
+        let rx = self.card.transmit(apdu, &mut rx_buf)?;
+        Ok(rx.to_vec())
+    }
+
+    fn supports_extended_length(&self) -> bool {
+        self.extended_length
+    }
+}
+
+/// Checks the CTAP status byte at the start of a response and returns the remaining payload.
+pub(crate) fn strip_ctap_status(cmd: u8, response: &[u8]) -> Result<Vec<u8>, PFError> {
+    let Some((&status, payload)) = response.split_first() else {
+        log::error!("Device sent empty payload response.");
+        return Err(PFError::Device("Empty response".into()));
+    };
+
+    if status == Ctap2Error::KeepaliveCancel as u8 {
+        log::info!("Command 0x{:02X} was cancelled", cmd);
+        return Err(PFError::Cancelled);
+    }
+
+    if status != 0x00 {
+        let error = Ctap2Error::from_code(status);
+        log::error!(
+            "FIDO Operation returned failure status: 0x{:02X} ({})",
+            status,
+            error.name()
+        );
+        return Err(PFError::Ctap(error));
+    }
+
+    log::debug!(
+        "Command 0x{:02X} successful. Response payload len: {}",
+        cmd,
+        payload.len()
+    );
+    Ok(payload.to_vec())
+}
@@ -0,0 +1,123 @@
+//! Progress reporting and cancellation for long running CTAPHID requests.
+//!
+//! Requests that wait for user presence can take up to the key's touch timeout. While the key
+//! works on them it sends `CTAPHID_KEEPALIVE` packets; the transport records their status in an
+//! [`OperationMonitor`] so the UI can ask the user to touch the key, and checks the monitor for a
+//! cancellation request between packets.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Monitored operations currently running, see [`OperationMonitor::track`].
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Status byte of a `CTAPHID_KEEPALIVE` packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepaliveStatus {
+    /// The key is working on the request.
+    Processing,
+    /// The key is waiting for the user to touch it.
+    UpNeeded,
+}
+
+impl KeepaliveStatus {
+    pub fn from_u8(status: u8) -> Option<Self> {
+        match status {
+            0x01 => Some(Self::Processing),
+            0x02 => Some(Self::UpNeeded),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct MonitorState {
+    status: Mutex<Option<KeepaliveStatus>>,
+    progress: Mutex<Option<(usize, usize)>>,
+    cancelled: AtomicBool,
+}
+
+/// Shared between the thread running a request and whoever displays it. Cheap to clone; all
+/// clones observe the same request.
+#[derive(Debug, Clone, Default)]
+pub struct OperationMonitor {
+    state: Arc<MonitorState>,
+}
+
+impl OperationMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The status of the last keepalive, or `None` if the key has not sent one yet (or the
+    /// request has finished).
+    pub fn status(&self) -> Option<KeepaliveStatus> {
+        *self
+            .state
+            .status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn set_status(&self, status: Option<KeepaliveStatus>) {
+        *self
+            .state
+            .status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = status;
+    }
+
+    /// For operations made of several requests: how many are done, out of how many.
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        *self
+            .state
+            .progress
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn set_progress(&self, done: usize, total: usize) {
+        *self
+            .state
+            .progress
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some((done, total));
+    }
+
+    /// Whether the key is currently waiting for a touch.
+    pub fn needs_touch(&self) -> bool {
+        self.status() == Some(KeepaliveStatus::UpNeeded)
+    }
+
+    /// Asks the transport to send `CTAPHID_CANCEL`. The request then fails with
+    /// [`PFError::Cancelled`](crate::error::PFError::Cancelled).
+    pub fn cancel(&self) {
+        log::info!("Cancelling the pending FIDO request");
+        self.state.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Counts the operation as running until the returned guard is dropped. Such operations
+    /// may power cycle or re-enumerate the key on purpose, so hot-plug handling holds off.
+    pub(crate) fn track(&self) -> InFlight {
+        IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+        InFlight(())
+    }
+
+    /// Whether any monitored operation is running.
+    pub fn any_in_flight() -> bool {
+        IN_FLIGHT.load(Ordering::SeqCst) > 0
+    }
+}
+
+/// Returned by [`OperationMonitor::track`].
+pub struct InFlight(());
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
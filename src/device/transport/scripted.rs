@@ -0,0 +1,244 @@
+//! In-memory transports that replay a fixed script of request/response exchanges.
+//!
+//! These let the rescue and FIDO protocol code run without a key attached. Clones share their
+//! script, so a test can hand one clone to a [`DeviceSession`](crate::device::session) and
+//! check [`is_finished`](ScriptedApduTransport::is_finished) on the other afterwards.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use super::{ApduTransport, CtapHidTransport, strip_ctap_status};
+use crate::device::fido::hid::{CTAPHID_INIT, CTAPHID_PING, HidReportDevice};
+use crate::error::PFError;
+
+/// Expected request bytes. `None` accepts any request.
+type Expected = Option<Vec<u8>>;
+/// Pending exchanges, shared between clones.
+type Script<T> = Arc<Mutex<VecDeque<T>>>;
+
+/// The channel a [`ScriptedCtapHidTransport`] sends on.
+pub const SCRIPTED_CID: u32 = 0x0102_0304;
+
+/// Replays APDU responses in order, checking each command against the script.
+#[derive(Clone, Default)]
+pub struct ScriptedApduTransport {
+    exchanges: Script<(Expected, Vec<u8>)>,
+    extended_length: bool,
+}
+
+impl ScriptedApduTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expects `apdu` as the next command and answers it with `response` (data + `SW1 SW2`).
+    pub fn expect(self, apdu: impl Into<Vec<u8>>, response: impl Into<Vec<u8>>) -> Self {
+        self.push(Some(apdu.into()), response.into())
+    }
+
+    /// Answers the next command with `response`, whatever it is.
+    pub fn respond(self, response: impl Into<Vec<u8>>) -> Self {
+        self.push(None, response.into())
+    }
+
+    /// Accepts extended length APDUs instead of making the APDU layer chain long commands.
+    pub fn extended_length(mut self, supported: bool) -> Self {
+        self.extended_length = supported;
+        self
+    }
+
+    /// Returns `true` once every scripted exchange has been consumed.
+    pub fn is_finished(&self) -> bool {
+        self.exchanges.lock().unwrap().is_empty()
+    }
+
+    fn push(self, expected: Expected, response: Vec<u8>) -> Self {
+        self.exchanges
+            .lock()
+            .unwrap()
+            .push_back((expected, response));
+        self
+    }
+}
+
+impl ApduTransport for ScriptedApduTransport {
+    fn transmit(&self, apdu: &[u8]) -> Result<Vec<u8>, PFError> {
+        let (expected, response) = next_exchange(&self.exchanges, apdu)?;
+        check_request(expected.as_deref(), apdu)?;
+        Ok(response)
+    }
+
+    fn supports_extended_length(&self) -> bool {
+        self.extended_length
+    }
+}
+
+/// Replays CTAPHID responses in order, checking each command against the script.
+#[derive(Clone)]
+pub struct ScriptedCtapHidTransport {
+    vid: u16,
+    pid: u16,
+    product_name: String,
+    exchanges: Script<(u8, Expected, Result<Vec<u8>, PFError>)>,
+}
+
+impl ScriptedCtapHidTransport {
+    pub fn new(vid: u16, pid: u16, product_name: impl Into<String>) -> Self {
+        Self {
+            vid,
+            pid,
+            product_name: product_name.into(),
+            exchanges: Arc::default(),
+        }
+    }
+
+    /// Expects `payload` on CTAPHID command `cmd` next and answers it with `response`.
+    ///
+    /// `response` is the raw CTAP response, starting with the status byte.
+    pub fn expect(
+        self,
+        cmd: u8,
+        payload: impl Into<Vec<u8>>,
+        response: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.push(cmd, Some(payload.into()), Ok(response.into()))
+    }
+
+    /// Answers the next CTAPHID command `cmd` with `response`, whatever the payload is.
+    ///
+    /// Useful for requests carrying a `pinUvAuthParam` that the test does not want to compute.
+    pub fn respond(self, cmd: u8, response: impl Into<Vec<u8>>) -> Self {
+        self.push(cmd, None, Ok(response.into()))
+    }
+
+    /// Fails the next CTAPHID command `cmd` with `error`, as the transport would when the key
+    /// goes away.
+    pub fn fail(self, cmd: u8, error: PFError) -> Self {
+        self.push(cmd, None, Err(error))
+    }
+
+    /// Returns `true` once every scripted exchange has been consumed.
+    pub fn is_finished(&self) -> bool {
+        self.exchanges.lock().unwrap().is_empty()
+    }
+
+    fn push(self, cmd: u8, expected: Expected, response: Result<Vec<u8>, PFError>) -> Self {
+        self.exchanges
+            .lock()
+            .unwrap()
+            .push_back((cmd, expected, response));
+        self
+    }
+
+    /// The scripted response to `payload` on command `cmd`, as is.
+    fn next(&self, cmd: u8, payload: &[u8]) -> Result<Vec<u8>, PFError> {
+        let (expected_cmd, expected, response) = next_exchange(&self.exchanges, payload)?;
+        if expected_cmd != cmd {
+            return Err(PFError::Io(format!(
+                "Scripted transport expected command 0x{:02X}, got 0x{:02X}",
+                expected_cmd, cmd
+            )));
+        }
+        check_request(expected.as_deref(), payload)?;
+        response
+    }
+}
+
+impl CtapHidTransport for ScriptedCtapHidTransport {
+    fn send_cbor(&self, cmd: u8, payload: &[u8]) -> Result<Vec<u8>, PFError> {
+        strip_ctap_status(cmd, &self.next(cmd, payload)?)
+    }
+
+    fn vid(&self) -> u16 {
+        self.vid
+    }
+
+    fn pid(&self) -> u16 {
+        self.pid
+    }
+
+    fn product_name(&self) -> &str {
+        &self.product_name
+    }
+
+    fn cid(&self) -> u32 {
+        SCRIPTED_CID
+    }
+
+    /// Answered by a `CTAPHID_INIT` exchange whose response is the new channel ID.
+    fn allocate_channel(&self) -> Result<u32, PFError> {
+        let response = self.next(CTAPHID_INIT, &[])?;
+        let cid = response.try_into().map_err(|_| {
+            PFError::Io("Scripted CTAPHID_INIT response is not a channel ID".into())
+        })?;
+        Ok(u32::from_be_bytes(cid))
+    }
+
+    /// Answered by a `CTAPHID_PING` exchange, whose response is returned as is.
+    fn ping_on(&self, _cid: u32, payload: &[u8]) -> Result<Vec<u8>, PFError> {
+        self.next(CTAPHID_PING, payload)
+    }
+}
+
+/// Raw HID reports for [`HidTransport`](crate::device::fido::hid::HidTransport): plays back
+/// queued input reports, one per read, and records the output reports written.
+#[derive(Clone, Default)]
+pub struct ScriptedHidDevice {
+    reports: Script<Vec<u8>>,
+    written: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl ScriptedHidDevice {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues an input report, zero padded to the 64 byte report size.
+    pub fn report(self, report: impl Into<Vec<u8>>) -> Self {
+        let mut report = report.into();
+        report.resize(64, 0);
+        self.reports.lock().unwrap().push_back(report);
+        self
+    }
+
+    /// The output reports written so far, report ID included.
+    pub fn written(&self) -> Vec<Vec<u8>> {
+        self.written.lock().unwrap().clone()
+    }
+}
+
+impl HidReportDevice for ScriptedHidDevice {
+    fn write(&self, report: &[u8]) -> hidapi::HidResult<usize> {
+        self.written.lock().unwrap().push(report.to_vec());
+        Ok(report.len())
+    }
+
+    /// Returns `Ok(0)`, as hidapi does on a timeout, once the queued reports run out.
+    fn read_timeout(&self, buf: &mut [u8], _timeout_ms: i32) -> hidapi::HidResult<usize> {
+        let Some(report) = self.reports.lock().unwrap().pop_front() else {
+            return Ok(0);
+        };
+        let len = report.len().min(buf.len());
+        buf[..len].copy_from_slice(&report[..len]);
+        Ok(len)
+    }
+}
+
+fn next_exchange<T>(exchanges: &Mutex<VecDeque<T>>, request: &[u8]) -> Result<T, PFError> {
+    exchanges.lock().unwrap().pop_front().ok_or_else(|| {
+        PFError::Io(format!(
+            "Scripted transport has no response left for request {:02X?}",
+            request
+        ))
+    })
+}
+
+fn check_request(expected: Option<&[u8]>, request: &[u8]) -> Result<(), PFError> {
+    match expected {
+        Some(expected) if expected != request => Err(PFError::Io(format!(
+            "Scripted transport expected request {:02X?}, got {:02X?}",
+            expected, request
+        ))),
+        _ => Ok(()),
+    }
+}
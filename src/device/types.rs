@@ -2,6 +2,10 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::device::fido::constants::{CredProtectPolicy, MAX_RESIDENT_CREDENTIALS};
+use crate::device::fido::cose::CredentialPublicKey;
+use crate::device::fido::pin_policy::PinComplexityPolicy;
+
 struct PForgeState {
     device_info: DeviceInfo,
 }
@@ -58,6 +62,48 @@ pub struct FullDeviceStatus {
     pub method: DeviceMethod,
 }
 
+/// A connected key found during enumeration.
+///
+/// A single pico-key usually exposes both a PC/SC reader (rescue applet) and a FIDO HID
+/// interface. When both can be matched by serial number they are merged into one handle,
+/// otherwise each interface is reported as its own candidate.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceHandle {
+    pub reader_name: Option<String>,
+    pub hid_path: Option<String>,
+    pub vid: u16,
+    pub pid: u16,
+    pub product_name: String,
+    pub serial: Option<String>,
+    pub firmware_version: Option<String>,
+}
+
+impl DeviceHandle {
+    /// Stable identifier used to keep a selection across refreshes.
+    pub fn id(&self) -> String {
+        match (&self.serial, &self.hid_path, &self.reader_name) {
+            (Some(serial), _, _) => format!("serial:{}", serial),
+            (None, Some(path), _) => format!("hid:{}", path),
+            (None, None, Some(reader)) => format!("reader:{}", reader),
+            (None, None, None) => format!("usb:{:04X}:{:04X}", self.vid, self.pid),
+        }
+    }
+
+    /// Whether this handle points at the built-in simulator rather than real hardware.
+    pub fn is_simulated(&self) -> bool {
+        self.hid_path.as_deref() == Some(crate::device::simulator::SIMULATOR_HID_PATH)
+    }
+
+    /// Short human readable name for pickers.
+    pub fn label(&self) -> String {
+        match &self.serial {
+            Some(serial) => format!("{} ({})", self.product_name, serial),
+            None => format!("{} ({:04X}:{:04X})", self.product_name, self.vid, self.pid),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum DeviceMethod {
     #[serde(rename = "FIDO")]
@@ -76,9 +122,56 @@ pub struct FidoDeviceInfo {
     pub options: std::collections::HashMap<String, bool>,
     pub max_msg_size: i32,
     pub pin_protocols: Vec<u32>,
-    // pub remaining_disc_creds: u32,
+    /// GetInfo `remainingDiscoverableCredentials`, if the key reports it.
+    pub remaining_disc_creds: Option<u32>,
+    /// Credential counts from getCredsMetadata. That needs the PIN, so it is filled in by the
+    /// session once the passkeys have been listed.
+    pub creds_metadata: Option<CredsMetadata>,
     pub min_pin_length: u32,
     pub firmware_version: String,
+    /// PIN attempts left before the PIN is blocked, if the key supports a PIN.
+    pub pin_retries: Option<u32>,
+    /// Built-in user verification attempts left, if the key has built-in UV.
+    pub uv_retries: Option<u32>,
+    /// The key refuses PIN attempts until it is unplugged (after PIN_AUTH_BLOCKED).
+    pub power_cycle_required: bool,
+    /// The key reports its device secret as encrypted with a host key. The session unlocks
+    /// such keys when it connects.
+    pub auth_encryption: bool,
+    /// The secret is still locked after connecting, e.g. because it was encrypted on another
+    /// computer.
+    pub auth_encryption_locked: bool,
+    /// Rules the key enforces on new PINs.
+    #[serde(skip)]
+    pub pin_complexity_policy: PinComplexityPolicy,
+    /// Largest serialized large-blob array the key stores, if it supports large blobs.
+    pub max_large_blob_array: Option<u32>,
+}
+
+impl FidoDeviceInfo {
+    /// Discoverable credential slots in use and in total, and whether those are estimated.
+    /// Without getCredsMetadata the total is assumed to be pico-fido's
+    /// `MAX_RESIDENT_CREDENTIALS`.
+    pub fn credential_slots(&self) -> Option<(u32, u32, bool)> {
+        if let Some(metadata) = self.creds_metadata {
+            return Some((
+                metadata.existing,
+                metadata.existing + metadata.remaining,
+                false,
+            ));
+        }
+        let total = MAX_RESIDENT_CREDENTIALS as u32;
+        self.remaining_disc_creds
+            .map(|remaining| (total.saturating_sub(remaining), total.max(remaining), true))
+    }
+}
+
+/// Discoverable credential counts reported by credential management getCredsMetadata.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CredsMetadata {
+    pub existing: u32,
+    pub remaining: u32,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -90,4 +183,56 @@ pub struct StoredCredential {
     pub user_display_name: String,
     pub user_id: String,
     pub credential_id: String,
+    /// The credential's public key, if the key reported one PicoForge can decode.
+    #[serde(skip)]
+    pub public_key: Option<CredentialPublicKey>,
+    /// Key that encrypts this credential's entry in the large-blob array.
+    #[serde(skip)]
+    pub large_blob_key: Option<Vec<u8>>,
+    /// credProtect level (1-3), if the key reported one.
+    pub cred_protect: Option<u8>,
+    /// Whether the credential may be used for third-party payments (SPC).
+    pub third_party_payment: bool,
+}
+
+impl StoredCredential {
+    pub fn cred_protect_policy(&self) -> Option<CredProtectPolicy> {
+        self.cred_protect
+            .and_then(|level| CredProtectPolicy::from_id(level as i128))
+    }
+}
+
+/// Outcome of deleting several passkeys in one go.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkDeleteReport {
+    pub deleted: usize,
+    /// Passkeys that were not deleted, with the reason.
+    pub failed: Vec<(StoredCredential, String)>,
+}
+
+/// One entry of the large-blob array.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LargeBlobEntry {
+    /// Hex of the entry's nonce, which identifies it within the array.
+    pub id: String,
+    /// Bytes the entry takes up in the array (encrypted, compressed).
+    pub size: usize,
+    /// Size of the blob once decrypted and decompressed.
+    pub original_size: u64,
+    /// The credential whose largeBlobKey decrypts the entry, if it is still on the key.
+    pub owner: Option<StoredCredential>,
+}
+
+/// The large-blob array as read from the key.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LargeBlobArray {
+    pub entries: Vec<LargeBlobEntry>,
+    /// Size of the serialized array, trailer included.
+    pub size: usize,
+    /// The trailer matched and the array decoded. A corrupt array shows no entries and can
+    /// only be reset.
+    pub intact: bool,
 }
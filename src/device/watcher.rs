@@ -0,0 +1,216 @@
+//! Background detection of keys being plugged in or pulled out.
+//!
+//! Smart Card readers are tracked with `SCardGetStatusChange` (including the PnP pseudo reader
+//! where the platform supports it, so new readers show up without polling). hidapi has no
+//! arrival notifications, so FIDO HID interfaces are found by diffing the device list once a
+//! second.
+
+use pcsc::{Context, ReaderState, Scope, State};
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+use std::time::Duration;
+
+use crate::device::fido::hid::HID_USAGE_PAGE_FIDO;
+
+const STATUS_CHANGE_TIMEOUT: Duration = Duration::from_secs(1);
+const HID_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const PCSC_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HotplugEvent {
+    ReaderAdded(String),
+    ReaderRemoved(String),
+    /// A card was inserted into or removed from an existing reader, e.g. the key rebooted.
+    CardChanged(String),
+    HidArrived(String),
+    HidRemoved(String),
+}
+
+/// Owns the watcher threads. They stop on their own once the watcher is dropped and they have
+/// something to report.
+pub struct DeviceWatcher {
+    events: Receiver<HotplugEvent>,
+}
+
+impl DeviceWatcher {
+    pub fn spawn() -> Self {
+        let (tx, events) = channel();
+
+        let pcsc_tx = tx.clone();
+        thread::Builder::new()
+            .name("pcsc-watcher".into())
+            .spawn(move || watch_readers(pcsc_tx))
+            .map_err(|e| log::error!("Failed to start the PC/SC watcher: {}", e))
+            .ok();
+
+        thread::Builder::new()
+            .name("hid-watcher".into())
+            .spawn(move || watch_hid(tx))
+            .map_err(|e| log::error!("Failed to start the HID watcher: {}", e))
+            .ok();
+
+        Self { events }
+    }
+
+    /// Returns every event reported since the last call, without blocking.
+    pub fn poll(&self) -> Vec<HotplugEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+fn is_pnp(rs: &ReaderState) -> bool {
+    rs.name() == pcsc::PNP_NOTIFICATION()
+}
+
+fn is_dead(rs: &ReaderState) -> bool {
+    rs.event_state().intersects(State::UNKNOWN | State::IGNORE)
+}
+
+fn reader_name(name: &CStr) -> String {
+    name.to_string_lossy().into_owned()
+}
+
+/// Runs until the receiving side goes away. Re-establishes the context if the PC/SC service
+/// restarts.
+fn watch_readers(tx: Sender<HotplugEvent>) {
+    loop {
+        let ctx = match Context::establish(Scope::User) {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                log::debug!("PC/SC not available for hot-plug detection: {}", e);
+                thread::sleep(PCSC_RETRY_INTERVAL);
+                continue;
+            }
+        };
+
+        match watch_readers_with(&ctx, &tx) {
+            Ok(()) => return,
+            Err(e) => {
+                log::warn!("PC/SC watcher lost its context ({}), restarting", e);
+                thread::sleep(PCSC_RETRY_INTERVAL);
+            }
+        }
+    }
+}
+
+/// Returns `Ok` when the receiver is gone and `Err` when the context has to be re-established.
+fn watch_readers_with(ctx: &Context, tx: &Sender<HotplugEvent>) -> Result<(), pcsc::Error> {
+    let mut states = vec![ReaderState::new(pcsc::PNP_NOTIFICATION(), State::UNAWARE)];
+    let mut initialised = false;
+
+    loop {
+        for rs in states.iter().filter(|rs| !is_pnp(rs) && is_dead(rs)) {
+            log::debug!("Reader removed: {:?}", rs.name());
+            if tx
+                .send(HotplugEvent::ReaderRemoved(reader_name(rs.name())))
+                .is_err()
+            {
+                return Ok(());
+            }
+        }
+        // Platforms without PnP notifications (macOS) mark the pseudo reader as unknown. Drop it
+        // there too; new readers are then still picked up by the listing below within a second.
+        states.retain(|rs| !is_dead(rs));
+
+        let names = match ctx.list_readers_owned() {
+            Ok(names) => names,
+            Err(pcsc::Error::NoReadersAvailable) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        for name in names {
+            if states.iter().any(|rs| rs.name() == name.as_c_str()) {
+                continue;
+            }
+            // Readers present at startup are not news
+            if initialised {
+                log::debug!("Reader added: {:?}", name);
+                if tx
+                    .send(HotplugEvent::ReaderAdded(reader_name(&name)))
+                    .is_err()
+                {
+                    return Ok(());
+                }
+            }
+            states.push(ReaderState::new(name, State::UNAWARE));
+        }
+
+        // Remember whether a card was present before syncing, so we only report insertions
+        // and removals. Other bits (INUSE, EXCLUSIVE) flip every time we connect ourselves.
+        let was_present: Vec<bool> = states
+            .iter()
+            .map(|rs| rs.event_state().contains(State::PRESENT))
+            .collect();
+        for rs in &mut states {
+            rs.sync_current_state();
+        }
+
+        match ctx.get_status_change(STATUS_CHANGE_TIMEOUT, &mut states) {
+            Ok(()) | Err(pcsc::Error::Timeout) => {}
+            Err(e) => return Err(e),
+        }
+
+        for (rs, was_present) in states.iter().zip(was_present) {
+            if is_pnp(rs) || is_dead(rs) || !initialised {
+                continue;
+            }
+            let present = rs.event_state().contains(State::PRESENT);
+            if present != was_present {
+                log::debug!(
+                    "Card {} in {:?}",
+                    if present { "inserted" } else { "removed" },
+                    rs.name()
+                );
+                if tx
+                    .send(HotplugEvent::CardChanged(reader_name(rs.name())))
+                    .is_err()
+                {
+                    return Ok(());
+                }
+            }
+        }
+        initialised = true;
+    }
+}
+
+fn fido_hid_paths(api: &hidapi::HidApi) -> HashSet<String> {
+    api.device_list()
+        .filter(|d| d.usage_page() == HID_USAGE_PAGE_FIDO)
+        .map(|d| d.path().to_string_lossy().into_owned())
+        .collect()
+}
+
+fn watch_hid(tx: Sender<HotplugEvent>) {
+    let mut api = match hidapi::HidApi::new() {
+        Ok(api) => api,
+        Err(e) => {
+            log::warn!("HID hot-plug detection unavailable: {}", e);
+            return;
+        }
+    };
+    let mut known = fido_hid_paths(&api);
+
+    loop {
+        thread::sleep(HID_POLL_INTERVAL);
+        if let Err(e) = api.refresh_devices() {
+            log::debug!("Failed to refresh HID device list: {}", e);
+            continue;
+        }
+        let current = fido_hid_paths(&api);
+
+        let arrived = current
+            .difference(&known)
+            .map(|p| HotplugEvent::HidArrived(p.clone()));
+        let removed = known
+            .difference(&current)
+            .map(|p| HotplugEvent::HidRemoved(p.clone()));
+        for event in arrived.chain(removed) {
+            log::debug!("{:?}", event);
+            if tx.send(event).is_err() {
+                return;
+            }
+        }
+        known = current;
+    }
+}
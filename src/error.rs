@@ -1,3 +1,5 @@
+use crate::device::fido::constants::Ctap2Error;
+
 /// Custom error types for Pico Forge application.
 #[derive(Debug, thiserror::Error)]
 pub enum PFError {
@@ -9,6 +11,59 @@ pub enum PFError {
     Io(String),
     #[error("Device Error: {0}")]
     Device(String),
+    #[error("Device disconnected: {0}")]
+    Disconnected(String),
+    /// The request reached the key but no complete answer came back. The key may have acted on
+    /// it, so unlike [`PFError::Disconnected`] it must not be sent again.
+    #[error("Device did not respond: {0}")]
+    NoResponse(String),
+    #[error("Operation cancelled")]
+    Cancelled,
+    /// The key answered with a CTAP status or CTAPHID error code.
+    #[error("{0}")]
+    Ctap(#[from] Ctap2Error),
+}
+
+impl PFError {
+    /// The CTAP status the key reported, if that is what went wrong.
+    pub fn ctap_error(&self) -> Option<Ctap2Error> {
+        match self {
+            PFError::Ctap(e) => Some(*e),
+            _ => None,
+        }
+    }
+
+    /// What the user can do about the error, if there is a known fix.
+    pub fn remediation(&self) -> Option<&'static str> {
+        match self {
+            PFError::Ctap(e) => e.remediation(),
+            PFError::Disconnected(_) | PFError::NoResponse(_) => {
+                Some("Check that the key is plugged in and try again.")
+            }
+            _ => None,
+        }
+    }
+
+    /// The error followed by its remediation, for showing in dialogs.
+    pub fn user_message(&self) -> String {
+        match self.remediation() {
+            Some(hint) => format!("{} {}", self, hint),
+            None => self.to_string(),
+        }
+    }
+
+    /// Whether the PIN the user entered (or we cached) can no longer be used as is.
+    pub fn invalidates_pin(&self) -> bool {
+        matches!(
+            self.ctap_error(),
+            Some(
+                Ctap2Error::PinInvalid
+                    | Ctap2Error::PinBlocked
+                    | Ctap2Error::PinAuthBlocked
+                    | Ctap2Error::PinNotSet
+            )
+        )
+    }
 }
 
 // Allow error to be serialized to string for Tauri
@@ -36,9 +91,64 @@ impl serde::Serialize for PFError {
                 state.serialize_field("type", "Device")?;
                 state.serialize_field("message", msg)?;
             }
+            PFError::Disconnected(msg) => {
+                state.serialize_field("type", "Disconnected")?;
+                state.serialize_field("message", msg)?;
+            }
+            PFError::NoResponse(msg) => {
+                state.serialize_field("type", "NoResponse")?;
+                state.serialize_field("message", msg)?;
+            }
+            PFError::Cancelled => {
+                state.serialize_field("type", "Cancelled")?;
+                state.serialize_field("message", "Operation cancelled")?;
+            }
+            PFError::Ctap(err) => {
+                state.serialize_field("type", err.name())?;
+                state.serialize_field("message", err.explanation())?;
+            }
         }
         state.end()
     }
 }
 
 // pub type Result<T> = std::result::Result<T, PFError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pin_errors_invalidate_the_pin() {
+        assert!(PFError::Ctap(Ctap2Error::PinInvalid).invalidates_pin());
+        assert!(PFError::Ctap(Ctap2Error::PinAuthBlocked).invalidates_pin());
+        assert!(PFError::Ctap(Ctap2Error::PinBlocked).invalidates_pin());
+        // A fresh PIN token fixes these, the PIN itself is fine
+        assert!(!PFError::Ctap(Ctap2Error::PinAuthInvalid).invalidates_pin());
+        assert!(!PFError::Disconnected("Unplugged".into()).invalidates_pin());
+        assert!(!PFError::Cancelled.invalidates_pin());
+    }
+
+    #[test]
+    fn user_message_appends_the_remediation() {
+        assert_eq!(
+            PFError::Ctap(Ctap2Error::PinInvalid).user_message(),
+            "The PIN is incorrect. (CTAP2_ERR_PIN_INVALID) Check the PIN and try again. \
+             The key blocks it after too many wrong attempts."
+        );
+        assert_eq!(
+            PFError::Ctap(Ctap2Error::PinAuthBlocked).user_message(),
+            "Too many wrong PINs in a row, PIN entry is blocked until the key is power cycled. \
+             (CTAP2_ERR_PIN_AUTH_BLOCKED) Unplug the key, plug it back in and try again."
+        );
+        assert_eq!(
+            PFError::Disconnected("Failed to write HID packet".into()).user_message(),
+            "Device disconnected: Failed to write HID packet \
+             Check that the key is plugged in and try again."
+        );
+        assert_eq!(
+            PFError::Device("Bad response".into()).user_message(),
+            "Device Error: Bad response"
+        );
+    }
+}
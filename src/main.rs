@@ -15,6 +15,9 @@ mod ui;
 
 fn main() {
     logging::logger_init();
+    if std::env::args().any(|arg| arg == "--demo") {
+        device::simulator::enable_demo_mode();
+    }
     let app = Application::new().with_assets(ui::assets::Assets);
 
     app.run(move |cx| {
@@ -75,7 +78,7 @@ fn main() {
             };
 
             cx.open_window(window_options, |window, cx| {
-                let view = cx.new(ApplicationRoot::new);
+                let view = cx.new(|cx| ApplicationRoot::new(window, cx));
                 cx.new(|cx| Root::new(view, window, cx))
             })?;
 
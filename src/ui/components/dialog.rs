@@ -4,8 +4,20 @@ use gpui_component::{
     button::{Button, ButtonVariant, ButtonVariants},
     h_flex,
     input::{Input, InputEvent, InputState},
+    progress::Progress,
+    switch::Switch,
     v_flex,
 };
+use std::time::Duration;
+
+use crate::device::fido::constants::RESET_WINDOW_SECS;
+use crate::device::fido::pin_policy::PinComplexityPolicy;
+use crate::device::transport::OperationMonitor;
+use crate::device::types::FidoDeviceInfo;
+
+const MONITOR_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Remaining PIN attempts at or below which PIN dialogs ask for an explicit override.
+const LOW_PIN_RETRIES: u32 = 3;
 
 #[derive(Clone)]
 enum DialogPhase {
@@ -15,6 +27,179 @@ enum DialogPhase {
     Error(String),
 }
 
+/// How many PIN attempts the key has left, shown in every dialog that sends a PIN.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PinAttempts {
+    pub retries: Option<u32>,
+    pub power_cycle_required: bool,
+}
+
+impl PinAttempts {
+    pub fn from_info(info: Option<&FidoDeviceInfo>) -> Self {
+        Self {
+            retries: info.and_then(|i| i.pin_retries),
+            power_cycle_required: info.is_some_and(|i| i.power_cycle_required),
+        }
+    }
+
+    pub fn is_low(&self) -> bool {
+        self.retries.is_some_and(|r| r <= LOW_PIN_RETRIES)
+    }
+
+    /// Whether a dialog may send the PIN, given whether the user overrode the warning.
+    pub fn allows_attempt(&self, overridden: bool) -> bool {
+        self.retries != Some(0) && (!self.is_low() || overridden)
+    }
+}
+
+/// Checks a new PIN against the basic length rule and the key's complexity policy, so the user
+/// sees what is wrong before the key answers with PIN_POLICY_VIOLATION.
+fn check_new_pin(pin: &str, policy: PinComplexityPolicy) -> Result<(), String> {
+    if pin.len() < 4 {
+        return Err("PIN must be at least 4 characters".to_string());
+    }
+    match policy.violation(pin) {
+        Some(msg) => Err(msg.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// The complexity rules a new PIN must follow. `None` when the key enforces none.
+fn pin_policy_hint(policy: PinComplexityPolicy, cx: &App) -> Option<AnyElement> {
+    if policy.is_empty() {
+        return None;
+    }
+    let rules = policy
+        .iter()
+        .map(|rule| PinComplexityPolicy::describe(rule).1)
+        .collect::<Vec<_>>()
+        .join(". ");
+    Some(
+        div()
+            .text_sm()
+            .text_color(cx.theme().muted_foreground)
+            .child(format!("This key's PIN policy: {}.", rules))
+            .into_any_element(),
+    )
+}
+
+/// Warning about the remaining PIN attempts, with a switch the user has to turn on before a
+/// dialog sends a PIN when few are left. `None` when there is nothing to warn about.
+fn pin_attempts_warning(
+    attempts: PinAttempts,
+    overridden: bool,
+    on_override: impl Fn(&bool, &mut Window, &mut App) + 'static,
+    cx: &App,
+) -> Option<AnyElement> {
+    if !attempts.is_low() && !attempts.power_cycle_required {
+        return None;
+    }
+
+    let mut warning = v_flex()
+        .gap_2()
+        .px_3()
+        .py_2()
+        .rounded_md()
+        .bg(cx.theme().warning.opacity(0.1))
+        .text_color(cx.theme().warning)
+        .text_sm();
+
+    if attempts.power_cycle_required {
+        warning = warning.child(
+            "The key stopped accepting PINs after several wrong attempts. Unplug it and plug it back in before trying again.",
+        );
+    }
+
+    match attempts.retries {
+        Some(0) => {
+            warning = warning.child(
+                "The PIN is blocked. The key has to be reset, which deletes all passkeys on it.",
+            );
+        }
+        Some(retries) if attempts.is_low() => {
+            warning = warning
+                .child(format!(
+                    "Only {} PIN attempt{} left. If they run out, the key has to be reset, which deletes all passkeys on it.",
+                    retries,
+                    if retries == 1 { "" } else { "s" }
+                ))
+                .child(
+                    h_flex()
+                        .justify_between()
+                        .items_center()
+                        .child("I am sure of the PIN, try anyway")
+                        .child(
+                            Switch::new("pin-attempts-override")
+                                .checked(overridden)
+                                .on_click(on_override),
+                        ),
+                );
+        }
+        _ => {}
+    }
+
+    Some(warning.into_any_element())
+}
+
+/// The remaining PIN attempts and whether the user overrode the low-attempts warning. Every
+/// dialog that sends a PIN keeps one and asks it before sending; dialogs laid out by their
+/// view show it as a view of its own.
+pub struct PinAttemptsNotice {
+    attempts: PinAttempts,
+    overridden: bool,
+}
+
+impl PinAttemptsNotice {
+    pub fn new(attempts: PinAttempts) -> Self {
+        Self {
+            attempts,
+            overridden: false,
+        }
+    }
+
+    pub fn allows_attempt(&self) -> bool {
+        self.attempts.allows_attempt(self.overridden)
+    }
+
+    /// Updates the remaining attempts, e.g. after a wrong PIN. A changed count has to be
+    /// overridden again. Returns whether anything changed.
+    fn update(&mut self, attempts: PinAttempts) -> bool {
+        if self.attempts == attempts {
+            return false;
+        }
+        self.attempts = attempts;
+        self.overridden = false;
+        true
+    }
+
+    /// See [`PinAttemptsNotice::update`].
+    pub fn set_pin_attempts(&mut self, attempts: PinAttempts, cx: &mut Context<Self>) {
+        if self.update(attempts) {
+            cx.notify();
+        }
+    }
+
+    /// The warning for a dialog `V` that keeps this notice where `notice` points, so the
+    /// override switch can reach it. `None` when there is nothing to warn about.
+    fn warning<V: 'static>(
+        &self,
+        notice: fn(&mut V) -> &mut Self,
+        cx: &mut Context<V>,
+    ) -> Option<AnyElement> {
+        let override_listener = cx.listener(move |this: &mut V, checked: &bool, _, cx| {
+            notice(this).overridden = *checked;
+            cx.notify();
+        });
+        pin_attempts_warning(self.attempts, self.overridden, override_listener, cx)
+    }
+}
+
+impl Render for PinAttemptsNotice {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        div().children(self.warning(|this| this, cx))
+    }
+}
+
 pub struct PinPromptContent {
     phase: DialogPhase,
     title: SharedString,
@@ -22,15 +207,38 @@ pub struct PinPromptContent {
     confirm_label: SharedString,
     pin_input: Entity<InputState>,
     on_confirm: std::rc::Rc<dyn Fn(String, WeakEntity<PinPromptContent>, &mut App)>,
+    attempts: PinAttemptsNotice,
+    monitor: Option<OperationMonitor>,
     _subscription: Subscription,
+    _monitor_task: Option<Task<()>>,
 }
 
 impl PinPromptContent {
     fn set_loading(&mut self, cx: &mut Context<Self>) {
         self.phase = DialogPhase::Loading;
+        self.monitor = None;
+        self._monitor_task = None;
         cx.notify();
     }
 
+    /// Follows `monitor` while the operation runs: shows a touch prompt when the key waits for
+    /// the user and turns the Cancel button into a way to abort the request.
+    pub fn watch_monitor(&mut self, monitor: OperationMonitor, cx: &mut Context<Self>) {
+        self.monitor = Some(monitor);
+        self._monitor_task = Some(cx.spawn(async move |this, cx| {
+            loop {
+                cx.background_executor().timer(MONITOR_POLL_INTERVAL).await;
+                let loading = this.update(cx, |this, cx| {
+                    cx.notify();
+                    matches!(this.phase, DialogPhase::Loading)
+                });
+                if !matches!(loading, Ok(true)) {
+                    break;
+                }
+            }
+        }));
+    }
+
     pub fn set_success(&mut self, msg: String, cx: &mut Context<Self>) {
         self.phase = DialogPhase::Success(msg);
         cx.notify();
@@ -41,8 +249,18 @@ impl PinPromptContent {
         cx.notify();
     }
 
+    /// Updates the remaining attempts, e.g. after a wrong PIN. A changed count has to be
+    /// overridden again.
+    pub fn set_pin_attempts(&mut self, attempts: PinAttempts, cx: &mut Context<Self>) {
+        if self.attempts.update(attempts) {
+            cx.notify();
+        }
+    }
+
     fn trigger_confirm(&mut self, cx: &mut Context<Self>) {
-        if matches!(self.phase, DialogPhase::Loading | DialogPhase::Success(_)) {
+        if matches!(self.phase, DialogPhase::Loading | DialogPhase::Success(_))
+            || !self.attempts.allows_attempt()
+        {
             return;
         }
         let pin = self.pin_input.read(cx).text().to_string();
@@ -57,6 +275,8 @@ impl PinPromptContent {
 impl Render for PinPromptContent {
     fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
         let phase = self.phase.clone();
+        let attempts_warning = self.attempts.warning(|this| &mut this.attempts, cx);
+        let can_attempt = self.attempts.allows_attempt();
 
         match &phase {
             DialogPhase::Success(msg) => v_flex()
@@ -85,23 +305,38 @@ impl Render for PinPromptContent {
                 )
                 .into_any_element(),
 
-            DialogPhase::Loading => v_flex()
-                .gap_4()
-                .child(self.description.clone())
-                .child(Input::new(&self.pin_input).disabled(true))
-                .child(
-                    h_flex()
-                        .justify_end()
-                        .gap_2()
-                        .child(Button::new("cancel").label("Cancel").disabled(true))
-                        .child(
+            DialogPhase::Loading => {
+                let monitor = self.monitor.clone();
+                let cancelled = monitor.as_ref().is_some_and(|m| m.is_cancelled());
+                let needs_touch = monitor.as_ref().is_some_and(|m| m.needs_touch()) && !cancelled;
+
+                let cancel = match monitor {
+                    Some(monitor) if !cancelled => Button::new("cancel")
+                        .label("Cancel")
+                        .on_click(move |_, _, _| monitor.cancel()),
+                    Some(_) => Button::new("cancel").label("Cancelling...").disabled(true),
+                    None => Button::new("cancel").label("Cancel").disabled(true),
+                };
+
+                v_flex()
+                    .gap_4()
+                    .child(self.description.clone())
+                    .child(Input::new(&self.pin_input).disabled(true))
+                    .children(needs_touch.then(|| touch_prompt(cx)))
+                    .child(
+                        h_flex().justify_end().gap_2().child(cancel).child(
                             Button::new("confirm")
                                 .primary()
-                                .label("Loading...")
+                                .label(if needs_touch {
+                                    "Waiting for touch..."
+                                } else {
+                                    "Loading..."
+                                })
                                 .loading(true),
                         ),
-                )
-                .into_any_element(),
+                    )
+                    .into_any_element()
+            }
 
             DialogPhase::Error(err_msg) => {
                 let pin_input = self.pin_input.clone();
@@ -122,6 +357,7 @@ impl Render for PinPromptContent {
                             .text_sm()
                             .child(err_msg.clone()),
                     )
+                    .children(attempts_warning)
                     .child(Input::new(&pin_input))
                     .child(
                         h_flex()
@@ -136,6 +372,7 @@ impl Render for PinPromptContent {
                                 Button::new("confirm")
                                     .primary()
                                     .label(confirm_label)
+                                    .disabled(!can_attempt)
                                     .on_click(move |_, _, cx| {
                                         let pin = pin_input.read(cx).text().to_string();
                                         if !pin.is_empty() {
@@ -159,6 +396,7 @@ impl Render for PinPromptContent {
                 v_flex()
                     .gap_4()
                     .child(self.description.clone())
+                    .children(attempts_warning)
                     .child(Input::new(&pin_input))
                     .child(
                         h_flex()
@@ -173,6 +411,7 @@ impl Render for PinPromptContent {
                                 Button::new("confirm")
                                     .primary()
                                     .label(confirm_label)
+                                    .disabled(!can_attempt)
                                     .on_click(move |_, _, cx| {
                                         let pin = pin_input.read(cx).text().to_string();
                                         if !pin.is_empty() {
@@ -190,10 +429,27 @@ impl Render for PinPromptContent {
     }
 }
 
+/// Banner asking the user to touch the key, shown while it reports `UPNEEDED` keepalives.
+fn touch_prompt(cx: &App) -> Div {
+    h_flex()
+        .gap_2()
+        .items_center()
+        .px_3()
+        .py_2()
+        .rounded_md()
+        .bg(cx.theme().primary.opacity(0.1))
+        .child(
+            gpui_component::Icon::new(gpui_component::IconName::Info)
+                .text_color(cx.theme().primary),
+        )
+        .child("Touch your key now")
+}
+
 pub fn open_pin_prompt(
     title: &str,
     description: &str,
     confirm_label: &str,
+    attempts: PinAttempts,
     window: &mut Window,
     cx: &mut App,
     on_confirm: impl Fn(String, WeakEntity<PinPromptContent>, &mut App) + 'static,
@@ -225,7 +481,10 @@ pub fn open_pin_prompt(
             confirm_label,
             pin_input: pin_for_sub,
             on_confirm: std::rc::Rc::new(on_confirm),
+            attempts: PinAttemptsNotice::new(attempts),
+            monitor: None,
             _subscription: sub,
+            _monitor_task: None,
         }
     });
 
@@ -245,6 +504,10 @@ pub struct ConfirmContent {
     ok_label: SharedString,
     ok_variant: ButtonVariant,
     on_ok: std::rc::Rc<dyn Fn(WeakEntity<ConfirmContent>, &mut App)>,
+    attempts: PinAttemptsNotice,
+    /// Set for batch operations, which show their progress and can be cancelled.
+    monitor: Option<OperationMonitor>,
+    _monitor_task: Option<Task<()>>,
 }
 
 impl ConfirmContent {
@@ -253,6 +516,25 @@ impl ConfirmContent {
         cx.notify();
     }
 
+    /// Shows the progress `monitor` reports while the operation runs, with a Cancel button
+    /// that stops it after the current item.
+    pub fn watch_progress(&mut self, monitor: OperationMonitor, cx: &mut Context<Self>) {
+        self.monitor = Some(monitor);
+        self._monitor_task = Some(cx.spawn(async move |this, cx| {
+            loop {
+                cx.background_executor().timer(MONITOR_POLL_INTERVAL).await;
+                let loading = this.update(cx, |this, cx| {
+                    cx.notify();
+                    matches!(this.phase, DialogPhase::Loading)
+                });
+                if !matches!(loading, Ok(true)) {
+                    break;
+                }
+            }
+        }));
+        cx.notify();
+    }
+
     pub fn set_success(&mut self, msg: String, cx: &mut Context<Self>) {
         self.phase = DialogPhase::Success(msg);
         cx.notify();
@@ -262,11 +544,20 @@ impl ConfirmContent {
         self.phase = DialogPhase::Error(msg);
         cx.notify();
     }
+
+    /// See [`PinPromptContent::set_pin_attempts`].
+    pub fn set_pin_attempts(&mut self, attempts: PinAttempts, cx: &mut Context<Self>) {
+        if self.attempts.update(attempts) {
+            cx.notify();
+        }
+    }
 }
 
 impl Render for ConfirmContent {
     fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
         let phase = self.phase.clone();
+        let attempts_warning = self.attempts.warning(|this| &mut this.attempts, cx);
+        let can_attempt = self.attempts.allows_attempt();
 
         match &phase {
             DialogPhase::Success(msg) => v_flex()
@@ -295,22 +586,50 @@ impl Render for ConfirmContent {
                 )
                 .into_any_element(),
 
-            DialogPhase::Loading => v_flex()
-                .gap_4()
-                .child(self.message.clone())
-                .child(
-                    h_flex()
-                        .justify_end()
-                        .gap_2()
-                        .child(Button::new("cancel").label("Cancel").disabled(true))
-                        .child(
-                            Button::new("ok")
-                                .with_variant(self.ok_variant)
-                                .label("Loading...")
-                                .loading(true),
-                        ),
-                )
-                .into_any_element(),
+            DialogPhase::Loading => {
+                let monitor = self.monitor.clone();
+                let progress = monitor.as_ref().and_then(OperationMonitor::progress);
+                let cancelled = monitor.as_ref().is_some_and(OperationMonitor::is_cancelled);
+
+                v_flex()
+                    .gap_4()
+                    .child(self.message.clone())
+                    .children(progress.map(|(done, total)| {
+                        let percent = if total == 0 {
+                            100.0
+                        } else {
+                            done as f32 / total as f32 * 100.0
+                        };
+                        v_flex()
+                            .gap_2()
+                            .child(Progress::new().value(percent))
+                            .child(
+                                div()
+                                    .text_sm()
+                                    .text_color(cx.theme().muted_foreground)
+                                    .child(format!("{} of {} done", done, total)),
+                            )
+                    }))
+                    .child(
+                        h_flex()
+                            .justify_end()
+                            .gap_2()
+                            .child(match monitor {
+                                Some(monitor) => Button::new("cancel")
+                                    .label(if cancelled { "Cancelling..." } else { "Cancel" })
+                                    .disabled(cancelled)
+                                    .on_click(move |_, _, _| monitor.cancel()),
+                                None => Button::new("cancel").label("Cancel").disabled(true),
+                            })
+                            .child(
+                                Button::new("ok")
+                                    .with_variant(self.ok_variant)
+                                    .label("Loading...")
+                                    .loading(true),
+                            ),
+                    )
+                    .into_any_element()
+            }
 
             DialogPhase::Error(err_msg) => {
                 let ok_label = self.ok_label.clone();
@@ -331,6 +650,7 @@ impl Render for ConfirmContent {
                             .text_sm()
                             .child(err_msg.clone()),
                     )
+                    .children(attempts_warning)
                     .child(
                         h_flex()
                             .justify_end()
@@ -344,6 +664,7 @@ impl Render for ConfirmContent {
                                 Button::new("ok")
                                     .with_variant(ok_variant)
                                     .label(ok_label)
+                                    .disabled(!can_attempt)
                                     .on_click(move |_, _, cx| {
                                         if let Some(h) = handle.upgrade() {
                                             h.update(cx, |this, cx| this.set_loading(cx));
@@ -364,6 +685,7 @@ impl Render for ConfirmContent {
                 v_flex()
                     .gap_4()
                     .child(self.message.clone())
+                    .children(attempts_warning)
                     .child(
                         h_flex()
                             .justify_end()
@@ -377,6 +699,7 @@ impl Render for ConfirmContent {
                                 Button::new("ok")
                                     .with_variant(ok_variant)
                                     .label(ok_label)
+                                    .disabled(!can_attempt)
                                     .on_click(move |_, _, cx| {
                                         if let Some(h) = handle.upgrade() {
                                             h.update(cx, |this, cx| this.set_loading(cx));
@@ -399,6 +722,30 @@ pub fn open_confirm(
     window: &mut Window,
     cx: &mut App,
     on_ok: impl Fn(WeakEntity<ConfirmContent>, &mut App) + 'static,
+) {
+    open_pin_confirm(
+        title,
+        message,
+        ok_label,
+        ok_variant,
+        PinAttempts::default(),
+        window,
+        cx,
+        on_ok,
+    );
+}
+
+/// Like [`open_confirm`], for actions that send a stored PIN to the key.
+#[allow(clippy::too_many_arguments)]
+pub fn open_pin_confirm(
+    title: &str,
+    message: String,
+    ok_label: &str,
+    ok_variant: ButtonVariant,
+    attempts: PinAttempts,
+    window: &mut Window,
+    cx: &mut App,
+    on_ok: impl Fn(WeakEntity<ConfirmContent>, &mut App) + 'static,
 ) {
     let title_str = SharedString::from(title.to_string());
     let dialog_title = title_str.clone();
@@ -410,6 +757,9 @@ pub fn open_confirm(
         ok_label: SharedString::from(ok_label.to_string()),
         ok_variant,
         on_ok: std::rc::Rc::new(on_ok),
+        attempts: PinAttemptsNotice::new(attempts),
+        monitor: None,
+        _monitor_task: None,
     });
 
     window.open_dialog(cx, move |dialog, _, _| {
@@ -427,6 +777,8 @@ pub struct ChangePinContent {
     new_pin: Entity<InputState>,
     confirm_pin: Entity<InputState>,
     on_confirm: std::rc::Rc<dyn Fn(String, String, WeakEntity<ChangePinContent>, &mut App)>,
+    attempts: PinAttemptsNotice,
+    policy: PinComplexityPolicy,
     _subscriptions: Vec<Subscription>,
 }
 
@@ -446,8 +798,17 @@ impl ChangePinContent {
         cx.notify();
     }
 
+    /// See [`PinPromptContent::set_pin_attempts`].
+    pub fn set_pin_attempts(&mut self, attempts: PinAttempts, cx: &mut Context<Self>) {
+        if self.attempts.update(attempts) {
+            cx.notify();
+        }
+    }
+
     fn trigger_confirm(&mut self, cx: &mut Context<Self>) {
-        if matches!(self.phase, DialogPhase::Loading | DialogPhase::Success(_)) {
+        if matches!(self.phase, DialogPhase::Loading | DialogPhase::Success(_))
+            || !self.attempts.allows_attempt()
+        {
             return;
         }
 
@@ -464,8 +825,8 @@ impl ChangePinContent {
             return;
         }
 
-        if new_val.len() < 4 {
-            self.set_error("PIN must be at least 4 characters".to_string(), cx);
+        if let Err(msg) = check_new_pin(&new_val, self.policy) {
+            self.set_error(msg, cx);
             return;
         }
 
@@ -478,6 +839,8 @@ impl ChangePinContent {
 impl Render for ChangePinContent {
     fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
         let phase = self.phase.clone();
+        let attempts_warning = self.attempts.warning(|this| &mut this.attempts, cx);
+        let can_attempt = self.attempts.allows_attempt();
 
         match &phase {
             DialogPhase::Success(msg) => v_flex()
@@ -509,6 +872,7 @@ impl Render for ChangePinContent {
             DialogPhase::Loading => v_flex()
                 .gap_4()
                 .child("Enter your current PIN and choose a new one.")
+                .children(pin_policy_hint(self.policy, cx))
                 .child(
                     v_flex()
                         .gap_4()
@@ -538,11 +902,14 @@ impl Render for ChangePinContent {
                 let new = self.new_pin.clone();
                 let confirm = self.confirm_pin.clone();
                 let on_confirm = self.on_confirm.clone();
+                let policy = self.policy;
                 let handle = cx.entity().downgrade();
 
                 v_flex()
                     .gap_4()
                     .child("Enter your current PIN and choose a new one.")
+                    .children(pin_policy_hint(self.policy, cx))
+                    .children(attempts_warning)
                     .child(
                         div()
                             .px_3()
@@ -572,43 +939,45 @@ impl Render for ChangePinContent {
                                     .label("Cancel")
                                     .on_click(|_, window, cx| window.close_dialog(cx)),
                             )
-                            .child(Button::new("confirm").primary().label("Confirm").on_click(
-                                move |_, _, cx| {
-                                    let current_val = current.read(cx).text().to_string();
-                                    let new_val = new.read(cx).text().to_string();
-                                    let confirm_val = confirm.read(cx).text().to_string();
+                            .child(
+                                Button::new("confirm")
+                                    .primary()
+                                    .label("Confirm")
+                                    .disabled(!can_attempt)
+                                    .on_click(move |_, _, cx| {
+                                        let current_val = current.read(cx).text().to_string();
+                                        let new_val = new.read(cx).text().to_string();
+                                        let confirm_val = confirm.read(cx).text().to_string();
 
-                                    if current_val.is_empty() {
-                                        return;
-                                    }
+                                        if current_val.is_empty() {
+                                            return;
+                                        }
 
-                                    if new_val != confirm_val {
-                                        if let Some(h) = handle.upgrade() {
-                                            h.update(cx, |this, cx| {
-                                                this.set_error("PINs do not match".to_string(), cx);
-                                            });
+                                        if new_val != confirm_val {
+                                            if let Some(h) = handle.upgrade() {
+                                                h.update(cx, |this, cx| {
+                                                    this.set_error(
+                                                        "PINs do not match".to_string(),
+                                                        cx,
+                                                    );
+                                                });
+                                            }
+                                            return;
                                         }
-                                        return;
-                                    }
 
-                                    if new_val.len() < 4 {
-                                        if let Some(h) = handle.upgrade() {
-                                            h.update(cx, |this, cx| {
-                                                this.set_error(
-                                                    "PIN must be at least 4 characters".to_string(),
-                                                    cx,
-                                                );
-                                            });
+                                        if let Err(msg) = check_new_pin(&new_val, policy) {
+                                            if let Some(h) = handle.upgrade() {
+                                                h.update(cx, |this, cx| this.set_error(msg, cx));
+                                            }
+                                            return;
                                         }
-                                        return;
-                                    }
 
-                                    if let Some(h) = handle.upgrade() {
-                                        h.update(cx, |this, cx| this.set_loading(cx));
-                                    }
-                                    on_confirm(current_val, new_val, handle.clone(), cx);
-                                },
-                            )),
+                                        if let Some(h) = handle.upgrade() {
+                                            h.update(cx, |this, cx| this.set_loading(cx));
+                                        }
+                                        on_confirm(current_val, new_val, handle.clone(), cx);
+                                    }),
+                            ),
                     )
                     .into_any_element()
             }
@@ -618,11 +987,14 @@ impl Render for ChangePinContent {
                 let new = self.new_pin.clone();
                 let confirm = self.confirm_pin.clone();
                 let on_confirm = self.on_confirm.clone();
+                let policy = self.policy;
                 let handle = cx.entity().downgrade();
 
                 v_flex()
                     .gap_4()
                     .child("Enter your current PIN and choose a new one.")
+                    .children(pin_policy_hint(self.policy, cx))
+                    .children(attempts_warning)
                     .child(
                         v_flex()
                             .gap_4()
@@ -642,43 +1014,45 @@ impl Render for ChangePinContent {
                                     .label("Cancel")
                                     .on_click(|_, window, cx| window.close_dialog(cx)),
                             )
-                            .child(Button::new("confirm").primary().label("Confirm").on_click(
-                                move |_, _, cx| {
-                                    let current_val = current.read(cx).text().to_string();
-                                    let new_val = new.read(cx).text().to_string();
-                                    let confirm_val = confirm.read(cx).text().to_string();
+                            .child(
+                                Button::new("confirm")
+                                    .primary()
+                                    .label("Confirm")
+                                    .disabled(!can_attempt)
+                                    .on_click(move |_, _, cx| {
+                                        let current_val = current.read(cx).text().to_string();
+                                        let new_val = new.read(cx).text().to_string();
+                                        let confirm_val = confirm.read(cx).text().to_string();
 
-                                    if current_val.is_empty() {
-                                        return;
-                                    }
+                                        if current_val.is_empty() {
+                                            return;
+                                        }
 
-                                    if new_val != confirm_val {
-                                        if let Some(h) = handle.upgrade() {
-                                            h.update(cx, |this, cx| {
-                                                this.set_error("PINs do not match".to_string(), cx);
-                                            });
+                                        if new_val != confirm_val {
+                                            if let Some(h) = handle.upgrade() {
+                                                h.update(cx, |this, cx| {
+                                                    this.set_error(
+                                                        "PINs do not match".to_string(),
+                                                        cx,
+                                                    );
+                                                });
+                                            }
+                                            return;
                                         }
-                                        return;
-                                    }
 
-                                    if new_val.len() < 4 {
-                                        if let Some(h) = handle.upgrade() {
-                                            h.update(cx, |this, cx| {
-                                                this.set_error(
-                                                    "PIN must be at least 4 characters".to_string(),
-                                                    cx,
-                                                );
-                                            });
+                                        if let Err(msg) = check_new_pin(&new_val, policy) {
+                                            if let Some(h) = handle.upgrade() {
+                                                h.update(cx, |this, cx| this.set_error(msg, cx));
+                                            }
+                                            return;
                                         }
-                                        return;
-                                    }
 
-                                    if let Some(h) = handle.upgrade() {
-                                        h.update(cx, |this, cx| this.set_loading(cx));
-                                    }
-                                    on_confirm(current_val, new_val, handle.clone(), cx);
-                                },
-                            )),
+                                        if let Some(h) = handle.upgrade() {
+                                            h.update(cx, |this, cx| this.set_loading(cx));
+                                        }
+                                        on_confirm(current_val, new_val, handle.clone(), cx);
+                                    }),
+                            ),
                     )
                     .into_any_element()
             }
@@ -687,6 +1061,8 @@ impl Render for ChangePinContent {
 }
 
 pub fn open_change_pin(
+    attempts: PinAttempts,
+    policy: PinComplexityPolicy,
     window: &mut Window,
     cx: &mut App,
     on_confirm: impl Fn(String, String, WeakEntity<ChangePinContent>, &mut App) + 'static,
@@ -725,6 +1101,8 @@ pub fn open_change_pin(
             new_pin,
             confirm_pin: confirm_for_sub,
             on_confirm: std::rc::Rc::new(on_confirm),
+            attempts: PinAttemptsNotice::new(attempts),
+            policy,
             _subscriptions: vec![sub],
         }
     });
@@ -743,6 +1121,7 @@ pub struct SetPinContent {
     new_pin: Entity<InputState>,
     confirm_pin: Entity<InputState>,
     on_confirm: std::rc::Rc<dyn Fn(String, WeakEntity<SetPinContent>, &mut App)>,
+    policy: PinComplexityPolicy,
     _subscriptions: Vec<Subscription>,
 }
 
@@ -775,8 +1154,8 @@ impl SetPinContent {
             return;
         }
 
-        if new_val.len() < 4 {
-            self.set_error("PIN must be at least 4 characters".to_string(), cx);
+        if let Err(msg) = check_new_pin(&new_val, self.policy) {
+            self.set_error(msg, cx);
             return;
         }
 
@@ -820,6 +1199,7 @@ impl Render for SetPinContent {
             DialogPhase::Loading => v_flex()
                 .gap_4()
                 .child("Choose a PIN for your pico-key.")
+                .children(pin_policy_hint(self.policy, cx))
                 .child(
                     v_flex()
                         .gap_4()
@@ -846,11 +1226,13 @@ impl Render for SetPinContent {
                 let new = self.new_pin.clone();
                 let confirm = self.confirm_pin.clone();
                 let on_confirm = self.on_confirm.clone();
+                let policy = self.policy;
                 let handle = cx.entity().downgrade();
 
                 v_flex()
                     .gap_4()
                     .child("Choose a PIN for your pico-key.")
+                    .children(pin_policy_hint(self.policy, cx))
                     .child(
                         div()
                             .px_3()
@@ -892,14 +1274,9 @@ impl Render for SetPinContent {
                                         return;
                                     }
 
-                                    if new_val.len() < 4 {
+                                    if let Err(msg) = check_new_pin(&new_val, policy) {
                                         if let Some(h) = handle.upgrade() {
-                                            h.update(cx, |this, cx| {
-                                                this.set_error(
-                                                    "PIN must be at least 4 characters".to_string(),
-                                                    cx,
-                                                );
-                                            });
+                                            h.update(cx, |this, cx| this.set_error(msg, cx));
                                         }
                                         return;
                                     }
@@ -918,11 +1295,13 @@ impl Render for SetPinContent {
                 let new = self.new_pin.clone();
                 let confirm = self.confirm_pin.clone();
                 let on_confirm = self.on_confirm.clone();
+                let policy = self.policy;
                 let handle = cx.entity().downgrade();
 
                 v_flex()
                     .gap_4()
                     .child("Choose a PIN for your pico-key.")
+                    .children(pin_policy_hint(self.policy, cx))
                     .child(
                         v_flex()
                             .gap_4()
@@ -954,14 +1333,9 @@ impl Render for SetPinContent {
                                         return;
                                     }
 
-                                    if new_val.len() < 4 {
+                                    if let Err(msg) = check_new_pin(&new_val, policy) {
                                         if let Some(h) = handle.upgrade() {
-                                            h.update(cx, |this, cx| {
-                                                this.set_error(
-                                                    "PIN must be at least 4 characters".to_string(),
-                                                    cx,
-                                                );
-                                            });
+                                            h.update(cx, |this, cx| this.set_error(msg, cx));
                                         }
                                         return;
                                     }
@@ -980,6 +1354,7 @@ impl Render for SetPinContent {
 }
 
 pub fn open_setup_pin(
+    policy: PinComplexityPolicy,
     window: &mut Window,
     cx: &mut App,
     on_confirm: impl Fn(String, WeakEntity<SetPinContent>, &mut App) + 'static,
@@ -1012,6 +1387,7 @@ pub fn open_setup_pin(
             new_pin,
             confirm_pin: confirm_for_sub,
             on_confirm: std::rc::Rc::new(on_confirm),
+            policy,
             _subscriptions: vec![sub],
         }
     });
@@ -1027,9 +1403,38 @@ pub fn open_setup_pin(
 pub struct StatusContent {
     phase: DialogPhase,
     title: SharedString,
+    message: SharedString,
+    monitor: Option<OperationMonitor>,
+    _monitor_task: Option<Task<()>>,
 }
 
 impl StatusContent {
+    fn new(title: SharedString, message: SharedString) -> Self {
+        Self {
+            phase: DialogPhase::Loading,
+            title,
+            message,
+            monitor: None,
+            _monitor_task: None,
+        }
+    }
+
+    fn watch_monitor(&mut self, monitor: OperationMonitor, cx: &mut Context<Self>) {
+        self.monitor = Some(monitor);
+        self._monitor_task = Some(cx.spawn(async move |this, cx| {
+            loop {
+                cx.background_executor().timer(MONITOR_POLL_INTERVAL).await;
+                let loading = this.update(cx, |this, cx| {
+                    cx.notify();
+                    matches!(this.phase, DialogPhase::Loading)
+                });
+                if !matches!(loading, Ok(true)) {
+                    break;
+                }
+            }
+        }));
+    }
+
     pub fn set_success(&mut self, msg: String, cx: &mut Context<Self>) {
         self.phase = DialogPhase::Success(msg);
         cx.notify();
@@ -1108,17 +1513,51 @@ impl Render for StatusContent {
                     .into_any_element()
             }
 
-            _ => v_flex()
-                .gap_4()
-                .items_center()
-                .child("Applying configuration...")
-                .child(
-                    Button::new("loading")
-                        .primary()
-                        .label("Applying...")
-                        .loading(true),
-                )
-                .into_any_element(),
+            _ => {
+                let Some(monitor) = self.monitor.clone() else {
+                    return v_flex()
+                        .gap_4()
+                        .items_center()
+                        .child(self.message.clone())
+                        .child(
+                            Button::new("loading")
+                                .primary()
+                                .label("Applying...")
+                                .loading(true),
+                        )
+                        .into_any_element();
+                };
+
+                let cancelled = monitor.is_cancelled();
+                let needs_touch = monitor.needs_touch() && !cancelled;
+
+                v_flex()
+                    .gap_4()
+                    .child(self.message.clone())
+                    .children(needs_touch.then(|| touch_prompt(cx)))
+                    .child(
+                        h_flex()
+                            .justify_end()
+                            .gap_2()
+                            .child(
+                                Button::new("cancel")
+                                    .label(if cancelled { "Cancelling..." } else { "Cancel" })
+                                    .disabled(cancelled)
+                                    .on_click(move |_, _, _| monitor.cancel()),
+                            )
+                            .child(
+                                Button::new("loading")
+                                    .primary()
+                                    .label(if needs_touch {
+                                        "Waiting for touch..."
+                                    } else {
+                                        "Working..."
+                                    })
+                                    .loading(true),
+                            ),
+                    )
+                    .into_any_element()
+            }
         }
     }
 }
@@ -1131,10 +1570,7 @@ pub fn open_status_dialog(
     let title_str = SharedString::from(title.to_string());
     let dialog_title = title_str.clone();
 
-    let content = cx.new(|_cx| StatusContent {
-        phase: DialogPhase::Loading,
-        title: title_str,
-    });
+    let content = cx.new(|_cx| StatusContent::new(title_str, "Applying configuration...".into()));
 
     let handle = content.downgrade();
 
@@ -1148,3 +1584,847 @@ pub fn open_status_dialog(
 
     handle
 }
+
+/// A [`StatusContent`] dialog for an operation that may wait for the user. It shows a touch
+/// prompt while the key asks for user presence and a Cancel button that aborts the request.
+pub fn open_progress_dialog(
+    title: &str,
+    message: &str,
+    monitor: OperationMonitor,
+    window: &mut Window,
+    cx: &mut App,
+) -> WeakEntity<StatusContent> {
+    let title_str = SharedString::from(title.to_string());
+    let dialog_title = title_str.clone();
+    let message = SharedString::from(message.to_string());
+
+    let content = cx.new(|cx| {
+        let mut content = StatusContent::new(title_str, message);
+        content.watch_monitor(monitor, cx);
+        content
+    });
+
+    let handle = content.downgrade();
+
+    window.open_dialog(cx, move |dialog, _, _| {
+        dialog
+            .title(dialog_title.clone())
+            .child(content.clone())
+            .overlay_closable(false)
+            .close_button(false)
+    });
+
+    handle
+}
+
+fn error_banner(msg: String, cx: &App) -> Div {
+    div()
+        .px_3()
+        .py_2()
+        .rounded_md()
+        .bg(cx.theme().danger.opacity(0.1))
+        .text_color(cx.theme().danger)
+        .text_sm()
+        .child(msg)
+}
+
+/// Number of recovery words the user re-enters to confirm they wrote the list down.
+const BACKUP_CHECK_WORDS: usize = 3;
+
+#[derive(Clone)]
+enum BackupPhase {
+    Pin(Option<String>),
+    Loading,
+    Words,
+    Verify(Option<String>),
+    Done,
+}
+
+/// Backup dialog: PIN, then the recovery words, then re-entering a few of them.
+pub struct BackupContent {
+    phase: BackupPhase,
+    pin_input: Entity<InputState>,
+    words: Vec<String>,
+    /// Indices into `words` the user has to re-enter, in ascending order.
+    check_positions: Vec<usize>,
+    check_inputs: Vec<Entity<InputState>>,
+    attempts: PinAttemptsNotice,
+    on_confirm: std::rc::Rc<dyn Fn(String, WeakEntity<BackupContent>, &mut App)>,
+    _subscription: Subscription,
+}
+
+impl BackupContent {
+    pub fn set_words(&mut self, words: Vec<String>, cx: &mut Context<Self>) {
+        let count = BACKUP_CHECK_WORDS.min(words.len());
+        let mut positions =
+            rand::seq::index::sample(&mut rand::rng(), words.len(), count).into_vec();
+        positions.sort_unstable();
+        self.check_positions = positions;
+        self.words = words;
+        self.phase = BackupPhase::Words;
+        cx.notify();
+    }
+
+    pub fn set_error(&mut self, msg: String, cx: &mut Context<Self>) {
+        self.phase = BackupPhase::Pin(Some(msg));
+        cx.notify();
+    }
+
+    /// See [`PinPromptContent::set_pin_attempts`].
+    pub fn set_pin_attempts(&mut self, attempts: PinAttempts, cx: &mut Context<Self>) {
+        if self.attempts.update(attempts) {
+            cx.notify();
+        }
+    }
+
+    fn set_phase(&mut self, phase: BackupPhase, cx: &mut Context<Self>) {
+        self.phase = phase;
+        cx.notify();
+    }
+
+    fn trigger_confirm(&mut self, cx: &mut Context<Self>) {
+        if !matches!(self.phase, BackupPhase::Pin(_)) || !self.attempts.allows_attempt() {
+            return;
+        }
+        let pin = self.pin_input.read(cx).text().to_string();
+        if !pin.is_empty() {
+            let handle = cx.entity().downgrade();
+            self.set_phase(BackupPhase::Loading, cx);
+            (self.on_confirm)(pin, handle, cx);
+        }
+    }
+
+    fn check_words(&mut self, cx: &mut Context<Self>) {
+        let all_match = self
+            .check_positions
+            .iter()
+            .zip(&self.check_inputs)
+            .all(|(&pos, input)| {
+                input.read(cx).text().to_string().trim().to_lowercase() == self.words[pos]
+            });
+
+        self.set_phase(
+            if all_match {
+                BackupPhase::Done
+            } else {
+                BackupPhase::Verify(Some(
+                    "The words do not match your list. Check what you wrote down.".to_string(),
+                ))
+            },
+            cx,
+        );
+    }
+}
+
+impl Render for BackupContent {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let phase = self.phase.clone();
+
+        match phase {
+            BackupPhase::Pin(error) => {
+                let attempts_warning = self.attempts.warning(|this| &mut this.attempts, cx);
+                let can_attempt = self.attempts.allows_attempt();
+
+                v_flex()
+                    .gap_4()
+                    .child("Enter your device PIN to read the encrypted backup of this key.")
+                    .children(error.map(|msg| error_banner(msg, cx)))
+                    .children(attempts_warning)
+                    .child(Input::new(&self.pin_input))
+                    .child(
+                        h_flex()
+                            .justify_end()
+                            .gap_2()
+                            .child(
+                                Button::new("cancel")
+                                    .label("Cancel")
+                                    .on_click(|_, window, cx| window.close_dialog(cx)),
+                            )
+                            .child(
+                                Button::new("confirm")
+                                    .primary()
+                                    .label("Continue")
+                                    .disabled(!can_attempt)
+                                    .on_click(cx.listener(|this, _, _, cx| {
+                                        this.trigger_confirm(cx);
+                                    })),
+                            ),
+                    )
+                    .into_any_element()
+            }
+
+            BackupPhase::Loading => v_flex()
+                .gap_4()
+                .child("Reading the backup from the key...")
+                .child(
+                    h_flex()
+                        .justify_end()
+                        .gap_2()
+                        .child(Button::new("cancel").label("Cancel").disabled(true))
+                        .child(
+                            Button::new("confirm")
+                                .primary()
+                                .label("Loading...")
+                                .loading(true),
+                        ),
+                )
+                .into_any_element(),
+
+            BackupPhase::Words => v_flex()
+                .gap_4()
+                .child(
+                    "Write these words down in order and keep them offline. Anyone who has them can restore this key's secret onto another key.",
+                )
+                .child(
+                    div()
+                        .grid()
+                        .grid_cols(3)
+                        .gap_2()
+                        .children(self.words.iter().enumerate().map(|(i, word)| {
+                            h_flex()
+                                .gap_2()
+                                .px_2()
+                                .py_1()
+                                .rounded_md()
+                                .bg(cx.theme().secondary)
+                                .child(
+                                    div()
+                                        .w_6()
+                                        .text_color(cx.theme().muted_foreground)
+                                        .child(format!("{}.", i + 1)),
+                                )
+                                .child(div().font_family("Mono").child(word.clone()))
+                        })),
+                )
+                .child(
+                    h_flex()
+                        .justify_end()
+                        .gap_2()
+                        .child(
+                            Button::new("cancel")
+                                .label("Cancel")
+                                .on_click(|_, window, cx| window.close_dialog(cx)),
+                        )
+                        .child(
+                            Button::new("next")
+                                .primary()
+                                .label("I Wrote Them Down")
+                                .on_click(cx.listener(|this, _, _, cx| {
+                                    this.set_phase(BackupPhase::Verify(None), cx);
+                                })),
+                        ),
+                )
+                .into_any_element(),
+
+            BackupPhase::Verify(error) => v_flex()
+                .gap_4()
+                .child("Enter the following words from your list to confirm you have them.")
+                .children(error.map(|msg| error_banner(msg, cx)))
+                .children(
+                    self.check_positions
+                        .iter()
+                        .zip(&self.check_inputs)
+                        .map(|(pos, input)| {
+                            v_flex()
+                                .gap_2()
+                                .child(format!("Word #{}", pos + 1))
+                                .child(Input::new(input))
+                        }),
+                )
+                .child(
+                    h_flex()
+                        .justify_end()
+                        .gap_2()
+                        .child(Button::new("back").label("Back").on_click(cx.listener(
+                            |this, _, _, cx| {
+                                this.set_phase(BackupPhase::Words, cx);
+                            },
+                        )))
+                        .child(
+                            Button::new("confirm")
+                                .primary()
+                                .label("Confirm")
+                                .on_click(cx.listener(|this, _, _, cx| {
+                                    this.check_words(cx);
+                                })),
+                        ),
+                )
+                .into_any_element(),
+
+            BackupPhase::Done => v_flex()
+                .gap_4()
+                .child(
+                    h_flex()
+                        .gap_2()
+                        .items_center()
+                        .child(
+                            gpui_component::Icon::new(gpui_component::IconName::CircleCheck)
+                                .text_color(cx.theme().green)
+                                .with_size(gpui_component::Size::Large),
+                        )
+                        .child("Backup confirmed"),
+                )
+                .child("Keep the words somewhere safe. You can restore them onto this or any other pico-fido key.")
+                .child(
+                    h_flex().justify_end().child(
+                        Button::new("done")
+                            .primary()
+                            .label("Done")
+                            .on_click(|_, window, cx| window.close_dialog(cx)),
+                    ),
+                )
+                .into_any_element(),
+        }
+    }
+}
+
+pub fn open_backup(
+    attempts: PinAttempts,
+    window: &mut Window,
+    cx: &mut App,
+    on_confirm: impl Fn(String, WeakEntity<BackupContent>, &mut App) + 'static,
+) {
+    let pin_input = cx.new(|cx| {
+        InputState::new(window, cx)
+            .placeholder("Enter FIDO PIN")
+            .masked(true)
+    });
+    let check_inputs = (0..BACKUP_CHECK_WORDS)
+        .map(|_| cx.new(|cx| InputState::new(window, cx).placeholder("Enter word")))
+        .collect();
+
+    let pin_for_sub = pin_input.clone();
+    let content = cx.new(|cx| {
+        let sub = cx.subscribe(&pin_for_sub, |this: &mut BackupContent, _, event, cx| {
+            if matches!(event, InputEvent::PressEnter { .. }) {
+                this.trigger_confirm(cx);
+            }
+        });
+
+        BackupContent {
+            phase: BackupPhase::Pin(None),
+            pin_input: pin_for_sub,
+            words: Vec::new(),
+            check_positions: Vec::new(),
+            check_inputs,
+            attempts: PinAttemptsNotice::new(attempts),
+            on_confirm: std::rc::Rc::new(on_confirm),
+            _subscription: sub,
+        }
+    });
+
+    window.open_dialog(cx, move |dialog, _, _| {
+        dialog
+            .title("Back Up Key")
+            .child(content.clone())
+            .overlay_closable(false)
+            .close_button(false)
+    });
+}
+
+/// What the user types to confirm restoring onto a key without a PIN.
+const RESTORE_CONFIRMATION: &str = "RESTORE";
+
+/// Takes the recovery words, and either the PIN of the key (if it has one) or a typed
+/// confirmation, since restoring replaces the key's secret.
+pub struct RestoreBackupContent {
+    phase: DialogPhase,
+    words_input: Entity<InputState>,
+    /// Set if the key has a PIN, which it checks before accepting the backup.
+    pin_input: Option<Entity<InputState>>,
+    /// Set if it has none.
+    confirm_input: Option<Entity<InputState>>,
+    attempts: PinAttemptsNotice,
+    on_confirm:
+        std::rc::Rc<dyn Fn(String, Option<String>, WeakEntity<RestoreBackupContent>, &mut App)>,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl RestoreBackupContent {
+    pub fn set_success(&mut self, msg: String, cx: &mut Context<Self>) {
+        self.phase = DialogPhase::Success(msg);
+        cx.notify();
+    }
+
+    pub fn set_error(&mut self, msg: String, cx: &mut Context<Self>) {
+        self.phase = DialogPhase::Error(msg);
+        cx.notify();
+    }
+
+    /// See [`PinPromptContent::set_pin_attempts`].
+    pub fn set_pin_attempts(&mut self, attempts: PinAttempts, cx: &mut Context<Self>) {
+        if self.attempts.update(attempts) {
+            cx.notify();
+        }
+    }
+
+    fn trigger_confirm(&mut self, cx: &mut Context<Self>) {
+        if matches!(self.phase, DialogPhase::Loading | DialogPhase::Success(_)) {
+            return;
+        }
+        let words = self.words_input.read(cx).text().to_string();
+        if words.trim().is_empty() {
+            return;
+        }
+
+        let pin = match &self.pin_input {
+            Some(input) => {
+                let pin = input.read(cx).text().to_string();
+                if pin.is_empty() || !self.attempts.allows_attempt() {
+                    return;
+                }
+                Some(pin)
+            }
+            None => None,
+        };
+        let confirmed = self
+            .confirm_input
+            .as_ref()
+            .is_none_or(|input| input.read(cx).text().trim() == RESTORE_CONFIRMATION);
+        if !confirmed {
+            self.phase = DialogPhase::Error(format!("Type {} to confirm", RESTORE_CONFIRMATION));
+            cx.notify();
+            return;
+        }
+
+        let handle = cx.entity().downgrade();
+        self.phase = DialogPhase::Loading;
+        cx.notify();
+        (self.on_confirm)(words, pin, handle, cx);
+    }
+}
+
+impl Render for RestoreBackupContent {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let phase = self.phase.clone();
+
+        if let DialogPhase::Success(msg) = phase {
+            return v_flex()
+                .gap_4()
+                .child(
+                    h_flex()
+                        .gap_2()
+                        .items_center()
+                        .child(
+                            gpui_component::Icon::new(gpui_component::IconName::CircleCheck)
+                                .text_color(cx.theme().green)
+                                .with_size(gpui_component::Size::Large),
+                        )
+                        .child("Backup restored"),
+                )
+                .child(msg)
+                .child(
+                    h_flex().justify_end().child(
+                        Button::new("done")
+                            .primary()
+                            .label("Done")
+                            .on_click(|_, window, cx| window.close_dialog(cx)),
+                    ),
+                )
+                .into_any_element();
+        }
+
+        let loading = matches!(phase, DialogPhase::Loading);
+        let error = match phase {
+            DialogPhase::Error(msg) => Some(msg),
+            _ => None,
+        };
+        let attempts_warning = self
+            .pin_input
+            .as_ref()
+            .and_then(|_| self.attempts.warning(|this| &mut this.attempts, cx));
+
+        v_flex()
+            .gap_4()
+            .child("Enter the recovery words of the backup, separated by spaces.")
+            .child(
+                div()
+                    .px_3()
+                    .py_2()
+                    .rounded_md()
+                    .bg(cx.theme().warning.opacity(0.1))
+                    .text_color(cx.theme().warning)
+                    .text_sm()
+                    .child("Restoring replaces the secret of the selected key. Passkeys created with its current secret stop working."),
+            )
+            .children(error.map(|msg| error_banner(msg, cx)))
+            .child(Input::new(&self.words_input).disabled(loading))
+            .children(self.pin_input.as_ref().map(|input| {
+                v_flex()
+                    .gap_2()
+                    .child("Enter the PIN of the selected key")
+                    .child(Input::new(input).disabled(loading))
+            }))
+            .children(attempts_warning)
+            .children(self.confirm_input.as_ref().map(|input| {
+                v_flex()
+                    .gap_2()
+                    .child(format!("Type {} to confirm", RESTORE_CONFIRMATION))
+                    .child(Input::new(input).disabled(loading))
+            }))
+            .child(
+                h_flex()
+                    .justify_end()
+                    .gap_2()
+                    .child(
+                        Button::new("cancel")
+                            .label("Cancel")
+                            .disabled(loading)
+                            .on_click(|_, window, cx| window.close_dialog(cx)),
+                    )
+                    .child(
+                        Button::new("confirm")
+                            .danger()
+                            .label(if loading { "Restoring..." } else { "Restore" })
+                            .loading(loading)
+                            .on_click(cx.listener(|this, _, _, cx| {
+                                this.trigger_confirm(cx);
+                            })),
+                    ),
+            )
+            .into_any_element()
+    }
+}
+
+/// Opens the restore dialog. `pin_set` is whether the selected key has a PIN, which then has to
+/// be entered instead of the typed confirmation; `on_confirm` receives the words and that PIN.
+pub fn open_restore_backup(
+    pin_set: bool,
+    attempts: PinAttempts,
+    window: &mut Window,
+    cx: &mut App,
+    on_confirm: impl Fn(String, Option<String>, WeakEntity<RestoreBackupContent>, &mut App) + 'static,
+) {
+    let words_input =
+        cx.new(|cx| InputState::new(window, cx).placeholder("e.g. abandon ability able about ..."));
+    let pin_input = pin_set.then(|| {
+        cx.new(|cx| {
+            InputState::new(window, cx)
+                .placeholder("Enter FIDO PIN")
+                .masked(true)
+        })
+    });
+    let confirm_input = (!pin_set)
+        .then(|| cx.new(|cx| InputState::new(window, cx).placeholder(RESTORE_CONFIRMATION)));
+
+    let content = cx.new(|cx| {
+        let subscriptions = std::iter::once(&words_input)
+            .chain(pin_input.as_ref())
+            .chain(confirm_input.as_ref())
+            .map(|input| {
+                cx.subscribe(input, |this: &mut RestoreBackupContent, _, event, cx| {
+                    if matches!(event, InputEvent::PressEnter { .. }) {
+                        this.trigger_confirm(cx);
+                    }
+                })
+            })
+            .collect();
+
+        RestoreBackupContent {
+            phase: DialogPhase::Input,
+            words_input: words_input.clone(),
+            pin_input: pin_input.clone(),
+            confirm_input: confirm_input.clone(),
+            attempts: PinAttemptsNotice::new(attempts),
+            on_confirm: std::rc::Rc::new(on_confirm),
+            _subscriptions: subscriptions,
+        }
+    });
+
+    window.open_dialog(cx, move |dialog, _, _| {
+        dialog
+            .title("Restore Backup")
+            .child(content.clone())
+            .overlay_closable(false)
+            .close_button(false)
+    });
+}
+
+/// Edits the user name and display name stored with a passkey.
+pub struct EditUserContent {
+    phase: DialogPhase,
+    name_input: Entity<InputState>,
+    display_name_input: Entity<InputState>,
+    on_confirm: std::rc::Rc<dyn Fn(String, String, WeakEntity<EditUserContent>, &mut App)>,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl EditUserContent {
+    pub fn set_success(&mut self, msg: String, cx: &mut Context<Self>) {
+        self.phase = DialogPhase::Success(msg);
+        cx.notify();
+    }
+
+    pub fn set_error(&mut self, msg: String, cx: &mut Context<Self>) {
+        self.phase = DialogPhase::Error(msg);
+        cx.notify();
+    }
+
+    fn trigger_confirm(&mut self, cx: &mut Context<Self>) {
+        if matches!(self.phase, DialogPhase::Loading | DialogPhase::Success(_)) {
+            return;
+        }
+        let name = self.name_input.read(cx).text().trim().to_string();
+        let display_name = self.display_name_input.read(cx).text().trim().to_string();
+        if name.is_empty() {
+            self.set_error("User name cannot be empty".to_string(), cx);
+            return;
+        }
+        let handle = cx.entity().downgrade();
+        self.phase = DialogPhase::Loading;
+        cx.notify();
+        (self.on_confirm)(name, display_name, handle, cx);
+    }
+}
+
+impl Render for EditUserContent {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let phase = self.phase.clone();
+
+        if let DialogPhase::Success(msg) = phase {
+            return v_flex()
+                .gap_4()
+                .child(
+                    h_flex()
+                        .gap_2()
+                        .items_center()
+                        .child(
+                            gpui_component::Icon::new(gpui_component::IconName::CircleCheck)
+                                .text_color(cx.theme().green)
+                                .with_size(gpui_component::Size::Large),
+                        )
+                        .child("Passkey updated"),
+                )
+                .child(msg)
+                .child(
+                    h_flex().justify_end().child(
+                        Button::new("done")
+                            .primary()
+                            .label("Done")
+                            .on_click(|_, window, cx| window.close_dialog(cx)),
+                    ),
+                )
+                .into_any_element();
+        }
+
+        let loading = matches!(phase, DialogPhase::Loading);
+        let error = match phase {
+            DialogPhase::Error(msg) => Some(msg),
+            _ => None,
+        };
+
+        v_flex()
+            .gap_4()
+            .child("The user ID stays the same, so the website still recognises the account.")
+            .children(error.map(|msg| error_banner(msg, cx)))
+            .child(
+                v_flex()
+                    .gap_4()
+                    .child("User Name")
+                    .child(Input::new(&self.name_input).disabled(loading))
+                    .child("Display Name")
+                    .child(Input::new(&self.display_name_input).disabled(loading)),
+            )
+            .child(
+                h_flex()
+                    .justify_end()
+                    .gap_2()
+                    .child(
+                        Button::new("cancel")
+                            .label("Cancel")
+                            .disabled(loading)
+                            .on_click(|_, window, cx| window.close_dialog(cx)),
+                    )
+                    .child(
+                        Button::new("confirm")
+                            .primary()
+                            .label(if loading { "Saving..." } else { "Save" })
+                            .loading(loading)
+                            .on_click(cx.listener(|this, _, _, cx| {
+                                this.trigger_confirm(cx);
+                            })),
+                    ),
+            )
+            .into_any_element()
+    }
+}
+
+pub fn open_edit_user(
+    user_name: &str,
+    user_display_name: &str,
+    window: &mut Window,
+    cx: &mut App,
+    on_confirm: impl Fn(String, String, WeakEntity<EditUserContent>, &mut App) + 'static,
+) {
+    let name_input = cx.new(|cx| {
+        InputState::new(window, cx)
+            .placeholder("e.g. jane@example.com")
+            .default_value(user_name.to_string())
+    });
+    let display_name_input = cx.new(|cx| {
+        InputState::new(window, cx)
+            .placeholder("e.g. Jane Doe")
+            .default_value(user_display_name.to_string())
+    });
+
+    let content = cx.new(|cx| {
+        let subscriptions = [&name_input, &display_name_input]
+            .into_iter()
+            .map(|input| {
+                cx.subscribe(input, |this: &mut EditUserContent, _, event, cx| {
+                    if matches!(event, InputEvent::PressEnter { .. }) {
+                        this.trigger_confirm(cx);
+                    }
+                })
+            })
+            .collect();
+
+        EditUserContent {
+            phase: DialogPhase::Input,
+            name_input: name_input.clone(),
+            display_name_input: display_name_input.clone(),
+            on_confirm: std::rc::Rc::new(on_confirm),
+            _subscriptions: subscriptions,
+        }
+    });
+
+    window.open_dialog(cx, move |dialog, _, _| {
+        dialog
+            .title("Edit Passkey User")
+            .child(content.clone())
+            .overlay_closable(false)
+            .close_button(false)
+    });
+}
+
+/// What the user types to confirm a factory reset.
+const RESET_CONFIRMATION: &str = "RESET";
+
+/// Typed confirmation of a factory reset, with the choice of how to power cycle the key.
+pub struct ResetConfirmContent {
+    confirm_input: Entity<InputState>,
+    can_reboot: bool,
+    reboot: bool,
+    error: Option<String>,
+    on_confirm: std::rc::Rc<dyn Fn(bool, &mut Window, &mut App)>,
+    _subscription: Subscription,
+}
+
+impl ResetConfirmContent {
+    fn trigger_confirm(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let typed = self.confirm_input.read(cx).text().to_string();
+        if typed.trim() != RESET_CONFIRMATION {
+            self.error = Some(format!("Type {} to confirm", RESET_CONFIRMATION));
+            cx.notify();
+            return;
+        }
+        window.close_dialog(cx);
+        (self.on_confirm)(self.reboot, window, cx);
+    }
+}
+
+impl Render for ResetConfirmContent {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        v_flex()
+            .gap_4()
+            .child(error_banner(
+                "Resetting erases every passkey, the PIN and all FIDO settings on the key. This cannot be undone."
+                    .to_string(),
+                cx,
+            ))
+            .child(format!(
+                "The key only accepts a reset within {} seconds of being plugged in, so it has to be power cycled first. Touch it as soon as it asks.",
+                RESET_WINDOW_SECS
+            ))
+            .child(
+                h_flex()
+                    .justify_between()
+                    .items_center()
+                    .child(
+                        v_flex()
+                            .child("Reboot the key automatically")
+                            .child(
+                                div()
+                                    .text_sm()
+                                    .text_color(cx.theme().muted_foreground)
+                                    .child(if self.can_reboot {
+                                        "Otherwise you will be asked to unplug and replug it"
+                                    } else {
+                                        "This key has no rescue interface, unplug and replug it when asked"
+                                    }),
+                            ),
+                    )
+                    .child(
+                        Switch::new("reset-reboot")
+                            .checked(self.reboot)
+                            .disabled(!self.can_reboot)
+                            .on_click(cx.listener(|this, checked: &bool, _, cx| {
+                                this.reboot = *checked;
+                                cx.notify();
+                            })),
+                    ),
+            )
+            .child(format!("Type {} to confirm", RESET_CONFIRMATION))
+            .children(self.error.clone().map(|msg| error_banner(msg, cx)))
+            .child(Input::new(&self.confirm_input))
+            .child(
+                h_flex()
+                    .justify_end()
+                    .gap_2()
+                    .child(
+                        Button::new("cancel")
+                            .label("Cancel")
+                            .on_click(|_, window, cx| window.close_dialog(cx)),
+                    )
+                    .child(
+                        Button::new("confirm")
+                            .danger()
+                            .label("Reset Key")
+                            .on_click(cx.listener(|this, _, window, cx| {
+                                this.trigger_confirm(window, cx);
+                            })),
+                    ),
+            )
+    }
+}
+
+/// Asks for a typed confirmation before a factory reset. `on_confirm` receives whether to power
+/// cycle the key with a rescue reboot (only offered if `can_reboot`) instead of a replug.
+pub fn open_reset_confirm(
+    can_reboot: bool,
+    window: &mut Window,
+    cx: &mut App,
+    on_confirm: impl Fn(bool, &mut Window, &mut App) + 'static,
+) {
+    let confirm_input = cx.new(|cx| InputState::new(window, cx).placeholder(RESET_CONFIRMATION));
+
+    let input_for_sub = confirm_input.clone();
+    let content = cx.new(|cx| {
+        let sub = cx.subscribe_in(
+            &input_for_sub,
+            window,
+            |this: &mut ResetConfirmContent, _, event, window, cx| {
+                if matches!(event, InputEvent::PressEnter { .. }) {
+                    this.trigger_confirm(window, cx);
+                }
+            },
+        );
+
+        ResetConfirmContent {
+            confirm_input: input_for_sub,
+            can_reboot,
+            reboot: can_reboot,
+            error: None,
+            on_confirm: std::rc::Rc::new(on_confirm),
+            _subscription: sub,
+        }
+    });
+
+    window.open_dialog(cx, move |dialog, _, _| {
+        dialog
+            .title("Reset Key")
+            .child(content.clone())
+            .overlay_closable(false)
+            .close_button(false)
+    });
+}
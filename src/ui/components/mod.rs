@@ -3,4 +3,5 @@ pub mod card;
 pub mod dialog;
 pub mod page_view;
 pub mod sidebar;
+pub mod slot_gauge;
 pub mod tag;
@@ -1,4 +1,4 @@
-use crate::device::types::DeviceMethod;
+use crate::device::types::{DeviceHandle, DeviceMethod};
 use crate::ui::components::button::PFIconButton;
 use crate::ui::types::{ActiveView, GlobalDeviceState};
 use gpui::*;
@@ -18,6 +18,7 @@ pub struct AppSidebar<V: 'static> {
     state: GlobalDeviceState,
     on_select: Option<Rc<dyn Fn(&mut V, ActiveView, &mut Window, &mut Context<V>)>>,
     on_refresh: Option<Rc<dyn Fn(&mut V, &mut Window, &mut Context<V>)>>,
+    on_device_select: Option<Rc<dyn Fn(&mut V, DeviceHandle, &mut Window, &mut Context<V>)>>,
 }
 
 impl<V: 'static> AppSidebar<V> {
@@ -34,6 +35,7 @@ impl<V: 'static> AppSidebar<V> {
             state,
             on_select: None,
             on_refresh: None,
+            on_device_select: None,
         }
     }
 
@@ -53,6 +55,14 @@ impl<V: 'static> AppSidebar<V> {
         self
     }
 
+    pub fn on_device_select(
+        mut self,
+        handler: impl Fn(&mut V, DeviceHandle, &mut Window, &mut Context<V>) + 'static,
+    ) -> Self {
+        self.on_device_select = Some(Rc::new(handler));
+        self
+    }
+
     pub fn render(self, cx: &mut Context<V>) -> impl IntoElement {
         let width = self.width;
         let collapsed = self.collapsed;
@@ -65,6 +75,11 @@ impl<V: 'static> AppSidebar<V> {
 
         let on_refresh = self.on_refresh.clone();
         let on_refresh_collapsed = self.on_refresh.clone();
+        let device_picker = if collapsed {
+            None
+        } else {
+            self.device_picker(cx)
+        };
 
         v_flex()
             .h_full()
@@ -157,6 +172,12 @@ impl<V: 'static> AppSidebar<V> {
                                     "icons/scroll-text.svg",
                                     ActiveView::Logs,
                                 ))
+                                .child(self.menu_item(
+                                    cx,
+                                    "Diagnostics",
+                                    "icons/network.svg",
+                                    ActiveView::Diagnostics,
+                                ))
                                 .child(self.menu_item_icon_name(
                                     cx,
                                     "About",
@@ -251,6 +272,7 @@ impl<V: 'static> AppSidebar<V> {
                                             )
                                     }),
                             )
+                            .children(device_picker)
                             .child(
                                 PFIconButton::new(
                                     Icon::default().path("icons/refresh-cw.svg"),
@@ -268,6 +290,75 @@ impl<V: 'static> AppSidebar<V> {
             )
     }
 
+    /// List of connected keys, so the user can pick which one PicoForge talks to.
+    fn device_picker(&self, cx: &mut Context<V>) -> Option<AnyElement> {
+        if self.state.devices.is_empty() {
+            return None;
+        }
+
+        let selected_id = self.state.selected_device.as_ref().map(|d| d.id());
+        let muted_foreground = cx.theme().muted_foreground;
+        let sidebar_fg = cx.theme().sidebar_foreground;
+        let accent = cx.theme().sidebar_accent;
+
+        let mut list = v_flex().gap_1().child(
+            div()
+                .text_size(px(12.))
+                .font_weight(gpui::FontWeight::MEDIUM)
+                .text_color(muted_foreground)
+                .child(format!("Devices ({})", self.state.devices.len())),
+        );
+
+        for device in &self.state.devices {
+            let is_selected = selected_id.as_deref() == Some(device.id().as_str());
+            let interfaces = match (&device.reader_name, &device.hid_path) {
+                (Some(_), Some(_)) => "Rescue + FIDO",
+                (Some(_), None) => "Rescue only",
+                _ => "FIDO only",
+            };
+
+            let on_device_select = self.on_device_select.clone();
+            let device_for_click = device.clone();
+
+            list = list.child(
+                div()
+                    .id(SharedString::from(format!("device-{}", device.id())))
+                    .cursor_pointer()
+                    .px_2()
+                    .py_1()
+                    .rounded_md()
+                    .bg(if is_selected {
+                        accent
+                    } else {
+                        gpui::transparent_black()
+                    })
+                    .hover(|s| s.bg(accent))
+                    .on_click(cx.listener(move |this, _, window, cx| {
+                        if let Some(f) = &on_device_select {
+                            f(this, device_for_click.clone(), window, cx);
+                        }
+                    }))
+                    .child(
+                        div()
+                            .text_size(px(12.))
+                            .text_color(sidebar_fg)
+                            .whitespace_nowrap()
+                            .overflow_hidden()
+                            .text_ellipsis()
+                            .child(device.label()),
+                    )
+                    .child(
+                        div()
+                            .text_size(px(10.))
+                            .text_color(muted_foreground)
+                            .child(interfaces),
+                    ),
+            );
+        }
+
+        Some(list.into_any_element())
+    }
+
     fn menu_item(
         &self,
         cx: &mut Context<V>,
@@ -0,0 +1,75 @@
+use gpui::*;
+use gpui_component::{ActiveTheme, h_flex, progress::Progress, v_flex};
+
+use crate::device::types::FidoDeviceInfo;
+
+/// Free slots at or below which the gauge warns that the key is running out.
+const LOW_FREE_SLOTS: u32 = 10;
+
+/// How many of the key's discoverable credential slots are taken.
+#[derive(IntoElement)]
+pub struct SlotGauge {
+    used: u32,
+    total: u32,
+    estimated: bool,
+}
+
+impl SlotGauge {
+    /// `None` if the key reports neither getCredsMetadata counts nor remaining credentials.
+    pub fn from_info(info: &FidoDeviceInfo) -> Option<Self> {
+        let (used, total, estimated) = info.credential_slots()?;
+        Some(Self {
+            used,
+            total,
+            estimated,
+        })
+    }
+}
+
+impl RenderOnce for SlotGauge {
+    fn render(self, _window: &mut Window, cx: &mut App) -> impl IntoElement {
+        let theme = cx.theme();
+        let free = self.total.saturating_sub(self.used);
+        let percent = if self.total == 0 {
+            100.0
+        } else {
+            self.used as f32 / self.total as f32 * 100.0
+        };
+
+        v_flex()
+            .gap_2()
+            .child(
+                h_flex()
+                    .justify_between()
+                    .text_sm()
+                    .child(
+                        div()
+                            .text_color(theme.muted_foreground)
+                            .child("Passkey Slots"),
+                    )
+                    .child(
+                        div()
+                            .text_color(theme.foreground)
+                            .child(format!("{} / {} used", self.used, self.total)),
+                    ),
+            )
+            .child(Progress::new().value(percent))
+            .children((free <= LOW_FREE_SLOTS).then(|| {
+                div()
+                    .text_xs()
+                    .text_color(theme.warning)
+                    .child(match free {
+                        0 => "The key is full. Delete passkeys before registering new ones."
+                            .to_string(),
+                        1 => "Only 1 slot left.".to_string(),
+                        n => format!("Only {} slots left.", n),
+                    })
+            }))
+            .children(self.estimated.then(|| {
+                div()
+                    .text_xs()
+                    .text_color(theme.muted_foreground)
+                    .child("Estimated from the free slots the key reports. Unlock the passkeys for exact counts.")
+            }))
+    }
+}
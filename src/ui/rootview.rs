@@ -1,9 +1,13 @@
 use crate::device::io;
+use crate::device::simulator;
+use crate::device::transport::OperationMonitor;
+use crate::device::types::DeviceHandle;
+use crate::device::watcher::DeviceWatcher;
 use crate::ui::components::sidebar::AppSidebar;
 use crate::ui::types::{ActiveView, GlobalDeviceState};
 use crate::ui::views::{
-    about::AboutView, config::ConfigView, home::HomeView, logs::LogsView, passkeys::PasskeysEvent,
-    passkeys::PasskeysView, security::SecurityView,
+    about::AboutView, config::ConfigView, diagnostics::DiagnosticsView, home::HomeView,
+    logs::LogsView, passkeys::PasskeysEvent, passkeys::PasskeysView, security::SecurityView,
 };
 
 use gpui::prelude::*;
@@ -16,37 +20,89 @@ use gpui_component::{
     scroll::ScrollableElement,
     v_flex,
 };
+use std::time::Duration;
+
+const HOTPLUG_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const HOTPLUG_SETTLE_DELAY: Duration = Duration::from_millis(750);
 
 pub struct ApplicationRoot {
     active_view: ActiveView,
     is_sidebar_collapsed: bool,
     state: GlobalDeviceState,
     device_loading: bool,
+    refresh_pending: bool,
     // Umm, why did my past self do this? This does not belong here.
     sidebar_width: Pixels,
     config_view: Option<Entity<ConfigView>>,
     passkeys_view: Option<Entity<PasskeysView>>,
     logs_view: Option<Entity<LogsView>>,
+    diagnostics_view: Option<Entity<DiagnosticsView>>,
+    _hotplug_task: Option<Task<()>>,
+    _refresh_task: Option<Task<()>>,
 }
 
 impl ApplicationRoot {
-    pub fn new(cx: &mut Context<Self>) -> Self {
+    pub fn new(window: &mut Window, cx: &mut Context<Self>) -> Self {
         let mut this = Self {
             active_view: ActiveView::Home,
             is_sidebar_collapsed: false,
             state: GlobalDeviceState::new(),
             device_loading: false,
+            refresh_pending: false,
             sidebar_width: px(255.),
             config_view: None,
             passkeys_view: None,
             logs_view: None,
+            diagnostics_view: None,
+            _hotplug_task: Self::watch_hotplug(window, cx),
+            _refresh_task: None,
         };
-        this.refresh_device_status(None, cx);
+        this.refresh_device_status(window, cx);
         this
     }
 
-    fn refresh_device_status(&mut self, window: Option<&mut Window>, cx: &mut Context<Self>) {
+    /// Refreshes the device list whenever a reader or FIDO HID interface comes or goes.
+    fn watch_hotplug(window: &mut Window, cx: &mut Context<Self>) -> Option<Task<()>> {
+        if simulator::is_demo_mode() {
+            return None;
+        }
+
+        let watcher = DeviceWatcher::spawn();
+        Some(cx.spawn_in(window, async move |this, cx| {
+            loop {
+                cx.background_executor().timer(HOTPLUG_POLL_INTERVAL).await;
+                let mut events = watcher.poll();
+                if events.is_empty() {
+                    continue;
+                }
+
+                // A key re-enumerating (after a reboot or VID/PID change) produces a burst of
+                // removals and arrivals, let it settle before looking at it.
+                cx.background_executor().timer(HOTPLUG_SETTLE_DELAY).await;
+                events.extend(watcher.poll());
+                // A reset or config write power cycles the key itself and holds its session,
+                // read it once that is done rather than halfway through.
+                while OperationMonitor::any_in_flight() {
+                    cx.background_executor().timer(HOTPLUG_POLL_INTERVAL).await;
+                    events.extend(watcher.poll());
+                }
+
+                let handled = this.update_in(cx, |this, window, cx| {
+                    log::info!("Hot-plug events: {:?}", events);
+                    this.refresh_device_status(window, cx);
+                });
+                if handled.is_err() {
+                    break;
+                }
+            }
+        }))
+    }
+
+    /// Re-reads the device list and the selected key's status on a background thread. A refresh
+    /// requested while one is running is queued, so a selection change is never lost.
+    fn refresh_device_status(&mut self, window: &mut Window, cx: &mut Context<Self>) {
         if self.device_loading {
+            self.refresh_pending = true;
             return;
         }
 
@@ -54,47 +110,99 @@ impl ApplicationRoot {
         self.state.error = None;
         cx.notify();
 
-        match io::read_device_details() {
-            Ok(status) => {
-                self.state.device_status = Some(status.clone());
-                self.state.error = None;
-
-                match io::get_fido_info() {
-                    Ok(fido) => {
-                        self.state.fido_info = Some(fido);
-                    }
-                    Err(e) => {
-                        log::error!("FIDO Info fetch failed: {}", e);
-                        self.state.fido_info = None;
-                    }
-                }
+        let current = self.state.selected_device.clone();
+        self._refresh_task = Some(cx.spawn_in(window, async move |this, cx| {
+            let state = cx
+                .background_executor()
+                .spawn(async move { read_device_state(current) })
+                .await;
 
-                if let Some(config_view) = &self.config_view {
-                    if let Some(window) = window {
-                        config_view.update(cx, |view, cx| {
-                            view.update_device_status(Some(status.clone()), window, cx);
-                        });
-                    }
-                }
+            let _ = this.update_in(cx, |this, window, cx| {
+                let requested = this.state.selected_device.take();
+                this.state = state;
+                this.sync_views(Some(window), cx);
+                this.device_loading = false;
+                cx.notify();
 
-                if let Some(passkeys_view) = &self.passkeys_view {
-                    let fido = self.state.fido_info.clone();
-                    passkeys_view.update(cx, |view, cx| {
-                        view.update_device_status(Some(status.clone()), fido, cx);
-                    });
+                if std::mem::take(&mut this.refresh_pending) {
+                    this.state.selected_device = requested.or(this.state.selected_device.take());
+                    this.refresh_device_status(window, cx);
                 }
-            }
-            Err(e) => {
-                self.state.device_status = None;
-                self.state.error = Some(format!("{}", e));
-                self.state.fido_info = None;
-            }
+            });
+        }));
+    }
+
+    /// Pushes the current selection and status into the views that keep their own copy, so they
+    /// never act on a key that has been pulled out.
+    fn sync_views(&self, window: Option<&mut Window>, cx: &mut Context<Self>) {
+        let device = self.state.selected_device.clone();
+        let status = self.state.device_status.clone();
+        let fido_info = self.state.fido_info.clone();
+
+        if let (Some(config_view), Some(window)) = (&self.config_view, window) {
+            config_view.update(cx, |view, cx| {
+                view.update_device_status(device.clone(), status.clone(), fido_info, window, cx);
+            });
         }
-        self.device_loading = false;
-        cx.notify();
+
+        if let Some(diagnostics_view) = &self.diagnostics_view {
+            diagnostics_view.update(cx, |view, cx| {
+                view.update_device(device.clone(), cx);
+            });
+        }
+
+        if let Some(passkeys_view) = &self.passkeys_view {
+            let fido = self.state.fido_info.clone();
+            passkeys_view.update(cx, |view, cx| {
+                view.update_device_status(device, status, fido, cx);
+            });
+        }
+    }
+
+    fn select_device(&mut self, device: DeviceHandle, window: &mut Window, cx: &mut Context<Self>) {
+        if self.state.selected_device.as_ref().map(|d| d.id()) == Some(device.id()) {
+            return;
+        }
+        log::info!("Switching to device {}", device.label());
+        self.state.selected_device = Some(device);
+        self.refresh_device_status(window, cx);
     }
 }
 
+/// Enumerates the connected keys and reads the status of the one to show: `current` if it is
+/// still connected, otherwise the first candidate. Blocks on PC/SC and HID I/O.
+fn read_device_state(current: Option<DeviceHandle>) -> GlobalDeviceState {
+    let devices = io::list_devices().unwrap_or_else(|e| {
+        log::error!("Device enumeration failed: {}", e);
+        Vec::new()
+    });
+
+    let selected = current
+        .as_ref()
+        .and_then(|current| devices.iter().find(|d| d.id() == current.id()))
+        .or_else(|| devices.first())
+        .cloned();
+
+    let mut state = GlobalDeviceState::new();
+    state.devices = devices;
+    state.selected_device = selected.clone();
+
+    match selected.map(|device| (io::read_device_details(&device), device)) {
+        Some((Ok(status), device)) => {
+            state.device_status = Some(status);
+            state.fido_info = io::get_fido_info(&device)
+                .inspect_err(|e| log::error!("FIDO Info fetch failed: {}", e))
+                .ok();
+        }
+        Some((Err(e), _)) => {
+            state.error = Some(format!("{}", e));
+        }
+        None => {}
+    }
+
+    state
+}
+
 impl Render for ApplicationRoot {
     fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
         let window_width = window.bounds().size.width;
@@ -152,6 +260,7 @@ impl Render for ApplicationRoot {
                             PasskeysView::new(
                                 window,
                                 cx,
+                                self.state.selected_device.clone(),
                                 self.state.device_status.clone(),
                                 self.state.fido_info.clone(),
                             )
@@ -175,7 +284,15 @@ impl Render for ApplicationRoot {
                 }
                 ActiveView::Configuration => {
                     let view = self.config_view.get_or_insert_with(|| {
-                        cx.new(|cx| ConfigView::new(window, cx, self.state.device_status.clone()))
+                        cx.new(|cx| {
+                            ConfigView::new(
+                                window,
+                                cx,
+                                self.state.selected_device.clone(),
+                                self.state.device_status.clone(),
+                                self.state.fido_info.clone(),
+                            )
+                        })
                     });
                     view.clone().into_any_element()
                 }
@@ -186,6 +303,14 @@ impl Render for ApplicationRoot {
                         .get_or_insert_with(|| cx.new(|cx| LogsView::new(window, cx)));
                     view.clone().into_any_element()
                 }
+                ActiveView::Diagnostics => {
+                    let view = self.diagnostics_view.get_or_insert_with(|| {
+                        cx.new(|cx| {
+                            DiagnosticsView::new(window, cx, self.state.selected_device.clone())
+                        })
+                    });
+                    view.clone().into_any_element()
+                }
                 ActiveView::About => AboutView::build(cx.theme()).into_any_element(),
             });
 
@@ -199,7 +324,10 @@ impl Render for ApplicationRoot {
             this.active_view = view;
         })
         .on_refresh(|this, window, cx| {
-            this.refresh_device_status(Some(window), cx);
+            this.refresh_device_status(window, cx);
+        })
+        .on_device_select(|this, device, window, cx| {
+            this.select_device(device, window, cx);
         });
 
         #[cfg(target_os = "macos")]
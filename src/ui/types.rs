@@ -1,6 +1,6 @@
 use gpui::SharedString;
 
-use crate::device::types::{FidoDeviceInfo, FullDeviceStatus};
+use crate::device::types::{DeviceHandle, FidoDeviceInfo, FullDeviceStatus};
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum ActiveView {
@@ -9,11 +9,14 @@ pub enum ActiveView {
     Configuration,
     Security,
     Logs,
+    Diagnostics,
     About,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct GlobalDeviceState {
+    pub devices: Vec<DeviceHandle>,
+    pub selected_device: Option<DeviceHandle>,
     pub device_status: Option<FullDeviceStatus>,
     pub fido_info: Option<FidoDeviceInfo>,
     pub error: Option<String>,
@@ -22,6 +25,8 @@ pub struct GlobalDeviceState {
 impl GlobalDeviceState {
     pub fn new() -> Self {
         Self {
+            devices: Vec::new(),
+            selected_device: None,
             device_status: None,
             fido_info: None,
             error: None,
@@ -1,9 +1,10 @@
 use crate::device::io;
-use crate::device::types::{AppConfigInput, FullDeviceStatus};
+use crate::device::transport::OperationMonitor;
+use crate::device::types::{AppConfigInput, DeviceHandle, FidoDeviceInfo, FullDeviceStatus};
 use crate::ui::components::{
     card::Card,
     dialog,
-    dialog::{PinPromptContent, StatusContent},
+    dialog::{PinAttempts, PinPromptContent, StatusContent},
     page_view::PageView,
 };
 use crate::ui::types::{LedDriverType, UsbIdentityPreset};
@@ -74,7 +75,10 @@ pub struct ConfigView {
     power_cycle: bool,
     enable_secp256k1: bool,
     loading: bool,
+    device: Option<DeviceHandle>,
     device_status: Option<FullDeviceStatus>,
+    /// GetInfo as last read, for the retry counters shown in the PIN prompt.
+    fido_info: Option<FidoDeviceInfo>,
     is_custom_vendor: bool,
     _task: Option<Task<()>>,
 }
@@ -83,7 +87,9 @@ impl ConfigView {
     pub fn new(
         window: &mut Window,
         cx: &mut Context<Self>,
+        device: Option<DeviceHandle>,
         device_status: Option<FullDeviceStatus>,
+        fido_info: Option<FidoDeviceInfo>,
     ) -> Self {
         let config = device_status.as_ref().map(|s| &s.config);
 
@@ -210,7 +216,9 @@ impl ConfigView {
             power_cycle: config.map(|c| c.power_cycle_on_reset).unwrap_or(false),
             enable_secp256k1: config.map(|c| c.enable_secp256k1).unwrap_or(true),
             loading: false,
+            device,
             device_status: device_status.clone(),
+            fido_info,
             is_custom_vendor,
             _task: None,
         }
@@ -224,21 +232,49 @@ impl ConfigView {
         dialog_handle: StatusDialogHandle,
         cx: &mut Context<Self>,
     ) {
+        let Some(device) = self.device.clone() else {
+            let msg = "No device selected.".to_string();
+            match &dialog_handle {
+                StatusDialogHandle::Pin(dh) => {
+                    let _ = dh.update(cx, |d, cx| d.set_error(msg, cx));
+                }
+                StatusDialogHandle::Status(dh) => {
+                    let _ = dh.update(cx, |d, cx| d.set_error(msg, cx));
+                }
+            }
+            return;
+        };
+
         self.loading = true;
         cx.notify();
 
+        let monitor = OperationMonitor::new();
+        if let StatusDialogHandle::Pin(dh) = &dialog_handle {
+            let _ = dh.update(cx, |d, cx| d.watch_monitor(monitor.clone(), cx));
+        }
+
         let entity = cx.entity().downgrade();
 
         self._task = Some(cx.spawn(async move |_, cx| {
-            let result = cx
+            let device_for_write = device.clone();
+            let (result, fido_info) = cx
                 .background_executor()
-                .spawn(async move { io::write_config(changes, method, pin) })
+                .spawn(async move {
+                    let uses_pin = pin.is_some();
+                    let result =
+                        io::write_config(&device_for_write, changes, method, pin, &monitor);
+                    // The PIN attempt changed the retry counters
+                    let fido_info = uses_pin
+                        .then(|| io::get_fido_info(&device_for_write).ok())
+                        .flatten();
+                    (result, fido_info)
+                })
                 .await;
 
             let new_status_result = if result.is_ok() {
                 Some(
                     cx.background_executor()
-                        .spawn(async move { io::read_device_details() })
+                        .spawn(async move { io::read_device_details(&device) })
                         .await,
                 )
             } else {
@@ -247,6 +283,9 @@ impl ConfigView {
 
             let _ = entity.update(cx, |this, cx| {
                 this.loading = false;
+                if fido_info.is_some() {
+                    this.fido_info = fido_info;
+                }
 
                 match result {
                     Ok(msg) => {
@@ -291,13 +330,21 @@ impl ConfigView {
                         log::error!("Error saving config: {}", e);
                         match &dialog_handle {
                             StatusDialogHandle::Pin(dh) => {
+                                let attempts = this.pin_attempts();
                                 let _ = dh.update(cx, |d, cx| {
-                                    d.set_error(format!("Failed to apply: {}", e), cx);
+                                    d.set_pin_attempts(attempts, cx);
+                                    d.set_error(
+                                        format!("Failed to apply: {}", e.user_message()),
+                                        cx,
+                                    );
                                 });
                             }
                             StatusDialogHandle::Status(dh) => {
                                 let _ = dh.update(cx, |d, cx| {
-                                    d.set_error(format!("Failed to apply: {}", e), cx);
+                                    d.set_error(
+                                        format!("Failed to apply: {}", e.user_message()),
+                                        cx,
+                                    );
                                 });
                             }
                         }
@@ -309,6 +356,12 @@ impl ConfigView {
         }));
     }
 
+    /// Current retry counters, so the PIN prompt can warn before the PIN gets blocked. They are
+    /// re-read in the background after every PIN attempt.
+    fn pin_attempts(&self) -> PinAttempts {
+        PinAttempts::from_info(self.fido_info.as_ref())
+    }
+
     fn open_pin_dialog(
         &mut self,
         changes: AppConfigInput,
@@ -321,6 +374,7 @@ impl ConfigView {
             "Authentication Required",
             "Enter your device PIN to apply changes.",
             "Confirm",
+            self.pin_attempts(),
             window,
             cx,
             move |pin, dialog_handle, cx| {
@@ -451,13 +505,18 @@ impl ConfigView {
 
     pub(crate) fn update_device_status(
         &mut self,
+        device: Option<DeviceHandle>,
         status: Option<FullDeviceStatus>,
+        fido_info: Option<FidoDeviceInfo>,
         window: &mut Window,
         cx: &mut Context<Self>,
     ) {
-        if self.device_status == status {
+        // Only a new device or status resets the form, the retry counters are just cached.
+        self.fido_info = fido_info;
+        if self.device == device && self.device_status == status {
             return;
         }
+        self.device = device;
         self.device_status = status.clone();
         let config = status.as_ref().map(|s| &s.config);
 
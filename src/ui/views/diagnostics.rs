@@ -0,0 +1,359 @@
+use crate::device::fido::diagnostics::{DiagnosticsReport, PING_SIZES};
+use crate::device::io;
+use crate::device::types::DeviceHandle;
+use crate::ui::components::{card::Card, page_view::PageView, tag::Tag};
+use gpui::*;
+use gpui_component::button::{Button, ButtonVariants};
+use gpui_component::{ActiveTheme, Disableable, Icon, Theme, WindowExt, h_flex, v_flex};
+use std::time::Duration;
+
+const REPORT_FILE_NAME: &str = "picoforge-diagnostics.txt";
+
+pub struct DiagnosticsView {
+    device: Option<DeviceHandle>,
+    report: Option<DiagnosticsReport>,
+    error: Option<String>,
+    running: bool,
+    _task: Option<Task<()>>,
+}
+
+impl DiagnosticsView {
+    pub fn new(
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+        device: Option<DeviceHandle>,
+    ) -> Self {
+        Self {
+            device,
+            report: None,
+            error: None,
+            running: false,
+            _task: None,
+        }
+    }
+
+    pub fn update_device(&mut self, device: Option<DeviceHandle>, cx: &mut Context<Self>) {
+        if self.device.as_ref().map(|d| d.id()) == device.as_ref().map(|d| d.id()) {
+            self.device = device;
+            return;
+        }
+        // Results belong to the previous key
+        self.device = device;
+        self.report = None;
+        self.error = None;
+        cx.notify();
+    }
+
+    fn run(&mut self, cx: &mut Context<Self>) {
+        if self.running {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            return;
+        };
+
+        self.running = true;
+        self.error = None;
+        cx.notify();
+
+        let entity = cx.entity().downgrade();
+        self._task = Some(cx.spawn(async move |_, cx| {
+            let result = cx
+                .background_executor()
+                .spawn(async move { io::run_diagnostics(&device) })
+                .await;
+
+            let _ = entity.update(cx, |this, cx| {
+                this.running = false;
+                match result {
+                    Ok(report) => this.report = Some(report),
+                    Err(e) => {
+                        log::error!("Diagnostics failed: {}", e);
+                        this.error = Some(e.to_string());
+                    }
+                }
+                cx.notify();
+            });
+        }));
+    }
+
+    fn save_report(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(text) = self.report.as_ref().map(|r| r.to_text()) else {
+            return;
+        };
+        let directory = directories::UserDirs::new()
+            .and_then(|dirs| dirs.document_dir().map(|d| d.to_path_buf()))
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        let path = cx.prompt_for_new_path(&directory, Some(REPORT_FILE_NAME));
+
+        cx.spawn_in(window, async move |_, cx| {
+            let Ok(Ok(Some(path))) = path.await else {
+                return;
+            };
+            let message = match std::fs::write(&path, text) {
+                Ok(()) => {
+                    log::info!("Saved diagnostics report to {}", path.display());
+                    format!("Report saved to {}", path.display())
+                }
+                Err(e) => {
+                    log::error!("Failed to save diagnostics report: {}", e);
+                    format!("Failed to save report: {}", e)
+                }
+            };
+            let _ = cx.update(|window, cx| window.push_notification(message, cx));
+        })
+        .detach();
+    }
+
+    fn render_summary(&self, report: &DiagnosticsReport, theme: &Theme) -> impl IntoElement {
+        let ms = |d: Option<Duration>| {
+            d.map(|d| format!("{:.2} ms", d.as_secs_f64() * 1000.0))
+                .unwrap_or_else(|| "-".into())
+        };
+
+        Card::new()
+            .title("Summary")
+            .icon(Icon::default().path("icons/chart-pie.svg"))
+            .header_right(
+                Tag::new(if report.passed() { "Pass" } else { "Fail" }).active(report.passed()),
+            )
+            .child(
+                div()
+                    .grid()
+                    .grid_cols(3)
+                    .gap_4()
+                    .child(Self::render_stat(
+                        "Latency p50",
+                        ms(report.percentile(50.0)),
+                        theme,
+                    ))
+                    .child(Self::render_stat(
+                        "Latency p95",
+                        ms(report.percentile(95.0)),
+                        theme,
+                    ))
+                    .child(Self::render_stat(
+                        "Latency p99",
+                        ms(report.percentile(99.0)),
+                        theme,
+                    ))
+                    .child(Self::render_stat(
+                        "Throughput",
+                        report
+                            .throughput()
+                            .map(|b| format!("{:.1} KB/s", b / 1024.0))
+                            .unwrap_or_else(|| "-".into()),
+                        theme,
+                    ))
+                    .child(Self::render_stat(
+                        "Failed Pings",
+                        report.failed_pings().to_string(),
+                        theme,
+                    ))
+                    .child(Self::render_stat(
+                        "Sequence Mismatches",
+                        report.sequence_mismatches().to_string(),
+                        theme,
+                    )),
+            )
+    }
+
+    fn render_stat(label: &str, value: String, theme: &Theme) -> impl IntoElement {
+        v_flex()
+            .gap_1()
+            .child(
+                div()
+                    .text_sm()
+                    .text_color(theme.muted_foreground)
+                    .child(label.to_string()),
+            )
+            .child(
+                div()
+                    .font_family("Mono")
+                    .text_color(theme.foreground)
+                    .child(value),
+            )
+    }
+
+    fn render_pings(&self, report: &DiagnosticsReport, theme: &Theme) -> impl IntoElement {
+        let ms = |d: Option<Duration>| {
+            d.map(|d| format!("{:.2}", d.as_secs_f64() * 1000.0))
+                .unwrap_or_else(|| "-".into())
+        };
+        let row = |cells: [String; 6], header: bool| {
+            let color = if header {
+                theme.muted_foreground
+            } else {
+                theme.foreground
+            };
+            div()
+                .grid()
+                .grid_cols(6)
+                .gap_2()
+                .text_sm()
+                .font_family("Mono")
+                .text_color(color)
+                .children(cells.into_iter().map(|c| div().child(c)))
+        };
+
+        Card::new()
+            .title("Ping Round Trips")
+            .icon(Icon::default().path("icons/network.svg"))
+            .description("CTAPHID_PING with growing payloads, latencies in milliseconds")
+            .child(
+                v_flex()
+                    .gap_2()
+                    .child(row(
+                        [
+                            "Bytes".into(),
+                            "OK".into(),
+                            "p50".into(),
+                            "p90".into(),
+                            "Max".into(),
+                            "Seq Err".into(),
+                        ],
+                        true,
+                    ))
+                    .children(report.pings.iter().map(|stats| {
+                        row(
+                            [
+                                stats.size.to_string(),
+                                format!("{}/{}", stats.round_trips.len(), stats.attempts),
+                                ms(stats.percentile(50.0)),
+                                ms(stats.percentile(90.0)),
+                                ms(stats.round_trips.iter().max().copied()),
+                                stats.sequence_mismatches.to_string(),
+                            ],
+                            false,
+                        )
+                    })),
+            )
+    }
+
+    fn render_channels(&self, report: &DiagnosticsReport, theme: &Theme) -> impl IntoElement {
+        Card::new()
+            .title("Channels")
+            .icon(Icon::default().path("icons/layout-dashboard.svg"))
+            .description("Pings on several CTAPHID channels in turn")
+            .child(
+                v_flex()
+                    .gap_2()
+                    .text_sm()
+                    .children(report.channels.iter().map(|channel| {
+                        h_flex()
+                            .justify_between()
+                            .child(
+                                div()
+                                    .font_family("Mono")
+                                    .child(format!("CID 0x{:08X}", channel.cid)),
+                            )
+                            .child(match &channel.error {
+                                None => Tag::new(format!("{} pings OK", channel.successes))
+                                    .active(true)
+                                    .into_any_element(),
+                                Some(e) => div()
+                                    .text_color(theme.danger)
+                                    .child(e.clone())
+                                    .into_any_element(),
+                            })
+                    }))
+                    .children(report.channel_error.as_ref().map(|e| {
+                        div()
+                            .text_color(theme.danger)
+                            .child(format!("Channel allocation failed: {}", e))
+                    })),
+            )
+    }
+}
+
+impl Render for DiagnosticsView {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let has_hid = self.device.as_ref().is_some_and(|d| d.hid_path.is_some());
+
+        let run_listener = cx.listener(|this, _, _, cx| this.run(cx));
+        let copy_listener = cx.listener(|this, _, _, cx| {
+            if let Some(report) = &this.report {
+                cx.write_to_clipboard(ClipboardItem::new_string(report.to_text()));
+            }
+        });
+        let save_listener = cx.listener(|this, _, window, cx| this.save_report(window, cx));
+
+        let theme = cx.theme();
+
+        let intro = Card::new()
+            .title("CTAPHID Transport Test")
+            .icon(Icon::default().path("icons/network.svg"))
+            .description(format!(
+                "Pings the key with {} payload sizes up to {} bytes and checks several channels at once.",
+                PING_SIZES.len(),
+                PING_SIZES.last().copied().unwrap_or_default()
+            ))
+            .child(
+                v_flex()
+                    .gap_4()
+                    .children(self.error.as_ref().map(|e| {
+                        div()
+                            .px_3()
+                            .py_2()
+                            .rounded_md()
+                            .bg(theme.danger.opacity(0.1))
+                            .text_color(theme.danger)
+                            .text_sm()
+                            .child(e.clone())
+                    }))
+                    .child(
+                        h_flex()
+                            .justify_end()
+                            .gap_2()
+                            .child(
+                                Button::new("copy_report")
+                                    .label("Copy Report")
+                                    .disabled(self.report.is_none())
+                                    .on_click(copy_listener),
+                            )
+                            .child(
+                                Button::new("save_report")
+                                    .label("Save Report")
+                                    .icon(Icon::default().path("icons/save.svg"))
+                                    .disabled(self.report.is_none())
+                                    .on_click(save_listener),
+                            )
+                            .child(
+                                Button::new("run_diagnostics")
+                                    .primary()
+                                    .label(if self.running {
+                                        "Running..."
+                                    } else {
+                                        "Run Diagnostics"
+                                    })
+                                    .loading(self.running)
+                                    .disabled(!has_hid || self.running)
+                                    .on_click(run_listener),
+                            ),
+                    ),
+            );
+
+        let mut content = v_flex().gap_6().child(intro);
+        if let Some(report) = &self.report {
+            content = content
+                .child(self.render_summary(report, theme))
+                .child(self.render_pings(report, theme))
+                .child(self.render_channels(report, theme));
+        } else if !has_hid {
+            content = content.child(
+                div()
+                    .text_sm()
+                    .text_color(theme.muted_foreground)
+                    .child("Connect a key with a FIDO HID interface to run the diagnostics."),
+            );
+        }
+
+        PageView::build(
+            "Diagnostics",
+            "Test the USB connection to your key and export the results for support.",
+            content.into_any_element(),
+            theme,
+        )
+    }
+}
@@ -1,9 +1,19 @@
-use crate::device::types::DeviceMethod;
-use crate::ui::components::{card::Card, page_view::PageView, tag::Tag};
+use crate::device::io;
+use crate::device::transport::OperationMonitor;
+use crate::device::types::{DeviceHandle, DeviceMethod};
+use crate::ui::components::{
+    card::Card, dialog, page_view::PageView, slot_gauge::SlotGauge, tag::Tag,
+};
 use crate::ui::types::GlobalDeviceState;
 use gpui::*;
 use gpui_component::StyledExt;
-use gpui_component::{Icon, IconName, Theme, h_flex, progress::Progress, v_flex};
+use gpui_component::{
+    Icon, IconName, Theme,
+    button::{Button, ButtonVariants},
+    h_flex,
+    progress::Progress,
+    v_flex,
+};
 
 pub struct HomeView;
 
@@ -81,6 +91,38 @@ impl HomeView {
             )
     }
 
+    /// Blinks the key, or asks for a touch if it cannot blink, so the user can tell which of
+    /// several connected keys is selected.
+    fn identify_device(device: DeviceHandle, window: &mut Window, cx: &mut App) {
+        let monitor = OperationMonitor::new();
+        let dialog_handle = dialog::open_progress_dialog(
+            "Identify Key",
+            &format!("Asking {} to identify itself...", device.label()),
+            monitor.clone(),
+            window,
+            cx,
+        );
+
+        cx.spawn(async move |cx| {
+            let result = cx
+                .background_executor()
+                .spawn(async move { io::identify(&device, &monitor) })
+                .await;
+
+            let _ = dialog_handle.update(cx, |d, cx| match result {
+                Ok(msg) => d.set_success(msg, cx),
+                Err(e) => {
+                    log::error!("Failed to identify key: {}", e);
+                    d.set_error(
+                        format!("Could not identify the key: {}", e.user_message()),
+                        cx,
+                    );
+                }
+            });
+        })
+        .detach();
+    }
+
     fn render_device_info(state: &GlobalDeviceState, theme: &Theme) -> impl IntoElement {
         let status = state.device_status.as_ref().unwrap();
         let info = &status.info;
@@ -88,9 +130,24 @@ impl HomeView {
 
         let flash_percent = (info.flash_used as f32 / info.flash_total as f32) * 100.0;
 
+        let identify_button = state
+            .selected_device
+            .clone()
+            .filter(|d| d.hid_path.is_some())
+            .map(|device| {
+                Button::new("identify_key")
+                    .ghost()
+                    .label("Blink / Identify")
+                    .tooltip("Blink this key's LED, or wait for a touch if it cannot blink")
+                    .on_click(move |_, window, cx| {
+                        Self::identify_device(device.clone(), window, cx);
+                    })
+            });
+
         Card::new()
             .title("Device Information")
             .icon(Icon::default().path("icons/cpu.svg"))
+            .header_right(div().children(identify_button))
             .child(
                 v_flex()
                     .gap_6()
@@ -194,6 +251,7 @@ impl HomeView {
                     )
                     .child(div().h_px().bg(theme.border))
                     .child(Self::render_kv("AAGUID", fido.aaguid.clone(), theme, true))
+                    .children(SlotGauge::from_info(fido))
                     .into_any_element()
             } else {
                 div()
@@ -1,5 +1,6 @@
 pub mod about;
 pub mod config;
+pub mod diagnostics;
 pub mod home;
 pub mod logs;
 pub mod passkeys;
@@ -1,22 +1,95 @@
+use crate::device::fido::pin_policy::PinComplexityPolicy;
+use crate::device::fido::{enterprise_attestation, pem};
 use crate::device::io;
-use crate::device::types::{FidoDeviceInfo, FullDeviceStatus, StoredCredential};
+use crate::device::transport::OperationMonitor;
+use crate::device::types::{
+    BulkDeleteReport, DeviceHandle, FidoDeviceInfo, FullDeviceStatus, LargeBlobArray,
+    LargeBlobEntry, StoredCredential,
+};
+use crate::error::PFError;
 use crate::ui::components::{
     button::{PFButton, PFIconButton},
     card::Card,
     dialog,
-    dialog::{ChangePinContent, ConfirmContent, PinPromptContent, SetPinContent},
+    dialog::{
+        BackupContent, ChangePinContent, ConfirmContent, EditUserContent, PinAttempts,
+        PinAttemptsNotice, PinPromptContent, RestoreBackupContent, SetPinContent,
+    },
     page_view::PageView,
+    slot_gauge::SlotGauge,
 };
 use gpui::*;
 use gpui_component::button::{Button, ButtonVariant, ButtonVariants};
 use gpui_component::{
-    ActiveTheme, Icon, Placement, Sizable, StyledExt, Theme, WindowExt,
+    ActiveTheme, Disableable, Icon, Placement, Sizable, StyledExt, Theme, WindowExt,
     badge::Badge,
+    checkbox::Checkbox,
     h_flex,
     input::{Input, InputState},
     slider::{Slider, SliderState},
+    switch::Switch,
     v_flex,
 };
+use std::collections::HashSet;
+
+const CSR_FILE_NAME: &str = "enterprise-attestation.csr.pem";
+/// Failed passkeys listed by name in a bulk delete summary; the rest are only counted.
+const MAX_LISTED_FAILURES: usize = 8;
+
+/// Where save dialogs for exported files start.
+fn export_directory() -> std::path::PathBuf {
+    directories::UserDirs::new()
+        .and_then(|dirs| dirs.document_dir().map(|d| d.to_path_buf()))
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default()
+}
+
+/// What a bulk delete did, listing the passkeys that could not be deleted and why.
+fn bulk_delete_summary(report: &BulkDeleteReport, total: usize) -> String {
+    let mut summary = format!(
+        "Deleted {} of {} passkey{}.",
+        report.deleted,
+        total,
+        if total == 1 { "" } else { "s" }
+    );
+    for (cred, reason) in report.failed.iter().take(MAX_LISTED_FAILURES) {
+        summary.push_str(&format!(
+            "\n{} ({}): {}",
+            cred.rp_id, cred.user_name, reason
+        ));
+    }
+    if report.failed.len() > MAX_LISTED_FAILURES {
+        summary.push_str(&format!(
+            "\n...and {} more",
+            report.failed.len() - MAX_LISTED_FAILURES
+        ));
+    }
+    summary
+}
+
+/// Runs a PIN operation, then re-reads GetInfo so the retry counters and PIN state shown next
+/// reflect it. Both block on the key, so this belongs on a background thread.
+fn with_fido_info<R>(device: &DeviceHandle, op: impl FnOnce() -> R) -> (R, Option<FidoDeviceInfo>) {
+    let result = op();
+    let info = io::get_fido_info(device)
+        .inspect_err(|e| log::warn!("Failed to re-read FIDO info: {}", e))
+        .ok();
+    (result, info)
+}
+
+/// Shows a credBlob as text when it is printable UTF-8, otherwise as hex.
+fn describe_cred_blob(blob: &[u8]) -> String {
+    match std::str::from_utf8(blob) {
+        Ok(text) if !text.chars().any(char::is_control) => format!("\"{}\"", text),
+        _ => format!("{} (hex)", hex::encode(blob)),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum PublicKeyFormat {
+    Pem,
+    Jwk,
+}
 
 struct SliderLabel {
     slider: Entity<SliderState>,
@@ -29,17 +102,67 @@ impl Render for SliderLabel {
     }
 }
 
+/// Rule switches of the PIN complexity policy dialog.
+struct PinPolicyEditor {
+    policy: PinComplexityPolicy,
+}
+
+impl Render for PinPolicyEditor {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.theme();
+
+        v_flex()
+            .gap_3()
+            .children(PinComplexityPolicy::all().iter().map(|rule| {
+                let (name, description) = PinComplexityPolicy::describe(rule);
+                h_flex()
+                    .justify_between()
+                    .items_center()
+                    .child(
+                        v_flex().child(div().font_medium().child(name)).child(
+                            div()
+                                .text_sm()
+                                .text_color(theme.muted_foreground)
+                                .child(description),
+                        ),
+                    )
+                    .child(
+                        Switch::new(SharedString::from(format!("pin-policy-{}", rule.bits())))
+                            .checked(self.policy.contains(rule))
+                            .on_click(cx.listener(move |this, checked: &bool, _, cx| {
+                                this.policy.set(rule, *checked);
+                                cx.notify();
+                            })),
+                    )
+            }))
+    }
+}
+
 pub struct PasskeysView {
+    device: Option<DeviceHandle>,
     device_status: Option<FullDeviceStatus>,
     fido_info: Option<FidoDeviceInfo>,
     credentials: Vec<StoredCredential>,
     unlocked: bool,
     cached_pin: Option<String>,
     loading: bool,
+    /// Large-blob array read since the storage was unlocked.
+    large_blobs: Option<LargeBlobArray>,
+    /// credBlob of the passkey shown in the details sheet, keyed by credential ID.
+    cred_blob: Option<(String, CredBlobRead)>,
+    /// Credential IDs ticked in the list for bulk deletion.
+    selected: HashSet<String>,
 
     _task: Option<Task<()>>,
 }
 
+#[derive(Clone)]
+enum CredBlobRead {
+    Reading,
+    Done(Option<Vec<u8>>),
+    Failed(String),
+}
+
 pub enum PasskeysEvent {
     Notification(String),
     CloseDialog,
@@ -51,29 +174,40 @@ impl PasskeysView {
     pub fn new(
         _window: &mut Window,
         _cx: &mut Context<Self>,
+        device: Option<DeviceHandle>,
         device_status: Option<FullDeviceStatus>,
         fido_info: Option<FidoDeviceInfo>,
     ) -> Self {
         Self {
+            device,
             device_status,
             fido_info,
             credentials: Vec::new(),
             unlocked: false,
             cached_pin: None,
             loading: false,
+            large_blobs: None,
+            cred_blob: None,
+            selected: HashSet::new(),
             _task: None,
         }
     }
 
     pub fn update_device_status(
         &mut self,
+        device: Option<DeviceHandle>,
         status: Option<FullDeviceStatus>,
         fido_info: Option<FidoDeviceInfo>,
         cx: &mut Context<Self>,
     ) {
-        if self.device_status == status && self.fido_info == fido_info {
+        if self.device == device && self.device_status == status && self.fido_info == fido_info {
             return;
         }
+        // Cached PIN and credentials belong to the previous key.
+        if self.device.as_ref().map(|d| d.id()) != device.as_ref().map(|d| d.id()) {
+            self.lock_storage(cx);
+        }
+        self.device = device;
         self.device_status = status;
         self.fido_info = fido_info;
         cx.notify();
@@ -88,6 +222,12 @@ impl PasskeysView {
         if self.loading {
             return;
         }
+        let Some(device) = self.device.clone() else {
+            let _ = dialog_handle.update(cx, |d, cx| {
+                d.set_error("No device selected.".to_string(), cx);
+            });
+            return;
+        };
         self.loading = true;
         cx.notify();
 
@@ -96,12 +236,15 @@ impl PasskeysView {
 
         self._task = Some(cx.spawn(async move |_, cx| {
             let pin_for_bg = pin.clone();
-            let result = cx
+            let (result, fido_info) = cx
                 .background_executor()
-                .spawn(async move { io::get_credentials(pin_for_bg) })
+                .spawn(async move {
+                    with_fido_info(&device, || io::get_credentials(&device, pin_for_bg))
+                })
                 .await;
 
             let _ = entity.update(cx, |this, cx| {
+                this.set_fido_info(fido_info);
                 this.loading = false;
                 match result {
                     Ok(creds) => {
@@ -115,8 +258,11 @@ impl PasskeysView {
                     }
                     Err(e) => {
                         log::error!("Failed to unlock storage: {}", e);
+                        this.handle_pin_error(&e, cx);
+                        let attempts = PinAttempts::from_info(this.fido_info.as_ref());
                         let _ = dialog_handle.update(cx, |d, cx| {
-                            d.set_error(format!("Failed to unlock: {}", e), cx);
+                            d.set_pin_attempts(attempts, cx);
+                            d.set_error(format!("Failed to unlock: {}", e.user_message()), cx);
                         });
                     }
                 }
@@ -129,9 +275,30 @@ impl PasskeysView {
         self.unlocked = false;
         self.cached_pin = None;
         self.credentials.clear();
+        self.large_blobs = None;
+        self.cred_blob = None;
+        self.selected.clear();
         cx.notify();
     }
 
+    /// Locks the storage again when the key no longer accepts the PIN (wrong, blocked or
+    /// needing a power cycle), so the stale cached PIN is not retried.
+    fn handle_pin_error(&mut self, e: &PFError, cx: &mut Context<Self>) {
+        if !e.invalidates_pin() {
+            return;
+        }
+        log::warn!("Key rejected the PIN ({}), locking storage", e);
+        self.lock_storage(cx);
+    }
+
+    /// Takes the GetInfo read after an operation by [`with_fido_info`]. Keeps the previous one
+    /// if that read failed.
+    fn set_fido_info(&mut self, info: Option<FidoDeviceInfo>) {
+        if info.is_some() {
+            self.fido_info = info;
+        }
+    }
+
     fn execute_delete(
         &mut self,
         credential_id: String,
@@ -142,6 +309,12 @@ impl PasskeysView {
         if self.loading {
             return;
         }
+        let Some(device) = self.device.clone() else {
+            let _ = dialog_handle.update(cx, |d, cx| {
+                d.set_error("No device selected.".to_string(), cx);
+            });
+            return;
+        };
         self.loading = true;
         cx.notify();
 
@@ -150,24 +323,33 @@ impl PasskeysView {
 
         self._task = Some(cx.spawn(async move |_, cx| {
             let pin_for_bg = pin.clone();
-            let result = cx
+            let device_for_bg = device.clone();
+            let (result, fido_info) = cx
                 .background_executor()
-                .spawn(async move { io::delete_credential(pin_for_bg, credential_id) })
+                .spawn(async move {
+                    with_fido_info(&device_for_bg, || {
+                        io::delete_credential(&device_for_bg, pin_for_bg, credential_id)
+                    })
+                })
                 .await;
 
             let _ = entity.update(cx, |this, cx| match result {
                 Ok(_) => {
                     log::info!("Credential deleted successfully.");
-                    this.refresh_credentials(pin, cx);
+                    this.refresh_credentials(device, pin, cx);
                     let _ = dialog_handle.update(cx, |d, cx| {
                         d.set_success("Credential deleted successfully.".to_string(), cx);
                     });
                 }
                 Err(e) => {
                     log::error!("Error deleting credential: {}", e);
+                    this.set_fido_info(fido_info);
                     this.loading = false;
+                    this.handle_pin_error(&e, cx);
+                    let attempts = PinAttempts::from_info(this.fido_info.as_ref());
                     let _ = dialog_handle.update(cx, |d, cx| {
-                        d.set_error(format!("Error deleting: {}", e), cx);
+                        d.set_pin_attempts(attempts, cx);
+                        d.set_error(format!("Error deleting: {}", e.user_message()), cx);
                     });
                     cx.notify();
                 }
@@ -175,31 +357,368 @@ impl PasskeysView {
         }));
     }
 
-    fn refresh_credentials(&mut self, pin: String, cx: &mut Context<Self>) {
+    fn toggle_selected(&mut self, credential_id: &str, selected: bool, cx: &mut Context<Self>) {
+        if selected {
+            self.selected.insert(credential_id.to_string());
+        } else {
+            self.selected.remove(credential_id);
+        }
+        cx.notify();
+    }
+
+    fn selected_credentials(&self) -> Vec<StoredCredential> {
+        self.credentials
+            .iter()
+            .filter(|c| self.selected.contains(&c.credential_id))
+            .cloned()
+            .collect()
+    }
+
+    /// Confirms deleting `credentials` in one batch: the whole selection, or every passkey of
+    /// one relying party.
+    fn open_bulk_delete_dialog(
+        &mut self,
+        credentials: Vec<StoredCredential>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if credentials.is_empty() {
+            return;
+        }
+        let Some(pin) = self.cached_pin.clone() else {
+            window.push_notification("Session expired, please unlock again.", cx);
+            self.lock_storage(cx);
+            return;
+        };
+
+        let mut rp_ids: Vec<&str> = credentials.iter().map(|c| c.rp_id.as_str()).collect();
+        rp_ids.sort_unstable();
+        rp_ids.dedup();
+        let sites = match rp_ids.as_slice() {
+            [rp_id] => rp_id.to_string(),
+            [first, second] => format!("{} and {}", first, second),
+            [first, second, rest @ ..] => {
+                format!("{}, {} and {} more sites", first, second, rest.len())
+            }
+            [] => String::new(),
+        };
+        let message = format!(
+            "Are you sure you want to delete {} passkey{} for {}? Accounts that rely on them will need another way to sign in.",
+            credentials.len(),
+            if credentials.len() == 1 { "" } else { "s" },
+            sites
+        );
+
+        let view_handle = cx.entity().downgrade();
+        let attempts = self.pin_attempts();
+        dialog::open_pin_confirm(
+            "Delete Passkeys",
+            message,
+            "Delete All",
+            ButtonVariant::Danger,
+            attempts,
+            window,
+            cx,
+            move |dialog_handle, cx| {
+                let _ = view_handle.update(cx, |this, cx| {
+                    this.execute_bulk_delete(credentials.clone(), pin.clone(), dialog_handle, cx);
+                });
+            },
+        );
+    }
+
+    fn execute_bulk_delete(
+        &mut self,
+        credentials: Vec<StoredCredential>,
+        pin: String,
+        dialog_handle: WeakEntity<ConfirmContent>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.loading {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            let _ = dialog_handle.update(cx, |d, cx| {
+                d.set_error("No device selected.".to_string(), cx);
+            });
+            return;
+        };
+        self.loading = true;
+        cx.notify();
+
+        let monitor = OperationMonitor::new();
+        let _ = dialog_handle.update(cx, |d, cx| d.watch_progress(monitor.clone(), cx));
+
+        let total = credentials.len();
         let entity = cx.entity().downgrade();
         self._task = Some(cx.spawn(async move |_, cx| {
-            let result = cx
+            let pin_for_bg = pin.clone();
+            let device_for_bg = device.clone();
+            let (result, fido_info) = cx
+                .background_executor()
+                .spawn(async move {
+                    with_fido_info(&device_for_bg, || {
+                        io::delete_credentials(&device_for_bg, pin_for_bg, credentials, &monitor)
+                    })
+                })
+                .await;
+
+            let _ = entity.update(cx, |this, cx| match result {
+                Ok(report) => {
+                    this.refresh_credentials(device, pin, cx);
+                    let _ = dialog_handle.update(cx, |d, cx| {
+                        if report.failed.is_empty() {
+                            d.set_success(bulk_delete_summary(&report, total), cx);
+                        } else {
+                            d.set_error(bulk_delete_summary(&report, total), cx);
+                        }
+                    });
+                }
+                Err(e) => {
+                    log::error!("Error deleting credentials: {}", e);
+                    this.set_fido_info(fido_info);
+                    this.loading = false;
+                    this.handle_pin_error(&e, cx);
+                    let attempts = PinAttempts::from_info(this.fido_info.as_ref());
+                    let _ = dialog_handle.update(cx, |d, cx| {
+                        d.set_pin_attempts(attempts, cx);
+                        d.set_error(format!("Error deleting: {}", e.user_message()), cx);
+                    });
+                    cx.notify();
+                }
+            });
+        }));
+    }
+
+    fn open_edit_user_dialog(
+        &mut self,
+        cred: StoredCredential,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(pin) = self.cached_pin.clone() else {
+            window.push_notification("Session expired, please unlock again.", cx);
+            self.lock_storage(cx);
+            return;
+        };
+        let view_handle = cx.entity().downgrade();
+        let (user_name, user_display_name) =
+            (cred.user_name.clone(), cred.user_display_name.clone());
+
+        dialog::open_edit_user(
+            &user_name,
+            &user_display_name,
+            window,
+            cx,
+            move |name, display_name, dialog_handle, cx| {
+                let _ = view_handle.update(cx, |this, cx| {
+                    this.update_credential_user(
+                        cred.clone(),
+                        pin.clone(),
+                        name,
+                        display_name,
+                        dialog_handle,
+                        cx,
+                    );
+                });
+            },
+        );
+    }
+
+    fn update_credential_user(
+        &mut self,
+        cred: StoredCredential,
+        pin: String,
+        user_name: String,
+        user_display_name: String,
+        dialog_handle: WeakEntity<EditUserContent>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.loading {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            let _ = dialog_handle.update(cx, |d, cx| {
+                d.set_error("No device selected.".to_string(), cx);
+            });
+            return;
+        };
+        self.loading = true;
+        cx.notify();
+
+        log::info!("Updating passkey user for {}...", cred.rp_id);
+        let entity = cx.entity().downgrade();
+
+        self._task = Some(cx.spawn(async move |_, cx| {
+            let pin_for_bg = pin.clone();
+            let device_for_bg = device.clone();
+            let (result, fido_info) = cx
+                .background_executor()
+                .spawn(async move {
+                    with_fido_info(&device_for_bg, || {
+                        io::update_credential_user(
+                            &device_for_bg,
+                            pin_for_bg,
+                            cred,
+                            user_name,
+                            user_display_name,
+                        )
+                    })
+                })
+                .await;
+
+            let _ = entity.update(cx, |this, cx| match result {
+                Ok(msg) => {
+                    log::info!("Passkey user updated.");
+                    this.refresh_credentials(device, pin, cx);
+                    let _ = dialog_handle.update(cx, |d, cx| d.set_success(msg, cx));
+                }
+                Err(e) => {
+                    log::error!("Error updating passkey user: {}", e);
+                    this.set_fido_info(fido_info);
+                    this.loading = false;
+                    this.handle_pin_error(&e, cx);
+                    let _ = dialog_handle.update(cx, |d, cx| {
+                        d.set_error(format!("Error updating: {}", e.user_message()), cx);
+                    });
+                    cx.notify();
+                }
+            });
+        }));
+    }
+
+    /// Reads the credBlob of the passkey shown in the details sheet. Runs a getAssertion of its
+    /// own, so it is only done on request.
+    fn read_cred_blob(&mut self, cred: StoredCredential, cx: &mut Context<Self>) {
+        let Some(device) = self.device.clone() else {
+            return;
+        };
+        let Some(pin) = self.cached_pin.clone() else {
+            cx.emit(PasskeysEvent::Notification(
+                "Session expired, please unlock again.".into(),
+            ));
+            self.lock_storage(cx);
+            return;
+        };
+        let credential_id = cred.credential_id.clone();
+        self.cred_blob = Some((credential_id.clone(), CredBlobRead::Reading));
+        cx.notify();
+
+        cx.spawn(async move |this, cx| {
+            let (result, fido_info) =
+                cx.background_executor()
+                    .spawn(async move {
+                        with_fido_info(&device, || io::read_cred_blob(&device, pin, cred))
+                    })
+                    .await;
+
+            let _ = this.update(cx, |this, cx| {
+                this.set_fido_info(fido_info);
+                let read = match result {
+                    Ok(blob) => CredBlobRead::Done(blob),
+                    Err(e) => {
+                        log::error!("Failed to read credBlob: {}", e);
+                        this.handle_pin_error(&e, cx);
+                        CredBlobRead::Failed(e.user_message())
+                    }
+                };
+                if matches!(&this.cred_blob, Some((id, _)) if *id == credential_id) {
+                    this.cred_blob = Some((credential_id, read));
+                }
+                cx.notify();
+            });
+        })
+        .detach();
+    }
+
+    /// Saves a passkey's public key as a PEM SubjectPublicKeyInfo or a JWK.
+    fn export_public_key(
+        &mut self,
+        cred: StoredCredential,
+        format: PublicKeyFormat,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(public_key) = &cred.public_key else {
+            return;
+        };
+        let (contents, extension) = match format {
+            PublicKeyFormat::Pem => (public_key.to_pem(), "pem"),
+            PublicKeyFormat::Jwk => (public_key.to_jwk(), "jwk"),
+        };
+        let contents = match contents {
+            Ok(contents) => contents,
+            Err(e) => {
+                cx.emit(PasskeysEvent::Notification(e.user_message()));
+                return;
+            }
+        };
+
+        let file_name = format!("{}-{}.{}", cred.rp_id, cred.user_name, extension)
+            .replace(['/', '\\', ':'], "_");
+        let path = cx.prompt_for_new_path(&export_directory(), Some(&file_name));
+        cx.spawn(async move |this, cx| {
+            let Ok(Ok(Some(path))) = path.await else {
+                return;
+            };
+            let message = match std::fs::write(&path, contents) {
+                Ok(()) => {
+                    log::info!("Saved public key to {}", path.display());
+                    format!("Public key saved to {}", path.display())
+                }
+                Err(e) => {
+                    log::error!("Failed to save public key: {}", e);
+                    format!("Failed to save public key: {}", e)
+                }
+            };
+            let _ = this.update(cx, |_, cx| {
+                cx.emit(PasskeysEvent::Notification(message));
+            });
+        })
+        .detach();
+    }
+
+    fn refresh_credentials(&mut self, device: DeviceHandle, pin: String, cx: &mut Context<Self>) {
+        let entity = cx.entity().downgrade();
+        self._task = Some(cx.spawn(async move |_, cx| {
+            let (result, fido_info) = cx
                 .background_executor()
-                .spawn(async move { io::get_credentials(pin) })
+                .spawn(async move { with_fido_info(&device, || io::get_credentials(&device, pin)) })
                 .await;
 
             let _ = entity.update(cx, |this, cx| {
+                this.set_fido_info(fido_info);
                 this.loading = false;
-                if let Ok(creds) = result {
-                    this.credentials = creds;
+                match result {
+                    Ok(creds) => {
+                        this.selected
+                            .retain(|id| creds.iter().any(|c| c.credential_id == *id));
+                        this.credentials = creds;
+                    }
+                    Err(e) => {
+                        log::error!("Failed to refresh credentials: {}", e);
+                        this.handle_pin_error(&e, cx);
+                    }
                 }
                 cx.notify();
             });
         }));
     }
 
+    /// The retry counters for PIN dialogs to warn with. Every PIN operation re-reads them in
+    /// the background, so the cached GetInfo is current.
+    fn pin_attempts(&self) -> PinAttempts {
+        PinAttempts::from_info(self.fido_info.as_ref())
+    }
+
     fn open_unlock_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
         let view_handle = cx.entity().downgrade();
+        let attempts = self.pin_attempts();
 
         dialog::open_pin_prompt(
             "Unlock Storage",
             "Enter your device PIN to view saved passkeys",
             "Unlock",
+            attempts,
             window,
             cx,
             move |pin, dialog_handle, cx| {
@@ -221,12 +740,14 @@ impl PasskeysView {
         let pin_str = pin.clone();
         let name = cred.rp_id.clone();
         let view_handle = cx.entity().downgrade();
+        let attempts = self.pin_attempts();
 
-        dialog::open_confirm(
+        dialog::open_pin_confirm(
             "Delete Passkey",
             format!("Are you sure you want to delete the passkey for {}?", name),
             "Delete",
             ButtonVariant::Danger,
+            attempts,
             window,
             cx,
             move |dialog_handle, cx| {
@@ -237,63 +758,1265 @@ impl PasskeysView {
         );
     }
 
-    fn open_change_pin_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
-        let view_handle = cx.entity().downgrade();
+    fn pin_policy(&self) -> PinComplexityPolicy {
+        self.fido_info
+            .as_ref()
+            .map(|f| f.pin_complexity_policy)
+            .unwrap_or_default()
+    }
 
-        dialog::open_change_pin(window, cx, move |current, new, dialog_handle, cx| {
-            let _ = view_handle.update(cx, |this, cx| {
-                this.change_pin(current, new, dialog_handle, cx);
-            });
+    fn open_pin_policy_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let editor = cx.new(|_| PinPolicyEditor {
+            policy: self.pin_policy(),
         });
-    }
+        let current_pin = cx.new(|cx| {
+            InputState::new(window, cx)
+                .placeholder("Enter current PIN")
+                .masked(true)
+        });
+        let attempts = self.pin_attempts();
+        let attempts_notice = cx.new(|_| PinAttemptsNotice::new(attempts));
 
-    fn open_setup_pin_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
         let view_handle = cx.entity().downgrade();
 
-        dialog::open_setup_pin(window, cx, move |new_pin, dialog_handle, cx| {
-            let _ = view_handle.update(cx, |this, cx| {
-                this.setup_pin(new_pin, dialog_handle, cx);
-            });
-        });
-    }
+        window.open_dialog(cx, move |dialog, _, _| {
+            let view = view_handle.clone();
+            let editor = editor.clone();
+            let current = current_pin.clone();
+            let attempts_notice = attempts_notice.clone();
 
-    fn setup_pin(
-        &mut self,
-        new: String,
-        dialog_handle: WeakEntity<SetPinContent>,
-        cx: &mut Context<Self>,
-    ) {
-        if self.loading {
-            return;
-        }
-        self.loading = true;
-        cx.notify();
+            dialog
+                .title("PIN Complexity Policy")
+                .child(
+                    "Choose the rules the key enforces whenever a PIN is set or changed. The current PIN is not checked.",
+                )
+                .child(
+                    v_flex()
+                        .gap_4()
+                        .pb_4()
+                        .child(editor.clone())
+                        .child(attempts_notice.clone())
+                        .child("Current PIN")
+                        .child(Input::new(&current)),
+                )
+                .footer(move |_, _window, _cx, _| {
+                    let view = view.clone();
+                    let editor = editor.clone();
+                    let current = current.clone();
+                    let attempts_notice = attempts_notice.clone();
+
+                    vec![
+                        Button::new("cancel")
+                            .label("Cancel")
+                            .on_click(|_, window, cx| window.close_dialog(cx)),
+                        Button::new("save-policy")
+                            .primary()
+                            .label("Save")
+                            .on_click(move |_, _, cx| {
+                                let current_val = current.read(cx).text().to_string();
+                                let policy = editor.read(cx).policy;
+
+                                if current_val.is_empty() {
+                                    return;
+                                }
+                                if !attempts_notice.read(cx).allows_attempt() {
+                                    let _ = view.update(cx, |_, cx| {
+                                        cx.emit(PasskeysEvent::Notification("Confirm the PIN attempt warning first".to_string()));
+                                    });
+                                    return;
+                                }
+                                let _ = view.update(cx, |this, cx| {
+                                    this.set_pin_policy(
+                                        current_val,
+                                        policy,
+                                        attempts_notice.downgrade(),
+                                        cx,
+                                    );
+                                });
+                            }),
+                    ]
+                })
+        });
+    }
+
+    fn set_pin_policy(
+        &mut self,
+        pin: String,
+        policy: PinComplexityPolicy,
+        attempts_notice: WeakEntity<PinAttemptsNotice>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.loading {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            cx.emit(PasskeysEvent::Notification(
+                "No device selected".to_string(),
+            ));
+            return;
+        };
+        self.loading = true;
+        cx.notify();
+        log::info!("Setting PIN complexity policy to {:?}...", policy);
+        let entity = cx.entity().downgrade();
+
+        self._task = Some(cx.spawn(async move |_, cx| {
+            let (result, fido_info) = cx
+                .background_executor()
+                .spawn(async move {
+                    with_fido_info(&device, || {
+                        io::set_pin_complexity_policy(&device, pin, policy)
+                    })
+                })
+                .await;
+
+            let _ = entity.update(cx, |this, cx| {
+                this.set_fido_info(fido_info);
+                this.loading = false;
+                match result {
+                    Ok(msg) => {
+                        log::info!("{}", msg);
+                        cx.emit(PasskeysEvent::CloseDialog);
+                        cx.emit(PasskeysEvent::Notification(msg));
+                    }
+                    Err(e) => {
+                        log::error!("Failed to set PIN complexity policy: {}", e);
+                        this.handle_pin_error(&e, cx);
+                        let attempts = PinAttempts::from_info(this.fido_info.as_ref());
+                        let _ =
+                            attempts_notice.update(cx, |n, cx| n.set_pin_attempts(attempts, cx));
+                        cx.emit(PasskeysEvent::Notification(format!(
+                            "Failed to set PIN policy: {}",
+                            e.user_message()
+                        )));
+                    }
+                }
+                cx.notify();
+            });
+        }));
+    }
+
+    fn open_change_pin_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let view_handle = cx.entity().downgrade();
+        let attempts = self.pin_attempts();
+
+        dialog::open_change_pin(
+            attempts,
+            self.pin_policy(),
+            window,
+            cx,
+            move |current, new, dialog_handle, cx| {
+                let _ = view_handle.update(cx, |this, cx| {
+                    this.change_pin(current, new, dialog_handle, cx);
+                });
+            },
+        );
+    }
+
+    fn open_always_uv_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let enabled = self
+            .fido_info
+            .as_ref()
+            .and_then(|f| f.options.get("alwaysUv").copied())
+            .unwrap_or(false);
+        let view_handle = cx.entity().downgrade();
+        let attempts = self.pin_attempts();
+
+        dialog::open_pin_prompt(
+            "Always Require Verification",
+            if enabled {
+                "Enter your device PIN to let sites use passkeys without verification."
+            } else {
+                "Enter your device PIN to require verification for every passkey use."
+            },
+            if enabled { "Disable" } else { "Enable" },
+            attempts,
+            window,
+            cx,
+            move |pin, dialog_handle, cx| {
+                let _ = view_handle.update(cx, |this, cx| {
+                    this.toggle_always_uv(pin, dialog_handle, cx);
+                });
+            },
+        );
+    }
+
+    fn toggle_always_uv(
+        &mut self,
+        pin: String,
+        dialog_handle: WeakEntity<PinPromptContent>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.loading {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            let _ = dialog_handle.update(cx, |d, cx| {
+                d.set_error("No device selected.".to_string(), cx);
+            });
+            return;
+        };
+        self.loading = true;
+        cx.notify();
+
+        log::info!("Toggling alwaysUv...");
+        let entity = cx.entity().downgrade();
+
+        self._task = Some(cx.spawn(async move |_, cx| {
+            let (result, fido_info) = cx
+                .background_executor()
+                .spawn(
+                    async move { with_fido_info(&device, || io::toggle_always_uv(&device, pin)) },
+                )
+                .await;
+
+            let _ = entity.update(cx, |this, cx| {
+                this.set_fido_info(fido_info);
+                this.loading = false;
+                match result {
+                    Ok(enabled) => {
+                        log::info!("alwaysUv is now {}", enabled);
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_success(
+                                if enabled {
+                                    "Every passkey use now requires verification."
+                                } else {
+                                    "Verification is no longer always required."
+                                }
+                                .to_string(),
+                                cx,
+                            );
+                        });
+                    }
+                    Err(e) => {
+                        log::error!("Failed to toggle alwaysUv: {}", e);
+                        this.handle_pin_error(&e, cx);
+                        let attempts = PinAttempts::from_info(this.fido_info.as_ref());
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_pin_attempts(attempts, cx);
+                            d.set_error(format!("Error: {}", e.user_message()), cx);
+                        });
+                    }
+                }
+                cx.notify();
+            });
+        }));
+    }
+
+    fn open_auth_encryption_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let enabled = self.fido_info.as_ref().is_some_and(|f| f.auth_encryption);
+        let view_handle = cx.entity().downgrade();
+        let attempts = self.pin_attempts();
+
+        dialog::open_pin_prompt(
+            "Authentication Encryption",
+            if enabled {
+                "Enter your device PIN to store the key's secret unencrypted again."
+            } else {
+                "Enter your device PIN to encrypt the key's secret with a key kept on this computer. \
+                 Afterwards PicoForge must unlock the key each time it is plugged in, and other \
+                 computers cannot use it until encryption is turned off here."
+            },
+            if enabled { "Disable" } else { "Enable" },
+            attempts,
+            window,
+            cx,
+            move |pin, dialog_handle, cx| {
+                let _ = view_handle.update(cx, |this, cx| {
+                    this.set_auth_encryption(pin, !enabled, dialog_handle, cx);
+                });
+            },
+        );
+    }
+
+    fn set_auth_encryption(
+        &mut self,
+        pin: String,
+        enabled: bool,
+        dialog_handle: WeakEntity<PinPromptContent>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.loading {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            let _ = dialog_handle.update(cx, |d, cx| {
+                d.set_error("No device selected.".to_string(), cx);
+            });
+            return;
+        };
+        self.loading = true;
+        cx.notify();
+
+        log::info!("Setting authentication encryption to {}...", enabled);
+        let entity = cx.entity().downgrade();
+
+        self._task = Some(cx.spawn(async move |_, cx| {
+            let (result, fido_info) = cx
+                .background_executor()
+                .spawn(async move {
+                    with_fido_info(&device, || io::set_auth_encryption(&device, pin, enabled))
+                })
+                .await;
+
+            let _ = entity.update(cx, |this, cx| {
+                this.set_fido_info(fido_info);
+                this.loading = false;
+                match result {
+                    Ok(enabled) => {
+                        log::info!("Authentication encryption is now {}", enabled);
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_success(
+                                if enabled {
+                                    "The key's secret is now encrypted. PicoForge unlocks it \
+                                     whenever the key is connected."
+                                } else {
+                                    "The key's secret is no longer encrypted."
+                                }
+                                .to_string(),
+                                cx,
+                            );
+                        });
+                    }
+                    Err(e) => {
+                        log::error!("Failed to set authentication encryption: {}", e);
+                        this.handle_pin_error(&e, cx);
+                        let attempts = PinAttempts::from_info(this.fido_info.as_ref());
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_pin_attempts(attempts, cx);
+                            d.set_error(format!("Error: {}", e.user_message()), cx);
+                        });
+                    }
+                }
+                cx.notify();
+            });
+        }));
+    }
+
+    fn open_backup_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let view_handle = cx.entity().downgrade();
+        let attempts = self.pin_attempts();
+
+        dialog::open_backup(attempts, window, cx, move |pin, dialog_handle, cx| {
+            let _ = view_handle.update(cx, |this, cx| {
+                this.read_backup_words(pin, dialog_handle, cx);
+            });
+        });
+    }
+
+    fn read_backup_words(
+        &mut self,
+        pin: String,
+        dialog_handle: WeakEntity<BackupContent>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.loading {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            let _ = dialog_handle.update(cx, |d, cx| {
+                d.set_error("No device selected.".to_string(), cx);
+            });
+            return;
+        };
+        self.loading = true;
+        cx.notify();
+
+        log::info!("Reading encrypted backup...");
+        let entity = cx.entity().downgrade();
+
+        self._task = Some(cx.spawn(async move |_, cx| {
+            let (result, fido_info) = cx
+                .background_executor()
+                .spawn(
+                    async move { with_fido_info(&device, || io::get_backup_words(&device, pin)) },
+                )
+                .await;
+
+            let _ = entity.update(cx, |this, cx| {
+                this.set_fido_info(fido_info);
+                this.loading = false;
+                match result {
+                    Ok(words) => {
+                        log::info!("Encrypted backup read ({} words).", words.len());
+                        let _ = dialog_handle.update(cx, |d, cx| d.set_words(words, cx));
+                    }
+                    Err(e) => {
+                        log::error!("Failed to read backup: {}", e);
+                        this.handle_pin_error(&e, cx);
+                        let attempts = PinAttempts::from_info(this.fido_info.as_ref());
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_pin_attempts(attempts, cx);
+                            d.set_error(format!("Error: {}", e.user_message()), cx);
+                        });
+                    }
+                }
+                cx.notify();
+            });
+        }));
+    }
+
+    fn open_restore_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let view_handle = cx.entity().downgrade();
+        let pin_set = self
+            .fido_info
+            .as_ref()
+            .and_then(|f| f.options.get("clientPin").copied())
+            .unwrap_or(false);
+        let attempts = self.pin_attempts();
+
+        dialog::open_restore_backup(
+            pin_set,
+            attempts,
+            window,
+            cx,
+            move |words, pin, dialog_handle, cx| {
+                let _ = view_handle.update(cx, |this, cx| {
+                    this.restore_backup(words, pin, dialog_handle, cx);
+                });
+            },
+        );
+    }
+
+    fn restore_backup(
+        &mut self,
+        words: String,
+        pin: Option<String>,
+        dialog_handle: WeakEntity<RestoreBackupContent>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.loading {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            let _ = dialog_handle.update(cx, |d, cx| {
+                d.set_error("No device selected.".to_string(), cx);
+            });
+            return;
+        };
+        self.loading = true;
+        cx.notify();
+
+        log::info!("Restoring encrypted backup...");
+        let entity = cx.entity().downgrade();
+
+        self._task = Some(cx.spawn(async move |_, cx| {
+            let (result, fido_info) = cx
+                .background_executor()
+                .spawn(async move {
+                    with_fido_info(&device, || io::restore_backup(&device, words, pin))
+                })
+                .await;
+
+            let _ = entity.update(cx, |this, cx| {
+                this.set_fido_info(fido_info);
+                this.loading = false;
+                match result {
+                    Ok(msg) => {
+                        log::info!("{}", msg);
+                        // Credentials listed before belong to the replaced secret.
+                        this.lock_storage(cx);
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_success(
+                                "The key now uses the secret from the backup.".to_string(),
+                                cx,
+                            );
+                        });
+                    }
+                    Err(e) => {
+                        log::error!("Failed to restore backup: {}", e);
+                        this.handle_pin_error(&e, cx);
+                        let attempts = PinAttempts::from_info(this.fido_info.as_ref());
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_pin_attempts(attempts, cx);
+                            d.set_error(format!("Error: {}", e.user_message()), cx);
+                        });
+                    }
+                }
+                cx.notify();
+            });
+        }));
+    }
+
+    fn open_generate_csr_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let view_handle = cx.entity().downgrade();
+
+        dialog::open_confirm(
+            "Generate Attestation CSR",
+            "The key generates a new attestation key pair. Certificates issued for an earlier request stop working.".to_string(),
+            "Generate",
+            ButtonVariant::Primary,
+            window,
+            cx,
+            move |dialog_handle, cx| {
+                let _ = view_handle.update(cx, |this, cx| {
+                    this.generate_attestation_csr(dialog_handle, cx);
+                });
+            },
+        );
+    }
+
+    fn generate_attestation_csr(
+        &mut self,
+        dialog_handle: WeakEntity<ConfirmContent>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.loading {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            let _ = dialog_handle.update(cx, |d, cx| {
+                d.set_error("No device selected.".to_string(), cx);
+            });
+            return;
+        };
+        self.loading = true;
+        cx.notify();
+
+        let entity = cx.entity().downgrade();
+
+        self._task = Some(cx.spawn(async move |_, cx| {
+            let result = cx
+                .background_executor()
+                .spawn(async move { io::generate_attestation_csr(&device) })
+                .await;
+
+            let csr = match result {
+                Ok(csr) => csr,
+                Err(e) => {
+                    log::error!("Failed to generate CSR: {}", e);
+                    let _ = entity.update(cx, |this, cx| {
+                        this.loading = false;
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_error(format!("Error: {}", e.user_message()), cx);
+                        });
+                        cx.notify();
+                    });
+                    return;
+                }
+            };
+
+            let path = entity.update(cx, |this, cx| {
+                this.loading = false;
+                let _ = dialog_handle.update(cx, |d, cx| {
+                    d.set_success(
+                        "The key generated a new attestation key pair. Choose where to save the request.".to_string(),
+                        cx,
+                    );
+                });
+                cx.notify();
+                cx.prompt_for_new_path(&export_directory(), Some(CSR_FILE_NAME))
+            });
+            let Ok(path) = path else {
+                return;
+            };
+            let Ok(Ok(Some(path))) = path.await else {
+                return;
+            };
+
+            let contents = pem::encode(enterprise_attestation::CSR_PEM_LABEL, &csr);
+            let message = match std::fs::write(&path, contents) {
+                Ok(()) => {
+                    log::info!("Saved attestation CSR to {}", path.display());
+                    format!("CSR saved to {}", path.display())
+                }
+                Err(e) => {
+                    log::error!("Failed to save attestation CSR: {}", e);
+                    format!("Failed to save CSR: {}", e)
+                }
+            };
+            let _ = entity.update(cx, |_, cx| {
+                cx.emit(PasskeysEvent::Notification(message));
+            });
+        }));
+    }
+
+    fn pick_attestation_certificate(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let paths = cx.prompt_for_paths(PathPromptOptions {
+            files: true,
+            directories: false,
+            multiple: false,
+            prompt: Some("Upload".into()),
+        });
+
+        cx.spawn_in(window, async move |this, cx| {
+            let Ok(Ok(Some(paths))) = paths.await else {
+                return;
+            };
+            let Some(path) = paths.into_iter().next() else {
+                return;
+            };
+
+            let certificate = std::fs::read(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+                .and_then(|data| {
+                    pem::decode_pem_or_der(enterprise_attestation::CERTIFICATE_PEM_LABEL, &data)
+                        .map_err(|e| e.to_string())
+                });
+
+            let _ = this.update_in(cx, |this, window, cx| match certificate {
+                Ok(certificate) => this.open_enterprise_attestation_dialog(certificate, window, cx),
+                Err(message) => {
+                    log::error!("{}", message);
+                    cx.emit(PasskeysEvent::Notification(message));
+                }
+            });
+        })
+        .detach();
+    }
+
+    fn open_enterprise_attestation_dialog(
+        &mut self,
+        certificate: Vec<u8>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let view_handle = cx.entity().downgrade();
+        let attempts = self.pin_attempts();
+
+        dialog::open_pin_prompt(
+            "Enable Enterprise Attestation",
+            "Enter your device PIN to upload the certificate and enable enterprise attestation.",
+            "Enable",
+            attempts,
+            window,
+            cx,
+            move |pin, dialog_handle, cx| {
+                let _ = view_handle.update(cx, |this, cx| {
+                    this.provision_enterprise_attestation(
+                        pin,
+                        certificate.clone(),
+                        dialog_handle,
+                        cx,
+                    );
+                });
+            },
+        );
+    }
+
+    fn provision_enterprise_attestation(
+        &mut self,
+        pin: String,
+        certificate: Vec<u8>,
+        dialog_handle: WeakEntity<PinPromptContent>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.loading {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            let _ = dialog_handle.update(cx, |d, cx| {
+                d.set_error("No device selected.".to_string(), cx);
+            });
+            return;
+        };
+        self.loading = true;
+        cx.notify();
+
+        log::info!("Provisioning enterprise attestation...");
+        let entity = cx.entity().downgrade();
+
+        self._task = Some(cx.spawn(async move |_, cx| {
+            let (result, fido_info) = cx
+                .background_executor()
+                .spawn(async move {
+                    with_fido_info(&device, || {
+                        io::provision_enterprise_attestation(&device, pin, certificate)
+                    })
+                })
+                .await;
+
+            let _ = entity.update(cx, |this, cx| {
+                this.set_fido_info(fido_info);
+                this.loading = false;
+                match result {
+                    Ok(msg) => {
+                        log::info!("{}", msg);
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_success(
+                                "Certificate uploaded and enterprise attestation enabled."
+                                    .to_string(),
+                                cx,
+                            );
+                        });
+                    }
+                    Err(e) => {
+                        log::error!("Enterprise attestation setup failed: {}", e);
+                        this.handle_pin_error(&e, cx);
+                        let attempts = PinAttempts::from_info(this.fido_info.as_ref());
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_pin_attempts(attempts, cx);
+                            d.set_error(format!("Error: {}", e.user_message()), cx);
+                        });
+                    }
+                }
+                cx.notify();
+            });
+        }));
+    }
+
+    fn open_setup_pin_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let view_handle = cx.entity().downgrade();
+
+        dialog::open_setup_pin(
+            self.pin_policy(),
+            window,
+            cx,
+            move |new_pin, dialog_handle, cx| {
+                let _ = view_handle.update(cx, |this, cx| {
+                    this.setup_pin(new_pin, dialog_handle, cx);
+                });
+            },
+        );
+    }
+
+    fn setup_pin(
+        &mut self,
+        new: String,
+        dialog_handle: WeakEntity<SetPinContent>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.loading {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            let _ = dialog_handle.update(cx, |d, cx| {
+                d.set_error("No device selected.".to_string(), cx);
+            });
+            return;
+        };
+        self.loading = true;
+        cx.notify();
+
+        log::info!("Setting up FIDO PIN...");
+        let entity = cx.entity().downgrade();
+
+        self._task = Some(cx.spawn(async move |_, cx| {
+            let (result, fido_info) = cx
+                .background_executor()
+                .spawn(async move {
+                    with_fido_info(&device, || io::change_fido_pin(&device, None, new))
+                })
+                .await;
+
+            let _ = entity.update(cx, |this, cx| {
+                this.set_fido_info(fido_info);
+                this.loading = false;
+                match result {
+                    Ok(msg) => {
+                        log::info!("PIN configured: {}", msg);
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_success("PIN configured successfully.".to_string(), cx);
+                        });
+                    }
+                    Err(e) => {
+                        log::error!("PIN setup failed: {}", e);
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_error(format!("Error: {}", e.user_message()), cx);
+                        });
+                    }
+                }
+                cx.notify();
+            });
+        }));
+    }
+
+    fn open_min_pin_length_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let current_min = self
+            .fido_info
+            .as_ref()
+            .map(|f| f.min_pin_length)
+            .unwrap_or(4);
+
+        let slider = cx.new(|_| {
+            SliderState::new()
+                .min(4.0)
+                .max(63.0)
+                .step(1.0)
+                .default_value(current_min as f32)
+        });
+
+        let current_pin = cx.new(|cx| {
+            InputState::new(window, cx)
+                .placeholder("Enter current PIN")
+                .masked(true)
+        });
+        let new_pin = cx.new(|cx| {
+            InputState::new(window, cx)
+                .placeholder("Enter new PIN")
+                .masked(true)
+        });
+        let confirm_pin = cx.new(|cx| {
+            InputState::new(window, cx)
+                .placeholder("Confirm new PIN")
+                .masked(true)
+        });
+
+        // Create the label view
+        let label_view = cx.new(|_cx| SliderLabel {
+            slider: slider.clone(),
+        });
+        let attempts = self.pin_attempts();
+        let attempts_notice = cx.new(|_| PinAttemptsNotice::new(attempts));
+        let policy = self.pin_policy();
+
+        let view_handle = cx.entity().downgrade();
+
+        window.open_dialog(cx, move |dialog, _, _| {
+            let view = view_handle.clone();
+            let current = current_pin.clone();
+            let new = new_pin.clone();
+            let confirm = confirm_pin.clone();
+            let slider_handle = slider.clone();
+            let attempts_notice = attempts_notice.clone();
+
+            dialog
+                .title("Update Minimum PIN Length")
+                .child(
+                    "Set the minimum allowed PIN length (4-63 characters) and enter a new PIN that meets this requirement.",
+                )
+                .child(
+                    v_flex()
+                        .gap_4()
+                        .pb_4()
+                        .child(
+                             v_flex()
+                                .gap_2()
+                                .child(label_view.clone())
+                                .child(Slider::new(&slider_handle))
+                        )
+                        .child(attempts_notice.clone())
+                        .child("Current PIN")
+                        .child(Input::new(&current))
+                        .child(
+                             v_flex()
+                                 .gap_2()
+                                 .child(format!("New PIN (min {} chars)", current_min)) 
+                                 .child(Input::new(&new))
+                        )
+                        .child("Confirm New PIN")
+                        .child(Input::new(&confirm)),
+                )
+                .footer(move |_, _window, _cx, _| {
+                    let view = view.clone();
+                    let current = current.clone();
+                    let new = new.clone();
+                    let confirm = confirm.clone();
+                    let slider = slider_handle.clone();
+                    let attempts_notice = attempts_notice.clone();
+
+                    vec![
+                        Button::new("cancel")
+                            .label("Cancel")
+                            .on_click(|_, window, cx| window.close_dialog(cx)),
+                        Button::new("update")
+                            .primary()
+                            .label("Update")
+                            .on_click(move |_, _, cx| {
+                                let current_val = current.read(cx).text().to_string();
+                                let new_val = new.read(cx).text().to_string();
+                                let confirm_val = confirm.read(cx).text().to_string();
+                                let min_len = slider.read(cx).value().start() as u8;
+
+                                if current_val.is_empty() {
+                                    return;
+                                }
+                                if !attempts_notice.read(cx).allows_attempt() {
+                                    let _ = view.update(cx, |_, cx| {
+                                        cx.emit(PasskeysEvent::Notification("Confirm the PIN attempt warning first".to_string()));
+                                    });
+                                    return;
+                                }
+
+                                if !new_val.is_empty() {
+                                    if new_val != confirm_val {
+                                        let _ = view.update(cx, |_, cx| {
+                                            cx.emit(PasskeysEvent::Notification("PINs do not match".to_string()));
+                                        });
+                                        return;
+                                    }
+                                    if new_val.len() < min_len as usize {
+                                        let _ = view.update(cx, |_, cx| {
+                                            cx.emit(PasskeysEvent::Notification(format!("PIN must be at least {} characters", min_len)));
+                                        });
+                                        return;
+                                    }
+                                    if let Some(msg) = policy.violation(&new_val) {
+                                        let _ = view.update(cx, |_, cx| {
+                                            cx.emit(PasskeysEvent::Notification(msg.to_string()));
+                                        });
+                                        return;
+                                    }
+                                }
+                                let _ = view.update(cx, |this, cx| {
+                                    this.update_min_length(
+                                        current_val,
+                                        min_len,
+                                        new_val,
+                                        attempts_notice.downgrade(),
+                                        cx,
+                                    );
+                                });
+                            }),
+                    ]
+                })
+        });
+    }
+
+    fn change_pin(
+        &mut self,
+        current: String,
+        new: String,
+        dialog_handle: WeakEntity<ChangePinContent>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.loading {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            let _ = dialog_handle.update(cx, |d, cx| {
+                d.set_error("No device selected.".to_string(), cx);
+            });
+            return;
+        };
+        self.loading = true;
+        cx.notify();
+
+        log::info!("Changing FIDO PIN...");
+        let entity = cx.entity().downgrade();
+
+        self._task = Some(cx.spawn(async move |_, cx| {
+            let (result, fido_info) = cx
+                .background_executor()
+                .spawn(async move {
+                    with_fido_info(&device, || io::change_fido_pin(&device, Some(current), new))
+                })
+                .await;
+
+            let _ = entity.update(cx, |this, cx| {
+                this.set_fido_info(fido_info);
+                this.loading = false;
+                match result {
+                    Ok(msg) => {
+                        log::info!("PIN changed: {}", msg);
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_success("PIN changed successfully.".to_string(), cx);
+                        });
+                    }
+                    Err(e) => {
+                        log::error!("PIN change failed: {}", e);
+                        this.handle_pin_error(&e, cx);
+                        let attempts = PinAttempts::from_info(this.fido_info.as_ref());
+                        let _ = dialog_handle.update(cx, |d, cx| {
+                            d.set_pin_attempts(attempts, cx);
+                            d.set_error(format!("Error: {}", e.user_message()), cx);
+                        });
+                    }
+                }
+                cx.notify();
+            });
+        }));
+    }
+
+    fn update_min_length(
+        &mut self,
+        current: String,
+        min_len: u8,
+        new_pin: String,
+        attempts_notice: WeakEntity<PinAttemptsNotice>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.loading {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            cx.emit(PasskeysEvent::Notification(
+                "No device selected".to_string(),
+            ));
+            return;
+        };
+        self.loading = true;
+        cx.notify();
+        log::info!("Updating minimum PIN length to {}...", min_len);
+        let entity = cx.entity().downgrade();
+
+        self._task = Some(cx.spawn(async move |_, cx| {
+            // 1. Set Min Length
+            let current_for_bg = current.clone();
+            let device_for_bg = device.clone();
+            let (res_len, fido_info) = cx
+                .background_executor()
+                .spawn(async move {
+                    with_fido_info(&device_for_bg, || {
+                        io::set_min_pin_length(&device_for_bg, current_for_bg, min_len)
+                    })
+                })
+                .await;
+
+            if let Err(e) = res_len {
+                log::error!("Failed to set minimum PIN length: {}", e);
+                let _ = entity.update(cx, |this, cx| {
+                    this.set_fido_info(fido_info);
+                    this.loading = false;
+                    this.handle_pin_error(&e, cx);
+                    let attempts = PinAttempts::from_info(this.fido_info.as_ref());
+                    let _ = attempts_notice.update(cx, |n, cx| n.set_pin_attempts(attempts, cx));
+                    cx.emit(PasskeysEvent::Notification(format!(
+                        "Failed to set length: {}",
+                        e.user_message()
+                    )));
+                    cx.notify();
+                });
+                return;
+            }
+
+            if !new_pin.is_empty() {
+                let device_for_bg = device.clone();
+                let (res_pin, fido_info) = cx
+                    .background_executor()
+                    .spawn(async move {
+                        with_fido_info(&device_for_bg, || {
+                            io::change_fido_pin(&device_for_bg, Some(current), new_pin)
+                        })
+                    })
+                    .await;
+                let _ = entity.update(cx, |this, cx| {
+                    this.set_fido_info(fido_info);
+                    this.loading = false;
+                    match res_pin {
+                        Ok(_) => {
+                            log::info!("Minimum length and PIN updated successfully.");
+                            cx.emit(PasskeysEvent::CloseDialog);
+                            cx.emit(PasskeysEvent::Notification(
+                                "Minimum length and PIN updated".to_string(),
+                            ));
+                        }
+                        Err(e) => {
+                            log::error!("Length set, but PIN change failed: {}", e);
+                            cx.emit(PasskeysEvent::Notification(format!(
+                                "Length set, but PIN change failed: {}",
+                                e.user_message()
+                            )));
+                        }
+                    }
+                    cx.notify();
+                });
+            } else {
+                let _ = entity.update(cx, |this, cx| {
+                    this.set_fido_info(fido_info);
+                    this.loading = false;
+                    log::info!("Minimum PIN length updated to {}.", min_len);
+                    cx.emit(PasskeysEvent::CloseDialog);
+                    cx.emit(PasskeysEvent::Notification(format!(
+                        "Minimum length updated to {}",
+                        min_len
+                    )));
+                    cx.notify();
+                });
+            }
+        }));
+    }
+
+    fn open_reset_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(device) = self.device.clone() else {
+            return;
+        };
+        let entity = cx.entity().downgrade();
+
+        dialog::open_reset_confirm(
+            device.reader_name.is_some(),
+            window,
+            cx,
+            move |reboot, window, cx| {
+                let monitor = OperationMonitor::new();
+                let message = if reboot {
+                    "The key restarts now. Touch it as soon as it blinks."
+                } else {
+                    "Unplug the key and plug it back in, then touch it as soon as it blinks."
+                };
+                let dialog_handle = dialog::open_progress_dialog(
+                    "Factory Reset",
+                    message,
+                    monitor.clone(),
+                    window,
+                    cx,
+                );
+
+                let device = device.clone();
+                let entity = entity.clone();
+                cx.spawn(async move |cx| {
+                    let (result, fido_info) = cx
+                        .background_executor()
+                        .spawn(async move {
+                            with_fido_info(&device, || {
+                                io::reset_authenticator(&device, reboot, &monitor)
+                            })
+                        })
+                        .await;
+
+                    let _ = entity.update(cx, |this, cx| {
+                        // Whatever happened, the key may have rebooted with different state.
+                        this.set_fido_info(fido_info);
+                        if result.is_ok() {
+                            this.lock_storage(cx);
+                        }
+                        cx.notify();
+                    });
+                    let _ = dialog_handle.update(cx, |d, cx| match result {
+                        Ok(msg) => d.set_success(msg, cx),
+                        Err(e) => {
+                            log::error!("Factory reset failed: {}", e);
+                            d.set_error(format!("Reset failed: {}", e.user_message()), cx);
+                        }
+                    });
+                })
+                .detach();
+            },
+        );
+    }
+
+    fn load_large_blobs(&mut self, cx: &mut Context<Self>) {
+        if self.loading {
+            return;
+        }
+        let (Some(device), Some(pin)) = (self.device.clone(), self.cached_pin.clone()) else {
+            return;
+        };
+        self.loading = true;
+        cx.notify();
+
+        let entity = cx.entity().downgrade();
+        self._task = Some(cx.spawn(async move |_, cx| {
+            let (result, fido_info) = cx
+                .background_executor()
+                .spawn(async move { with_fido_info(&device, || io::get_large_blobs(&device, pin)) })
+                .await;
+
+            let _ = entity.update(cx, |this, cx| {
+                this.set_fido_info(fido_info);
+                this.loading = false;
+                match result {
+                    Ok(array) => {
+                        log::info!(
+                            "Large-blob array read: {} entries, {} bytes",
+                            array.entries.len(),
+                            array.size
+                        );
+                        this.large_blobs = Some(array);
+                    }
+                    Err(e) => {
+                        log::error!("Failed to read large blobs: {}", e);
+                        this.handle_pin_error(&e, cx);
+                        cx.emit(PasskeysEvent::Notification(format!(
+                            "Failed to read large blobs: {}",
+                            e.user_message()
+                        )));
+                    }
+                }
+                cx.notify();
+            });
+        }));
+    }
 
-        log::info!("Setting up FIDO PIN...");
-        let entity = cx.entity().downgrade();
+    /// Confirms deleting one large-blob entry, or resetting the whole array if `entry` is
+    /// `None`.
+    fn open_large_blob_dialog(
+        &mut self,
+        entry: Option<LargeBlobEntry>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(pin) = self.cached_pin.clone() else {
+            window.push_notification("Session expired, please unlock again.", cx);
+            self.lock_storage(cx);
+            return;
+        };
+        let (title, message, ok_label) = match &entry {
+            Some(entry) => (
+                "Delete Large Blob",
+                match &entry.owner {
+                    Some(owner) => format!(
+                        "Delete the {} byte blob stored by {} for {}?",
+                        entry.size, owner.rp_id, owner.user_name
+                    ),
+                    None => format!("Delete the {} byte blob?", entry.size),
+                },
+                "Delete",
+            ),
+            None => (
+                "Reset Large Blobs",
+                "Replace the large-blob array with an empty one? Every stored blob is lost."
+                    .to_string(),
+                "Reset",
+            ),
+        };
+        let entry_id = entry.map(|e| e.id);
+        let view_handle = cx.entity().downgrade();
+        let attempts = self.pin_attempts();
+
+        dialog::open_pin_confirm(
+            title,
+            message,
+            ok_label,
+            ButtonVariant::Danger,
+            attempts,
+            window,
+            cx,
+            move |dialog_handle, cx| {
+                let _ = view_handle.update(cx, |this, cx| {
+                    this.write_large_blobs(entry_id.clone(), pin.clone(), dialog_handle, cx);
+                });
+            },
+        );
+    }
+
+    fn write_large_blobs(
+        &mut self,
+        entry_id: Option<String>,
+        pin: String,
+        dialog_handle: WeakEntity<ConfirmContent>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.loading {
+            return;
+        }
+        let Some(device) = self.device.clone() else {
+            let _ = dialog_handle.update(cx, |d, cx| {
+                d.set_error("No device selected.".to_string(), cx);
+            });
+            return;
+        };
+        self.loading = true;
+        cx.notify();
 
+        let entity = cx.entity().downgrade();
         self._task = Some(cx.spawn(async move |_, cx| {
-            let result = cx
+            let (result, fido_info) = cx
                 .background_executor()
-                .spawn(async move { io::change_fido_pin(None, new) })
+                .spawn(async move {
+                    with_fido_info(&device, || match entry_id {
+                        Some(id) => io::delete_large_blob(&device, pin, id),
+                        None => io::reset_large_blobs(&device, pin),
+                    })
+                })
                 .await;
 
             let _ = entity.update(cx, |this, cx| {
+                this.set_fido_info(fido_info);
                 this.loading = false;
                 match result {
                     Ok(msg) => {
-                        log::info!("PIN configured: {}", msg);
-                        if let Ok(info) = io::get_fido_info() {
-                            this.fido_info = Some(info);
-                        }
-                        let _ = dialog_handle.update(cx, |d, cx| {
-                            d.set_success("PIN configured successfully.".to_string(), cx);
-                        });
+                        log::info!("{}", msg);
+                        let _ = dialog_handle.update(cx, |d, cx| d.set_success(msg, cx));
+                        this.load_large_blobs(cx);
                     }
                     Err(e) => {
-                        log::error!("PIN setup failed: {}", e);
+                        log::error!("Failed to update large blobs: {}", e);
+                        this.handle_pin_error(&e, cx);
+                        let attempts = PinAttempts::from_info(this.fido_info.as_ref());
                         let _ = dialog_handle.update(cx, |d, cx| {
-                            d.set_error(format!("Error: {}", e), cx);
+                            d.set_pin_attempts(attempts, cx);
+                            d.set_error(format!("Error: {}", e.user_message()), cx);
                         });
                     }
                 }
@@ -302,250 +2025,453 @@ impl PasskeysView {
         }));
     }
 
-    fn open_min_pin_length_dialog(&mut self, window: &mut Window, cx: &mut Context<Self>) {
-        let current_min = self
+    fn render_pin_policy_row(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let pin_set = self
             .fido_info
             .as_ref()
-            .map(|f| f.min_pin_length)
-            .unwrap_or(4);
+            .and_then(|f| f.options.get("clientPin").copied())
+            .unwrap_or(false);
+        let policy = self.pin_policy();
 
-        let slider = cx.new(|_| {
-            SliderState::new()
-                .min(4.0)
-                .max(63.0)
-                .step(1.0)
-                .default_value(current_min as f32)
-        });
+        let theme = cx.theme();
 
-        let current_pin = cx.new(|cx| {
-            InputState::new(window, cx)
-                .placeholder("Enter current PIN")
-                .masked(true)
-        });
-        let new_pin = cx.new(|cx| {
-            InputState::new(window, cx)
-                .placeholder("Enter new PIN")
-                .masked(true)
-        });
-        let confirm_pin = cx.new(|cx| {
-            InputState::new(window, cx)
-                .placeholder("Confirm new PIN")
-                .masked(true)
-        });
+        div()
+            .flex()
+            .items_center()
+            .justify_between()
+            .p_4()
+            .border_1()
+            .border_color(theme.border)
+            .rounded_lg()
+            .child(
+                v_flex()
+                    .child(div().font_medium().child("PIN Complexity Policy"))
+                    .child(div().text_sm().text_color(theme.muted_foreground).child(
+                        if policy.is_empty() {
+                            "No rules beyond the minimum length".to_string()
+                        } else {
+                            policy
+                                .iter()
+                                .map(|rule| PinComplexityPolicy::describe(rule).0)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        },
+                    )),
+            )
+            .child(
+                PFButton::new("Edit Policy")
+                    .id("edit-pin-policy-btn")
+                    .with_colors(rgb(0x222225), rgb(0x2a2a2d), rgb(0x333336))
+                    .disabled(!pin_set || self.loading)
+                    .on_click(cx.listener(|this, _, window, cx| {
+                        this.open_pin_policy_dialog(window, cx);
+                    })),
+            )
+    }
 
-        // Create the label view
-        let label_view = cx.new(|_cx| SliderLabel {
-            slider: slider.clone(),
-        });
+    fn render_always_uv_row(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let pin_set = self
+            .fido_info
+            .as_ref()
+            .and_then(|f| f.options.get("clientPin").copied())
+            .unwrap_or(false);
+        // Keys without the option in GetInfo do not support toggling it.
+        let always_uv = self
+            .fido_info
+            .as_ref()
+            .and_then(|f| f.options.get("alwaysUv").copied());
 
-        let view_handle = cx.entity().downgrade();
+        let theme = cx.theme();
 
-        window.open_dialog(cx, move |dialog, _, _| {
-            let view = view_handle.clone();
-            let current = current_pin.clone();
-            let new = new_pin.clone();
-            let confirm = confirm_pin.clone();
-            let slider_handle = slider.clone();
+        div()
+            .flex()
+            .items_center()
+            .justify_between()
+            .p_4()
+            .border_1()
+            .border_color(theme.border)
+            .rounded_lg()
+            .child(
+                v_flex()
+                    .child(div().font_medium().child("Always Require Verification"))
+                    .child(div().text_sm().text_color(theme.muted_foreground).child(
+                        match always_uv {
+                            Some(true) => "Every passkey use requires the PIN",
+                            Some(false) => "Sites may use passkeys without the PIN",
+                            None => "Not supported by this key",
+                        },
+                    )),
+            )
+            .child(
+                Switch::new("always-uv")
+                    .checked(always_uv.unwrap_or(false))
+                    .disabled(!pin_set || always_uv.is_none() || self.loading)
+                    .on_click(cx.listener(|this, _: &bool, window, cx| {
+                        this.open_always_uv_dialog(window, cx);
+                    })),
+            )
+    }
 
-            dialog
-                .title("Update Minimum PIN Length")
-                .child(
-                    "Set the minimum allowed PIN length (4-63 characters) and enter a new PIN that meets this requirement.",
-                )
-                .child(
-                    v_flex()
-                        .gap_4()
-                        .pb_4()
-                        .child(
-                             v_flex()
-                                .gap_2()
-                                .child(label_view.clone())
-                                .child(Slider::new(&slider_handle))
-                        )
-                        .child("Current PIN")
-                        .child(Input::new(&current))
-                        .child(
-                             v_flex()
-                                 .gap_2()
-                                 .child(format!("New PIN (min {} chars)", current_min)) 
-                                 .child(Input::new(&new))
-                        )
-                        .child("Confirm New PIN")
-                        .child(Input::new(&confirm)),
-                )
-                .footer(move |_, _window, _cx, _| {
-                    let view = view.clone();
-                    let current = current.clone();
-                    let new = new.clone();
-                    let confirm = confirm.clone();
-                    let slider = slider_handle.clone();
+    fn render_backup(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let pin_set = self
+            .fido_info
+            .as_ref()
+            .and_then(|f| f.options.get("clientPin").copied())
+            .unwrap_or(false);
 
-                    vec![
-                        Button::new("cancel")
-                            .label("Cancel")
-                            .on_click(|_, window, cx| window.close_dialog(cx)),
-                        Button::new("update")
-                            .primary()
-                            .label("Update")
-                            .on_click(move |_, _, cx| {
-                                let current_val = current.read(cx).text().to_string();
-                                let new_val = new.read(cx).text().to_string();
-                                let confirm_val = confirm.read(cx).text().to_string();
-                                let min_len = slider.read(cx).value().start() as u8;
+        let theme = cx.theme();
 
-                                if current_val.is_empty() {
-                                    return;
-                                }
+        Card::new()
+            .title("Backup & Recovery")
+            .icon(Icon::default().path("icons/shield-check.svg"))
+            .description("Keep an offline copy of this key's secret as recovery words")
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .justify_between()
+                    .p_4()
+                    .border_1()
+                    .border_color(theme.border)
+                    .rounded_lg()
+                    .child(
+                        v_flex()
+                            .child(div().font_medium().child("Encrypted Backup"))
+                            .child(div().text_sm().text_color(theme.muted_foreground).child(
+                                if pin_set {
+                                    "Restore the words onto this or a replacement key"
+                                } else {
+                                    "Set up a PIN to create a backup"
+                                },
+                            )),
+                    )
+                    .child(
+                        h_flex()
+                            .gap_2()
+                            .child(
+                                PFButton::new("Back Up")
+                                    .id("backup-btn")
+                                    .with_colors(rgb(0x222225), rgb(0x2a2a2d), rgb(0x333336))
+                                    .disabled(!pin_set || self.loading)
+                                    .on_click(cx.listener(|this, _, window, cx| {
+                                        this.open_backup_dialog(window, cx);
+                                    })),
+                            )
+                            .child(
+                                PFButton::new("Restore")
+                                    .id("restore-btn")
+                                    .with_colors(rgb(0x222225), rgb(0x2a2a2d), rgb(0x333336))
+                                    .disabled(self.loading)
+                                    .on_click(cx.listener(|this, _, window, cx| {
+                                        this.open_restore_dialog(window, cx);
+                                    })),
+                            ),
+                    ),
+            )
+    }
 
-                                if !new_val.is_empty() {
-                                    if new_val != confirm_val {
-                                        let _ = view.update(cx, |_, cx| {
-                                            cx.emit(PasskeysEvent::Notification("PINs do not match".to_string()));
-                                        });
-                                        return;
-                                    }
-                                    if new_val.len() < min_len as usize {
-                                        let _ = view.update(cx, |_, cx| {
-                                            cx.emit(PasskeysEvent::Notification(format!("PIN must be at least {} characters", min_len)));
-                                        });
-                                        return;
+    fn render_auth_encryption(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let pin_set = self
+            .fido_info
+            .as_ref()
+            .and_then(|f| f.options.get("clientPin").copied())
+            .unwrap_or(false);
+        let enabled = self.fido_info.as_ref().is_some_and(|f| f.auth_encryption);
+        let locked = self
+            .fido_info
+            .as_ref()
+            .is_some_and(|f| f.auth_encryption_locked);
+
+        let theme = cx.theme();
+
+        Card::new()
+            .title("Authentication Encryption")
+            .icon(Icon::default().path("icons/lock.svg"))
+            .description("Keep the key's secret encrypted until this computer unlocks it")
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .justify_between()
+                    .p_4()
+                    .border_1()
+                    .border_color(theme.border)
+                    .rounded_lg()
+                    .child(
+                        v_flex()
+                            .child(div().font_medium().child("Encrypt Device Secret"))
+                            .child(div().text_sm().text_color(theme.muted_foreground).child(
+                                match (enabled, pin_set) {
+                                    (true, _) if locked => {
+                                        "On, locked: the key was encrypted on another computer"
                                     }
-                                }
-                                let _ = view.update(cx, |this, cx| {
-                                    this.update_min_length(current_val, min_len, new_val, cx);
-                                });
-                            }),
-                    ]
-                })
-        });
+                                    (true, _) => "On, unlocked automatically when connected",
+                                    (false, true) => "Off",
+                                    (false, false) => "Set up a PIN to enable encryption",
+                                },
+                            )),
+                    )
+                    .child(
+                        Switch::new("auth-encryption")
+                            .checked(enabled)
+                            .disabled(!pin_set || self.loading)
+                            .on_click(cx.listener(|this, _: &bool, window, cx| {
+                                this.open_auth_encryption_dialog(window, cx);
+                            })),
+                    ),
+            )
     }
 
-    fn change_pin(
-        &mut self,
-        current: String,
-        new: String,
-        dialog_handle: WeakEntity<ChangePinContent>,
-        cx: &mut Context<Self>,
-    ) {
-        if self.loading {
-            return;
-        }
-        self.loading = true;
-        cx.notify();
+    fn render_enterprise_attestation(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let pin_set = self
+            .fido_info
+            .as_ref()
+            .and_then(|f| f.options.get("clientPin").copied())
+            .unwrap_or(false);
+        // Keys without the `ep` option in GetInfo do not support enterprise attestation.
+        let ep = self
+            .fido_info
+            .as_ref()
+            .and_then(|f| f.options.get("ep").copied());
+
+        let theme = cx.theme();
 
-        log::info!("Changing FIDO PIN...");
-        let entity = cx.entity().downgrade();
+        Card::new()
+            .title("Enterprise Attestation")
+            .icon(Icon::default().path("icons/building-2.svg"))
+            .description("Issue attestation certificates for this key from your own CA")
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .justify_between()
+                    .p_4()
+                    .border_1()
+                    .border_color(theme.border)
+                    .rounded_lg()
+                    .child(
+                        v_flex().child(div().font_medium().child("Status")).child(
+                            div()
+                                .text_sm()
+                                .text_color(theme.muted_foreground)
+                                .child(match ep {
+                                    Some(true) => "Enabled",
+                                    Some(false) => "Not enabled",
+                                    None => "Not supported by this key",
+                                }),
+                        ),
+                    )
+                    .child(
+                        h_flex()
+                            .gap_2()
+                            .child(
+                                PFButton::new("Generate CSR")
+                                    .id("ea-csr-btn")
+                                    .with_colors(rgb(0x222225), rgb(0x2a2a2d), rgb(0x333336))
+                                    .disabled(ep.is_none() || self.loading)
+                                    .on_click(cx.listener(|this, _, window, cx| {
+                                        this.open_generate_csr_dialog(window, cx);
+                                    })),
+                            )
+                            .child(
+                                PFButton::new("Upload Certificate")
+                                    .id("ea-upload-btn")
+                                    .with_colors(rgb(0x222225), rgb(0x2a2a2d), rgb(0x333336))
+                                    .disabled(ep.is_none() || !pin_set || self.loading)
+                                    .on_click(cx.listener(|this, _, window, cx| {
+                                        this.pick_attestation_certificate(window, cx);
+                                    })),
+                            ),
+                    ),
+            )
+    }
 
-        self._task = Some(cx.spawn(async move |_, cx| {
-            let result = cx
-                .background_executor()
-                .spawn(async move { io::change_fido_pin(Some(current), new) })
-                .await;
+    fn render_factory_reset(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let can_reset = self.device.as_ref().is_some_and(|d| d.hid_path.is_some());
 
-            let _ = entity.update(cx, |this, cx| {
-                this.loading = false;
-                match result {
-                    Ok(msg) => {
-                        log::info!("PIN changed: {}", msg);
-                        if let Ok(info) = io::get_fido_info() {
-                            this.fido_info = Some(info);
-                        }
-                        let _ = dialog_handle.update(cx, |d, cx| {
-                            d.set_success("PIN changed successfully.".to_string(), cx);
-                        });
-                    }
-                    Err(e) => {
-                        log::error!("PIN change failed: {}", e);
-                        let _ = dialog_handle.update(cx, |d, cx| {
-                            d.set_error(format!("Error: {}", e), cx);
-                        });
-                    }
-                }
-                cx.notify();
-            });
-        }));
+        let theme = cx.theme();
+
+        Card::new()
+            .title("Factory Reset")
+            .icon(Icon::default().path("icons/triangle-alert.svg"))
+            .description("Erase all passkeys, the PIN and FIDO settings")
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .justify_between()
+                    .p_4()
+                    .border_1()
+                    .border_color(theme.border)
+                    .rounded_lg()
+                    .child(
+                        v_flex()
+                            .child(div().font_medium().child("Reset FIDO Application"))
+                            .child(div().text_sm().text_color(theme.muted_foreground).child(
+                                if can_reset {
+                                    "The key has to be power cycled and touched to confirm"
+                                } else {
+                                    "Connect the key's FIDO interface to reset it"
+                                },
+                            )),
+                    )
+                    .child(
+                        Button::new("factory-reset")
+                            .label("Reset Key")
+                            .danger()
+                            .disabled(!can_reset || self.loading)
+                            .on_click(cx.listener(|this, _, window, cx| {
+                                this.open_reset_dialog(window, cx);
+                            })),
+                    ),
+            )
     }
 
-    fn update_min_length(
-        &mut self,
-        current: String,
-        min_len: u8,
-        new_pin: String,
-        cx: &mut Context<Self>,
-    ) {
-        if self.loading {
-            return;
+    fn render_large_blobs(&self, cx: &mut Context<Self>) -> Option<impl IntoElement> {
+        let info = self.fido_info.as_ref()?;
+        if info.options.get("largeBlobs") != Some(&true) {
+            return None;
         }
-        self.loading = true;
-        cx.notify();
-        log::info!("Updating minimum PIN length to {}...", min_len);
-        let entity = cx.entity().downgrade();
+        let capacity = info.max_large_blob_array;
 
-        self._task = Some(cx.spawn(async move |_, cx| {
-            // 1. Set Min Length
-            let current_for_bg = current.clone();
-            let res_len = cx
-                .background_executor()
-                .spawn(async move { io::set_min_pin_length(current_for_bg, min_len) })
-                .await;
+        let theme = cx.theme();
+        let row = || {
+            div()
+                .flex()
+                .items_center()
+                .justify_between()
+                .p_4()
+                .border_1()
+                .border_color(theme.border)
+                .rounded_lg()
+        };
+        let muted = |text: String| {
+            div()
+                .text_sm()
+                .text_color(theme.muted_foreground)
+                .child(text)
+        };
 
-            if let Err(e) = res_len {
-                log::error!("Failed to set minimum PIN length: {}", e);
-                let _ = entity.update(cx, |this, cx| {
-                    this.loading = false;
-                    cx.emit(PasskeysEvent::Notification(format!(
-                        "Failed to set length: {}",
-                        e
-                    )));
-                    cx.notify();
-                });
-                return;
-            }
+        let body = match (&self.large_blobs, self.unlocked) {
+            (_, false) => v_flex()
+                .child(row().child(muted("Unlock storage to browse large blobs".to_string()))),
+            (None, true) => v_flex().child(
+                row()
+                    .child(muted(
+                        "Read the array to see which passkeys store data in it".to_string(),
+                    ))
+                    .child(
+                        Button::new("read-large-blobs")
+                            .label("Read Array")
+                            .disabled(self.loading)
+                            .on_click(cx.listener(|this, _, _, cx| {
+                                this.load_large_blobs(cx);
+                            })),
+                    ),
+            ),
+            (Some(array), true) => {
+                let usage = match capacity {
+                    Some(max) => format!(
+                        "{} entries, {} of {} bytes used",
+                        array.entries.len(),
+                        array.size,
+                        max
+                    ),
+                    None => format!("{} entries, {} bytes used", array.entries.len(), array.size),
+                };
 
-            if !new_pin.is_empty() {
-                let res_pin = cx
-                    .background_executor()
-                    .spawn(async move { io::change_fido_pin(Some(current), new_pin) })
-                    .await;
-                let _ = entity.update(cx, |this, cx| {
-                    this.loading = false;
-                    match res_pin {
-                        Ok(_) => {
-                            log::info!("Minimum length and PIN updated successfully.");
-                            cx.emit(PasskeysEvent::CloseDialog);
-                            cx.emit(PasskeysEvent::Notification(
-                                "Minimum length and PIN updated".to_string(),
-                            ));
-                            if let Ok(info) = io::get_fido_info() {
-                                this.fido_info = Some(info);
-                            }
-                        }
-                        Err(e) => {
-                            log::error!("Length set, but PIN change failed: {}", e);
-                            cx.emit(PasskeysEvent::Notification(format!(
-                                "Length set, but PIN change failed: {}",
-                                e
-                            )));
-                        }
-                    }
-                    cx.notify();
-                });
-            } else {
-                let _ = entity.update(cx, |this, cx| {
-                    this.loading = false;
-                    log::info!("Minimum PIN length updated to {}.", min_len);
-                    cx.emit(PasskeysEvent::CloseDialog);
-                    cx.emit(PasskeysEvent::Notification(format!(
-                        "Minimum length updated to {}",
-                        min_len
-                    )));
-                    if let Ok(info) = io::get_fido_info() {
-                        this.fido_info = Some(info);
-                    }
-                    cx.notify();
-                });
+                v_flex()
+                    .gap_3()
+                    .child(
+                        h_flex()
+                            .justify_between()
+                            .items_center()
+                            .child(muted(usage))
+                            .child(
+                                h_flex()
+                                    .gap_2()
+                                    .child(
+                                        Button::new("refresh-large-blobs")
+                                            .label("Refresh")
+                                            .small()
+                                            .disabled(self.loading)
+                                            .on_click(cx.listener(|this, _, _, cx| {
+                                                this.load_large_blobs(cx);
+                                            })),
+                                    )
+                                    .child(
+                                        Button::new("reset-large-blobs")
+                                            .label("Reset Array")
+                                            .small()
+                                            .danger()
+                                            .disabled(self.loading)
+                                            .on_click(cx.listener(|this, _, window, cx| {
+                                                this.open_large_blob_dialog(None, window, cx);
+                                            })),
+                                    ),
+                            ),
+                    )
+                    .children((!array.intact).then(|| {
+                        row()
+                            .border_color(theme.danger)
+                            .child(
+                                v_flex()
+                                    .child(div().font_medium().child("Array is corrupt"))
+                                    .child(muted(
+                                        "Its checksum does not match, so clients ignore it. Reset it to make large blobs usable again."
+                                            .to_string(),
+                                    )),
+                            )
+                    }))
+                    .children((array.intact && array.entries.is_empty()).then(|| {
+                        row().child(muted("No large blobs stored".to_string()))
+                    }))
+                    .children(array.entries.iter().enumerate().map(|(index, entry)| {
+                        let owner = match &entry.owner {
+                            Some(owner) => format!("{} ({})", owner.rp_id, owner.user_name),
+                            None => "No matching passkey".to_string(),
+                        };
+                        let entry_for_click = entry.clone();
+
+                        row()
+                            .child(
+                                v_flex()
+                                    .child(div().font_medium().child(owner))
+                                    .child(muted(format!(
+                                        "{} bytes stored, {} bytes decompressed",
+                                        entry.size, entry.original_size
+                                    ))),
+                            )
+                            .child(
+                                Button::new(SharedString::from(format!("delete-blob-{}", index)))
+                                    .ghost()
+                                    .small()
+                                    .disabled(self.loading)
+                                    .child(
+                                        Icon::default()
+                                            .path("icons/trash-2.svg")
+                                            .size_4()
+                                            .text_color(theme.muted_foreground),
+                                    )
+                                    .on_click(cx.listener(move |this, _, window, cx| {
+                                        this.open_large_blob_dialog(
+                                            Some(entry_for_click.clone()),
+                                            window,
+                                            cx,
+                                        );
+                                    })),
+                            )
+                    }))
             }
-        }));
+        };
+
+        Some(
+            Card::new()
+                .title("Large Blobs")
+                .icon(Icon::default().path("icons/hard-drive.svg"))
+                .description("Data that websites store on the key alongside passkeys")
+                .child(body),
+        )
     }
 
     fn render_no_device(&self, theme: &Theme) -> impl IntoElement {
@@ -585,12 +2511,21 @@ impl PasskeysView {
     fn render_pin_management(&self, cx: &mut Context<Self>) -> impl IntoElement {
         let status_row = self.render_pin_status_row(cx).into_any_element();
         let min_len_row = self.render_min_pin_length_row(cx).into_any_element();
+        let pin_policy_row = self.render_pin_policy_row(cx).into_any_element();
+        let always_uv_row = self.render_always_uv_row(cx).into_any_element();
 
         Card::new()
             .title("PIN Management")
             .icon(Icon::default().path("icons/key.svg"))
             .description("Configure FIDO2 PIN security")
-            .child(v_flex().gap_4().child(status_row).child(min_len_row))
+            .child(
+                v_flex()
+                    .gap_4()
+                    .child(status_row)
+                    .child(min_len_row)
+                    .child(pin_policy_row)
+                    .child(always_uv_row),
+            )
     }
 
     fn render_pin_status_row(&self, cx: &mut Context<Self>) -> impl IntoElement {
@@ -600,6 +2535,9 @@ impl PasskeysView {
             .and_then(|f| f.options.get("clientPin").copied())
             .unwrap_or(false);
 
+        let attempts = PinAttempts::from_info(self.fido_info.as_ref());
+        let uv_retries = self.fido_info.as_ref().and_then(|f| f.uv_retries);
+
         let listener = cx.listener(move |this, _, window, cx| {
             if pin_set {
                 this.open_change_pin_dialog(window, cx);
@@ -630,7 +2568,29 @@ impl PasskeysView {
                             } else {
                                 "No PIN configured"
                             }),
-                    ),
+                    )
+                    .children(attempts.retries.filter(|_| pin_set).map(|retries| {
+                        div()
+                            .text_sm()
+                            .text_color(if attempts.is_low() {
+                                theme.warning
+                            } else {
+                                theme.muted_foreground
+                            })
+                            .child(format!("{} PIN attempts left", retries))
+                    }))
+                    .children(uv_retries.map(|retries| {
+                        div()
+                            .text_sm()
+                            .text_color(theme.muted_foreground)
+                            .child(format!("{} built-in verification attempts left", retries))
+                    }))
+                    .children(attempts.power_cycle_required.then(|| {
+                        div()
+                            .text_sm()
+                            .text_color(theme.warning)
+                            .child("Unplug and replug the key before entering the PIN again")
+                    })),
             )
             .child(
                 PFButton::new(if pin_set { "Change PIN" } else { "Set up PIN" })
@@ -737,6 +2697,7 @@ impl PasskeysView {
                         .with_text_color(rgb(0x18181b)),
                     ),
             )
+            .children(self.fido_info.as_ref().and_then(SlotGauge::from_info))
     }
 
     fn render_unlocked_state(&self, cx: &mut Context<Self>) -> impl IntoElement {
@@ -799,6 +2760,11 @@ impl PasskeysView {
                                 .on_click(lock_listener),
                             ),
                     )
+                    .children(self.fido_info.as_ref().and_then(SlotGauge::from_info))
+                    .children(
+                        (!self.credentials.is_empty())
+                            .then(|| self.render_selection_bar(cx).into_any_element()),
+                    )
                     .child(if self.credentials.is_empty() {
                         self.render_empty_credentials_with_theme(theme)
                             .into_any_element()
@@ -813,6 +2779,61 @@ impl PasskeysView {
             )
     }
 
+    fn render_selection_bar(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let selected = self.selected.len();
+        let all_selected = selected == self.credentials.len();
+        let select_listener = cx.listener(move |this, _, _, cx| {
+            if all_selected {
+                this.selected.clear();
+            } else {
+                this.selected = this
+                    .credentials
+                    .iter()
+                    .map(|c| c.credential_id.clone())
+                    .collect();
+            }
+            cx.notify();
+        });
+        let delete_listener = cx.listener(|this, _, window, cx| {
+            let credentials = this.selected_credentials();
+            this.open_bulk_delete_dialog(credentials, window, cx);
+        });
+        let theme = cx.theme();
+
+        h_flex()
+            .justify_between()
+            .items_center()
+            .child(
+                h_flex()
+                    .gap_3()
+                    .items_center()
+                    .child(
+                        Button::new("select-all-creds")
+                            .small()
+                            .label(if all_selected {
+                                "Clear Selection"
+                            } else {
+                                "Select All"
+                            })
+                            .on_click(select_listener),
+                    )
+                    .child(
+                        div()
+                            .text_sm()
+                            .text_color(theme.muted_foreground)
+                            .child(format!("{} selected", selected)),
+                    ),
+            )
+            .child(
+                Button::new("delete-selected-creds")
+                    .small()
+                    .with_variant(ButtonVariant::Danger)
+                    .label("Delete Selected")
+                    .disabled(selected == 0 || self.loading)
+                    .on_click(delete_listener),
+            )
+    }
+
     fn render_empty_credentials_with_theme(&self, theme: &Theme) -> impl IntoElement {
         v_flex()
             .items_center()
@@ -861,6 +2882,12 @@ impl PasskeysView {
             this.open_credential_details(&cred_for_click, window, cx);
         });
 
+        let selected = self.selected.contains(&cred.credential_id);
+        let credential_id = cred.credential_id.clone();
+        let select_listener = cx.listener(move |this, checked: &bool, _, cx| {
+            this.toggle_selected(&credential_id, *checked, cx);
+        });
+
         let theme = cx.theme();
 
         div()
@@ -871,7 +2898,11 @@ impl PasskeysView {
             .cursor_pointer()
             .on_click(click_listener)
             .border_1()
-            .border_color(theme.border)
+            .border_color(if selected {
+                theme.primary
+            } else {
+                theme.border
+            })
             .rounded_xl()
             .p_4()
             .hover(|s| s.bg(theme.accent).border_color(theme.primary))
@@ -885,6 +2916,21 @@ impl PasskeysView {
                             .items_center()
                             .flex_1()
                             .min_w_0()
+                            .child(
+                                // Ticking the box must not also open the details sheet.
+                                div()
+                                    .on_mouse_down(MouseButton::Left, |_, _, cx| {
+                                        cx.stop_propagation()
+                                    })
+                                    .child(
+                                        Checkbox::new(SharedString::from(format!(
+                                            "cred-select-{}",
+                                            cred.credential_id
+                                        )))
+                                        .checked(selected)
+                                        .on_click(select_listener),
+                                    ),
+                            )
                             .child(
                                 div()
                                     .size_10()
@@ -966,8 +3012,41 @@ impl PasskeysView {
         };
         let user_id = cred.user_id.clone();
         let credential_id = cred.credential_id.clone();
+        let cred_for_edit = cred.clone();
+        let public_key = cred.public_key.as_ref().map(|key| match key.curve_name() {
+            Some(curve) => format!("{} ({})", key.algorithm_name(), curve),
+            None => key.algorithm_name(),
+        });
+        let protection = match (cred.cred_protect, cred.cred_protect_policy()) {
+            (Some(level), Some(policy)) => format!("Level {} · {}", level, policy.name()),
+            (Some(level), None) => format!("Level {} (unknown)", level),
+            (None, _) => "Not reported".to_string(),
+        };
+        let protection_note = cred
+            .cred_protect_policy()
+            .map(|policy| policy.description());
+        let large_blob_key = if cred.large_blob_key.is_some() {
+            "Present"
+        } else {
+            "None"
+        };
+        let third_party_payment = cred.third_party_payment;
+        let rp_credentials: Vec<StoredCredential> = self
+            .credentials
+            .iter()
+            .filter(|c| c.rp_id == cred.rp_id)
+            .cloned()
+            .collect();
+        self.cred_blob = None;
+        let view_handle = cx.entity().downgrade();
 
         window.open_sheet_at(Placement::Bottom, cx, move |sheet, _, cx| {
+            let cred_blob = view_handle
+                .upgrade()
+                .and_then(|view| match &view.read(cx).cred_blob {
+                    Some((id, read)) if *id == credential_id => Some(read.clone()),
+                    _ => None,
+                });
             let theme = cx.theme();
 
             let header_row = h_flex()
@@ -1002,6 +3081,22 @@ impl PasskeysView {
                                 .font_family("monospace")
                                 .child(user_name.clone()),
                         ),
+                )
+                .child(div().flex_1())
+                .child(
+                    Button::new("edit-cred-user")
+                        .small()
+                        .label("Edit")
+                        .on_click({
+                            let view_handle = view_handle.clone();
+                            let cred = cred_for_edit.clone();
+                            move |_, window, cx| {
+                                window.close_sheet(cx);
+                                let _ = view_handle.update(cx, |this, cx| {
+                                    this.open_edit_user_dialog(cred.clone(), window, cx);
+                                });
+                            }
+                        }),
                 );
 
             let separator = div().w_full().h(px(1.)).bg(theme.border);
@@ -1058,7 +3153,7 @@ impl PasskeysView {
                             .child(description),
                     ),
                 )
-                .size(px(500.))
+                .size(px(640.))
                 .resizable(false)
                 .margin_top(px(0.))
                 .child(
@@ -1073,7 +3168,131 @@ impl PasskeysView {
                                 "Credential ID (Hex)",
                                 credential_id.clone(),
                                 true,
-                            )),
+                            ))
+                            .children(public_key.clone().map(|description| {
+                                let export_button =
+                                    |id: &'static str,
+                                     label: &'static str,
+                                     format: PublicKeyFormat| {
+                                        let view_handle = view_handle.clone();
+                                        let cred = cred_for_edit.clone();
+                                        Button::new(id).small().label(label).on_click(
+                                            move |_, _, cx| {
+                                                let _ = view_handle.update(cx, |this, cx| {
+                                                    this.export_public_key(
+                                                        cred.clone(),
+                                                        format,
+                                                        cx,
+                                                    );
+                                                });
+                                            },
+                                        )
+                                    };
+                                h_flex()
+                                    .justify_between()
+                                    .items_end()
+                                    .child(detail_field("Public Key", description, false))
+                                    .child(
+                                        h_flex()
+                                            .gap_2()
+                                            .child(export_button(
+                                                "export-pem",
+                                                "Export PEM",
+                                                PublicKeyFormat::Pem,
+                                            ))
+                                            .child(export_button(
+                                                "export-jwk",
+                                                "Export JWK",
+                                                PublicKeyFormat::Jwk,
+                                            )),
+                                    )
+                            }))
+                            .child(
+                                h_flex()
+                                    .gap_8()
+                                    .items_start()
+                                    .child(
+                                        detail_field("Protection", protection.clone(), false)
+                                            .children(protection_note.map(|note| {
+                                                div()
+                                                    .text_xs()
+                                                    .text_color(theme.muted_foreground)
+                                                    .child(note)
+                                            })),
+                                    )
+                                    .child(detail_field(
+                                        "Large Blob Key",
+                                        large_blob_key.to_string(),
+                                        false,
+                                    ))
+                                    .children(third_party_payment.then(|| {
+                                        detail_field(
+                                            "Third-Party Payment",
+                                            "Allowed".to_string(),
+                                            false,
+                                        )
+                                    })),
+                            )
+                            .child({
+                                let (value, mono) = match &cred_blob {
+                                    None => ("Not read yet".to_string(), false),
+                                    Some(CredBlobRead::Reading) => {
+                                        ("Reading...".to_string(), false)
+                                    }
+                                    Some(CredBlobRead::Done(None)) => ("None".to_string(), false),
+                                    Some(CredBlobRead::Done(Some(blob))) => {
+                                        (describe_cred_blob(blob), true)
+                                    }
+                                    Some(CredBlobRead::Failed(e)) => (e.clone(), false),
+                                };
+                                let view_handle = view_handle.clone();
+                                let cred = cred_for_edit.clone();
+                                h_flex()
+                                    .justify_between()
+                                    .items_end()
+                                    .gap_4()
+                                    .child(detail_field("Credential Blob", value, mono))
+                                    .child(
+                                        Button::new("read-cred-blob")
+                                            .small()
+                                            .label("Read")
+                                            .loading(matches!(
+                                                cred_blob,
+                                                Some(CredBlobRead::Reading)
+                                            ))
+                                            .on_click(move |_, _, cx| {
+                                                let _ = view_handle.update(cx, |this, cx| {
+                                                    this.read_cred_blob(cred.clone(), cx);
+                                                });
+                                            }),
+                                    )
+                            })
+                            .child(
+                                h_flex().justify_end().child(
+                                    Button::new("delete-rp-creds")
+                                        .small()
+                                        .with_variant(ButtonVariant::Danger)
+                                        .label(format!(
+                                            "Delete All for {} ({})",
+                                            rp_id,
+                                            rp_credentials.len()
+                                        ))
+                                        .on_click({
+                                            let view_handle = view_handle.clone();
+                                            let rp_credentials = rp_credentials.clone();
+                                            move |_, window, cx| {
+                                                window.close_sheet(cx);
+                                                let _ = view_handle.update(cx, |this, cx| {
+                                                    this.open_bulk_delete_dialog(
+                                                        rp_credentials.clone(),
+                                                        window,
+                                                        cx,
+                                                    );
+                                                });
+                                            }
+                                        }),
+                                ),
+                            ),
                     ),
                 )
         });
@@ -1129,7 +3348,12 @@ impl Render for PasskeysView {
         let content = v_flex()
             .gap_6()
             .child(self.render_pin_management(cx))
-            .child(self.render_stored_passkeys(cx));
+            .child(self.render_backup(cx))
+            .child(self.render_auth_encryption(cx))
+            .child(self.render_enterprise_attestation(cx))
+            .child(self.render_stored_passkeys(cx))
+            .children(self.render_large_blobs(cx))
+            .child(self.render_factory_reset(cx));
 
         let theme = cx.theme();
 